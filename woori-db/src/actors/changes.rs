@@ -0,0 +1,122 @@
+use actix::prelude::*;
+use std::{collections::BTreeMap, sync::Arc};
+use tokio::sync::broadcast;
+
+use crate::{
+    io::read::committed_records,
+    model::{error::Error, log::LogRecord},
+};
+
+use super::wql::Executor;
+
+/// Transactions kept for subscriptions that are behind. A subscription that lags more
+/// than this ends, and resumes from the day logs.
+pub const CHANGES_CAPACITY: usize = 1024;
+
+/// Sequence number and records of a transaction, sent once it committed.
+pub type Committed = (u64, Arc<Vec<LogRecord>>);
+
+/// Subscribes to the transactions committed from now on. Returns the records of the
+/// transactions from `from` on that already committed, and the subscription to the ones
+/// after them.
+pub struct SubscribeChanges {
+    pub from: Option<u64>,
+}
+
+impl Message for SubscribeChanges {
+    type Result = Result<(Vec<LogRecord>, Subscription), Error>;
+}
+
+impl Handler<SubscribeChanges> for Executor {
+    type Result = Result<(Vec<LogRecord>, Subscription), Error>;
+
+    fn handle(&mut self, msg: SubscribeChanges, _: &mut Self::Context) -> Self::Result {
+        let receiver = self.changes.subscribe();
+        // Transactions from here on may still be committing, or fail, so they are only
+        // sent once the subscription receives them.
+        let committed = self.committed()?;
+        let records = match msg.from {
+            Some(from) if from < committed => committed_records(&*self.storage, from, committed)?,
+            _ => Vec::new(),
+        };
+        let from = msg.from.unwrap_or(committed).max(committed);
+
+        Ok((records, Subscription::new(receiver, from)))
+    }
+}
+
+/// Committed transactions in sequence order. Transactions that arrive ahead of the next
+/// one, such as replicated ones, wait for it.
+pub struct Subscription {
+    receiver: broadcast::Receiver<Committed>,
+    next_tx_seq: u64,
+    pending: BTreeMap<u64, Arc<Vec<LogRecord>>>,
+}
+
+impl Subscription {
+    pub fn new(receiver: broadcast::Receiver<Committed>, next_tx_seq: u64) -> Self {
+        Self {
+            receiver,
+            next_tx_seq,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Records of the next committed transaction, `None` once the subscription lagged
+    /// behind or the executor stopped.
+    pub async fn next(&mut self) -> Option<Arc<Vec<LogRecord>>> {
+        loop {
+            if let Some(records) = self.pending.remove(&self.next_tx_seq) {
+                self.next_tx_seq += 1;
+                return Some(records);
+            }
+            match self.receiver.recv().await {
+                Ok((tx_seq, records)) if tx_seq >= self.next_tx_seq => {
+                    self.pending.insert(tx_seq, records);
+                }
+                Ok(_) => (),
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::wql::Action;
+
+    fn committed(tx_seq: u64) -> Committed {
+        let record = LogRecord {
+            tx_seq: Some(tx_seq),
+            ..LogRecord::new(Action::Insert, "changes")
+        };
+        (tx_seq, Arc::new(vec![record]))
+    }
+
+    async fn next_tx_seq(subscription: &mut Subscription) -> Option<u64> {
+        subscription
+            .next()
+            .await
+            .map(|records| records[0].tx_seq.unwrap())
+    }
+
+    #[actix_rt::test]
+    async fn subscription_in_sequence_order() {
+        let (sender, receiver) = broadcast::channel(CHANGES_CAPACITY);
+        let mut subscription = Subscription::new(receiver, 2);
+
+        sender.send(committed(1)).unwrap();
+        sender.send(committed(3)).unwrap();
+        sender.send(committed(2)).unwrap();
+        sender.send(committed(5)).unwrap();
+        sender.send(committed(4)).unwrap();
+        assert_eq!(next_tx_seq(&mut subscription).await, Some(2));
+        assert_eq!(next_tx_seq(&mut subscription).await, Some(3));
+        assert_eq!(next_tx_seq(&mut subscription).await, Some(4));
+        assert_eq!(next_tx_seq(&mut subscription).await, Some(5));
+
+        drop(sender);
+        assert_eq!(next_tx_seq(&mut subscription).await, None);
+    }
+}
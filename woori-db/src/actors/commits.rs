@@ -0,0 +1,206 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use futures::channel::oneshot;
+use tokio::sync::broadcast;
+
+use crate::{
+    io::storage::Storage,
+    model::{error::Error, log::LogRecord},
+};
+
+use super::changes::Committed;
+
+/// Transactions appended to the day logs, answered in the order they were appended once
+/// their commits resolve. When a commit fails, the log is truncated back to its records,
+/// dropping every transaction appended after it too, and the next transaction reuses its
+/// sequence number, so committed sequence numbers never skip one.
+pub(crate) struct Commits {
+    /// Sequence number the next transaction is appended with.
+    next_tx_seq: u64,
+    /// Every transaction below it committed.
+    committed: u64,
+    /// Identifies the next append, since sequence numbers of failed ones are reused.
+    next_id: u64,
+    pending: BTreeMap<u64, Pending>,
+}
+
+struct Pending {
+    tx_seq: u64,
+    log: String,
+    offset: usize,
+    records: Vec<LogRecord>,
+    outcome: Option<Result<(), Error>>,
+    answer: oneshot::Sender<Result<(), Error>>,
+}
+
+impl Commits {
+    pub(crate) fn new(next_tx_seq: u64) -> Self {
+        Self {
+            next_tx_seq,
+            committed: next_tx_seq,
+            next_id: 0,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn next_tx_seq(&self) -> u64 {
+        self.next_tx_seq
+    }
+
+    /// Sequence number every transaction below which committed.
+    pub(crate) fn committed(&self) -> u64 {
+        self.committed
+    }
+
+    /// Counts transaction `tx_seq`, replicated from the leader, as committed.
+    pub(crate) fn replicated(&mut self, tx_seq: u64) {
+        self.next_tx_seq = self.next_tx_seq.max(tx_seq + 1);
+        self.committed = self.committed.max(tx_seq + 1);
+    }
+
+    /// Offset of the first transaction appended to `log` whose commit is pending. Its
+    /// records and the ones after it may still be truncated, so they aren't replicated.
+    pub(crate) fn pending_offset(&self, log: &str) -> Option<usize> {
+        self.pending
+            .values()
+            .find(|pending| pending.log == log)
+            .map(|pending| pending.offset)
+    }
+
+    /// Tracks transaction `tx_seq`, whose `records` were appended at `offset` of `log`.
+    /// Returns the id to resolve its commit with and where its outcome is answered.
+    pub(crate) fn append(
+        &mut self,
+        tx_seq: u64,
+        log: &str,
+        offset: usize,
+        records: Vec<LogRecord>,
+    ) -> (u64, oneshot::Receiver<Result<(), Error>>) {
+        let (answer, outcome) = oneshot::channel();
+        let id = self.next_id;
+        self.next_id += 1;
+        self.next_tx_seq = tx_seq + 1;
+        self.pending.insert(
+            id,
+            Pending {
+                tx_seq,
+                log: log.to_owned(),
+                offset,
+                records,
+                outcome: None,
+                answer,
+            },
+        );
+        (id, outcome)
+    }
+
+    /// Records the outcome of the commit of append `id`, then answers the transactions
+    /// whose commit and every earlier one resolved. Committed ones are sent to `changes`.
+    pub(crate) fn resolve(
+        &mut self,
+        storage: &dyn Storage,
+        changes: &broadcast::Sender<Committed>,
+        id: u64,
+        outcome: Result<(), Error>,
+    ) {
+        match self.pending.get_mut(&id) {
+            Some(pending) => pending.outcome = Some(outcome),
+            // Already failed by an earlier commit.
+            None => return,
+        }
+
+        while let Some(entry) = self.pending.first_entry() {
+            match entry.get().outcome {
+                None => return,
+                Some(Ok(())) => {
+                    let pending = entry.remove();
+                    self.committed = pending.tx_seq + 1;
+                    let _ = changes.send((pending.tx_seq, Arc::new(pending.records)));
+                    let _ = pending.answer.send(Ok(()));
+                }
+                Some(Err(_)) => {
+                    self.fail(storage);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Truncates the logs back to the first failed transaction and fails every pending
+    /// one, whose records were appended after it.
+    fn fail(&mut self, storage: &dyn Storage) {
+        let mut truncated: Vec<String> = Vec::new();
+        for (i, (_, pending)) in std::mem::take(&mut self.pending).into_iter().enumerate() {
+            if i == 0 {
+                self.next_tx_seq = pending.tx_seq;
+            }
+            if !truncated.contains(&pending.log) {
+                if let Err(e) = truncate(storage, &pending.log, pending.offset) {
+                    log::error!(
+                        "Failed to truncate {} back to {}: {}",
+                        pending.log,
+                        pending.offset,
+                        e
+                    );
+                }
+                truncated.push(pending.log.clone());
+            }
+            let outcome = match pending.outcome {
+                Some(Err(e)) if i == 0 => Err(e),
+                _ => Err(Error::Io(std::io::Error::other(format!(
+                    "records at offset {} of {} were dropped after an earlier commit failed",
+                    pending.offset, pending.log
+                )))),
+            };
+            let _ = pending.answer.send(outcome);
+        }
+    }
+}
+
+/// Drops what was appended to `log` from `offset` on, which didn't become durable.
+fn truncate(storage: &dyn Storage, log: &str, offset: usize) -> Result<(), Error> {
+    if storage.log_len(log)? > offset {
+        storage.truncate_log(log, offset)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::storage::MemoryStorage;
+
+    #[test]
+    fn failed_commit_drops_the_later_ones() {
+        let storage = MemoryStorage::new();
+        storage.append_log("2021_01_01.log", b"0123456789").unwrap();
+        let (changes, mut committed) = broadcast::channel(8);
+        let mut commits = Commits::new(1);
+
+        let (first, mut first_outcome) = commits.append(1, "2021_01_01.log", 0, Vec::new());
+        let (second, mut second_outcome) = commits.append(2, "2021_01_01.log", 4, Vec::new());
+        let (third, mut third_outcome) = commits.append(3, "2021_01_01.log", 6, Vec::new());
+        assert_eq!(commits.next_tx_seq(), 4);
+        assert_eq!(commits.pending_offset("2021_01_01.log"), Some(0));
+
+        commits.resolve(&storage, &changes, third, Ok(()));
+        assert!(matches!(third_outcome.try_recv(), Ok(None)));
+        commits.resolve(&storage, &changes, first, Ok(()));
+        assert!(matches!(first_outcome.try_recv(), Ok(Some(Ok(())))));
+        assert_eq!(committed.try_recv().unwrap().0, 1);
+        assert_eq!(commits.committed(), 2);
+        assert_eq!(commits.pending_offset("2021_01_01.log"), Some(4));
+
+        commits.resolve(&storage, &changes, second, Err(Error::LockData));
+        assert!(matches!(
+            second_outcome.try_recv(),
+            Ok(Some(Err(Error::LockData)))
+        ));
+        assert!(matches!(third_outcome.try_recv(), Ok(Some(Err(_)))));
+        assert!(committed.try_recv().is_err());
+        assert_eq!(storage.log_len("2021_01_01.log").unwrap(), 4);
+        assert_eq!(commits.committed(), 2);
+        assert_eq!(commits.next_tx_seq(), 2);
+        assert_eq!(commits.pending_offset("2021_01_01.log"), None);
+    }
+}
@@ -31,7 +31,7 @@ impl Handler<WriteWithEncryption> for Executor {
     fn handle(&mut self, msg: WriteWithEncryption, _: &mut Self::Context) -> Self::Result {
         use crate::io::write::write_to_encrypts;
         let encrypt_log = to_string_pretty(&msg, pretty_config()).map_err(Error::Serialization)?;
-        Ok(write_to_encrypts(&encrypt_log)?)
+        write_to_encrypts(&*self.storage, &encrypt_log)
     }
 }
 
@@ -176,7 +176,7 @@ fn pretty_config() -> PrettyConfig {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::{actors::wql::Executor, io::read::assert_encrypt};
+    use crate::{actors::wql::Executor, io::read::assert_encrypt, io::storage::default_storage};
 
     #[actix_rt::test]
     async fn write_uniques() {
@@ -184,7 +184,7 @@ mod test {
             entity: String::from("my-entity"),
             encrypts: vec![String::from("id"), String::from("ssn")],
         };
-        let actor = Executor::new().start();
+        let actor = Executor::new(default_storage()).start();
 
         let resp = actor.send(encrypts).await.unwrap();
         assert!(resp.is_ok());
@@ -199,7 +199,7 @@ mod test {
             encrypts: vec![String::from("id"), String::from("ssn")],
             data: Arc::new(Arc::new(Mutex::new(data.clone()))),
         };
-        let actor = Executor::new().start();
+        let actor = Executor::new(default_storage()).start();
 
         let resp = actor.send(encrypts).await.unwrap();
         assert!(resp.is_ok());
@@ -1,13 +1,14 @@
 use actix::prelude::*;
 use chrono::{DateTime, Utc};
 use std::collections::HashMap;
+use uuid::Uuid;
 use wql::Types;
 
-use crate::model::error::Error;
+use crate::model::{error::Error, log::LogRecord, wql::Action};
 use crate::{actors::wql::Executor, model::DataRegister};
 
 pub type HistoryRegistry = (HashMap<String, Types>, DateTime<Utc>, Option<DataRegister>);
-pub struct History(pub String);
+pub struct History(pub LogRecord);
 
 impl Message for History {
     type Result = Result<HistoryRegistry, Error>;
@@ -17,72 +18,62 @@ impl Handler<History> for Executor {
     type Result = Result<HistoryRegistry, Error>;
 
     fn handle(&mut self, msg: History, _: &mut Self::Context) -> Self::Result {
-        let fractions = msg.0.split('|').collect::<Vec<&str>>();
-        if fractions[0].eq("INSERT") {
-            let date = get_date(&fractions);
-            let content = get_insert_content(&fractions);
-            let previous_registry = None;
-            Ok((content?, date?, previous_registry))
-        } else if fractions[0].eq("UPDATE_SET")
-            || fractions[0].eq("UPDATE_CONTENT")
-            || fractions[0].eq("DELETE")
-        {
-            let date = get_date(&fractions);
-            let content = get_other_content(&fractions);
-            let previous_registry = get_previous_registry(&fractions);
-            Ok((content?, date?, previous_registry?))
-        } else {
-            Err(Error::FailedToParseState)
+        match msg.0.action {
+            Action::Insert => {
+                let date = msg.0.tx_time.ok_or(Error::FailedToParseState);
+                let content = get_content(&msg.0);
+                let previous_registry = None;
+                Ok((content?, date?, previous_registry))
+            }
+            Action::UpdateSet | Action::UpdateContent | Action::Delete => {
+                let date = msg.0.tx_time.ok_or(Error::FailedToParseState);
+                let content = get_content(&msg.0);
+                let previous_registry = get_previous_registry(&msg.0);
+                Ok((content?, date?, previous_registry?))
+            }
+            _ => Err(Error::FailedToParseState),
         }
     }
 }
 
-fn get_insert_content(fractions: &[&str]) -> Result<HashMap<String, Types>, Error> {
-    let state = fractions
-        .last()
-        .ok_or(Error::FailedToParseState)?
-        .to_owned();
-    let state = &state[..(state.len() - 1)];
+/// Register of the version of the id written at `tx_time`, where a newest first history
+/// page continues from.
+pub struct VersionRegister {
+    pub entity: String,
+    pub uuid: Uuid,
+    pub tx_time: DateTime<Utc>,
+}
 
-    let resp: Result<HashMap<String, Types>, Error> = match ron::de::from_str(state) {
-        Ok(x) => Ok(x),
-        Err(_) => Err(Error::FailedToParseState),
-    };
-    resp
+impl Message for VersionRegister {
+    type Result = Result<DataRegister, Error>;
 }
 
-fn get_other_content(fractions: &[&str]) -> Result<HashMap<String, Types>, Error> {
-    let state = fractions
-        .get(fractions.len() - 2)
-        .ok_or(Error::FailedToParseState)?
-        .to_owned();
+impl Handler<VersionRegister> for Executor {
+    type Result = Result<DataRegister, Error>;
 
-    let resp: Result<HashMap<String, Types>, Error> = match ron::de::from_str(state) {
-        Ok(x) => Ok(x),
-        Err(_) => Err(Error::FailedToParseState),
-    };
-    resp
+    fn handle(&mut self, msg: VersionRegister, _: &mut Self::Context) -> Self::Result {
+        self.time_index
+            .version(&msg.entity, &msg.uuid, msg.tx_time)
+            .cloned()
+            .ok_or(Error::HistoryCursorNotValid)
+    }
 }
 
-fn get_date(fractions: &[&str]) -> Result<DateTime<Utc>, Error> {
-    let state = fractions
-        .get(1)
-        .ok_or(Error::FailedToParseState)?
-        .to_owned();
+fn get_content(record: &LogRecord) -> Result<HashMap<String, Types>, Error> {
+    let state = record.state.as_ref().ok_or(Error::FailedToParseState)?;
 
-    let resp: Result<DateTime<Utc>, Error> = match ron::de::from_str(state) {
+    let resp: Result<HashMap<String, Types>, Error> = match ron::de::from_str(state) {
         Ok(x) => Ok(x),
         Err(_) => Err(Error::FailedToParseState),
     };
     resp
 }
 
-fn get_previous_registry(fractions: &[&str]) -> Result<Option<DataRegister>, Error> {
-    let state = fractions
-        .last()
-        .ok_or(Error::FailedToParseRegistry)?
-        .to_owned();
-    let state = &state[..(state.len() - 1)];
+fn get_previous_registry(record: &LogRecord) -> Result<Option<DataRegister>, Error> {
+    let state = record
+        .previous_registry
+        .as_ref()
+        .ok_or(Error::FailedToParseRegistry)?;
 
     let resp: Result<(DataRegister, Vec<u8>), Error> = match ron::de::from_str(state) {
         Ok(x) => Ok(x),
@@ -1,6 +1,9 @@
+pub mod changes;
+mod commits;
 pub mod encrypts;
 pub mod history;
 pub mod recovery;
+pub mod replication;
 pub mod scheduler;
 pub mod state;
 pub mod uniques;
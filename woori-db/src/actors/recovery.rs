@@ -1,10 +1,24 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
 use actix::prelude::*;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+use wql::Types;
 
 use crate::{
-    core::pretty_config_inner,
-    io::write::{local_data, offset_counter},
-    model::error::Error,
-    repository::local::LocalContext,
+    core::valid_time,
+    io::{
+        record::encode,
+        write::{local_checkpoint, local_delta, offset_counter, time_index},
+    },
+    model::{error::Error, DataRegister},
+    repository::{
+        local::{LocalContext, LocalDelta},
+        time_index::TimeEntry,
+    },
 };
 
 use super::wql::Executor;
@@ -27,17 +41,79 @@ impl Handler<OffsetCounter> for Executor {
     type Result = Result<(), Error>;
 
     fn handle(&mut self, msg: OffsetCounter, _: &mut Self::Context) -> Self::Result {
-        Ok(offset_counter(msg.offset)?)
+        if self.batch.is_some() {
+            return Ok(());
+        }
+        offset_counter(&*self.storage, msg.offset)
     }
 }
 
+/// Journals a change already applied to `data`, and checkpoints `data` once the
+/// journal grows past the configured checkpoint size. Inside a `BEGIN ... COMMIT` block the
+/// change is journaled on commit. An upsert of a new state also adds it to the time
+/// index, with the time and sequence number of its transaction, and an eviction adds a
+/// tombstone once the register of its record is set.
 pub struct LocalData {
-    pub data: LocalContext,
+    pub delta: LocalDelta,
+    pub data: Arc<Mutex<LocalContext>>,
+    pub tx: Option<(DateTime<Utc>, u64)>,
+    pub register: Option<DataRegister>,
 }
 
 impl LocalData {
-    pub fn new(data: LocalContext) -> Self {
-        Self { data }
+    pub fn new(delta: LocalDelta, data: Arc<Mutex<LocalContext>>) -> Self {
+        Self {
+            delta,
+            data,
+            tx: None,
+            register: None,
+        }
+    }
+
+    pub fn at(mut self, tx_time: DateTime<Utc>, tx_seq: u64) -> Self {
+        self.tx = Some((tx_time, tx_seq));
+        self
+    }
+
+    /// Register of the record of an eviction.
+    pub fn recorded_at(mut self, register: DataRegister) -> Self {
+        self.register = Some(register);
+        self
+    }
+
+    fn time_entry(&self) -> Option<TimeEntry> {
+        let (tx_time, tx_seq) = self.tx?;
+        match (&self.delta, &self.register) {
+            (LocalDelta::Upsert(entity, uuid, (register, state)), _) => {
+                let state: HashMap<String, Types> = bincode::deserialize(state).ok()?;
+                let (valid_from, valid_to) = valid_time(&state);
+                Some(TimeEntry {
+                    entity: entity.to_owned(),
+                    uuid: *uuid,
+                    tx_time,
+                    tx_seq: Some(tx_seq),
+                    valid_from,
+                    valid_to,
+                    register: register.clone(),
+                    evicted: false,
+                })
+            }
+            (LocalDelta::EvictId(entity, uuid), Some(register)) => Some(TimeEntry::evicted(
+                entity,
+                *uuid,
+                tx_time,
+                Some(tx_seq),
+                register.clone(),
+            )),
+            (LocalDelta::EvictEntity(entity), Some(register)) => Some(TimeEntry::evicted(
+                entity,
+                Uuid::nil(),
+                tx_time,
+                Some(tx_seq),
+                register.clone(),
+            )),
+            _ => None,
+        }
     }
 }
 
@@ -49,7 +125,26 @@ impl Handler<LocalData> for Executor {
     type Result = Result<(), Error>;
 
     fn handle(&mut self, msg: LocalData, _: &mut Self::Context) -> Self::Result {
-        let data_str = ron::ser::to_string_pretty(&msg.data, pretty_config_inner())?;
-        Ok(local_data(&data_str)?)
+        let entry = msg.time_entry();
+        if let Some(batch) = &mut self.batch {
+            batch.deltas.extend(encode(&msg.delta)?);
+            batch.time_entries.extend(entry);
+            return Ok(());
+        }
+        if let Some(entry) = entry {
+            time_index(&*self.storage, &encode(&entry)?)?;
+            self.time_index.insert(entry);
+        }
+        let journal_size = local_delta(&*self.storage, &encode(&msg.delta)?)?;
+        if journal_size < crate::config::config().checkpoint_size {
+            return Ok(());
+        }
+
+        let checkpoint = if let Ok(guard) = msg.data.lock() {
+            encode(&*guard)?
+        } else {
+            return Err(Error::LockData);
+        };
+        local_checkpoint(&*self.storage, &checkpoint)
     }
 }
@@ -0,0 +1,383 @@
+use actix::prelude::*;
+use actix_web::client::Client;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{
+    core::replay::{apply, RecoveredContext},
+    io::{
+        record::{encode, record_len, Records},
+        storage::Storage,
+        write::time_index,
+    },
+    model::{error::Error, log::LogRecord, wql::Action, DataRegister},
+    repository::{
+        index::IndexContext,
+        local::{EncryptContext, LocalContext, LocalDelta, UniquenessContext},
+        time_index::TimeEntry,
+    },
+};
+
+use super::wql::Executor;
+
+/// Most bytes of a day log sent at once to a follower. A chunk ends at a record boundary,
+/// so it only holds more when its first record is larger.
+pub const CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+/// Header with the day log the bytes of a chunk belong to.
+pub const LOG_HEADER: &str = "Woori-Log";
+/// Header with the day log after the one of the chunk, if there is one.
+pub const NEXT_LOG_HEADER: &str = "Woori-Next-Log";
+
+/// Bytes of a day log from an offset, as sent to followers.
+#[derive(Debug, Default)]
+pub struct LogChunk {
+    /// Day log of the bytes, `None` when there are no logs.
+    pub log: Option<String>,
+    /// Next day log, which followers move to once they read `log` to its end.
+    pub next_log: Option<String>,
+    pub bytes: Vec<u8>,
+}
+
+/// Reads the complete records of `log` from `offset` on, at most `CHUNK_SIZE` of them, without
+/// reading the rest of the log. Reads the first day log when `log` is `None`. Records of
+/// transactions whose commit is pending aren't read, since a failed commit truncates them.
+pub struct ReadLogChunk {
+    pub log: Option<String>,
+    pub offset: usize,
+}
+
+impl Message for ReadLogChunk {
+    type Result = Result<LogChunk, Error>;
+}
+
+impl Handler<ReadLogChunk> for Executor {
+    type Result = Result<LogChunk, Error>;
+
+    fn handle(&mut self, msg: ReadLogChunk, _: &mut Self::Context) -> Self::Result {
+        // Held while reading, so that no commit resolves and truncates the log meanwhile.
+        let commits = self.commits.lock().map_err(|_| Error::LockData)?;
+        let ReadLogChunk { log, offset } = msg;
+        let logs = self.storage.list_logs()?;
+        let log = match log.or_else(|| logs.first().map(|(log, _)| log.to_owned())) {
+            Some(log) => log,
+            None => return Ok(LogChunk::default()),
+        };
+        // Followers only move to the next log once they read the committed end of this one.
+        let pending = commits.pending_offset(&log);
+        let next_log = logs
+            .iter()
+            .map(|(name, _)| name)
+            .find(|name| pending.is_none() && **name > log)
+            .cloned();
+        let committed_len = pending.map_or(usize::MAX, |pending| pending.saturating_sub(offset));
+        if committed_len == 0 || !logs.iter().any(|(name, _)| *name == log) {
+            return Ok(LogChunk {
+                log: Some(log),
+                next_log,
+                bytes: Vec::new(),
+            });
+        }
+
+        let mut tail = self
+            .storage
+            .read_log_range(&log, offset, CHUNK_SIZE.min(committed_len))?;
+        if let Some(length) = record_len(&tail).filter(|length| *length > tail.len()) {
+            tail = self
+                .storage
+                .read_log_range(&log, offset, length.min(committed_len))?;
+        }
+        let mut records = Records::<LogRecord>::new(&tail);
+        let mut end = 0;
+        while records.next().is_some() {
+            if end > 0 && records.valid_len() > CHUNK_SIZE {
+                break;
+            }
+            end = records.valid_len();
+        }
+
+        Ok(LogChunk {
+            log: Some(log),
+            next_log,
+            bytes: tail[..end].to_vec(),
+        })
+    }
+}
+
+/// Committed records a follower replicated, so that they count as committed transactions,
+/// reach the time index and are streamed at `/changes`.
+pub struct Replicated(pub Vec<(LogRecord, DataRegister)>);
+
+impl Message for Replicated {
+    type Result = Result<(), Error>;
+}
+
+impl Handler<Replicated> for Executor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: Replicated, _: &mut Self::Context) -> Self::Result {
+        let mut entries = Vec::new();
+        let mut transactions: Vec<(u64, Vec<LogRecord>)> = Vec::new();
+        for (record, register) in msg.0 {
+            entries.extend(TimeEntry::of(&record, register));
+            if let Some(tx_seq) = record.tx_seq {
+                self.replicated(tx_seq)?;
+                match transactions.last_mut() {
+                    Some((last, records)) if *last == tx_seq => records.push(record),
+                    _ => transactions.push((tx_seq, vec![record])),
+                }
+            }
+        }
+
+        let journal = entries
+            .iter()
+            .map(encode)
+            .collect::<Result<Vec<_>, _>>()?
+            .concat();
+        if !journal.is_empty() {
+            time_index(&*self.storage, &journal)?;
+        }
+        entries
+            .into_iter()
+            .for_each(|entry| self.time_index.insert(entry));
+        for (tx_seq, records) in transactions {
+            let _ = self.changes.send((tx_seq, Arc::new(records)));
+        }
+
+        Ok(())
+    }
+}
+
+/// State a follower applies the replicated records to, shared with its handlers.
+pub struct FollowerContext {
+    pub local_data: Arc<Mutex<LocalContext>>,
+    pub uniqueness: Arc<Mutex<UniquenessContext>>,
+    pub encryption: Arc<Mutex<EncryptContext>>,
+    pub indexes: Arc<Mutex<IndexContext>>,
+}
+
+/// Tails the day logs of the leader from the end of the local ones. The bytes are appended
+/// to the local day log of the same name, so registers are the same as in the leader, and
+/// their committed records are applied to the shared state.
+pub struct Follower {
+    leader: String,
+    token: Option<String>,
+    interval: Duration,
+    storage: Arc<dyn Storage>,
+    executor: Addr<Executor>,
+    context: FollowerContext,
+    /// Day log being replicated and how many of its bytes were.
+    position: Option<(String, usize)>,
+    /// Records of a `BEGIN ... COMMIT` block whose `COMMIT` wasn't replicated yet.
+    block: Option<Vec<(LogRecord, DataRegister)>>,
+}
+
+impl Actor for Follower {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Context<Self>) {
+        log::info!("Replicating {}", self.leader);
+        self.poll(ctx);
+    }
+}
+
+impl Follower {
+    pub fn new(
+        leader: &str,
+        token: Option<String>,
+        interval: Duration,
+        storage: Arc<dyn Storage>,
+        executor: Addr<Executor>,
+        context: FollowerContext,
+    ) -> Result<Self, Error> {
+        let position = match storage.list_logs()?.pop() {
+            Some((log, false)) => Some((log.clone(), storage.log_len(&log)?)),
+            Some((log, true)) => Some((log.clone(), storage.read_log(&log)?.len())),
+            None => None,
+        };
+
+        Ok(Self {
+            leader: leader.trim_end_matches('/').to_owned(),
+            token,
+            interval,
+            storage,
+            executor,
+            context,
+            position,
+            block: None,
+        })
+    }
+
+    /// Asks the leader for the bytes after `position`, then asks again right away when
+    /// there were some, or after `interval` otherwise.
+    fn poll(&mut self, ctx: &mut Context<Self>) {
+        let url = match &self.position {
+            Some((log, offset)) => format!(
+                "{}/replication/log?log={}&offset={}",
+                self.leader, log, offset
+            ),
+            None => format!("{}/replication/log", self.leader),
+        };
+        let mut request = Client::new().get(url);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let fetch = async move {
+            let mut response = request.send().await.map_err(|e| e.to_string())?;
+            if !response.status().is_success() {
+                return Err(format!("leader answered {}", response.status()));
+            }
+            let header = |name| {
+                response
+                    .headers()
+                    .get(name)
+                    .and_then(|value| value.to_str().ok())
+                    .map(ToOwned::to_owned)
+            };
+            let (log, next_log) = (header(LOG_HEADER), header(NEXT_LOG_HEADER));
+            let bytes = response
+                .body()
+                .limit(2 * CHUNK_SIZE)
+                .await
+                .map_err(|e| e.to_string())?;
+
+            Ok(LogChunk {
+                log,
+                next_log,
+                bytes: bytes.to_vec(),
+            })
+        };
+
+        ctx.spawn(fetch.into_actor(self).map(|fetched, act, ctx| {
+            let progressed = match fetched.map(|chunk| act.replicate(chunk)) {
+                Ok(Ok(progressed)) => progressed,
+                Ok(Err(e)) => {
+                    log::error!("Failed to apply the records replicated: {}", e);
+                    false
+                }
+                Err(e) => {
+                    log::warn!("Failed to replicate {}: {}", act.leader, e);
+                    false
+                }
+            };
+            if progressed {
+                act.poll(ctx);
+            } else {
+                ctx.run_later(act.interval, |act, ctx| act.poll(ctx));
+            }
+        }));
+    }
+
+    /// Appends `chunk` to the local day log and applies its committed records, returning
+    /// whether the position moved.
+    pub fn replicate(&mut self, chunk: LogChunk) -> Result<bool, Error> {
+        let log = match chunk.log {
+            Some(log) => log,
+            None => return Ok(false),
+        };
+        let offset = match &self.position {
+            Some((current, offset)) if *current == log => *offset,
+            _ => 0,
+        };
+        if chunk.bytes.is_empty() {
+            return Ok(match chunk.next_log {
+                Some(next_log) => {
+                    self.position = Some((next_log, 0));
+                    true
+                }
+                None => false,
+            });
+        }
+
+        self.storage.append_log(&log, &chunk.bytes)?;
+        let mut committed = Vec::new();
+        let mut records = Records::<LogRecord>::new(&chunk.bytes);
+        while let Some((start, record)) = records.next() {
+            let register = DataRegister {
+                file_name: log.clone(),
+                offset: offset + start,
+                bytes_length: records.valid_len() - start,
+            };
+            let record = match record {
+                Ok(record) => record,
+                Err(e) => {
+                    log::error!(
+                        "Unrecoverable record at offset {} of {}: {}",
+                        register.offset,
+                        log,
+                        e
+                    );
+                    continue;
+                }
+            };
+            match (&record.action, &mut self.block) {
+                (Action::Begin, _) => self.block = Some(Vec::new()),
+                (Action::Commit, Some(_)) => {
+                    committed.extend(self.block.take().unwrap_or_default())
+                }
+                (_, Some(block)) => block.push((record, register)),
+                (_, None) => committed.push((record, register)),
+            }
+        }
+        self.position = Some((log, offset + chunk.bytes.len()));
+
+        self.apply(&committed)?;
+        self.executor.do_send(Replicated(committed));
+        Ok(true)
+    }
+
+    /// Applies committed records to the shared state, like a replay of the logs does.
+    fn apply(&self, committed: &[(LogRecord, DataRegister)]) -> Result<(), Error> {
+        let FollowerContext {
+            local_data,
+            uniqueness,
+            encryption,
+            indexes,
+        } = &self.context;
+        let (mut local_data, mut uniqueness, mut encryption, mut indexes) = match (
+            local_data.lock(),
+            uniqueness.lock(),
+            encryption.lock(),
+            indexes.lock(),
+        ) {
+            (Ok(local_data), Ok(uniqueness), Ok(encryption), Ok(indexes)) => {
+                (local_data, uniqueness, encryption, indexes)
+            }
+            _ => return Err(Error::LockData),
+        };
+        let mut context = RecoveredContext {
+            local_data: std::mem::take(&mut *local_data),
+            uniqueness: std::mem::take(&mut *uniqueness),
+            encryption: std::mem::take(&mut *encryption),
+            indexes: indexes.definitions(),
+            offset: 0,
+        };
+
+        let mut deltas: Vec<LocalDelta> = Vec::new();
+        let mut created_index = false;
+        for (record, register) in committed {
+            match apply(&mut context, record.clone(), register.clone()) {
+                Ok(Some(delta)) => deltas.push(delta),
+                Ok(None) => created_index = true,
+                Err(e) => log::error!(
+                    "Unrecoverable record at offset {} of {}: {}",
+                    register.offset,
+                    register.file_name,
+                    e
+                ),
+            }
+        }
+
+        *local_data = context.local_data;
+        *uniqueness = context.uniqueness;
+        *encryption = context.encryption;
+        if created_index {
+            *indexes = IndexContext::new(context.indexes, &local_data);
+        } else {
+            deltas.iter().for_each(|delta| indexes.apply(delta));
+        }
+        Ok(())
+    }
+}
@@ -1,16 +1,24 @@
+use crate::{config::ArchivePolicy, io::storage::Storage, model::error::Error};
 use actix::prelude::*;
-use chrono::{Local, TimeZone, Utc};
+use chrono::{Local, NaiveDate, Utc};
 use cron::Schedule;
-use std::{
-    fs::OpenOptions,
-    io::{BufReader, BufWriter},
-    path::PathBuf,
-    process::Command,
-    str::FromStr,
-    time::Duration,
-};
+use serde::Serialize;
+use std::{collections::BTreeMap, str::FromStr, sync::Arc, time::Duration};
 
-pub struct Scheduler;
+/// Archives day logs older than the policy threshold, on the policy cron or on request.
+pub struct Scheduler {
+    storage: Arc<dyn Storage>,
+    policy: ArchivePolicy,
+    schedule: Schedule,
+}
+
+/// Day logs processed by one archival run.
+#[derive(Debug, Default, Serialize)]
+pub struct ArchiveReport {
+    pub archived: Vec<String>,
+    /// Logs that could not be archived, with the reason.
+    pub failed: BTreeMap<String, String>,
+}
 
 impl Actor for Scheduler {
     type Context = Context<Self>;
@@ -18,7 +26,7 @@ impl Actor for Scheduler {
     fn started(&mut self, ctx: &mut Context<Self>) {
         log::debug!("Actor is alive");
 
-        ctx.run_later(duration_until_next(), move |this, ctx| {
+        ctx.run_later(self.duration_until_next(), move |this, ctx| {
             this.schedule_task(ctx)
         });
     }
@@ -29,58 +37,142 @@ impl Actor for Scheduler {
 }
 
 impl Scheduler {
+    pub fn new(storage: Arc<dyn Storage>, policy: ArchivePolicy) -> Self {
+        let schedule = Schedule::from_str(&policy.cron)
+            .unwrap_or_else(|e| panic!("Invalid archive cron {}: {}", policy.cron, e));
+        Self {
+            storage,
+            policy,
+            schedule,
+        }
+    }
+
+    /// Archives every day log older than `after_days`.
+    pub fn archive(&self) -> Result<ArchiveReport, Error> {
+        let date_to_clear =
+            Utc::now().date_naive() - chrono::Duration::days(self.policy.after_days);
+        let mut report = ArchiveReport::default();
+
+        self.storage
+            .list_logs()?
+            .iter()
+            .filter(|(_, is_archived)| !is_archived)
+            .filter(|(file_name, _)| {
+                NaiveDate::parse_from_str(file_name.trim_end_matches(".log"), "%Y_%m_%d")
+                    .is_ok_and(|date| date < date_to_clear)
+            })
+            .for_each(|(file_name, _)| {
+                match self
+                    .storage
+                    .archive_log(file_name, self.policy.compression_level)
+                {
+                    Ok(()) => report.archived.push(file_name.to_owned()),
+                    Err(e) => {
+                        report.failed.insert(file_name.to_owned(), e.to_string());
+                    }
+                }
+            });
+
+        Ok(report)
+    }
+
     fn schedule_task(&self, ctx: &mut Context<Self>) {
-        use glob::glob;
         log::debug!("schedule_task event - {:?}", Local::now());
-        let date_to_clear = Utc::now() - chrono::Duration::days(10);
-        let files: Vec<PathBuf> = glob("*data/*.log")
-            .unwrap()
-            .map(std::result::Result::unwrap)
-            .collect();
-
-        files.iter().for_each(|f| {
-            if let Some(file_name) = f.to_str() {
-                let date = file_name.replace(".log", "").replace("data/", "");
-                let file_date =
-                    Utc.datetime_from_str(&format!("{} 00:00:00", date), "%Y_%m_%d %H:%M:%S");
-
-                if file_date.is_ok() && file_date.unwrap() < date_to_clear {
-                    use std::io;
-                    let file_zip = file_name.replace(".log", ".zst");
-                    let file = OpenOptions::new().read(true).open(file_name).unwrap();
-                    let mut reader = BufReader::new(file);
-                    let write = OpenOptions::new()
-                        .create(true)
-                        .write(true)
-                        .append(false)
-                        .open(&file_zip)
-                        .unwrap();
-                    let writer = BufWriter::new(write);
-
-                    let mut encoder = zstd::stream::Encoder::new(writer, 22).unwrap();
-                    io::copy(&mut reader, &mut encoder).unwrap();
-                    encoder.finish().unwrap();
-
-                    Command::new("rm")
-                        .arg("-rf")
-                        .arg(&file_name)
-                        .output()
-                        .expect("Couldn't remove file");
+        match self.archive() {
+            Ok(report) => {
+                if !report.archived.is_empty() {
+                    log::info!("Archived {:?}", report.archived);
+                }
+                for (file_name, e) in &report.failed {
+                    log::error!("Failed to archive {}: {}", file_name, e);
                 }
             }
-        });
+            Err(e) => log::error!("Failed to list logs to archive: {}", e),
+        }
 
-        ctx.run_later(duration_until_next(), move |this, ctx| {
+        ctx.run_later(self.duration_until_next(), move |this, ctx| {
             this.schedule_task(ctx)
         });
     }
+
+    fn duration_until_next(&self) -> Duration {
+        let now = Local::now();
+        let next = self.schedule.upcoming(Local).next().unwrap_or(now);
+        let duration_until = next.signed_duration_since(now);
+        Duration::from_millis(duration_until.num_milliseconds().max(0) as u64)
+    }
 }
 
-pub fn duration_until_next() -> Duration {
-    let cron_expression = "@daily"; // or "0 0 0 */1 * *"; every day at midnight
-    let cron_schedule = Schedule::from_str(cron_expression).unwrap();
-    let now = Local::now();
-    let next = cron_schedule.upcoming(Local).next().unwrap();
-    let duration_until = next.signed_duration_since(now);
-    Duration::from_millis(duration_until.num_milliseconds() as u64)
+/// Runs the archival job now, outside of its schedule.
+pub struct RunArchival;
+
+impl Message for RunArchival {
+    type Result = Result<ArchiveReport, Error>;
+}
+
+impl Handler<RunArchival> for Scheduler {
+    type Result = Result<ArchiveReport, Error>;
+
+    fn handle(&mut self, _: RunArchival, _: &mut Self::Context) -> Self::Result {
+        self.archive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::storage::FsStorage;
+    use std::{fs, path::Path};
+
+    fn policy(after_days: i64) -> ArchivePolicy {
+        ArchivePolicy {
+            after_days,
+            compression_level: 3,
+            ..ArchivePolicy::default()
+        }
+    }
+
+    #[test]
+    fn archives_logs_older_than_policy() {
+        let dir = Path::new("data/scheduler_test");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir).unwrap();
+        let storage = Arc::new(FsStorage::new(dir));
+        let today = Utc::now().format("%Y_%m_%d.log").to_string();
+        storage.append_log("2021_01_01.log", b"old").unwrap();
+        storage.append_log(&today, b"new").unwrap();
+
+        let report = Scheduler::new(storage.clone(), policy(10))
+            .archive()
+            .unwrap();
+        let logs = storage.list_logs().unwrap();
+        let _ = fs::remove_dir_all(dir);
+
+        assert_eq!(report.archived, vec!["2021_01_01.log".to_string()]);
+        assert!(report.failed.is_empty());
+        assert_eq!(
+            logs,
+            vec![("2021_01_01.log".to_string(), true), (today, false)]
+        );
+    }
+
+    #[test]
+    fn reports_failed_logs() {
+        let dir = Path::new("data/scheduler_failure_test");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join("not_a_dir"), b"").unwrap();
+        let storage = Arc::new(FsStorage::new(dir).with_archive_dir(&dir.join("not_a_dir")));
+        storage.append_log("2021_01_01.log", b"old").unwrap();
+
+        let report = Scheduler::new(storage.clone(), policy(10))
+            .archive()
+            .unwrap();
+        let logs = storage.list_logs();
+        let _ = fs::remove_dir_all(dir);
+
+        assert!(report.archived.is_empty());
+        assert!(report.failed.contains_key("2021_01_01.log"));
+        assert_eq!(logs.unwrap(), vec![("2021_01_01.log".to_string(), false)]);
+    }
 }
@@ -4,9 +4,12 @@ use std::collections::HashMap;
 use wql::{MatchCondition, Types};
 
 use crate::actors::wql::Executor;
-use crate::{model::error::Error, repository::local::StateInfo};
+use crate::{
+    model::{error::Error, log::LogRecord, wql::Action},
+    repository::local::StateInfo,
+};
 
-pub struct State(pub String);
+pub struct State(pub LogRecord);
 
 impl Message for State {
     type Result = Result<HashMap<String, Types>, Error>;
@@ -18,40 +21,22 @@ impl Handler<State> for Executor {
     fn handle(&mut self, msg: State, _: &mut Self::Context) -> Self::Result {
         use ron::de::from_str;
 
-        let fractions = msg.0.split('|').collect::<Vec<&str>>();
-        if fractions[0].eq("INSERT") {
-            let state = fractions
-                .last()
-                .ok_or(Error::FailedToParseState)?
-                .to_owned();
-            let state = &state[..(state.len() - 1)];
-
-            let resp: Result<HashMap<String, Types>, Error> = match from_str(state) {
-                Ok(x) => Ok(x),
-                Err(_) => Err(Error::FailedToParseState),
-            };
-            resp
-        } else if fractions[0].eq("UPDATE_SET")
-            || fractions[0].eq("UPDATE_CONTENT")
-            || fractions[0].eq("DELETE")
-        {
-            let state = fractions
-                .get(fractions.len() - 2)
-                .ok_or(Error::FailedToParseState)?
-                .to_owned();
-
-            let resp: Result<HashMap<String, Types>, Error> = match from_str(state) {
-                Ok(x) => Ok(x),
-                Err(_) => Err(Error::FailedToParseState),
-            };
-            resp
-        } else {
-            Err(Error::FailedToParseState)
+        match msg.0.action {
+            Action::Insert | Action::UpdateSet | Action::UpdateContent | Action::Delete => {
+                let state = msg.0.state.ok_or(Error::FailedToParseState)?;
+
+                let resp: Result<HashMap<String, Types>, Error> = match from_str(&state) {
+                    Ok(x) => Ok(x),
+                    Err(_) => Err(Error::FailedToParseState),
+                };
+                resp
+            }
+            _ => Err(Error::FailedToParseState),
         }
     }
 }
 
-pub struct PreviousRegistry(pub String);
+pub struct PreviousRegistry(pub LogRecord);
 
 impl Message for PreviousRegistry {
     type Result = Result<Option<StateInfo>, Error>;
@@ -63,26 +48,21 @@ impl Handler<PreviousRegistry> for Executor {
     fn handle(&mut self, msg: PreviousRegistry, _: &mut Self::Context) -> Self::Result {
         use ron::de::from_str;
 
-        let fractions = msg.0.split('|').collect::<Vec<&str>>();
-        if fractions[0].eq("INSERT") {
-            Ok(None)
-        } else if fractions[0].eq("UPDATE_SET")
-            || fractions[0].eq("UPDATE_CONTENT")
-            || fractions[0].eq("DELETE")
-        {
-            let state = fractions
-                .last()
-                .ok_or(Error::FailedToParseRegistry)?
-                .to_owned();
-            let state = &state[..(state.len() - 1)];
-
-            let resp: Result<StateInfo, Error> = match from_str(state) {
-                Ok(x) => Ok(x),
-                Err(_) => Err(Error::FailedToParseRegistry),
-            };
-            Ok(Some(resp?))
-        } else {
-            Err(Error::FailedToParseRegistry)
+        match msg.0.action {
+            Action::Insert => Ok(None),
+            Action::UpdateSet | Action::UpdateContent | Action::Delete => {
+                let state = msg
+                    .0
+                    .previous_registry
+                    .ok_or(Error::FailedToParseRegistry)?;
+
+                let resp: Result<StateInfo, Error> = match from_str(&state) {
+                    Ok(x) => Ok(x),
+                    Err(_) => Err(Error::FailedToParseRegistry),
+                };
+                Ok(Some(resp?))
+            }
+            _ => Err(Error::FailedToParseRegistry),
         }
     }
 }
@@ -255,11 +235,11 @@ fn l_match_types(cond: Types, state: Types) -> bool {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::actors::wql::Executor;
+    use crate::{actors::wql::Executor, io::storage::default_storage};
 
     #[actix_rt::test]
     async fn test_all_matches() {
-        let actor = Executor::new().start();
+        let actor = Executor::new(default_storage()).start();
         let conds = MatchCondition::All(vec![
             MatchCondition::Eq("e".to_string(), Types::String(String::from("hello"))),
             MatchCondition::NotEq("f".to_string(), Types::Boolean(false)),
@@ -282,7 +262,7 @@ mod test {
 
     #[actix_rt::test]
     async fn test_any_matches() {
-        let actor = Executor::new().start();
+        let actor = Executor::new(default_storage()).start();
         let conds = MatchCondition::Any(vec![
             MatchCondition::Eq("e".to_string(), Types::String(String::from("hellwo"))),
             MatchCondition::NotEq("f".to_string(), Types::Boolean(true)),
@@ -305,7 +285,7 @@ mod test {
 
     #[actix_rt::test]
     async fn test_any_fail() {
-        let actor = Executor::new().start();
+        let actor = Executor::new(default_storage()).start();
         let conds = MatchCondition::Any(vec![
             MatchCondition::Eq("e".to_string(), Types::String(String::from("hellwo"))),
             MatchCondition::NotEq("f".to_string(), Types::Boolean(true)),
@@ -328,7 +308,7 @@ mod test {
 
     #[actix_rt::test]
     async fn test_all_fail() {
-        let actor = Executor::new().start();
+        let actor = Executor::new(default_storage()).start();
         let conds = MatchCondition::All(vec![
             MatchCondition::Eq("e".to_string(), Types::String(String::from("hello"))),
             MatchCondition::NotEq("f".to_string(), Types::Boolean(false)),
@@ -30,7 +30,7 @@ impl Handler<WriteWithUniqueKeys> for Executor {
         use crate::io::write::write_to_uniques;
         let unique_log =
             to_string_pretty(&msg, pretty_config_inner()).map_err(Error::Serialization)?;
-        Ok(write_to_uniques(&unique_log)?)
+        write_to_uniques(&*self.storage, &unique_log)
     }
 }
 
@@ -70,7 +70,7 @@ impl Handler<CreateWithUniqueKeys> for Executor {
         }
         let unique_ron =
             ron::ser::to_string_pretty(&uniqueness_data.clone(), pretty_config_inner())?;
-        unique_data(&unique_ron)?;
+        unique_data(&*self.storage, &unique_ron)?;
         Ok(())
     }
 }
@@ -133,7 +133,10 @@ impl Handler<CheckForUniqueKeys> for Executor {
             }
             let unique_ron =
                 ron::ser::to_string_pretty(&uniqueness_data.clone(), pretty_config_inner())?;
-            unique_data(&unique_ron)?;
+            match &mut self.batch {
+                Some(batch) => batch.unique_data = Some(unique_ron),
+                None => unique_data(&*self.storage, &unique_ron)?,
+            }
         }
 
         Ok(())
@@ -143,8 +146,8 @@ impl Handler<CheckForUniqueKeys> for Executor {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::actors::wql::Executor;
     use crate::io::read::assert_uniques;
+    use crate::{actors::wql::Executor, io::storage::default_storage};
 
     #[actix_rt::test]
     async fn write_uniques() {
@@ -152,7 +155,7 @@ mod test {
             entity: String::from("my-entity"),
             uniques: vec![String::from("id"), String::from("ssn")],
         };
-        let actor = Executor::new().start();
+        let actor = Executor::new(default_storage()).start();
 
         let resp = actor.send(uniques).await.unwrap();
         assert!(resp.is_ok());
@@ -167,7 +170,7 @@ mod test {
             uniques: vec![String::from("id"), String::from("ssn")],
             data: Arc::new(Arc::new(Mutex::new(data.clone()))),
         };
-        let actor = Executor::new().start();
+        let actor = Executor::new(default_storage()).start();
 
         let resp = actor.send(uniques).await.unwrap();
         assert!(resp.is_ok());
@@ -4,15 +4,22 @@ use std::collections::{BTreeMap, HashMap, HashSet};
 use uuid::Uuid;
 use wql::Types;
 
-use crate::{core::query::filter_keys_and_hash, io::read::read_date_log, model::error::Error};
+use crate::{
+    core::query::filter_keys_and_hash,
+    io::{read::read_log, storage::Storage},
+    model::{error::Error, DataRegister},
+    repository::time_index::Until,
+};
 
 use super::wql::Executor;
+
+/// States of the id written strictly between `start_date` and `end_date`, whatever days
+/// they span.
 pub struct ReadEntityRange {
     entity_name: String,
     uuid: Uuid,
     start_date: DateTime<Utc>,
     end_date: DateTime<Utc>,
-    date_log: String,
 }
 
 impl ReadEntityRange {
@@ -21,14 +28,12 @@ impl ReadEntityRange {
         uuid: Uuid,
         start_date: DateTime<Utc>,
         end_date: DateTime<Utc>,
-        date_log: String,
     ) -> Self {
         Self {
             entity_name: entity_name.to_owned(),
             uuid,
             start_date,
             end_date,
-            date_log,
         }
     }
 }
@@ -41,86 +46,37 @@ impl Handler<ReadEntityRange> for Executor {
     type Result = Result<BTreeMap<DateTime<Utc>, HashMap<String, Types>>, Error>;
 
     fn handle(&mut self, msg: ReadEntityRange, _: &mut Self::Context) -> Self::Result {
-        use ron::de::from_str;
-        let date_log = msg.date_log.clone();
-        let date_log = read_date_log(date_log)?;
-        let mut hm = BTreeMap::new();
-        date_log.split(';').try_for_each(|line| {
-            let fractions = line.split('|').collect::<Vec<&str>>();
-
-            if fractions[0].eq("INSERT")
-                && fractions[3].eq(&msg.entity_name)
-                && fractions[2].eq(&msg.uuid.to_string())
-            {
-                let state = fractions
-                    .last()
-                    .ok_or(Error::FailedToParseState)?
-                    .to_owned();
-                let date: Result<DateTime<Utc>, Error> = match from_str(fractions[1]) {
-                    Ok(x) => Ok(x),
-                    Err(_) => Err(Error::FailedToParseDate),
-                };
-                let date = date?;
-
-                if date > msg.start_date && date < msg.end_date {
-                    let resp: Result<HashMap<String, Types>, Error> = match from_str(state) {
-                        Ok(x) => Ok(x),
-                        Err(_) => Err(Error::FailedToParseState),
-                    };
-                    match resp {
-                        Ok(map) => {
-                            let map = filter_keys_and_hash(map, None);
-                            hm.insert(date, map);
-                        }
-                        Err(e) => return Err(e),
-                    };
-                }
-            } else if (fractions[0].eq("UPDATE_SET") || fractions[0].eq("UPDATE_CONTENT"))
-                && fractions[3].eq(&msg.entity_name)
-                && fractions[2].eq(&msg.uuid.to_string())
-            {
-                let state = fractions
-                    .get(fractions.len() - 2)
-                    .ok_or(Error::FailedToParseState)?
-                    .to_owned();
-                let date: Result<DateTime<Utc>, Error> = match from_str(fractions[1]) {
-                    Ok(x) => Ok(x),
-                    Err(_) => Err(Error::FailedToParseDate),
-                };
-                let date = date?;
-
-                if date > msg.start_date && date < msg.end_date {
-                    let resp: Result<HashMap<String, Types>, Error> = match from_str(state) {
-                        Ok(x) => Ok(x),
-                        Err(_) => Err(Error::FailedToParseState),
-                    };
-                    match resp {
-                        Ok(map) => {
-                            let map = filter_keys_and_hash(map, None);
-                            hm.insert(date, map);
-                        }
-                        Err(e) => return Err(e),
-                    };
-                }
-            }
-            Ok(())
-        })?;
-
-        Ok(hm)
+        self.time_index
+            .between(&msg.entity_name, &msg.uuid, msg.start_date, msg.end_date)
+            .into_iter()
+            .map(|(date, register)| {
+                let state = read_state(&*self.storage, register.clone())?;
+                Ok((date, filter_keys_and_hash(state, None)))
+            })
+            .collect()
     }
 }
 
+/// Latest states of `entity_name` up to `until`, among the ones valid at `valid` when
+/// set.
 pub struct ReadEntitiesAt {
     entity_name: String,
-    date_log: String,
+    until: Until,
+    valid: Option<DateTime<Utc>>,
     keys: Option<HashSet<String>>,
 }
 
 impl ReadEntitiesAt {
-    pub fn new(entity_name: &str, date_log: String, keys: Option<HashSet<String>>) -> Self {
+    pub fn new(
+        entity_name: &str,
+        until: Until,
+        valid: Option<DateTime<Utc>>,
+        keys: Option<HashSet<String>>,
+    ) -> Self {
         Self {
             entity_name: entity_name.to_owned(),
-            date_log,
+            until,
+            valid,
             keys,
         }
     }
@@ -134,68 +90,40 @@ impl Handler<ReadEntitiesAt> for Executor {
     type Result = Result<HashMap<String, HashMap<String, Types>>, Error>;
 
     fn handle(&mut self, msg: ReadEntitiesAt, _: &mut Self::Context) -> Self::Result {
-        use ron::de::from_str;
-        let date_log = msg.date_log.clone();
-        let date_log = read_date_log(date_log)?;
+        self.check_committed(msg.until)?;
         let mut hm = HashMap::new();
-        date_log.split(';').try_for_each(|line| {
-            let fractions = line.split('|').collect::<Vec<&str>>();
-            if fractions[0].eq("INSERT") && fractions[3].eq(&msg.entity_name) {
-                let state = fractions
-                    .last()
-                    .ok_or(Error::FailedToParseState)?
-                    .to_owned();
-
-                let resp: Result<HashMap<String, Types>, Error> = match from_str(state) {
-                    Ok(x) => Ok(x),
-                    Err(_) => Err(Error::FailedToParseState),
-                };
-                match resp {
-                    Ok(map) => {
-                        let map = filter_keys_and_hash(map, msg.keys.clone());
-                        hm.insert(fractions[2].to_owned(), map);
-                    }
-                    Err(e) => return Err(e),
-                };
-            } else if (fractions[0].eq("UPDATE_SET") || fractions[0].eq("UPDATE_CONTENT"))
-                && fractions[3].eq(&msg.entity_name)
+        for uuid in self.time_index.ids(&msg.entity_name) {
+            if let Some(register) =
+                self.time_index
+                    .at(&msg.entity_name, &uuid, msg.until, msg.valid)
             {
-                let state = fractions
-                    .get(fractions.len() - 2)
-                    .ok_or(Error::FailedToParseState)?
-                    .to_owned();
-
-                let resp: Result<HashMap<String, Types>, Error> = match from_str(state) {
-                    Ok(x) => Ok(x),
-                    Err(_) => Err(Error::FailedToParseState),
-                };
-                match resp {
-                    Ok(map) => {
-                        let map = filter_keys_and_hash(map, msg.keys.clone());
-                        hm.insert(fractions[2].to_owned(), map);
-                    }
-                    Err(e) => return Err(e),
-                };
+                let state = read_state(&*self.storage, register.clone())?;
+                hm.insert(
+                    uuid.to_string(),
+                    filter_keys_and_hash(state, msg.keys.clone()),
+                );
             }
-            Ok(())
-        })?;
+        }
 
         Ok(hm)
     }
 }
 
+/// Latest state of the id up to `until`, among the ones valid at `valid` when set.
 pub struct ReadEntityIdAt {
     entity_name: String,
     uuid: Uuid,
-    date_log: String,
+    until: Until,
+    valid: Option<DateTime<Utc>>,
 }
 
 impl ReadEntityIdAt {
-    pub fn new(entity_name: &str, uuid: Uuid, date_log: String) -> Self {
+    pub fn new(entity_name: &str, uuid: Uuid, until: Until, valid: Option<DateTime<Utc>>) -> Self {
         Self {
             entity_name: entity_name.to_owned(),
             uuid,
-            date_log,
+            until,
+            valid,
         }
     }
 }
@@ -208,55 +136,35 @@ impl Handler<ReadEntityIdAt> for Executor {
     type Result = Result<HashMap<String, Types>, Error>;
 
     fn handle(&mut self, msg: ReadEntityIdAt, _: &mut Self::Context) -> Self::Result {
-        use ron::de::from_str;
-        let date_log = msg.date_log.clone();
-        let date_log = read_date_log(date_log)?;
-        let mut hm = HashMap::new();
-        date_log.split(';').try_for_each(|line| {
-            let fractions = line.split('|').collect::<Vec<&str>>();
-
-            if fractions[0].eq("INSERT")
-                && fractions[3].eq(&msg.entity_name)
-                && fractions[2].eq(&msg.uuid.to_string())
-            {
-                let state = fractions
-                    .last()
-                    .ok_or(Error::FailedToParseState)?
-                    .to_owned();
+        self.check_committed(msg.until)?;
+        match self
+            .time_index
+            .at(&msg.entity_name, &msg.uuid, msg.until, msg.valid)
+        {
+            Some(register) => read_state(&*self.storage, register.clone()),
+            None => Ok(HashMap::new()),
+        }
+    }
+}
 
-                let resp: Result<HashMap<String, Types>, Error> = match from_str(state) {
-                    Ok(x) => Ok(x),
-                    Err(_) => Err(Error::FailedToParseState),
-                };
-                match resp {
-                    Ok(map) => {
-                        hm = map;
-                    }
-                    Err(e) => return Err(e),
-                };
-            } else if (fractions[0].eq("UPDATE_SET") || fractions[0].eq("UPDATE_CONTENT"))
-                && fractions[3].eq(&msg.entity_name)
-                && fractions[2].eq(&msg.uuid.to_string())
-            {
-                let state = fractions
-                    .get(fractions.len() - 2)
-                    .ok_or(Error::FailedToParseState)?
-                    .to_owned();
+impl Executor {
+    /// Fails with `TxNotCommitted` when `until` is a transaction that wasn't committed.
+    fn check_committed(&self, until: Until) -> Result<(), Error> {
+        match until {
+            Until::Tx(tx_seq) if !self.is_committed(tx_seq)? => Err(Error::TxNotCommitted(tx_seq)),
+            _ => Ok(()),
+        }
+    }
+}
 
-                let resp: Result<HashMap<String, Types>, Error> = match from_str(state) {
-                    Ok(x) => Ok(x),
-                    Err(_) => Err(Error::FailedToParseState),
-                };
-                match resp {
-                    Ok(map) => {
-                        hm = map;
-                    }
-                    Err(e) => return Err(e),
-                };
-            }
-            Ok(())
-        })?;
+/// State written by the record at `register`.
+fn read_state(
+    storage: &dyn Storage,
+    register: DataRegister,
+) -> Result<HashMap<String, Types>, Error> {
+    let state = read_log(storage, register)?
+        .state
+        .ok_or(Error::FailedToParseState)?;
 
-        Ok(hm)
-    }
+    ron::de::from_str(&state).map_err(|_| Error::FailedToParseState)
 }
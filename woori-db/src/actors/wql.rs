@@ -1,48 +1,412 @@
+use crate::{
+    config::{config, Durability},
+    io::{
+        durability::Committer,
+        read,
+        record::{decode, encode},
+        storage::Storage,
+        write::{index_data, local_checkpoint, local_delta, time_index, unique_data, write_to_log},
+    },
+    model::{error::Error, log::LogRecord, wql::Action, DataRegister},
+    repository::{
+        local::LocalContext,
+        time_index::{TimeEntry, TimeIndex},
+    },
+};
 use actix::prelude::*;
 use chrono::{DateTime, Utc};
-use std::io::Error;
+use futures::future;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::broadcast;
 use uuid::Uuid;
 
+use super::{
+    changes::{Committed, CHANGES_CAPACITY},
+    commits::Commits,
+};
 use crate::core::wql::{
-    create_entity, delete_entity_content, evict_entity_content, evict_entity_id_content,
-    insert_entity_content, update_content_entity_content, update_set_entity_content,
+    create_entity, create_index, delete_entity_content, evict_entity_content,
+    evict_entity_id_content, insert_entity_content, transaction_marker,
+    update_content_entity_content, update_set_entity_content,
 };
 
-pub struct Executor;
+pub struct Executor {
+    pub storage: Arc<dyn Storage>,
+    committer: Committer,
+    pub(crate) time_index: TimeIndex,
+    pub(crate) batch: Option<Batch>,
+    /// Committed transactions, for `/changes` subscriptions.
+    pub(crate) changes: broadcast::Sender<Committed>,
+    pub(crate) commits: Arc<Mutex<Commits>>,
+}
+
+/// Writes of an open `BEGIN ... COMMIT` block, held back until it commits.
+#[derive(Default)]
+pub(crate) struct Batch {
+    log: String,
+    tx_seq: u64,
+    /// Length of `log` when the block began, where its records will be appended.
+    offset: usize,
+    records: Vec<u8>,
+    pub(crate) deltas: Vec<u8>,
+    pub(crate) unique_data: Option<String>,
+    pub(crate) time_entries: Vec<TimeEntry>,
+    changes: Vec<LogRecord>,
+}
 
 impl Actor for Executor {
     type Context = Context<Self>;
 }
 
 impl Executor {
-    pub fn new() -> Self {
-        Executor {}
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        let config = config();
+        Self::with_durability(
+            storage,
+            config.durability,
+            Duration::from_millis(config.group_commit_window_ms),
+        )
+    }
+
+    pub fn with_durability(
+        storage: Arc<dyn Storage>,
+        durability: Durability,
+        window: Duration,
+    ) -> Self {
+        let tx_seq = read::last_tx_seq(&*storage).unwrap_or_else(|e| {
+            log::error!("Failed to read the last transaction sequence number: {}", e);
+            0
+        }) + 1;
+        let time_index = load_time_index(&*storage).unwrap_or_else(|e| {
+            log::error!("Failed to load the time index: {}", e);
+            TimeIndex::default()
+        });
+        Executor {
+            committer: Committer::new(storage.clone(), durability, window),
+            storage,
+            time_index,
+            batch: None,
+            changes: broadcast::channel(CHANGES_CAPACITY).0,
+            commits: Arc::new(Mutex::new(Commits::new(tx_seq))),
+        }
+    }
+
+    /// Whether transaction `tx_seq` was committed. Transactions whose commit is pending
+    /// don't count yet.
+    pub(crate) fn is_committed(&self, tx_seq: u64) -> Result<bool, Error> {
+        let commits = self.commits.lock().map_err(|_| Error::LockData)?;
+        Ok(tx_seq > 0 && tx_seq < commits.committed())
+    }
+
+    /// Sequence number every transaction below which committed.
+    pub(crate) fn committed(&self) -> Result<u64, Error> {
+        let commits = self.commits.lock().map_err(|_| Error::LockData)?;
+        Ok(commits.committed())
+    }
+
+    /// Sequence number the next transaction commits with.
+    pub(crate) fn next_tx_seq(&self) -> Result<u64, Error> {
+        let commits = self.commits.lock().map_err(|_| Error::LockData)?;
+        Ok(commits.next_tx_seq())
+    }
+
+    /// Counts transaction `tx_seq`, replicated from the leader, as committed.
+    pub(crate) fn replicated(&mut self, tx_seq: u64) -> Result<(), Error> {
+        let mut commits = self.commits.lock().map_err(|_| Error::LockData)?;
+        commits.replicated(tx_seq);
+        Ok(())
+    }
+
+    /// Appends `record` to today's log as the next transaction, resolving once it is as
+    /// durable as configured with the bytes written, the offset they were written at and
+    /// the transaction sequence number. When the commit fails the record is truncated
+    /// from the log, so it is never replayed. Inside a `BEGIN ... COMMIT` block the record
+    /// is buffered until `CommitBatch` and takes the sequence number of the block.
+    fn write(
+        &mut self,
+        mut record: LogRecord,
+    ) -> ResponseFuture<Result<(usize, usize, u64), Error>> {
+        if let Some(batch) = &mut self.batch {
+            return Box::pin(future::ready(batch.push(record)));
+        }
+        let appended = self
+            .commits
+            .lock()
+            .map_err(|_| Error::LockData)
+            .and_then(|mut commits| {
+                let tx_seq = commits.next_tx_seq();
+                record.tx_seq = Some(tx_seq);
+                let (log, bytes_written, offset) = write_to_log(&*self.storage, &record)?;
+                let (id, outcome) = commits.append(tx_seq, &log, offset, vec![record]);
+                Ok((log, bytes_written, offset, tx_seq, id, outcome))
+            });
+        let (log, bytes_written, offset, tx_seq, id, outcome) = match appended {
+            Ok(appended) => appended,
+            Err(e) => return Box::pin(future::ready(Err(e))),
+        };
+        let commit = self.committer.commit(&log);
+        let resolve = self.resolver(id);
+        Box::pin(async move {
+            resolve(commit.await)?;
+            outcome.await.unwrap_or(Err(Error::LockData))?;
+            Ok((bytes_written, offset, tx_seq))
+        })
+    }
+
+    /// Resolves the commit of append `id` with its outcome.
+    fn resolver(&self, id: u64) -> impl FnOnce(Result<(), Error>) -> Result<(), Error> {
+        let (storage, commits) = (self.storage.clone(), self.commits.clone());
+        let changes = self.changes.clone();
+        move |outcome| {
+            let mut commits = commits.lock().map_err(|_| Error::LockData)?;
+            commits.resolve(&*storage, &changes, id, outcome);
+            Ok(())
+        }
+    }
+}
+
+/// Loads the time index journal, rebuilding it from the day logs when it is empty.
+fn load_time_index(storage: &dyn Storage) -> Result<TimeIndex, Error> {
+    let mut entries = read::time_index(storage)?;
+    if entries.is_empty() {
+        entries = read::time_entries(storage)?;
+        let journal = entries
+            .iter()
+            .map(encode)
+            .collect::<Result<Vec<_>, _>>()?
+            .concat();
+        time_index(storage, &journal)?;
+    }
+
+    Ok(TimeIndex::new(entries))
+}
+
+impl Batch {
+    fn push(&mut self, mut record: LogRecord) -> Result<(usize, usize, u64), Error> {
+        let date = record.tx_time.unwrap_or_else(Utc::now);
+        if date.format("%Y_%m_%d.log").to_string() != self.log {
+            return Err(Error::TransactionSpansDays);
+        }
+        record.tx_seq = Some(self.tx_seq);
+        let bytes = encode(&record)?;
+        let offset = self.offset + self.records.len();
+        self.records.extend_from_slice(&bytes);
+        if !matches!(record.action, Action::Begin | Action::Commit) {
+            self.changes.push(record);
+        }
+        Ok((bytes.len(), offset, self.tx_seq))
+    }
+
+    /// Reads the record at `register` when it was written by this block, which is still
+    /// only buffered.
+    pub(crate) fn read(&self, register: &DataRegister) -> Option<Result<LogRecord, Error>> {
+        if register.file_name != self.log || register.offset < self.offset {
+            return None;
+        }
+        let start = register.offset - self.offset;
+        let bytes = self
+            .records
+            .get(start..start + register.bytes_length)
+            .ok_or(Error::CorruptedLogRecord);
+        Some(bytes.and_then(|bytes| Ok(decode(bytes)?.0)))
+    }
+}
+
+/// Opens a `BEGIN ... COMMIT` block. Returns the bytes its `BEGIN` record will take in
+/// today's log, the offset it will take them at and the sequence number the block
+/// commits with, like the other writes.
+pub struct BeginBatch;
+
+impl Message for BeginBatch {
+    type Result = Result<(usize, usize, u64), Error>;
+}
+
+impl Handler<BeginBatch> for Executor {
+    type Result = Result<(usize, usize, u64), Error>;
+
+    fn handle(&mut self, _: BeginBatch, _: &mut Self::Context) -> Self::Result {
+        let log = Utc::now().format("%Y_%m_%d.log").to_string();
+        let mut batch = Batch {
+            offset: self.storage.log_len(&log)?,
+            log,
+            tx_seq: self.next_tx_seq()?,
+            ..Batch::default()
+        };
+        let begin = batch.push(transaction_marker(Action::Begin))?;
+        self.batch = Some(batch);
+
+        Ok(begin)
+    }
+}
+
+/// Appends the records of the open block between `BEGIN` and `COMMIT` in a single write,
+/// then, once it commits, journals its time index entries and its deltas to `data` and
+/// writes its unique values. Returns the bytes of the `COMMIT` record.
+pub struct CommitBatch {
+    pub data: Arc<Mutex<LocalContext>>,
+}
+
+impl Message for CommitBatch {
+    type Result = Result<usize, Error>;
+}
+
+impl Handler<CommitBatch> for Executor {
+    type Result = ResponseActFuture<Self, Result<usize, Error>>;
+
+    fn handle(&mut self, msg: CommitBatch, _: &mut Self::Context) -> Self::Result {
+        let mut batch = match self.batch.take() {
+            Some(batch) => batch,
+            None => return Box::pin(fut::ready(Ok(0))),
+        };
+        let appended =
+            batch
+                .push(transaction_marker(Action::Commit))
+                .and_then(|(bytes_written, _, _)| {
+                    let mut commits = self.commits.lock().map_err(|_| Error::LockData)?;
+                    if commits.next_tx_seq() != batch.tx_seq {
+                        return Err(Error::TransactionLogMoved);
+                    }
+                    let offset = self.storage.append_log(&batch.log, &batch.records)?;
+                    if offset != batch.offset {
+                        // The registers of the block point at `batch.offset`.
+                        self.storage.truncate_log(&batch.log, offset)?;
+                        return Err(Error::TransactionLogMoved);
+                    }
+                    let records = std::mem::take(&mut batch.changes);
+                    let (id, outcome) = commits.append(batch.tx_seq, &batch.log, offset, records);
+                    Ok((bytes_written, id, outcome))
+                });
+        let (bytes_written, id, outcome) = match appended {
+            Ok(appended) => appended,
+            Err(e) => return Box::pin(fut::ready(Err(e))),
+        };
+
+        let commit = self.committer.commit(&batch.log);
+        let resolve = self.resolver(id);
+        let committed = async move {
+            resolve(commit.await)?;
+            outcome.await.unwrap_or(Err(Error::LockData))
+        };
+        Box::pin(committed.into_actor(self).map(move |committed, act, _| {
+            committed?;
+            let entries = batch
+                .time_entries
+                .iter()
+                .map(encode)
+                .collect::<Result<Vec<_>, _>>()?
+                .concat();
+            if !entries.is_empty() {
+                time_index(&*act.storage, &entries)?;
+            }
+            batch
+                .time_entries
+                .into_iter()
+                .for_each(|entry| act.time_index.insert(entry));
+            if let Some(unique_ron) = batch.unique_data {
+                unique_data(&*act.storage, &unique_ron)?;
+            }
+            if !batch.deltas.is_empty()
+                && local_delta(&*act.storage, &batch.deltas)? >= config().checkpoint_size
+            {
+                let checkpoint = if let Ok(guard) = msg.data.lock() {
+                    encode(&*guard)?
+                } else {
+                    return Err(Error::LockData);
+                };
+                local_checkpoint(&*act.storage, &checkpoint)?;
+            }
+            Ok(bytes_written)
+        }))
+    }
+}
+
+/// Drops the writes of the open block, leaving no trace of it in the logs.
+pub struct AbortBatch;
+
+impl Message for AbortBatch {
+    type Result = ();
+}
+
+impl Handler<AbortBatch> for Executor {
+    type Result = ();
+
+    fn handle(&mut self, _: AbortBatch, _: &mut Self::Context) -> Self::Result {
+        self.batch = None;
     }
 }
 
 pub struct CreateEntity {
     name: String,
+    uniques: Vec<String>,
+    encrypts: Vec<String>,
 }
 
 impl CreateEntity {
-    pub fn new(name: &str) -> Self {
+    pub fn new(name: &str, uniques: Vec<String>, encrypts: Vec<String>) -> Self {
         Self {
             name: name.to_owned(),
+            uniques,
+            encrypts,
         }
     }
 }
 
 impl Message for CreateEntity {
-    type Result = Result<(usize, bool), Error>;
+    type Result = Result<(usize, usize, u64), Error>;
 }
 
 impl Handler<CreateEntity> for Executor {
-    type Result = Result<(usize, bool), Error>;
+    type Result = ResponseFuture<Result<(usize, usize, u64), Error>>;
 
     fn handle(&mut self, msg: CreateEntity, _: &mut Self::Context) -> Self::Result {
-        use crate::io::write::write_to_log;
-        let entity = create_entity(&msg.name);
-        write_to_log(&entity)
+        let entity = create_entity(&msg.name, msg.uniques, msg.encrypts);
+        self.write(entity)
+    }
+}
+
+/// Logs the creation of indexes on `keys` of an entity, then persists `definitions`,
+/// every index definition as `ron`.
+pub struct CreateIndex {
+    pub name: String,
+    pub keys: Vec<String>,
+    pub definitions: String,
+}
+
+impl Message for CreateIndex {
+    type Result = Result<(usize, usize, u64), Error>;
+}
+
+impl Handler<CreateIndex> for Executor {
+    type Result = ResponseFuture<Result<(usize, usize, u64), Error>>;
+
+    fn handle(&mut self, msg: CreateIndex, _: &mut Self::Context) -> Self::Result {
+        let storage = self.storage.clone();
+        let write = self.write(create_index(&msg.name, &msg.keys));
+        Box::pin(async move {
+            let written = write.await?;
+            index_data(&*storage, &msg.definitions)?;
+            Ok(written)
+        })
+    }
+}
+
+/// Persists `definitions`, every index definition as `ron`.
+pub struct IndexData {
+    pub definitions: String,
+}
+
+impl Message for IndexData {
+    type Result = Result<(), Error>;
+}
+
+impl Handler<IndexData> for Executor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: IndexData, _: &mut Self::Context) -> Self::Result {
+        index_data(&*self.storage, &msg.definitions)
     }
 }
 
@@ -65,17 +429,19 @@ impl InsertEntityContent {
 }
 
 impl Message for InsertEntityContent {
-    type Result = Result<(DateTime<Utc>, Uuid, usize, bool), Error>;
+    type Result = Result<(DateTime<Utc>, Uuid, usize, usize, u64), Error>;
 }
 
 impl Handler<InsertEntityContent> for Executor {
-    type Result = Result<(DateTime<Utc>, Uuid, usize, bool), Error>;
+    type Result = ResponseFuture<Result<(DateTime<Utc>, Uuid, usize, usize, u64), Error>>;
 
     fn handle(&mut self, msg: InsertEntityContent, _: &mut Self::Context) -> Self::Result {
-        use crate::io::write::write_to_log;
         let (date, uuid, content) = insert_entity_content(&msg);
-        let (bytes_written, is_empty) = write_to_log(&content)?;
-        Ok((date, uuid, bytes_written, is_empty))
+        let write = self.write(content);
+        Box::pin(async move {
+            let (bytes_written, offset, tx_seq) = write.await?;
+            Ok((date, uuid, bytes_written, offset, tx_seq))
+        })
     }
 }
 
@@ -109,17 +475,19 @@ impl UpdateSetEntityContent {
 }
 
 impl Message for UpdateSetEntityContent {
-    type Result = Result<(DateTime<Utc>, usize, bool), Error>;
+    type Result = Result<(DateTime<Utc>, usize, usize, u64), Error>;
 }
 
 impl Handler<UpdateSetEntityContent> for Executor {
-    type Result = Result<(DateTime<Utc>, usize, bool), Error>;
+    type Result = ResponseFuture<Result<(DateTime<Utc>, usize, usize, u64), Error>>;
 
     fn handle(&mut self, msg: UpdateSetEntityContent, _: &mut Self::Context) -> Self::Result {
-        use crate::io::write::write_to_log;
         let (date, content) = update_set_entity_content(&msg);
-        let (bytes_written, is_empty) = write_to_log(&content)?;
-        Ok((date, bytes_written, is_empty))
+        let write = self.write(content);
+        Box::pin(async move {
+            let (bytes_written, offset, tx_seq) = write.await?;
+            Ok((date, bytes_written, offset, tx_seq))
+        })
     }
 }
 
@@ -151,17 +519,19 @@ impl UpdateContentEntityContent {
 }
 
 impl Message for UpdateContentEntityContent {
-    type Result = Result<(DateTime<Utc>, usize, bool), Error>;
+    type Result = Result<(DateTime<Utc>, usize, usize, u64), Error>;
 }
 
 impl Handler<UpdateContentEntityContent> for Executor {
-    type Result = Result<(DateTime<Utc>, usize, bool), Error>;
+    type Result = ResponseFuture<Result<(DateTime<Utc>, usize, usize, u64), Error>>;
 
     fn handle(&mut self, msg: UpdateContentEntityContent, _: &mut Self::Context) -> Self::Result {
-        use crate::io::write::write_to_log;
         let (date, content) = update_content_entity_content(&msg);
-        let (bytes_written, is_empty) = write_to_log(&content)?;
-        Ok((date, bytes_written, is_empty))
+        let write = self.write(content);
+        Box::pin(async move {
+            let (bytes_written, offset, tx_seq) = write.await?;
+            Ok((date, bytes_written, offset, tx_seq))
+        })
     }
 }
 
@@ -184,17 +554,19 @@ impl DeleteId {
 }
 
 impl Message for DeleteId {
-    type Result = Result<(DateTime<Utc>, usize, bool), Error>;
+    type Result = Result<(DateTime<Utc>, usize, usize, u64), Error>;
 }
 
 impl Handler<DeleteId> for Executor {
-    type Result = Result<(DateTime<Utc>, usize, bool), Error>;
+    type Result = ResponseFuture<Result<(DateTime<Utc>, usize, usize, u64), Error>>;
 
     fn handle(&mut self, msg: DeleteId, _: &mut Self::Context) -> Self::Result {
-        use crate::io::write::write_to_log;
         let (date, content) = delete_entity_content(&msg);
-        let (bytes_written, is_empty) = write_to_log(&content)?;
-        Ok((date, bytes_written, is_empty))
+        let write = self.write(content);
+        Box::pin(async move {
+            let (bytes_written, offset, tx_seq) = write.await?;
+            Ok((date, bytes_written, offset, tx_seq))
+        })
     }
 }
 
@@ -211,16 +583,19 @@ impl EvictEntity {
 }
 
 impl Message for EvictEntity {
-    type Result = Result<(usize, bool), Error>;
+    type Result = Result<(DateTime<Utc>, usize, usize, u64), Error>;
 }
 
 impl Handler<EvictEntity> for Executor {
-    type Result = Result<(usize, bool), Error>;
+    type Result = ResponseFuture<Result<(DateTime<Utc>, usize, usize, u64), Error>>;
 
     fn handle(&mut self, msg: EvictEntity, _: &mut Self::Context) -> Self::Result {
-        use crate::io::write::write_to_log;
-        let content = evict_entity_content(&msg.name);
-        Ok(write_to_log(&content)?)
+        let (date, content) = evict_entity_content(&msg.name);
+        let write = self.write(content);
+        Box::pin(async move {
+            let (bytes_written, offset, tx_seq) = write.await?;
+            Ok((date, bytes_written, offset, tx_seq))
+        })
     }
 }
 
@@ -239,16 +614,19 @@ impl EvictEntityId {
 }
 
 impl Message for EvictEntityId {
-    type Result = Result<(usize, bool), Error>;
+    type Result = Result<(DateTime<Utc>, usize, usize, u64), Error>;
 }
 
 impl Handler<EvictEntityId> for Executor {
-    type Result = Result<(usize, bool), Error>;
+    type Result = ResponseFuture<Result<(DateTime<Utc>, usize, usize, u64), Error>>;
 
     fn handle(&mut self, msg: EvictEntityId, _: &mut Self::Context) -> Self::Result {
-        use crate::io::write::write_to_log;
-        let content = evict_entity_id_content(&msg);
-        Ok(write_to_log(&content)?)
+        let (date, content) = evict_entity_id_content(&msg);
+        let write = self.write(content);
+        Box::pin(async move {
+            let (bytes_written, offset, tx_seq) = write.await?;
+            Ok((date, bytes_written, offset, tx_seq))
+        })
     }
 }
 
@@ -256,24 +634,37 @@ impl Handler<EvictEntityId> for Executor {
 mod test {
     use actix::Actor;
     use chrono::Utc;
+    use std::{
+        sync::{atomic::Ordering, Arc},
+        time::Duration,
+    };
 
-    use crate::io::read;
+    use crate::{
+        actors::changes::SubscribeChanges,
+        config::Durability,
+        io::{
+            durability::test::CountingStorage,
+            read,
+            record::{encode, Records},
+            storage::{default_storage, MemoryStorage, Storage},
+        },
+        model::{error::Error, log::LogRecord, wql::Action},
+    };
 
     use super::{
-        CreateEntity, DeleteId, EvictEntity, EvictEntityId, Executor, InsertEntityContent,
-        UpdateSetEntityContent,
+        BeginBatch, CommitBatch, CreateEntity, DeleteId, EvictEntity, EvictEntityId, Executor,
+        InsertEntityContent, UpdateSetEntityContent,
     };
 
     #[actix_rt::test]
     async fn create_test() {
-        let create = CreateEntity {
-            name: String::from("create-my-entity"),
-        };
-        let actor = Executor::new().start();
+        let create = CreateEntity::new("create-my-entity", Vec::new(), Vec::new());
+        let actor = Executor::new(default_storage()).start();
 
         let resp = actor.send(create).await.unwrap();
         assert!(resp.is_ok());
-        read::assert_content("CREATE_ENTITY|create-my-entity;")
+        read::assert_content("CREATE_ENTITY|");
+        read::assert_content("|create-my-entity;");
     }
 
     #[actix_rt::test]
@@ -284,7 +675,7 @@ mod test {
             uuid: None,
             datetime: Utc::now(),
         };
-        let actor = Executor::new().start();
+        let actor = Executor::new(default_storage()).start();
 
         let resp = actor.send(insert).await.unwrap();
         assert!(resp.is_ok());
@@ -293,6 +684,16 @@ mod test {
         read::assert_content("this is the content");
     }
 
+    #[actix_rt::test]
+    async fn tx_seq_test() {
+        let actor = Executor::new(default_storage()).start();
+        let insert = || InsertEntityContent::new("tx-seq-my-entity", "{}", None, Utc::now());
+
+        let first = actor.send(insert()).await.unwrap().unwrap();
+        let second = actor.send(insert()).await.unwrap().unwrap();
+        assert_eq!(second.4, first.4 + 1);
+    }
+
     #[actix_rt::test]
     async fn update_set_test() {
         let uuid = uuid::Uuid::new_v4();
@@ -304,7 +705,7 @@ mod test {
             Utc::now(),
             "this is the previous registry",
         );
-        let actor = Executor::new().start();
+        let actor = Executor::new(default_storage()).start();
 
         let resp = actor.send(update_set).await.unwrap();
         assert!(resp.is_ok());
@@ -327,7 +728,7 @@ mod test {
             Utc::now(),
             "this is the previous registry",
         );
-        let actor = Executor::new().start();
+        let actor = Executor::new(default_storage()).start();
 
         let resp = actor.send(update_content).await.unwrap();
         assert!(resp.is_ok());
@@ -345,7 +746,7 @@ mod test {
             uuid,
             "this is the previous registry",
         );
-        let actor = Executor::new().start();
+        let actor = Executor::new(default_storage()).start();
 
         let resp = actor.send(update_content).await.unwrap();
         assert!(resp.is_ok());
@@ -357,7 +758,7 @@ mod test {
     #[actix_rt::test]
     async fn evict_test() {
         let evict = EvictEntity::new("evict-my-entity");
-        let actor = Executor::new().start();
+        let actor = Executor::new(default_storage()).start();
 
         let resp = actor.send(evict).await.unwrap();
         assert!(resp.is_ok());
@@ -369,7 +770,7 @@ mod test {
     async fn evict_id_test() {
         let uuid = uuid::Uuid::new_v4();
         let evict = EvictEntityId::new("evict-id-my-entity", uuid);
-        let actor = Executor::new().start();
+        let actor = Executor::new(default_storage()).start();
 
         let resp = actor.send(evict).await.unwrap();
         assert!(resp.is_ok());
@@ -377,4 +778,72 @@ mod test {
         read::assert_content("evict-id-my-entity");
         read::assert_content(&uuid.to_string());
     }
+
+    #[actix_rt::test]
+    async fn batch_fails_when_the_log_moved() {
+        let storage = Arc::new(MemoryStorage::new());
+        let actor = Executor::new(storage.clone()).start();
+        let log = Utc::now().format("%Y_%m_%d.log").to_string();
+
+        actor.send(BeginBatch).await.unwrap().unwrap();
+        let insert = InsertEntityContent::new("moved-my-entity", "{}", None, Utc::now());
+        actor.send(insert).await.unwrap().unwrap();
+        storage.append_log(&log, b"moved").unwrap();
+        let data = Arc::new(std::sync::Mutex::new(Default::default()));
+        let commit = actor.send(CommitBatch { data }).await.unwrap();
+
+        assert!(matches!(commit, Err(Error::TransactionLogMoved)));
+        assert_eq!(storage.read_log(&log).unwrap(), b"moved");
+    }
+
+    #[actix_rt::test]
+    async fn failed_commit_is_truncated() {
+        let storage = Arc::new(CountingStorage::default());
+        let actor =
+            Executor::with_durability(storage.clone(), Durability::Fsync, Duration::default())
+                .start();
+        let insert = || InsertEntityContent::new("rollback-my-entity", "{}", None, Utc::now());
+        let log = Utc::now().format("%Y_%m_%d.log").to_string();
+
+        let first = actor.send(insert()).await.unwrap().unwrap();
+        let committed = storage.log_len(&log).unwrap();
+        assert_eq!(committed, first.3 + first.2);
+
+        storage.fail_syncs.store(true, Ordering::SeqCst);
+        assert!(actor.send(insert()).await.unwrap().is_err());
+        assert_eq!(storage.log_len(&log).unwrap(), committed);
+
+        storage.fail_syncs.store(false, Ordering::SeqCst);
+        let third = actor.send(insert()).await.unwrap().unwrap();
+        assert_eq!(third.3, committed);
+        assert_eq!(third.4, first.4 + 1);
+        assert_eq!(read::last_tx_seq(&*storage).unwrap(), third.4);
+        let records = storage.read_log(&log).unwrap();
+        assert_eq!(Records::<LogRecord>::new(&records).count(), 2);
+    }
+
+    #[actix_rt::test]
+    async fn subscriptions_resume_up_to_the_committed_transactions() {
+        let storage = Arc::new(MemoryStorage::new());
+        let executor = Executor::new(storage.clone());
+        let log = Utc::now().format("%Y_%m_%d.log").to_string();
+        let record = LogRecord {
+            tx_seq: Some(1),
+            ..LogRecord::new(Action::Insert, "pending-my-entity")
+        };
+        storage.append_log(&log, &encode(&record).unwrap()).unwrap();
+        let _pending = executor
+            .commits
+            .lock()
+            .unwrap()
+            .append(1, &log, 0, vec![record]);
+        let actor = executor.start();
+
+        let (records, _) = actor
+            .send(SubscribeChanges { from: Some(1) })
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(records.is_empty());
+    }
 }
@@ -1,5 +1,6 @@
 use std::sync::{Arc, Mutex};
 
+use actix::Addr;
 use actix_web::{web, HttpResponse, Responder};
 use bcrypt::hash;
 use chrono::Utc;
@@ -7,10 +8,13 @@ use ron::de::from_str;
 use uuid::Uuid;
 
 use crate::{
+    actors::scheduler::{ArchiveReport, RunArchival, Scheduler},
+    backup::{snapshot, BackupReport},
+    config::config,
     core::pretty_config_output,
     model::{
         error::{error_to_http, Error},
-        DataI64,
+        DataI64, DataStorage, DataTxGate,
     },
     repository::local::{SessionContext, SessionInfo},
 };
@@ -18,11 +22,15 @@ use crate::{
 use super::{
     io,
     models::{AdminInfo, User},
-    schemas::{CreateUserWithAdmin, DeleteUsersWithAdmin, UserId},
+    schemas::{AdminCredentials, CreateUserWithAdmin, DeleteUsersWithAdmin, UserId},
 };
 
-pub async fn create_user(body: String, admin: web::Data<AdminInfo>) -> impl Responder {
-    match create_user_controller(body, admin).await {
+pub async fn create_user(
+    body: String,
+    admin: web::Data<AdminInfo>,
+    storage: DataStorage,
+) -> impl Responder {
+    match create_user_controller(body, admin, storage).await {
         Err(e) => error_to_http(&e),
         Ok(body) => {
             #[cfg(feature = "json")]
@@ -46,6 +54,7 @@ pub async fn create_user(body: String, admin: web::Data<AdminInfo>) -> impl Resp
 pub async fn create_user_controller(
     body: String,
     admin: web::Data<AdminInfo>,
+    storage: DataStorage,
 ) -> Result<UserId, Error> {
     #[cfg(feature = "json")]
     let credentials: Result<CreateUserWithAdmin, Error> = match serde_json::from_str(&body) {
@@ -63,7 +72,7 @@ pub async fn create_user_controller(
             let new_user_id = Uuid::new_v4();
             if let Ok(new_user_hash) = hash(&cred.user_info.user_password, admin.cost()) {
                 let user = User::new(new_user_id, new_user_hash, cred.user_info.role);
-                if io::to_users_log(&user).is_ok() {
+                if io::to_users_log(storage.as_ref().as_ref(), &user).is_ok() {
                     Ok(UserId {
                         user_id: new_user_id,
                     })
@@ -83,8 +92,12 @@ pub async fn create_user_controller(
     }
 }
 
-pub async fn delete_users(body: String, admin: web::Data<AdminInfo>) -> impl Responder {
-    match delete_users_controller(body, admin).await {
+pub async fn delete_users(
+    body: String,
+    admin: web::Data<AdminInfo>,
+    storage: DataStorage,
+) -> impl Responder {
+    match delete_users_controller(body, admin, storage).await {
         Err(e) => error_to_http(&e),
         Ok(body) => {
             #[cfg(feature = "json")]
@@ -108,6 +121,7 @@ pub async fn delete_users(body: String, admin: web::Data<AdminInfo>) -> impl Res
 pub async fn delete_users_controller(
     body: String,
     admin: web::Data<AdminInfo>,
+    storage: DataStorage,
 ) -> Result<Vec<Uuid>, Error> {
     #[cfg(feature = "json")]
     let credentials: Result<DeleteUsersWithAdmin, Error> = match serde_json::from_str(&body) {
@@ -122,7 +136,7 @@ pub async fn delete_users_controller(
 
     if let Ok(cred) = credentials {
         if admin.is_valid_hash(&cred.admin_password, &cred.admin_id) {
-            if io::remove_users_from_log(&cred.users_ids).is_ok() {
+            if io::remove_users_from_log(storage.as_ref().as_ref(), &cred.users_ids).is_ok() {
                 Ok(cred.users_ids)
             } else {
                 Err(Error::FailedToDeleteUsers)
@@ -136,12 +150,102 @@ pub async fn delete_users_controller(
         ))
     }
 }
+pub async fn archive(
+    body: String,
+    admin: web::Data<AdminInfo>,
+    scheduler: web::Data<Addr<Scheduler>>,
+) -> impl Responder {
+    match archive_controller(body, admin, scheduler).await {
+        Err(e) => error_to_http(&e),
+        Ok(report) => {
+            #[cfg(feature = "json")]
+            match serde_json::to_string(&report) {
+                Ok(body) => HttpResponse::Ok().body(body),
+                Err(e) => error_to_http(&Error::SerdeJson(e)),
+            }
+            #[cfg(not(feature = "json"))]
+            match ron::ser::to_string_pretty(&report, pretty_config_output()) {
+                Ok(body) => HttpResponse::Ok().body(body),
+                Err(e) => error_to_http(&Error::Ron(e)),
+            }
+        }
+    }
+}
+
+/// Runs the `Scheduler` archival job right away, reporting the logs it processed.
+pub async fn archive_controller(
+    body: String,
+    admin: web::Data<AdminInfo>,
+    scheduler: web::Data<Addr<Scheduler>>,
+) -> Result<ArchiveReport, Error> {
+    #[cfg(feature = "json")]
+    let credentials: AdminCredentials = serde_json::from_str(&body)
+        .map_err(|e| Error::AuthenticationBadRequestBody(e.to_string()))?;
+    #[cfg(not(feature = "json"))]
+    let credentials: AdminCredentials =
+        from_str(&body).map_err(|e| Error::AuthenticationBadRequestBody(e.to_string()))?;
+
+    if admin.is_valid_hash(&credentials.admin_password, &credentials.admin_id) {
+        scheduler.send(RunArchival).await?
+    } else {
+        Err(Error::AuthenticationBadRequest)
+    }
+}
+
+pub async fn backup(
+    body: String,
+    admin: web::Data<AdminInfo>,
+    tx_gate: DataTxGate,
+    storage: DataStorage,
+) -> impl Responder {
+    match backup_controller(body, admin, tx_gate, storage).await {
+        Err(e) => error_to_http(&e),
+        Ok(report) => {
+            #[cfg(feature = "json")]
+            match serde_json::to_string(&report) {
+                Ok(body) => HttpResponse::Ok().body(body),
+                Err(e) => error_to_http(&Error::SerdeJson(e)),
+            }
+            #[cfg(not(feature = "json"))]
+            match ron::ser::to_string_pretty(&report, pretty_config_output()) {
+                Ok(body) => HttpResponse::Ok().body(body),
+                Err(e) => error_to_http(&Error::Ron(e)),
+            }
+        }
+    }
+}
+
+/// Snapshots the data while no transaction runs, then writes it to the backup directory.
+pub async fn backup_controller(
+    body: String,
+    admin: web::Data<AdminInfo>,
+    tx_gate: DataTxGate,
+    storage: DataStorage,
+) -> Result<BackupReport, Error> {
+    #[cfg(feature = "json")]
+    let credentials: AdminCredentials = serde_json::from_str(&body)
+        .map_err(|e| Error::AuthenticationBadRequestBody(e.to_string()))?;
+    #[cfg(not(feature = "json"))]
+    let credentials: AdminCredentials =
+        from_str(&body).map_err(|e| Error::AuthenticationBadRequestBody(e.to_string()))?;
+
+    if !admin.is_valid_hash(&credentials.admin_password, &credentials.admin_id) {
+        return Err(Error::AuthenticationBadRequest);
+    }
+    let snapshot = {
+        let _gate = tx_gate.write().await;
+        snapshot(&***storage)?
+    };
+    snapshot.write(&config().backup_dir)
+}
+
 pub async fn put_user_session(
     body: String,
     session_context: web::Data<Arc<Mutex<SessionContext>>>,
     expiration_time: DataI64,
+    storage: DataStorage,
 ) -> impl Responder {
-    match put_user_session_controller(body, session_context, expiration_time).await {
+    match put_user_session_controller(body, session_context, expiration_time, storage).await {
         Err(e) => error_to_http(&e),
         Ok(token) => HttpResponse::Created().body(token),
     }
@@ -151,6 +255,7 @@ pub async fn put_user_session_controller(
     body: String,
     session_context: web::Data<Arc<Mutex<SessionContext>>>,
     expiration_time: DataI64,
+    storage: DataStorage,
 ) -> Result<String, Error> {
     let exp_time: i64 = *expiration_time.into_inner();
     #[cfg(feature = "json")]
@@ -165,7 +270,7 @@ pub async fn put_user_session_controller(
     };
 
     if let Ok(user) = ok_user {
-        let user_registry = io::find_user(user.clone()).await;
+        let user_registry = io::find_user(storage.as_ref().as_ref(), user.clone()).await;
         if let Ok(reg) = user_registry {
             let (hash, roles) = reg.context();
             match bcrypt::verify(&(user.user_password), &hash) {
@@ -199,6 +304,7 @@ mod test {
             io::{assert_users_content, assert_users_not_content},
             schemas::UserId,
         },
+        backup::{verify, BackupReport},
         http::routes,
     };
     use actix_http::body::ResponseBody;
@@ -275,6 +381,74 @@ mod test {
         assert_eq!(body, "(\n error_type: \"AuthenticationBadRequest\",\n error_message: \"Bad request at authenticating endpoint\",\n)");
     }
 
+    #[ignore]
+    #[actix_rt::test]
+    async fn archive_ok() {
+        let mut app = test::init_service(App::new().configure(routes)).await;
+        let req = test::TestRequest::post()
+            .set_payload("(admin_id: \"your_admin\",admin_password: \"your_password\",)")
+            .uri("/auth/archive")
+            .to_request();
+
+        let mut resp = test::call_service(&mut app, req).await;
+        let body = resp.take_body().as_str().to_string();
+        assert!(resp.status().is_success());
+        assert_eq!(body, "(\n archived: [],\n failed: {},\n)");
+    }
+
+    #[ignore]
+    #[actix_rt::test]
+    async fn archive_wrong_admin() {
+        let mut app = test::init_service(App::new().configure(routes)).await;
+        let req = test::TestRequest::post()
+            .set_payload("(admin_id: \"your_admin\",admin_password: \"nice_password\",)")
+            .uri("/auth/archive")
+            .to_request();
+
+        let resp = test::call_service(&mut app, req).await;
+        assert!(resp.status().is_client_error());
+    }
+
+    #[ignore]
+    #[actix_rt::test]
+    async fn backup_ok() {
+        let mut app = test::init_service(App::new().configure(routes)).await;
+        let req = test::TestRequest::post()
+            .set_payload("(admin_id: \"your_admin\",admin_password: \"your_password\",user_info: (user_password: \"my_password\",role: [User,],),)")
+            .uri("/auth/createUser")
+            .to_request();
+        test::call_service(&mut app, req).await;
+
+        let req = test::TestRequest::post()
+            .set_payload("(admin_id: \"your_admin\",admin_password: \"your_password\",)")
+            .uri("/auth/backup")
+            .to_request();
+
+        let mut resp = test::call_service(&mut app, req).await;
+        let body = resp.take_body().as_str().to_string();
+        assert!(resp.status().is_success());
+
+        let report: BackupReport = ron::de::from_str(&body).unwrap();
+        let path = report.path;
+        let manifest = verify(&path);
+        let _ = std::fs::remove_dir_all(&path);
+
+        assert!(manifest.unwrap().files.contains_key("users_info.log"));
+    }
+
+    #[ignore]
+    #[actix_rt::test]
+    async fn backup_wrong_admin() {
+        let mut app = test::init_service(App::new().configure(routes)).await;
+        let req = test::TestRequest::post()
+            .set_payload("(admin_id: \"your_admin\",admin_password: \"nice_password\",)")
+            .uri("/auth/backup")
+            .to_request();
+
+        let resp = test::call_service(&mut app, req).await;
+        assert!(resp.status().is_client_error());
+    }
+
     #[ignore]
     #[actix_rt::test]
     async fn get_token_test() {
@@ -1,9 +1,4 @@
-use std::{
-    fs::OpenOptions,
-    io::{BufRead, BufReader, Seek, SeekFrom, Write},
-};
-
-use crate::model::error::Error;
+use crate::{io::storage::Storage, model::error::Error};
 use bcrypt::{hash, DEFAULT_COST};
 use chrono::{DateTime, Utc};
 use ron::from_str;
@@ -12,6 +7,8 @@ use uuid::Uuid;
 use super::models::{AdminInfo, User, UserRegistry};
 use super::schemas;
 
+const USERS_INFO_LOG: &str = "users_info.log";
+
 pub fn read_admin_info() -> Result<AdminInfo, Error> {
     #[cfg(test)]
     let admin = std::env::var("ADMIN").unwrap_or("your_admin".to_string());
@@ -36,67 +33,37 @@ pub fn read_admin_info() -> Result<AdminInfo, Error> {
     Ok(AdminInfo::new(admin, pswd_hash, cost))
 }
 
-pub fn to_users_log(user: &User) -> Result<(), Error> {
+pub fn to_users_log(storage: &dyn Storage, user: &User) -> Result<(), Error> {
     let utc: DateTime<Utc> = Utc::now();
-    let users_info_log = "data/users_info.log";
-
-    let mut file = OpenOptions::new()
-        .append(true)
-        .create(true)
-        .open(users_info_log)?;
-
     let log = user.format_user_log(utc)?;
-    file.write_all(log.as_bytes())?;
+    storage.append_file(USERS_INFO_LOG, log.as_bytes())?;
 
     Ok(())
 }
 
-pub fn remove_users_from_log(users: &[Uuid]) -> Result<(), Error> {
-    let users_info_log = "data/users_info.log";
-
-    let file = OpenOptions::new().read(true).open(users_info_log)?;
-
-    let lines = BufReader::new(file)
+pub fn remove_users_from_log(storage: &dyn Storage, users: &[Uuid]) -> Result<(), Error> {
+    let content = users_info(storage)?;
+    let lines = content
         .lines()
-        .filter(|line| {
-            if line.is_ok() {
-                !users
-                    .iter()
-                    .any(|user| line.as_ref().unwrap().contains(&user.to_string()))
-            } else {
-                false
-            }
-        })
-        .filter_map(Result::ok)
-        .collect::<Vec<String>>()
+        .filter(|line| !users.iter().any(|user| line.contains(&user.to_string())))
+        .collect::<Vec<&str>>()
         .join("\r\n");
 
-    // Improve this, OpenOptions is not overwriting this file
-    std::fs::remove_file(users_info_log)?;
-    let mut file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .open(users_info_log)?;
-
-    file.seek(SeekFrom::Start(0))?;
-    file.write_all(lines.as_bytes())?;
+    storage.write_file(USERS_INFO_LOG, lines.as_bytes())?;
 
     Ok(())
 }
 
-pub async fn find_user(user: schemas::User) -> Result<UserRegistry, Error> {
-    let users_info_log = "data/users_info.log";
-
-    let file = OpenOptions::new().read(true).open(users_info_log)?;
-    let buffer = BufReader::new(file);
+pub async fn find_user(storage: &dyn Storage, user: schemas::User) -> Result<UserRegistry, Error> {
+    let content = users_info(storage)?;
     let uuid = user.id;
 
-    let user_content = buffer
+    let user_content = content
         .lines()
-        .find(|l| (l.as_ref().unwrap_or(&String::new())).contains(&uuid.to_string()))
-        .ok_or(Error::Unknown)??;
+        .find(|l| l.contains(&uuid.to_string()))
+        .ok_or(Error::Unknown)?;
 
-    let user: Result<UserRegistry, Error> = match from_str(&user_content) {
+    let user: Result<UserRegistry, Error> = match from_str(user_content) {
         Ok(u) => Ok(u),
         Err(_) => Err(Error::Unknown),
     };
@@ -104,32 +71,23 @@ pub async fn find_user(user: schemas::User) -> Result<UserRegistry, Error> {
     user
 }
 
+fn users_info(storage: &dyn Storage) -> Result<String, Error> {
+    let bytes = storage
+        .read_file(USERS_INFO_LOG)?
+        .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound))?;
+    String::from_utf8(bytes).map_err(|_| Error::Unknown)
+}
+
 #[cfg(test)]
 pub fn assert_users_content(pat: &str) {
-    use chrono::prelude::*;
-    use std::io::Read;
-
-    let utc: DateTime<Utc> = Utc::now();
-    let user_log = utc.format("data/users_info.log").to_string();
-
-    let mut file = OpenOptions::new().read(true).open(user_log).unwrap();
-    let mut s = String::new();
-    file.read_to_string(&mut s).unwrap();
+    let s = users_info(&*crate::io::storage::test_storage()).unwrap();
 
     assert!(s.contains(pat));
 }
 
 #[cfg(test)]
 pub fn assert_users_not_content(pat: &str) {
-    use chrono::prelude::*;
-    use std::io::Read;
-
-    let utc: DateTime<Utc> = Utc::now();
-    let user_log = utc.format("data/users_info.log").to_string();
-
-    let mut file = OpenOptions::new().read(true).open(user_log).unwrap();
-    let mut s = String::new();
-    file.read_to_string(&mut s).unwrap();
+    let s = users_info(&*crate::io::storage::test_storage()).unwrap();
 
     assert!(!s.contains(pat));
 }
@@ -70,6 +70,41 @@ pub async fn history_validator(
     }
 }
 
+pub async fn changes_validator(
+    req: ServiceRequest,
+    credentials: BearerAuth,
+) -> Result<ServiceRequest, Error> {
+    if req.path().starts_with("/changes") {
+        let allow = req
+            .app_data::<web::Data<Arc<Mutex<SessionContext>>>>()
+            .and_then(|db| {
+                validate_token(&db, Some(credentials.token()), vec![Role::Read, Role::User])
+            });
+
+        if let Some(true) = allow {
+            Ok(req)
+        } else {
+            Err(crate::model::error::Error::AuthorizationBadRequest.into())
+        }
+    } else {
+        Ok(req)
+    }
+}
+
+/// Followers authenticate with the `replication_token` of the configuration, which
+/// leaders without one never accept.
+pub async fn replication_validator(
+    req: ServiceRequest,
+    credentials: BearerAuth,
+) -> Result<ServiceRequest, Error> {
+    let token = crate::config::config().replication_token.as_deref();
+    if !req.path().starts_with("/replication") || token == Some(credentials.token()) {
+        Ok(req)
+    } else {
+        Err(crate::model::error::Error::AuthorizationBadRequest.into())
+    }
+}
+
 fn validate_token(
     db: &Arc<Mutex<BTreeMap<String, SessionInfo>>>,
     token: Option<&str>,
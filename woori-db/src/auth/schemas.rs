@@ -15,6 +15,12 @@ pub struct DeleteUsersWithAdmin {
     pub users_ids: Vec<Uuid>,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct AdminCredentials {
+    pub admin_id: String,
+    pub admin_password: String,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct UserInfo {
     pub user_password: String,
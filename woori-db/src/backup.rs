@@ -0,0 +1,274 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{core::pretty_config_output, io::storage::Storage, model::error::Error};
+
+/// When set to a backup directory, the server replaces its data directory with the
+/// backup at startup.
+pub const RESTORE_FROM: &str = "RESTORE_FROM";
+
+const MANIFEST: &str = "manifest.ron";
+
+/// State files backed up along with the day logs, when they exist.
+const STATE_FILES: [&str; 9] = [
+    "checkpoint.log",
+    "delta.log",
+    "local_data.log",
+    "unique_data.log",
+    "index_data.log",
+    "uniques.log",
+    "encrypt.log",
+    "offset_counter.log",
+    "users_info.log",
+];
+
+/// Content of `manifest.ron`, written after every other file of a backup.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub created_at: DateTime<Utc>,
+    pub files: BTreeMap<String, FileDigest>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct FileDigest {
+    pub bytes: u64,
+    pub crc32: u32,
+}
+
+impl FileDigest {
+    fn new(content: &[u8]) -> Self {
+        Self {
+            bytes: content.len() as u64,
+            crc32: crc32fast::hash(content),
+        }
+    }
+}
+
+/// Where a backup was written and what it holds.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupReport {
+    pub path: PathBuf,
+    pub manifest: Manifest,
+}
+
+/// Day logs, archives and state files read at one instant.
+pub struct Snapshot {
+    created_at: DateTime<Utc>,
+    files: BTreeMap<String, Vec<u8>>,
+}
+
+/// Reads every day log, archive and state file of `storage`. Callers must keep
+/// transactions out while it runs, so none is half written in the snapshot.
+pub fn snapshot(storage: &dyn Storage) -> Result<Snapshot, Error> {
+    let mut files = BTreeMap::new();
+    for (log, is_archived) in storage.list_logs()? {
+        let archive = if is_archived {
+            storage.read_archive(&log)?
+        } else {
+            None
+        };
+        match archive {
+            Some(archive) => files.insert(log.replace(".log", ".zst"), archive),
+            None => files.insert(log.clone(), storage.read_log(&log)?),
+        };
+    }
+    if let Some(checkpoint) = storage.read_checkpoint()? {
+        files.insert(STATE_FILES[0].to_owned(), checkpoint);
+    }
+    for name in STATE_FILES.iter().skip(1) {
+        if let Some(content) = storage.read_file(name)? {
+            files.insert((*name).to_owned(), content);
+        }
+    }
+
+    Ok(Snapshot {
+        created_at: Utc::now(),
+        files,
+    })
+}
+
+impl Snapshot {
+    /// Writes the snapshot to a new directory of `backup_dir`, named after its instant.
+    pub fn write(self, backup_dir: &Path) -> Result<BackupReport, Error> {
+        let path = backup_dir.join(self.created_at.format("%Y_%m_%dT%H_%M_%S%.3f").to_string());
+        fs::create_dir_all(&path)?;
+
+        let mut files = BTreeMap::new();
+        for (name, content) in &self.files {
+            write_synced(&path.join(name), content)?;
+            files.insert(name.to_owned(), FileDigest::new(content));
+        }
+        let manifest = Manifest {
+            created_at: self.created_at,
+            files,
+        };
+        let content = ron::ser::to_string_pretty(&manifest, pretty_config_output())?;
+        write_synced(&path.join(MANIFEST), content.as_bytes())?;
+
+        Ok(BackupReport { path, manifest })
+    }
+}
+
+/// Checks every file listed in the manifest of `backup` has the listed size and checksum.
+pub fn verify(backup: &Path) -> Result<Manifest, Error> {
+    let content = fs::read_to_string(backup.join(MANIFEST))
+        .map_err(|e| Error::InvalidBackup(format!("{}: {}", MANIFEST, e)))?;
+    let manifest: Manifest = ron::de::from_str(&content)
+        .map_err(|e| Error::InvalidBackup(format!("{}: {}", MANIFEST, e)))?;
+
+    for (name, digest) in &manifest.files {
+        if Path::new(name).file_name().and_then(|n| n.to_str()) != Some(name) {
+            return Err(Error::InvalidBackup(format!("{} is not a file name", name)));
+        }
+        let content = fs::read(backup.join(name))
+            .map_err(|e| Error::InvalidBackup(format!("{}: {}", name, e)))?;
+        if FileDigest::new(&content) != *digest {
+            return Err(Error::InvalidBackup(format!(
+                "{} doesn't match its size or checksum",
+                name
+            )));
+        }
+    }
+
+    Ok(manifest)
+}
+
+/// Replaces `data_dir` with the verified `backup`. The replaced data directory is kept
+/// next to it, suffixed with the restore instant.
+pub fn restore(backup: &Path, data_dir: &Path) -> Result<Manifest, Error> {
+    let manifest = verify(backup)?;
+
+    let staging = sibling(data_dir, "restoring")?;
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    fs::create_dir_all(&staging)?;
+    for name in manifest.files.keys() {
+        fs::copy(backup.join(name), staging.join(name))?;
+        File::open(staging.join(name))?.sync_all()?;
+    }
+
+    if data_dir.exists() {
+        let replaced = sibling(
+            data_dir,
+            &format!("replaced_{}", Utc::now().format("%Y_%m_%dT%H_%M_%S")),
+        )?;
+        fs::rename(data_dir, replaced)?;
+    }
+    fs::rename(staging, data_dir)?;
+
+    Ok(manifest)
+}
+
+fn sibling(dir: &Path, suffix: &str) -> Result<PathBuf, Error> {
+    let name = dir.file_name().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{} has no directory name", dir.display()),
+        )
+    })?;
+    Ok(dir.with_file_name(format!("{}.{}", name.to_string_lossy(), suffix)))
+}
+
+fn write_synced(path: &Path, content: &[u8]) -> Result<(), Error> {
+    let mut file = File::create(path)?;
+    file.write_all(content)?;
+    Ok(file.sync_all()?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::storage::FsStorage;
+
+    fn storage_with_data(dir: &Path) -> FsStorage {
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir).unwrap();
+        let storage = FsStorage::new(dir);
+        storage.append_log("2021_01_01.log", b"old").unwrap();
+        storage.archive_log("2021_01_01.log", 3).unwrap();
+        storage.append_log("2021_01_02.log", b"new").unwrap();
+        storage.write_checkpoint(b"checkpoint").unwrap();
+        storage.write_file("offset_counter.log", b"3").unwrap();
+        storage
+    }
+
+    #[test]
+    fn backup_and_restore() {
+        let dir = Path::new("data/backup_test");
+        let storage = storage_with_data(&dir.join("data"));
+
+        let report = snapshot(&storage)
+            .unwrap()
+            .write(&dir.join("backups"))
+            .unwrap();
+        assert_eq!(
+            report.manifest.files.keys().collect::<Vec<_>>(),
+            vec![
+                "2021_01_01.zst",
+                "2021_01_02.log",
+                "checkpoint.log",
+                "offset_counter.log"
+            ]
+        );
+
+        storage
+            .append_log("2021_01_02.log", b" after backup")
+            .unwrap();
+        restore(&report.path, &dir.join("data")).unwrap();
+        let restored = FsStorage::new(&dir.join("data"));
+        let logs = restored.list_logs().unwrap();
+        let log = restored.read_log("2021_01_01.log").unwrap();
+        let day_log = restored.read_log("2021_01_02.log").unwrap();
+        let checkpoint = restored.read_checkpoint().unwrap();
+        let replaced = fs::read_dir(dir)
+            .unwrap()
+            .filter(|entry| {
+                entry
+                    .as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with("data.replaced_")
+            })
+            .count();
+        let _ = fs::remove_dir_all(dir);
+
+        assert_eq!(
+            logs,
+            vec![
+                ("2021_01_01.log".to_string(), true),
+                ("2021_01_02.log".to_string(), false)
+            ]
+        );
+        assert_eq!(log, b"old");
+        assert_eq!(day_log, b"new");
+        assert_eq!(checkpoint, Some(b"checkpoint".to_vec()));
+        assert_eq!(replaced, 1);
+    }
+
+    #[test]
+    fn restore_rejects_corrupted_backup() {
+        let dir = Path::new("data/backup_corrupted_test");
+        let storage = storage_with_data(&dir.join("data"));
+        let report = snapshot(&storage)
+            .unwrap()
+            .write(&dir.join("backups"))
+            .unwrap();
+
+        fs::write(report.path.join("2021_01_02.log"), b"nex").unwrap();
+        let restored = restore(&report.path, &dir.join("data"));
+        let logs = storage.list_logs().unwrap();
+        let _ = fs::remove_dir_all(dir);
+
+        assert!(matches!(restored, Err(Error::InvalidBackup(_))));
+        assert_eq!(logs.len(), 2);
+    }
+}
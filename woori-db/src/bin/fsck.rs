@@ -0,0 +1,47 @@
+use std::process::exit;
+
+use woori_db::fsck;
+
+const USAGE: &str = "Usage: wooridb-fsck [--repair]
+
+Checks every day log, archive and register in the data directory, configured like the
+server with DATA_DIR or CONFIG_FILE.
+
+  --repair  removes dangling registers and entities missing from the entity set
+
+Exits with 0 when clean, 1 when every problem was repaired, 4 when problems remain and
+8 when the check could not run.";
+
+fn main() {
+    let mut repair = false;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--repair" => repair = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            other => {
+                eprintln!("Unknown argument {}\n\n{}", other, USAGE);
+                exit(8);
+            }
+        }
+    }
+
+    match fsck::run(repair) {
+        Ok(report) => {
+            print!("{}", report);
+            if report.is_clean() {
+                exit(0);
+            } else if report.repaired && report.bad_records.is_empty() {
+                exit(1);
+            } else {
+                exit(4);
+            }
+        }
+        Err(e) => {
+            eprintln!("Integrity check failed: {}", e);
+            exit(8);
+        }
+    }
+}
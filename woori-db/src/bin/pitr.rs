@@ -0,0 +1,50 @@
+use std::{path::PathBuf, process::exit};
+
+use chrono::{DateTime, Utc};
+use woori_db::pitr;
+
+const USAGE: &str = "Usage: wooridb-pitr --at <RFC 3339 instant> --to <directory>
+
+Replays the day logs of the data directory, configured like the server with DATA_DIR or
+CONFIG_FILE, up to the given instant into a new data directory. Start a server on it with
+DATA_DIR=<directory>, and READ_ONLY=true to keep it from diverging.
+
+  --at  last transaction time to restore, like 2026-09-01T12:00:00Z
+  --to  directory to restore into, which must not exist or be empty";
+
+fn main() {
+    let mut at: Option<DateTime<Utc>> = None;
+    let mut to: Option<PathBuf> = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--at", Some(value)) => match DateTime::parse_from_rfc3339(&value) {
+                Ok(instant) => at = Some(instant.with_timezone(&Utc)),
+                Err(e) => fail(&format!("Invalid instant {}: {}", value, e)),
+            },
+            ("--to", Some(value)) => to = Some(PathBuf::from(value)),
+            ("-h", _) | ("--help", _) => {
+                println!("{}", USAGE);
+                return;
+            }
+            (other, _) => fail(&format!("Unknown or incomplete argument {}", other)),
+        }
+    }
+
+    let (at, to) = match (at, to) {
+        (Some(at), Some(to)) => (at, to),
+        _ => fail("Both --at and --to are required"),
+    };
+    match pitr::run(at, &to) {
+        Ok(report) => print!("{}", report),
+        Err(e) => {
+            eprintln!("Point-in-time restore failed: {}", e);
+            exit(1);
+        }
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}\n\n{}", message, USAGE);
+    exit(2);
+}
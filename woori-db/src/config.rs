@@ -0,0 +1,236 @@
+use std::{path::PathBuf, str::FromStr, sync::OnceLock};
+
+use serde::Deserialize;
+
+/// Server settings, read from the `ron` file at `CONFIG_FILE` (`wooridb.ron` when present).
+/// Environment variables take precedence over the file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Directory holding every log, state and archive file. Overridden by `DATA_DIR`.
+    pub data_dir: PathBuf,
+    /// Where data is persisted. Overridden by `STORAGE`, either `fs` or `memory`.
+    pub storage: StorageBackend,
+    /// Guarantee a transaction holds once it is answered. Overridden by `DURABILITY`,
+    /// one of `none`, `fsync` or `group_commit`.
+    pub durability: Durability,
+    /// How long a group commit waits for other transactions before syncing them
+    /// together. Overridden by `GROUP_COMMIT_WINDOW_MS`.
+    pub group_commit_window_ms: u64,
+    /// Size in bytes the delta journal reaches before the local data is checkpointed.
+    /// Overridden by `CHECKPOINT_SIZE`.
+    pub checkpoint_size: u64,
+    /// When and how the `Scheduler` archives old day logs.
+    pub archive: ArchivePolicy,
+    /// Directory online backups are written to. Overridden by `BACKUP_DIR`.
+    pub backup_dir: PathBuf,
+    /// Rejects every transaction, for instances serving a restored past state.
+    /// Overridden by `READ_ONLY=true`.
+    pub read_only: bool,
+    /// Base url of the leader whose day logs this instance replicates, such as
+    /// `http://localhost:1438`. A follower is read-only. Overridden by `LEADER`.
+    pub leader: Option<String>,
+    /// How often a follower asks the leader for new records once it caught up.
+    /// Overridden by `REPLICATION_INTERVAL_MS`.
+    pub replication_interval_ms: u64,
+    /// Bearer token followers send to `/replication` and leaders expect from them in
+    /// release mode. Overridden by `REPLICATION_TOKEN`.
+    pub replication_token: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum StorageBackend {
+    /// Files inside `data_dir`.
+    Fs,
+    /// Process memory, lost when the server stops. Useful for tests and throwaway instances.
+    Memory,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum Durability {
+    /// Records are handed to the OS, which may lose them on power loss.
+    None,
+    /// Every transaction syncs the day log before it is answered.
+    Fsync,
+    /// Transactions arriving within the group commit window share one sync.
+    GroupCommit,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ArchivePolicy {
+    /// Cron expression, with seconds, of the archival job. Overridden by `ARCHIVE_CRON`.
+    pub cron: String,
+    /// Day logs older than this many days are archived. Overridden by `ARCHIVE_AFTER_DAYS`.
+    pub after_days: i64,
+    /// zstd level of the archives, from 1 to 22. Overridden by `ARCHIVE_COMPRESSION_LEVEL`.
+    pub compression_level: i32,
+    /// Directory archives are moved to, `data_dir` when unset. Overridden by `ARCHIVE_DIR`.
+    pub archive_dir: Option<PathBuf>,
+}
+
+impl Default for ArchivePolicy {
+    fn default() -> Self {
+        Self {
+            cron: "@daily".to_owned(),
+            after_days: 10,
+            compression_level: 22,
+            archive_dir: None,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            data_dir: PathBuf::from("data"),
+            storage: StorageBackend::Fs,
+            durability: Durability::Fsync,
+            group_commit_window_ms: 2,
+            checkpoint_size: 1_048_576,
+            archive: ArchivePolicy::default(),
+            backup_dir: PathBuf::from("backups"),
+            read_only: false,
+            leader: None,
+            replication_interval_ms: 500,
+            replication_token: None,
+        }
+    }
+}
+
+impl Config {
+    fn load() -> Self {
+        let mut config = match std::env::var("CONFIG_FILE") {
+            Ok(file) => {
+                let content = std::fs::read_to_string(&file)
+                    .unwrap_or_else(|e| panic!("Failed to read CONFIG_FILE {}: {}", file, e));
+                Self::parse(&file, &content)
+            }
+            Err(_) => std::fs::read_to_string("wooridb.ron")
+                .map_or_else(|_| Self::default(), |c| Self::parse("wooridb.ron", &c)),
+        };
+
+        if let Ok(data_dir) = std::env::var("DATA_DIR") {
+            config.data_dir = PathBuf::from(data_dir);
+        }
+        match std::env::var("STORAGE").as_deref() {
+            Ok("fs") => config.storage = StorageBackend::Fs,
+            Ok("memory") => config.storage = StorageBackend::Memory,
+            Ok(other) => panic!("STORAGE must be either fs or memory, got {}", other),
+            Err(_) => (),
+        }
+        match std::env::var("DURABILITY").as_deref() {
+            Ok("none") => config.durability = Durability::None,
+            Ok("fsync") => config.durability = Durability::Fsync,
+            Ok("group_commit") => config.durability = Durability::GroupCommit,
+            Ok(other) => panic!(
+                "DURABILITY must be one of none, fsync or group_commit, got {}",
+                other
+            ),
+            Err(_) => (),
+        }
+        if let Some(window) = env_parse("GROUP_COMMIT_WINDOW_MS") {
+            config.group_commit_window_ms = window;
+        }
+        if let Some(size) = env_parse("CHECKPOINT_SIZE") {
+            config.checkpoint_size = size;
+        }
+        if let Ok(cron) = std::env::var("ARCHIVE_CRON") {
+            config.archive.cron = cron;
+        }
+        if let Some(after_days) = env_parse("ARCHIVE_AFTER_DAYS") {
+            config.archive.after_days = after_days;
+        }
+        if let Some(level) = env_parse("ARCHIVE_COMPRESSION_LEVEL") {
+            config.archive.compression_level = level;
+        }
+        if let Ok(archive_dir) = std::env::var("ARCHIVE_DIR") {
+            config.archive.archive_dir = Some(PathBuf::from(archive_dir));
+        }
+        if let Ok(backup_dir) = std::env::var("BACKUP_DIR") {
+            config.backup_dir = PathBuf::from(backup_dir);
+        }
+        if let Some(read_only) = env_parse("READ_ONLY") {
+            config.read_only = read_only;
+        }
+        if let Ok(leader) = std::env::var("LEADER") {
+            config.leader = Some(leader);
+        }
+        if let Some(interval) = env_parse("REPLICATION_INTERVAL_MS") {
+            config.replication_interval_ms = interval;
+        }
+        if let Ok(token) = std::env::var("REPLICATION_TOKEN") {
+            config.replication_token = Some(token);
+        }
+        config
+    }
+
+    fn parse(file: &str, content: &str) -> Self {
+        ron::de::from_str(content)
+            .unwrap_or_else(|e| panic!("Failed to parse config file {}: {}", file, e))
+    }
+}
+
+fn env_parse<T: FromStr>(name: &str) -> Option<T> {
+    std::env::var(name).ok().map(|value| {
+        value
+            .parse()
+            .unwrap_or_else(|_| panic!("{} has an invalid value {}", name, value))
+    })
+}
+
+pub fn config() -> &'static Config {
+    static CONFIG: OnceLock<Config> = OnceLock::new();
+    CONFIG.get_or_init(Config::load)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_config_file() {
+        let config = Config::parse("wooridb.ron", "(data_dir: \"/var/lib/wooridb\")");
+        assert_eq!(config.data_dir, PathBuf::from("/var/lib/wooridb"));
+        assert_eq!(config.storage, StorageBackend::Fs);
+
+        assert_eq!(config.archive.cron, "@daily");
+        assert_eq!(config.archive.after_days, 10);
+
+        assert_eq!(config.durability, Durability::Fsync);
+
+        let config = Config::parse(
+            "wooridb.ron",
+            "(storage: Memory, durability: GroupCommit, group_commit_window_ms: 5)",
+        );
+        assert_eq!(config.storage, StorageBackend::Memory);
+        assert_eq!(config.durability, Durability::GroupCommit);
+        assert_eq!(config.group_commit_window_ms, 5);
+        assert_eq!(config.checkpoint_size, 1_048_576);
+
+        let config = Config::parse(
+            "wooridb.ron",
+            "(archive: (after_days: 30, compression_level: 3, archive_dir: Some(\"/mnt/archive\")))",
+        );
+        assert_eq!(config.archive.after_days, 30);
+        assert_eq!(config.archive.compression_level, 3);
+        assert_eq!(config.archive.cron, "@daily");
+        assert_eq!(
+            config.archive.archive_dir,
+            Some(PathBuf::from("/mnt/archive"))
+        );
+
+        let config = Config::parse("wooridb.ron", "()");
+        assert_eq!(config.data_dir, PathBuf::from("data"));
+        assert_eq!(config.backup_dir, PathBuf::from("backups"));
+        assert!(!config.read_only);
+        assert_eq!(config.leader, None);
+
+        let config = Config::parse(
+            "wooridb.ron",
+            "(leader: Some(\"http://localhost:1438\"), replication_interval_ms: 100)",
+        );
+        assert_eq!(config.leader.as_deref(), Some("http://localhost:1438"));
+        assert_eq!(config.replication_interval_ms, 100);
+    }
+}
@@ -7,7 +7,6 @@ use std::collections::HashMap;
 use uuid::Uuid;
 use wql::Types;
 
-#[ignore]
 #[actix_rt::test]
 async fn test_select_all_limit_offset_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -41,7 +40,6 @@ async fn test_select_all_limit_offset_ok() {
     };
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_select_all_limit_count_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -64,7 +62,6 @@ async fn test_select_all_limit_count_ok() {
     assert!(body.contains("count: 3"));
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_select_all_count_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -87,7 +84,6 @@ async fn test_select_all_count_ok() {
     assert!(body.contains("count: 6"));
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_select_all_dedup_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -121,7 +117,6 @@ async fn test_select_all_dedup_ok() {
     };
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_select_all_dedup_nil_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -166,7 +161,6 @@ async fn test_select_all_dedup_nil_ok() {
     assert!(!body.contains("\"a\": Integer(123)"));
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_select_all_dedup_count_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -189,7 +183,6 @@ async fn test_select_all_dedup_count_ok() {
     assert!(body.contains("count: 5"));
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_select_all_group_by_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -228,7 +221,6 @@ async fn test_select_all_group_by_ok() {
         };
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_select_all_group_by_count_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -251,7 +243,6 @@ async fn test_select_all_group_by_count_ok() {
     assert!(body.contains("count: 4"));
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_select_where_group_by_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -295,7 +286,6 @@ async fn test_select_where_group_by_ok() {
         };
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_select_all_group_by_with_order_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -334,7 +324,6 @@ async fn test_select_all_group_by_with_order_ok() {
         };
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_select_all_order_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -369,6 +358,129 @@ async fn test_select_all_order_ok() {
     };
 }
 
+#[actix_rt::test]
+async fn test_select_all_aggregates_ok() {
+    let mut app = test::init_service(App::new().configure(routes)).await;
+
+    for req in inserts("aggregates_test") {
+        let _ = test::call_service(&mut app, req).await;
+    }
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("Select * FROM aggregates_test SUM a AVG b MIN a MAX a COUNT DISTINCT a")
+        .uri("/wql/query")
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_success());
+    let body = resp.take_body().as_str().to_string();
+    let result: HashMap<String, Types> = ron::de::from_str(&body).unwrap();
+    assert_eq!(result["sum_a"], Types::Integer(1388));
+    assert_eq!(result["avg_b"], Types::Float(12.3));
+    assert_eq!(result["min_a"], Types::Integer(25));
+    assert_eq!(result["max_a"], Types::Integer(475));
+    assert_eq!(result["count_distinct_a"], Types::Integer(5));
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("Select * FROM aggregates_test GROUP BY c SUM a COUNT")
+        .uri("/wql/query")
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+    let body = resp.take_body().as_str().to_string();
+    let result: HashMap<String, HashMap<String, Types>> = ron::de::from_str(&body).unwrap();
+    assert_eq!(result["Char('c')"]["sum_a"], Types::Integer(260));
+    assert_eq!(result["Char('d')"]["sum_a"], Types::Integer(710));
+    assert_eq!(result["Char('d')"]["count"], Types::Integer(2));
+    assert_eq!(result["Nil"]["sum_a"], Types::Integer(123));
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload(
+            "Select * FROM aggregates_test WHERE {
+            ?* aggregates_test:a ?a,
+            (> ?a 200),
+        } MAX a AVG a",
+        )
+        .uri("/wql/query")
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+    let body = resp.take_body().as_str().to_string();
+    let result: HashMap<String, Types> = ron::de::from_str(&body).unwrap();
+    assert_eq!(result["max_a"], Types::Integer(475));
+    assert_eq!(result["avg_a"], Types::Float(310.0));
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("Select * FROM aggregates_test SUM a SUM b")
+        .uri("/wql/query")
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+    let body = resp.take_body().as_str().to_string();
+    let result: HashMap<String, Types> = ron::de::from_str(&body).unwrap();
+    assert_eq!(result["sum_a"], Types::Integer(1388));
+    assert!(result.contains_key("sum_b"));
+
+    for (functions, count) in [("DEDUP c", 4), ("LIMIT 2", 2), ("OFFSET 4", 2)] {
+        let req = test::TestRequest::post()
+            .header("Content-Type", "application/wql")
+            .set_payload(format!(
+                "Select * FROM aggregates_test {} SUM a COUNT",
+                functions
+            ))
+            .uri("/wql/query")
+            .to_request();
+
+        let mut resp = test::call_service(&mut app, req).await;
+        let body = resp.take_body().as_str().to_string();
+        let result: HashMap<String, Types> = ron::de::from_str(&body).unwrap();
+        assert_eq!(result["count"], Types::Integer(count));
+    }
+}
+
+#[actix_rt::test]
+async fn test_select_all_group_by_having_ok() {
+    let mut app = test::init_service(App::new().configure(routes)).await;
+
+    for req in inserts("having_test") {
+        let _ = test::call_service(&mut app, req).await;
+    }
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("Select * FROM having_test GROUP BY c HAVING { (> count 1), (>= sum_a 300), }")
+        .uri("/wql/query")
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_success());
+    let body = resp.take_body().as_str().to_string();
+    let result: HashMap<String, BTreeMap<Uuid, HashMap<String, Types>>> =
+        ron::de::from_str(&body).unwrap();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result["Char('d')"].len(), 2);
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload(
+            "Select * FROM having_test GROUP BY c SUM a HAVING { (or (< sum_a 200) (== count 2)) }",
+        )
+        .uri("/wql/query")
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+    let body = resp.take_body().as_str().to_string();
+    let result: HashMap<String, HashMap<String, Types>> = ron::de::from_str(&body).unwrap();
+    assert_eq!(result.len(), 3);
+    assert_eq!(result["Char('c')"]["sum_a"], Types::Integer(260));
+    assert_eq!(result["Char('d')"]["sum_a"], Types::Integer(710));
+    assert_eq!(result["Nil"]["sum_a"], Types::Integer(123));
+}
+
 fn inserts(entity_name: &str) -> Vec<Request> {
     vec![
         test::TestRequest::post()
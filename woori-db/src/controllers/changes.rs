@@ -0,0 +1,81 @@
+use crate::model::error::{error_to_http, Error};
+use crate::{
+    actors::changes::SubscribeChanges,
+    model::{log::LogRecord, DataExecutor},
+    schemas::changes::{ChangeEvent, ChangesQuery},
+};
+
+use actix_web::{web, web::Bytes, HttpRequest, HttpResponse, Responder};
+use futures::{future, stream, StreamExt};
+use std::sync::Arc;
+
+pub async fn changes_handler(
+    req: HttpRequest,
+    query: web::Query<ChangesQuery>,
+    actor: DataExecutor,
+) -> impl Responder {
+    let query = query.into_inner();
+    // Reconnecting `EventSource`s send the id of the last event they received.
+    let from = query.from.or_else(|| {
+        req.headers()
+            .get("Last-Event-ID")
+            .and_then(|id| id.to_str().ok())
+            .and_then(|id| id.parse::<u64>().ok())
+            .map(|tx_seq| tx_seq + 1)
+    });
+
+    let (records, subscription) = match actor.send(SubscribeChanges { from }).await {
+        Ok(Ok(subscribed)) => subscribed,
+        Ok(Err(e)) => return error_to_http(&e),
+        Err(e) => return error_to_http(&e.into()),
+    };
+
+    let mut transactions: Vec<Vec<LogRecord>> = Vec::new();
+    for record in records {
+        match transactions.last_mut() {
+            Some(transaction) if transaction[0].tx_seq == record.tx_seq => transaction.push(record),
+            _ => transactions.push(vec![record]),
+        }
+    }
+    let live = stream::unfold(subscription, |mut subscription| async move {
+        subscription
+            .next()
+            .await
+            .map(|records| (records, subscription))
+    });
+    let entity = query.entity;
+    let events = stream::iter(transactions.into_iter().map(Arc::new))
+        .chain(live)
+        .map(move |records| events(&records, entity.as_deref()))
+        .filter(|events| future::ready(!matches!(events, Ok(events) if events.is_empty())));
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .streaming(Box::pin(events))
+}
+
+/// Server-Sent Events of the records of a transaction that belong to `entity`. Only the
+/// last one has an id, so that a stream cut in the middle of a `BEGIN ... COMMIT` block
+/// resumes at the start of the block.
+fn events(records: &[LogRecord], entity: Option<&str>) -> Result<Bytes, Error> {
+    let changes = records
+        .iter()
+        .filter(|record| entity.is_none_or(|entity| record.entity == entity))
+        .map(ChangeEvent::new)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut events = String::new();
+    for (i, change) in changes.iter().enumerate() {
+        #[cfg(feature = "json")]
+        let data = serde_json::to_string(change)?;
+        #[cfg(not(feature = "json"))]
+        let data = ron::ser::to_string(change).map_err(Error::Ron)?;
+
+        if i + 1 == changes.len() {
+            events.push_str(&format!("id: {}\n", change.tx_seq));
+        }
+        events.push_str(&format!("data: {}\n\n", data));
+    }
+
+    Ok(Bytes::from(events))
+}
@@ -0,0 +1,111 @@
+use crate::{http::routes, schemas::tx::TxResponse};
+use actix_http::body::{MessageBody, ResponseBody};
+use actix_web::{body::Body, test, App};
+use std::pin::Pin;
+
+#[actix_rt::test]
+async fn test_changes_stream_ok() {
+    let mut app = test::init_service(App::new().configure(routes)).await;
+    for entity in &["changes_a", "changes_b"] {
+        let req = test::TestRequest::post()
+            .header("Content-Type", "application/wql")
+            .set_payload(format!("CREATE ENTITY {}", entity))
+            .uri("/wql/tx")
+            .to_request();
+
+        let _ = test::call_service(&mut app, req).await;
+    }
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("INSERT {a: 1,} INTO changes_a")
+        .uri("/wql/tx")
+        .to_request();
+
+    let mut resp_insert = test::call_service(&mut app, req).await;
+    let body = resp_insert.take_body().as_str().to_string();
+    let insert: TxResponse = ron::de::from_str(&body).unwrap();
+    let uuid = insert.uuid.unwrap();
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("INSERT {b: 1,} INTO changes_b")
+        .uri("/wql/tx")
+        .to_request();
+
+    let _ = test::call_service(&mut app, req).await;
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/changes?entity=changes_a&from={}", insert.tx_seq))
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_success());
+    let mut stream = resp.take_body();
+    let event = next_event(&mut stream).await;
+    assert!(event.starts_with(&format!("id: {}\ndata: (", insert.tx_seq)));
+    assert!(event.contains("action:Insert"));
+    assert!(event.contains(&format!("uuid:Some(\"{}\")", uuid)));
+    assert!(event.contains("\"a\":Integer(1)"));
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload(format!("UPDATE changes_a SET {{a: 2,}} INTO {}", uuid))
+        .uri("/wql/tx")
+        .to_request();
+
+    let mut resp_update = test::call_service(&mut app, req).await;
+    let body = resp_update.take_body().as_str().to_string();
+    let update: TxResponse = ron::de::from_str(&body).unwrap();
+
+    let event = next_event(&mut stream).await;
+    assert!(event.starts_with(&format!("id: {}\n", update.tx_seq)));
+    assert!(event.contains("action:UpdateSet"));
+    assert!(event.contains("\"a\":Integer(2)"));
+    assert!(!event.contains("changes_b"));
+
+    let req = test::TestRequest::get()
+        .header("Last-Event-ID", insert.tx_seq.to_string())
+        .uri("/changes?entity=changes_a")
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+    let event = next_event(&mut resp.take_body()).await;
+    assert!(event.starts_with(&format!("id: {}\n", update.tx_seq)));
+    clear();
+}
+
+async fn next_event(stream: &mut ResponseBody<Body>) -> String {
+    let event = futures::future::poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx))
+        .await
+        .unwrap()
+        .unwrap();
+    String::from_utf8(event.to_vec()).unwrap()
+}
+
+trait BodyTest {
+    fn as_str(&self) -> &str;
+}
+
+impl BodyTest for ResponseBody<Body> {
+    fn as_str(&self) -> &str {
+        match self {
+            ResponseBody::Body(ref b) => match b {
+                Body::Bytes(ref by) => std::str::from_utf8(by).unwrap(),
+                _ => panic!(),
+            },
+            ResponseBody::Other(ref b) => match b {
+                Body::Bytes(ref by) => std::str::from_utf8(by).unwrap(),
+                _ => panic!(),
+            },
+        }
+    }
+}
+
+pub fn clear() {
+    std::process::Command::new("rm")
+        .arg("-rf")
+        .arg("data/*.log")
+        .output()
+        .expect("failed to execute process");
+}
@@ -1,25 +1,36 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use rayon::prelude::*;
 use uuid::Uuid;
-use wql::{Algebra, Clause, ToSelect, Types, Value};
+use wql::{Algebra, Clause, Function, ToSelect, Types, Value};
 
 use crate::{
-    core::registry::get_registries,
-    model::{error::Error, DataLocalContext, DataRegister},
+    model::{error::Error, DataIndexContext, DataLocalContext, DataRegister},
+    repository::{index::Lookup, local::StateInfo},
     schemas::query::Response as QueryResponse,
 };
 
-use crate::core::query::{dedup_states, get_limit_offset_count, get_result_after_manipulation};
+use crate::core::query::{
+    dedup_states, filter_keys_and_hash, get_limit_offset_count, get_path,
+    get_result_after_manipulation,
+};
 
 pub async fn select_where_controller(
     entity: String,
     args_to_select: ToSelect,
     clauses: Vec<Clause>,
     local_data: DataLocalContext,
+    indexes: DataIndexContext,
     functions: HashMap<String, wql::Algebra>,
 ) -> Result<QueryResponse, Error> {
-    let states = select_where(entity, args_to_select, clauses, local_data, &functions);
+    let states = select_where(
+        entity,
+        args_to_select,
+        clauses,
+        local_data,
+        indexes,
+        &functions,
+    );
     let count = matches!(functions.get("COUNT"), Some(Algebra::Count));
 
     Ok(get_result_after_manipulation(
@@ -34,6 +45,7 @@ pub async fn select_where(
     args_to_select: ToSelect,
     clauses: Vec<Clause>,
     local_data: DataLocalContext,
+    indexes: DataIndexContext,
     functions: &HashMap<String, wql::Algebra>,
 ) -> Result<BTreeMap<Uuid, HashMap<String, Types>>, Error> {
     let (limit, offset, _) = get_limit_offset_count(functions);
@@ -48,7 +60,14 @@ pub async fn select_where(
             }
         })
         .collect::<HashMap<String, String>>();
-    let registries = get_registries(&entity, &local_data)?;
+    let registries = where_registries(
+        &entity,
+        &args_to_select,
+        &clauses,
+        &args_to_key,
+        &local_data,
+        &indexes,
+    )?;
     let states = generate_state(&registries, args_to_select).await?;
     let states = filter_where_clauses(states, args_to_key, &clauses)
         .await
@@ -61,6 +80,83 @@ pub async fn select_where(
     Ok(states)
 }
 
+/// Registries of the entity ids that may satisfy `clauses`. Clauses on indexed keys
+/// narrow them down, the others are only checked against the states.
+fn where_registries(
+    entity: &str,
+    args_to_select: &ToSelect,
+    clauses: &[Clause],
+    args_to_key: &HashMap<String, String>,
+    local_data: &DataLocalContext,
+    indexes: &DataIndexContext,
+) -> Result<BTreeMap<Uuid, StateInfo>, Error> {
+    let local_data = if let Ok(guard) = local_data.lock() {
+        guard
+    } else {
+        return Err(Error::LockData);
+    };
+    let registries = if let Some(id_to_registries) = local_data.get(entity) {
+        id_to_registries
+    } else {
+        return Err(Error::EntityNotCreated(entity.to_owned()));
+    };
+    let indexes = if let Ok(guard) = indexes.lock() {
+        guard
+    } else {
+        return Err(Error::LockData);
+    };
+
+    let candidates = clauses
+        .iter()
+        .filter_map(|clause| {
+            let (key, lookup) = index_lookup(clause, args_to_key)?;
+            match args_to_select {
+                ToSelect::Keys(keys) if !keys.iter().any(|k| k == key) => None,
+                _ => indexes.candidates(entity, key, &lookup),
+            }
+        })
+        .reduce(|a, b| a.intersection(&b).copied().collect());
+
+    Ok(match candidates {
+        Some(ids) => ids
+            .iter()
+            .filter_map(|id| registries.get(id).map(|info| (*id, info.to_owned())))
+            .collect(),
+        None => registries.to_owned(),
+    })
+}
+
+/// Key and values an index can look up for `clause`.
+fn index_lookup<'a>(
+    clause: &'a Clause,
+    args_to_key: &'a HashMap<String, String>,
+) -> Option<(&'a str, Lookup<'a>)> {
+    match clause {
+        Clause::ContainsKeyValue(_, key, value) => Some((key, Lookup::Eq(value))),
+        Clause::SimpleComparisonFunction(f, key, value) => {
+            let lookup = match f {
+                Function::Eq => Lookup::Eq(value),
+                Function::G | Function::GEq => Lookup::From(value),
+                Function::L | Function::LEq => Lookup::To(value),
+                _ => return None,
+            };
+            Some((args_to_key.get(key)?, lookup))
+        }
+        Clause::ComplexComparisonFunctions(Function::In, key, set) => {
+            Some((args_to_key.get(key)?, Lookup::In(set)))
+        }
+        Clause::ComplexComparisonFunctions(Function::Between, key, start_end)
+            if start_end.len() == 2 =>
+        {
+            Some((
+                args_to_key.get(key)?,
+                Lookup::Between(&start_end[0], &start_end[1]),
+            ))
+        }
+        _ => None,
+    }
+}
+
 async fn filter_where_clauses(
     states: BTreeMap<Uuid, HashMap<String, Types>>,
     args_to_key: HashMap<String, String>,
@@ -80,7 +176,7 @@ async fn filter_where_clauses(
             }
             Clause::ContainsKeyValue(_, key, value) => {
                 for (id, state) in states.clone() {
-                    if !state.get(key).map_or(false, |v| value == v) {
+                    if !get_path(&state, key).map_or(false, |v| value == v) {
                         states.remove(&id);
                     }
                 }
@@ -88,7 +184,7 @@ async fn filter_where_clauses(
             Clause::SimpleComparisonFunction(f, key, value) => {
                 let key = args_to_key.get(key).unwrap_or(&default);
                 for (id, state) in states.clone() {
-                    state.get(key).map(|v| match f {
+                    get_path(&state, key).map(|v| match f {
                         wql::Function::Eq => {
                             if !(v == value) {
                                 states.remove(&id);
@@ -145,7 +241,7 @@ async fn filter_where_clauses(
             Clause::ComplexComparisonFunctions(wql::Function::In, key, set) => {
                 let key = args_to_key.get(key).unwrap_or(&default);
                 for (id, state) in states.clone() {
-                    if !state.get(key).map_or(false, |v| set.contains(v)) {
+                    if !get_path(&state, key).map_or(false, |v| set.contains(v)) {
                         states.remove(&id);
                     }
                 }
@@ -153,8 +249,7 @@ async fn filter_where_clauses(
             Clause::ComplexComparisonFunctions(wql::Function::Between, key, start_end) => {
                 let key = args_to_key.get(key).unwrap_or(&default);
                 for (id, state) in states.clone() {
-                    if !state
-                        .get(key)
+                    if !get_path(&state, key)
                         .map_or(false, |v| v >= &start_end[0] && v <= &start_end[1])
                     {
                         states.remove(&id);
@@ -181,11 +276,11 @@ fn or_clauses(
             Clause::Or(_, or_inner_clauses) => or_clauses(state, &args_to_key, or_inner_clauses),
             Clause::ContainsKeyValue(_, key, value) => {
                 let key = args_to_key.get(key).unwrap_or(&default);
-                state.get(key).map_or(false, |v| value == v)
+                get_path(state, key).map_or(false, |v| value == v)
             }
             Clause::SimpleComparisonFunction(f, key, value) => {
                 let key = args_to_key.get(key).unwrap_or(&default);
-                state.get(key).map_or(false, |v| match f {
+                get_path(state, key).map_or(false, |v| match f {
                     wql::Function::Eq => v == value,
                     wql::Function::NotEq => v != value,
                     wql::Function::GEq => v >= value,
@@ -215,13 +310,11 @@ fn or_clauses(
             }
             Clause::ComplexComparisonFunctions(wql::Function::In, key, set) => {
                 let key = args_to_key.get(key).unwrap_or(&default);
-                state.get(key).map_or(false, |v| set.contains(v))
+                get_path(state, key).map_or(false, |v| set.contains(v))
             }
             Clause::ComplexComparisonFunctions(wql::Function::Between, key, start_end) => {
                 let key = args_to_key.get(key).unwrap_or(&default);
-                state
-                    .get(key)
-                    .map_or(false, |v| v >= &start_end[0] && v <= &start_end[1])
+                get_path(state, key).map_or(false, |v| v >= &start_end[0] && v <= &start_end[1])
             }
             _ => false,
         })
@@ -232,23 +325,15 @@ async fn generate_state(
     registries: &BTreeMap<Uuid, (DataRegister, Vec<u8>)>,
     args_to_select: ToSelect,
 ) -> Result<BTreeMap<Uuid, HashMap<String, Types>>, Error> {
+    let keys = if let ToSelect::Keys(keys) = args_to_select {
+        Some(keys.into_iter().collect::<HashSet<String>>())
+    } else {
+        None
+    };
     let mut states: BTreeMap<Uuid, HashMap<String, Types>> = BTreeMap::new();
     for (uuid, (_, state)) in registries {
         let state: HashMap<String, Types> = bincode::deserialize(&state).unwrap();
-        let state = state
-            .into_par_iter()
-            .filter(|(_, v)| !v.is_hash())
-            .map(|(k, v)| (k.to_owned(), v.to_owned()));
-        let filtered = if let ToSelect::Keys(ref keys) = args_to_select {
-            state
-                .into_par_iter()
-                .filter(|(k, _)| keys.contains(k))
-                .collect::<HashMap<String, Types>>()
-        } else {
-            state.collect::<HashMap<String, Types>>()
-        };
-
-        states.insert(uuid.to_owned(), filtered);
+        states.insert(uuid.to_owned(), filter_keys_and_hash(state, keys.clone()));
     }
     Ok(states)
 }
@@ -7,8 +7,8 @@ use uuid::Uuid;
 use wql::Types;
 
 use super::tx_test::clear;
+use crate::schemas::tx::TxResponse;
 
-#[ignore]
 #[actix_rt::test]
 async fn simple_where_clause_eq() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -72,7 +72,6 @@ async fn simple_where_clause_eq() {
     clear();
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn clause_between() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -137,7 +136,6 @@ async fn clause_between() {
     clear();
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn clause_in() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -206,7 +204,6 @@ async fn clause_in() {
     clear();
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn clause_ge_le() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -273,7 +270,6 @@ async fn clause_ge_le() {
     clear();
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn clause_or() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -360,7 +356,6 @@ async fn clause_or() {
     clear();
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn clause_like() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -429,6 +424,182 @@ async fn clause_like() {
     clear();
 }
 
+#[actix_rt::test]
+async fn clauses_with_index() {
+    let mut app = test::init_service(App::new().configure(routes)).await;
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("CREATE INDEX test_where_index_missing #{a,}")
+        .uri("/wql/tx")
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_client_error());
+    let body = resp.take_body().as_str().to_string();
+    assert!(body.contains("Entity `test_where_index_missing` not created"));
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("CREATE ENTITY test_where_index")
+        .uri("/wql/tx")
+        .to_request();
+
+    let _ = test::call_service(&mut app, req).await;
+
+    let mut ids = Vec::new();
+    for payload in &[
+        "INSERT {a: 3, b: \"hello world\", c: 45.6,} INTO test_where_index",
+        "INSERT {a: 43, b: \"Julia Naomi\", c: 57.6,} INTO test_where_index",
+        "CREATE INDEX test_where_index #{a, c,}",
+        "INSERT {a: 948, b: \"Otavio Pace\", c: 5.6,} INTO test_where_index",
+        "INSERT {a: 123, b: \"hello johnny\", c: 4345.6,} INTO test_where_index",
+        "INSERT {b: \"no a\", c: [1, 2,],} INTO test_where_index",
+    ] {
+        let req = test::TestRequest::post()
+            .header("Content-Type", "application/wql")
+            .set_payload(*payload)
+            .uri("/wql/tx")
+            .to_request();
+
+        let mut resp = test::call_service(&mut app, req).await;
+        let body = resp.take_body().as_str().to_string();
+        assert!(resp.status().is_success(), "{}", body);
+        if payload.starts_with("CREATE") {
+            assert!(body.contains("Index on `a, c` of entity `test_where_index` created"));
+        }
+        let response: TxResponse = ron::de::from_str(&body).unwrap();
+        ids.extend(response.uuid);
+    }
+
+    let payload = format!("UPDATE test_where_index SET {{a: 50,}} INTO {}", ids[0]);
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload(payload)
+        .uri("/wql/tx")
+        .to_request();
+
+    let _ = test::call_service(&mut app, req).await;
+
+    for (query, expected) in &[
+        (
+            "?* test_where_index:a ?a, (between ?a 40 130),",
+            vec![1, 0, 3],
+        ),
+        ("?* test_where_index:a ?a, (>= ?a 900),", vec![2, 4]),
+        ("?* test_where_index:a ?a, (< ?a 45),", vec![1, 4]),
+        ("?* test_where_index:a 3,", vec![]),
+        ("?* test_where_index:c 57.6,", vec![1]),
+        (
+            "?* test_where_index:c ?c, ?* test_where_index:a ?a, (in ?c 57.6 5.6), (> ?a 100),",
+            vec![2],
+        ),
+    ] {
+        let req = test::TestRequest::post()
+            .header("Content-Type", "application/wql")
+            .set_payload(format!(
+                "Select * From test_where_index WHERE {{ {} }}",
+                query
+            ))
+            .uri("/wql/query")
+            .to_request();
+
+        let mut resp = test::call_service(&mut app, req).await;
+        let body = resp.take_body().as_str().to_string();
+        let result: BTreeMap<Uuid, HashMap<String, Types>> = ron::de::from_str(&body).unwrap();
+        let mut expected = expected.iter().map(|i| ids[*i]).collect::<Vec<Uuid>>();
+        expected.sort();
+        assert_eq!(
+            result.keys().cloned().collect::<Vec<Uuid>>(),
+            expected,
+            "{}",
+            query
+        );
+    }
+
+    clear();
+}
+
+#[actix_rt::test]
+async fn where_clause_key_paths() {
+    let mut app = test::init_service(App::new().configure(routes)).await;
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("CREATE ENTITY test_where_paths")
+        .uri("/wql/tx")
+        .to_request();
+
+    let _ = test::call_service(&mut app, req).await;
+
+    for (name, city, tag) in &[("a", "Seoul", 3), ("b", "Busan", 2), ("c", "Seoul", 1)] {
+        let req = test::TestRequest::post()
+            .header("Content-Type", "application/wql")
+            .set_payload(format!(
+                "INSERT {{name: \"{}\", address: {{ city: \"{}\", }}, tags: [{}, 10,],}} INTO test_where_paths",
+                name, city, tag
+            ))
+            .uri("/wql/tx")
+            .to_request();
+
+        let _ = test::call_service(&mut app, req).await;
+    }
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload(
+            "Select #{name, address.city, tags.0,} From test_where_paths WHERE {
+            ?* test_where_paths:address.city ?city,
+            (== ?city \"Seoul\"),
+        }",
+        )
+        .uri("/wql/query")
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+    let body = resp.take_body().as_str().to_string();
+    let result: BTreeMap<Uuid, HashMap<String, Types>> = ron::de::from_str(&body).unwrap();
+    assert_eq!(result.len(), 2);
+    for state in result.values() {
+        assert_eq!(state["address.city"], Types::String("Seoul".to_string()));
+        assert!(state.contains_key("tags.0"));
+        assert!(!state.contains_key("address"));
+    }
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("Select * From test_where_paths ORDER BY tags.0 :asc")
+        .uri("/wql/query")
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+    let body = resp.take_body().as_str().to_string();
+    let result: Vec<(Uuid, HashMap<String, Types>)> = ron::de::from_str(&body).unwrap();
+    let names = result
+        .iter()
+        .map(|(_, state)| state["name"].clone())
+        .collect::<Vec<Types>>();
+    assert_eq!(
+        names,
+        vec![
+            Types::String("c".to_string()),
+            Types::String("b".to_string()),
+            Types::String("a".to_string())
+        ]
+    );
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("Select * From test_where_paths DEDUP address.city")
+        .uri("/wql/query")
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+    let body = resp.take_body().as_str().to_string();
+    let result: BTreeMap<Uuid, HashMap<String, Types>> = ron::de::from_str(&body).unwrap();
+    assert_eq!(result.len(), 2);
+
+    clear();
+}
+
 trait BodyTest {
     fn as_str(&self) -> &str;
 }
@@ -1,15 +1,18 @@
 use crate::model::error::{error_to_http, Error};
 use crate::{
-    actors::history::History,
+    actors::history::{History, VersionRegister},
     core::pretty_config_output,
-    model::{DataExecutor, DataLocalContext},
-    schemas::history::EntityHistoryInfo,
+    model::{wql::Action, DataExecutor, DataLocalContext, DataRegister},
+    schemas::history::{
+        EntityHistoryInfo, HistoryCursor, HistoryOrder, HistoryPage, HistoryResponse,
+        HistoryVersion, StateDiff,
+    },
 };
 
 use actix_web::{HttpResponse, Responder};
 use chrono::{DateTime, Utc};
 use rayon::prelude::*;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use wql::Types;
 
 pub async fn history_handler(
@@ -41,7 +44,7 @@ pub async fn history_controller(
     body: String,
     local_data: DataLocalContext,
     actor: DataExecutor,
-) -> Result<BTreeMap<chrono::DateTime<Utc>, HashMap<std::string::String, Types>>, Error> {
+) -> Result<HistoryResponse, Error> {
     #[cfg(feature = "json")]
     let info: EntityHistoryInfo = serde_json::from_str(&body)?;
     #[cfg(not(feature = "json"))]
@@ -68,11 +71,27 @@ pub async fn history_controller(
         .to_owned();
         registry
     };
+    if info.limit.is_some() || info.cursor.is_some() || info.order.is_some() {
+        return history_page(info, registry.0, actor)
+            .await
+            .map(HistoryResponse::Page);
+    }
+
     let mut content = actor.send(registry.0).await??;
-    let mut btree: BTreeMap<DateTime<Utc>, HashMap<String, Types>> = BTreeMap::new();
+    let mut btree: BTreeMap<DateTime<Utc>, (Action, HashMap<String, Types>)> = BTreeMap::new();
+    // State before the first version in the time range, which the first diff starts from.
+    let mut previous_state = HashMap::new();
 
     loop {
+        let action = content.action.clone();
         let (entity_map, date, previous_registry) = actor.send(History(content.clone())).await??;
+        let entity_map = (action, entity_map);
+        if let Some(start) = info.start_datetime {
+            if date < start {
+                previous_state = entity_map.1;
+                break;
+            }
+        }
         if let (Some(start), Some(end)) = (info.start_datetime, info.end_datetime) {
             if date >= start && date <= end {
                 btree.insert(date, entity_map);
@@ -101,16 +120,135 @@ pub async fn history_controller(
 
     let filtered_tree = btree
         .into_par_iter()
-        .map(|(date, content)| {
+        .map(|(date, (action, content))| {
             (
                 date,
-                content
-                    .into_iter()
-                    .filter(|(_, v)| !v.is_hash())
-                    .collect::<HashMap<String, Types>>(),
+                (
+                    action,
+                    content
+                        .into_iter()
+                        .filter(|(_, v)| !v.is_hash())
+                        .collect::<HashMap<String, Types>>(),
+                ),
             )
         })
-        .collect::<BTreeMap<DateTime<Utc>, HashMap<String, Types>>>();
+        .collect::<BTreeMap<DateTime<Utc>, (Action, HashMap<String, Types>)>>();
+
+    if !info.diff {
+        return Ok(HistoryResponse::States(
+            filtered_tree
+                .into_iter()
+                .map(|(date, (_, content))| (date, content))
+                .collect(),
+        ));
+    }
+
+    let mut previous_state = previous_state
+        .into_iter()
+        .filter(|(_, v)| !v.is_hash())
+        .collect::<HashMap<String, Types>>();
+    let mut diffs = BTreeMap::new();
+    for (date, (action, content)) in filtered_tree {
+        diffs.insert(date, StateDiff::new(action, &previous_state, &content));
+        previous_state = content;
+    }
+
+    Ok(HistoryResponse::Diffs(diffs))
+}
+
+/// Reads a page of the history of the id. Newest first pages walk only the versions they
+/// answer, starting at the version of the id the cursor dates, looked up in the time
+/// index. Oldest first pages walk back to the start of the page and keep at most `limit`
+/// versions.
+async fn history_page(
+    info: EntityHistoryInfo,
+    latest: DataRegister,
+    actor: DataExecutor,
+) -> Result<HistoryPage, Error> {
+    let newest_first = info.order == Some(HistoryOrder::NewestFirst);
+    let (mut register, after) = match (info.cursor.clone(), newest_first) {
+        (None, _) => (latest, None),
+        (Some(HistoryCursor::Before(date)), true) => {
+            let version = VersionRegister {
+                entity: info.entity_key.clone(),
+                uuid: info.entity_id,
+                tx_time: date,
+            };
+            (actor.send(version).await??, None)
+        }
+        (Some(HistoryCursor::After(date)), false) => (latest, Some(date)),
+        _ => return Err(Error::HistoryCursorNotValid),
+    };
+    let limit = info.limit.unwrap_or(usize::MAX);
+
+    // Versions newest first, and the state before the oldest one for its diff.
+    let mut versions = VecDeque::new();
+    let mut previous_state = HashMap::new();
+    let mut has_more = false;
+    // Date of the next older version, where the next newest first page starts.
+    let mut before = None;
+    loop {
+        let record = actor.send(register.clone()).await??;
+        if record.entity != info.entity_key || record.uuid != Some(info.entity_id) {
+            return Err(Error::HistoryCursorNotValid);
+        }
+        let action = record.action.clone();
+        let (state, date, previous_registry) = actor.send(History(record)).await??;
+        let state = state
+            .into_iter()
+            .filter(|(_, v)| !v.is_hash())
+            .collect::<HashMap<String, Types>>();
+
+        if info.start_datetime.is_some_and(|start| date < start)
+            || after.is_some_and(|after| date <= after)
+        {
+            previous_state = state;
+            break;
+        }
+        if info.end_datetime.is_none_or(|end| date <= end) {
+            if newest_first && versions.len() == limit {
+                previous_state = state;
+                has_more = true;
+                before = Some(date);
+                break;
+            }
+            versions.push_back((date, action, state));
+            if versions.len() > limit {
+                versions.pop_front();
+                has_more = true;
+            }
+        }
+
+        match previous_registry {
+            Some(previous_registry) => register = previous_registry,
+            None => break,
+        }
+    }
+
+    let cursor = match (has_more, newest_first) {
+        (false, _) => None,
+        (true, true) => before.map(HistoryCursor::Before),
+        (true, false) => versions
+            .front()
+            .map(|(date, ..)| HistoryCursor::After(*date)),
+    };
+    let mut versions = versions
+        .into_iter()
+        .rev()
+        .map(|(date, action, state)| {
+            let version = if info.diff {
+                let diff = StateDiff::new(action, &previous_state, &state);
+                previous_state = state;
+                HistoryVersion::Diff(diff)
+            } else {
+                HistoryVersion::State(state)
+            };
+            (date, version)
+        })
+        .collect::<Vec<_>>();
+    if newest_first {
+        versions.reverse();
+    }
 
-    Ok(filtered_tree)
+    Ok(HistoryPage { versions, cursor })
 }
@@ -1,8 +1,12 @@
-use crate::{http::routes, schemas::tx::TxResponse};
+use crate::{
+    http::routes,
+    schemas::{history::HistoryCursor, tx::TxResponse},
+};
 use actix_http::body::ResponseBody;
 use actix_web::{body::Body, test, App};
-use chrono::Utc;
-use std::{thread, time};
+use chrono::{DateTime, Utc};
+use std::{collections::HashMap, thread, time};
+use wql::Types;
 
 #[actix_rt::test]
 async fn test_history_ok() {
@@ -163,6 +167,212 @@ async fn test_start_end_time_ok() {
     clear();
 }
 
+#[actix_rt::test]
+async fn test_history_diff_ok() {
+    let mut app = test::init_service(App::new().configure(routes)).await;
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("CREATE ENTITY test_history_diff")
+        .uri("/wql/tx")
+        .to_request();
+
+    let _ = test::call_service(&mut app, req).await;
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("INSERT {a: 123, b: 12.3,} INTO test_history_diff")
+        .uri("/wql/tx")
+        .to_request();
+
+    let mut resp_insert = test::call_service(&mut app, req).await;
+    let body = resp_insert.take_body().as_str().to_string();
+    let response: TxResponse = ron::de::from_str(&body).unwrap();
+    let uuid = response.uuid.unwrap();
+
+    let payload = format!(
+        "UPDATE test_history_diff SET {{a: 34, c: true,}} INTO {}",
+        uuid
+    );
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload(payload)
+        .uri("/wql/tx")
+        .to_request();
+
+    let _ = test::call_service(&mut app, req).await;
+
+    let payload = format!("UPDATE test_history_diff CONTENT {{a: 1,}} INTO {}", uuid);
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload(payload)
+        .uri("/wql/tx")
+        .to_request();
+
+    let _ = test::call_service(&mut app, req).await;
+
+    let payload = format!(
+        "(entity_key: \"test_history_diff\", entity_id: \"{}\", diff: true,)",
+        uuid
+    );
+    let req = test::TestRequest::post()
+        .set_payload(payload)
+        .uri("/entity-history")
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success());
+    let body = resp.take_body().as_str().to_string();
+    assert!(body.contains("action: Insert"));
+    assert!(body.contains("action: UpdateSet"));
+    assert!(body.contains("action: UpdateContent"));
+    assert!(body.contains("\"b\": Float(12.3)"));
+    assert!(body.contains("\"c\": Boolean(true)"));
+    assert!(body.contains("old: Integer(123),"));
+    assert!(body.contains("new: Integer(35),"));
+    assert!(!body.contains("tx_time"));
+    clear();
+}
+
+#[derive(serde::Deserialize)]
+struct Page {
+    versions: Vec<(DateTime<Utc>, HashMap<String, Types>)>,
+    cursor: Option<HistoryCursor>,
+}
+
+#[actix_rt::test]
+async fn test_history_pages_ok() {
+    let mut app = test::init_service(App::new().configure(routes)).await;
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("CREATE ENTITY test_history_pages")
+        .uri("/wql/tx")
+        .to_request();
+
+    let _ = test::call_service(&mut app, req).await;
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("INSERT {a: 0,} INTO test_history_pages")
+        .uri("/wql/tx")
+        .to_request();
+
+    let mut resp_insert = test::call_service(&mut app, req).await;
+    let body = resp_insert.take_body().as_str().to_string();
+    let response: TxResponse = ron::de::from_str(&body).unwrap();
+    let uuid = response.uuid.unwrap();
+
+    for a in 1..5 {
+        let payload = format!("UPDATE test_history_pages SET {{a: {},}} INTO {}", a, uuid);
+        let req = test::TestRequest::post()
+            .header("Content-Type", "application/wql")
+            .set_payload(payload)
+            .uri("/wql/tx")
+            .to_request();
+
+        let _ = test::call_service(&mut app, req).await;
+    }
+
+    let a = |page: Page| {
+        page.versions
+            .into_iter()
+            .map(|(_, state)| state["a"].clone())
+            .collect::<Vec<_>>()
+    };
+    let mut pages = Vec::new();
+    let mut cursor = String::from("None");
+    loop {
+        let payload = format!(
+            "(entity_key: \"test_history_pages\", entity_id: \"{}\", limit: Some(2), order: Some(NewestFirst), cursor: {},)",
+            uuid, cursor
+        );
+        let req = test::TestRequest::post()
+            .set_payload(payload)
+            .uri("/entity-history")
+            .to_request();
+
+        let mut resp = test::call_service(&mut app, req).await;
+        assert!(resp.status().is_success());
+        let body = resp.take_body().as_str().to_string();
+        let page: Page = ron::de::from_str(&body).unwrap();
+        let cursor_next = page.cursor.clone();
+        pages.push(a(page));
+        match cursor_next {
+            Some(next) => cursor = format!("Some({})", ron::ser::to_string(&next).unwrap()),
+            None => break,
+        }
+    }
+
+    assert_eq!(
+        pages,
+        vec![
+            vec![Types::Integer(4), Types::Integer(3)],
+            vec![Types::Integer(2), Types::Integer(1)],
+            vec![Types::Integer(0)],
+        ]
+    );
+
+    let payload = format!(
+        "(entity_key: \"test_history_pages\", entity_id: \"{}\", limit: Some(2), order: Some(NewestFirst), cursor: Some(Before(\"2021-03-02T05:00:19.817189987Z\")),)",
+        uuid
+    );
+    let req = test::TestRequest::post()
+        .set_payload(payload)
+        .uri("/entity-history")
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_client_error());
+    let body = resp.take_body().as_str().to_string();
+    assert!(body.contains("HistoryCursorNotValid"));
+
+    let payload = format!(
+        "(entity_key: \"test_history_pages\", entity_id: \"{}\", limit: Some(3), order: Some(OldestFirst),)",
+        uuid
+    );
+    let req = test::TestRequest::post()
+        .set_payload(payload)
+        .uri("/entity-history")
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+    let body = resp.take_body().as_str().to_string();
+    let page: Page = ron::de::from_str(&body).unwrap();
+    let cursor = ron::ser::to_string(page.cursor.as_ref().unwrap()).unwrap();
+    assert_eq!(
+        a(page),
+        vec![Types::Integer(0), Types::Integer(1), Types::Integer(2)]
+    );
+
+    let payload = format!(
+        "(entity_key: \"test_history_pages\", entity_id: \"{}\", limit: Some(3), order: Some(NewestFirst), cursor: Some({}),)",
+        uuid, cursor
+    );
+    let req = test::TestRequest::post()
+        .set_payload(payload)
+        .uri("/entity-history")
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_client_error());
+
+    let payload = format!(
+        "(entity_key: \"test_history_pages\", entity_id: \"{}\", limit: Some(3), order: Some(OldestFirst), cursor: Some({}),)",
+        uuid, cursor
+    );
+    let req = test::TestRequest::post()
+        .set_payload(payload)
+        .uri("/entity-history")
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+    let body = resp.take_body().as_str().to_string();
+    let page: Page = ron::de::from_str(&body).unwrap();
+    assert!(page.cursor.is_none());
+    assert_eq!(a(page), vec![Types::Integer(3), Types::Integer(4)]);
+    clear();
+}
+
 trait BodyTest {
     fn as_str(&self) -> &str;
 }
@@ -1,5 +1,8 @@
 #[cfg(test)]
 pub mod algebra_test;
+pub(crate) mod changes;
+#[cfg(test)]
+pub mod changes_test;
 pub mod clauses;
 #[cfg(test)]
 pub mod clauses_test;
@@ -14,6 +17,9 @@ pub mod query_test;
 pub(crate) mod relation;
 #[cfg(test)]
 pub mod relation_test;
+pub(crate) mod replication;
+#[cfg(test)]
+pub mod replication_test;
 pub(crate) mod tx;
 #[cfg(test)]
 pub mod tx_test;
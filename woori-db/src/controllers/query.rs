@@ -4,9 +4,10 @@ use std::{
 };
 
 use actix_web::{HttpResponse, Responder};
+use chrono::{DateTime, Utc};
 use rayon::prelude::*;
 use uuid::Uuid;
-use wql::{ToSelect, Types, Wql};
+use wql::{ToSelect, Types, When, Wql};
 
 use crate::{
     actors::{
@@ -21,8 +22,9 @@ use crate::{
     },
     model::{
         error::{error_to_http, Error},
-        DataEncryptContext, DataExecutor, DataLocalContext, DataRegister,
+        DataEncryptContext, DataExecutor, DataIndexContext, DataLocalContext, DataRegister,
     },
+    repository::time_index::Until,
     schemas::query::Response as QueryResponse,
 };
 
@@ -34,6 +36,7 @@ use super::{
 pub async fn wql_handler(
     body: String,
     local_data: DataLocalContext,
+    indexes: DataIndexContext,
     encryption: DataEncryptContext,
     actor: DataExecutor,
 ) -> impl Responder {
@@ -57,24 +60,31 @@ pub async fn wql_handler(
         Ok(Wql::SelectIds(entity, ToSelect::Keys(keys), uuids, functions)) => {
             select_keys_with_ids(entity, keys, uuids, local_data, functions).await
         }
-        Ok(Wql::SelectWhen(entity, ToSelect::All, None, date)) => {
-            select_all_when_controller(entity, date, actor).await
+        Ok(Wql::SelectWhen(entity, ToSelect::All, None, when, valid)) => {
+            select_all_when_controller(entity, when, valid, actor).await
         }
-        Ok(Wql::SelectWhen(entity, ToSelect::Keys(keys), None, date)) => {
-            select_keys_when_controller(entity, date, keys, actor).await
+        Ok(Wql::SelectWhen(entity, ToSelect::Keys(keys), None, when, valid)) => {
+            select_keys_when_controller(entity, when, valid, keys, actor).await
         }
-        Ok(Wql::SelectWhen(entity, ToSelect::All, Some(uuid), date)) => {
-            select_all_id_when_controller(entity, date, uuid, actor).await
+        Ok(Wql::SelectWhen(entity, ToSelect::All, Some(uuid), when, valid)) => {
+            select_all_id_when_controller(entity, when, valid, uuid, actor).await
         }
-        Ok(Wql::SelectWhen(entity, ToSelect::Keys(keys), Some(uuid), date)) => {
-            select_keys_id_when_controller(entity, date, keys, uuid, actor).await
+        Ok(Wql::SelectWhen(entity, ToSelect::Keys(keys), Some(uuid), when, valid)) => {
+            select_keys_id_when_controller(entity, when, valid, keys, uuid, actor).await
         }
         Ok(Wql::SelectWhenRange(entity_name, uuid, start_date, end_date)) => {
             select_all_when_range_controller(entity_name, uuid, start_date, end_date, actor).await
         }
         Ok(Wql::SelectWhere(entity_name, args_to_select, clauses, functions)) => {
-            select_where_controller(entity_name, args_to_select, clauses, local_data, functions)
-                .await
+            select_where_controller(
+                entity_name,
+                args_to_select,
+                clauses,
+                local_data,
+                indexes,
+                functions,
+            )
+            .await
         }
         Ok(Wql::CheckValue(entity, uuid, content)) => {
             check_value_controller(entity, uuid, content, local_data, encryption, actor).await
@@ -89,7 +99,7 @@ pub async fn wql_handler(
             union(queries, relation_type, local_data, actor).await
         }
         Ok(Wql::Join(entity_a, entity_b, queries)) => {
-            join(entity_a, entity_b, queries, local_data).await
+            join(entity_a, entity_b, queries, local_data, indexes).await
         }
         Ok(_) => Err(Error::NonSelectQuery),
         Err(e) => Err(Error::QueryFormat(e)),
@@ -163,41 +173,40 @@ async fn select_all_when_range_controller(
     end_date: String,
     actor: DataExecutor,
 ) -> Result<QueryResponse, Error> {
-    use chrono::{DateTime, Utc};
     let start_date: DateTime<Utc> = start_date
         .parse::<DateTime<Utc>>()
         .map_err(Error::DateTimeParse)?;
     let end_date: DateTime<Utc> = end_date
         .parse::<DateTime<Utc>>()
         .map_err(Error::DateTimeParse)?;
-    #[cfg(test)]
-    let date_log = start_date.format("data/%Y_%m_%d.txt").to_string();
-    #[cfg(not(test))]
-    let date_log = start_date.format("data/%Y_%m_%d.log").to_string();
-
     let result = actor
-        .send(ReadEntityRange::new(
-            &entity, uuid, start_date, end_date, date_log,
-        ))
+        .send(ReadEntityRange::new(&entity, uuid, start_date, end_date))
         .await??;
 
     Ok(result.into())
 }
+
+/// Last version a `WHEN AT` query sees, the latest at the date or the one of the
+/// transaction.
+fn when_until(when: When) -> Result<Until, Error> {
+    match when {
+        When::Date(date) => Ok(Until::Time(
+            date.parse::<DateTime<Utc>>()
+                .map_err(Error::DateTimeParse)?,
+        )),
+        When::Tx(tx_seq) => Ok(Until::Tx(tx_seq)),
+    }
+}
+
 async fn select_all_when_controller(
     entity: String,
-    date: String,
+    when: When,
+    valid: Option<DateTime<Utc>>,
     actor: DataExecutor,
 ) -> Result<QueryResponse, Error> {
-    use chrono::{DateTime, Utc};
-    let date = date
-        .parse::<DateTime<Utc>>()
-        .map_err(Error::DateTimeParse)?;
-    #[cfg(test)]
-    let date_log = date.format("data/%Y_%m_%d.txt").to_string();
-    #[cfg(not(test))]
-    let date_log = date.format("data/%Y_%m_%d.log").to_string();
+    let until = when_until(when)?;
     let result = actor
-        .send(ReadEntitiesAt::new(&entity, date_log, None))
+        .send(ReadEntitiesAt::new(&entity, until, valid, None))
         .await??;
 
     Ok(result.into())
@@ -205,20 +214,14 @@ async fn select_all_when_controller(
 
 pub async fn select_all_id_when_controller(
     entity: String,
-    date: String,
+    when: When,
+    valid: Option<DateTime<Utc>>,
     uuid: Uuid,
     actor: DataExecutor,
 ) -> Result<QueryResponse, Error> {
-    use chrono::{DateTime, Utc};
-    let date = date
-        .parse::<DateTime<Utc>>()
-        .map_err(Error::DateTimeParse)?;
-    #[cfg(test)]
-    let date_log = date.format("data/%Y_%m_%d.txt").to_string();
-    #[cfg(not(test))]
-    let date_log = date.format("data/%Y_%m_%d.log").to_string();
+    let until = when_until(when)?;
     let result = actor
-        .send(ReadEntityIdAt::new(&entity, uuid, date_log))
+        .send(ReadEntityIdAt::new(&entity, uuid, until, valid))
         .await??;
     let result = filter_keys_and_hash(result, None);
     Ok(result.into())
@@ -226,22 +229,16 @@ pub async fn select_all_id_when_controller(
 
 pub async fn select_keys_id_when_controller(
     entity: String,
-    date: String,
+    when: When,
+    valid: Option<DateTime<Utc>>,
     keys: Vec<String>,
     uuid: Uuid,
     actor: DataExecutor,
 ) -> Result<QueryResponse, Error> {
-    use chrono::{DateTime, Utc};
     let keys = keys.into_par_iter().collect::<HashSet<String>>();
-    let date = date
-        .parse::<DateTime<Utc>>()
-        .map_err(Error::DateTimeParse)?;
-    #[cfg(test)]
-    let date_log = date.format("data/%Y_%m_%d.txt").to_string();
-    #[cfg(not(test))]
-    let date_log = date.format("data/%Y_%m_%d.log").to_string();
+    let until = when_until(when)?;
     let result = actor
-        .send(ReadEntityIdAt::new(&entity, uuid, date_log))
+        .send(ReadEntityIdAt::new(&entity, uuid, until, valid))
         .await??;
     let result = filter_keys_and_hash(result, Some(keys));
 
@@ -250,22 +247,15 @@ pub async fn select_keys_id_when_controller(
 
 async fn select_keys_when_controller(
     entity: String,
-    date: String,
+    when: When,
+    valid: Option<DateTime<Utc>>,
     keys: Vec<String>,
     actor: DataExecutor,
 ) -> Result<QueryResponse, Error> {
-    use chrono::{DateTime, Utc};
     let keys = keys.into_par_iter().collect::<HashSet<String>>();
-    let date = date
-        .parse::<DateTime<Utc>>()
-        .map_err(Error::DateTimeParse)?;
-
-    #[cfg(test)]
-    let date_log = date.format("data/%Y_%m_%d.txt").to_string();
-    #[cfg(not(test))]
-    let date_log = date.format("data/%Y_%m_%d.log").to_string();
+    let until = when_until(when)?;
     let result = actor
-        .send(ReadEntitiesAt::new(&entity, date_log, Some(keys)))
+        .send(ReadEntitiesAt::new(&entity, until, valid, Some(keys)))
         .await??;
 
     Ok(result.into())
@@ -1,8 +1,8 @@
 use crate::{http::routes, schemas::tx::TxResponse};
 use actix_http::body::ResponseBody;
 use actix_web::{body::Body, test, App};
+use chrono::Utc;
 
-#[ignore]
 #[actix_rt::test]
 async fn test_select_all_id_post_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -67,7 +67,6 @@ async fn test_select_all_id_post_ok() {
     assert!(body.contains("\"c\": Nil"));
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_select_args_id_post_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -126,7 +125,6 @@ async fn test_create_on_query_endpoint() {
     );
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_select_all_post_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -181,7 +179,6 @@ async fn test_select_all_post_ok() {
     assert!(body.contains("Integer(123)"));
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_select_keys_post_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -236,7 +233,6 @@ async fn test_select_keys_post_ok() {
     assert!(!body.contains("Integer(123)"));
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_select_all_ids_post_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -303,7 +299,6 @@ async fn test_select_all_ids_post_ok() {
     assert!(body.contains(&uuid3.to_string()));
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_select_keys_ids_post_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -370,7 +365,6 @@ async fn test_select_keys_ids_post_ok() {
     assert!(body.contains(&uuid3.to_string()));
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_select_all_without_encrypts_post_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -415,10 +409,32 @@ async fn test_select_all_without_encrypts_post_ok() {
 #[actix_rt::test]
 async fn test_select_when_all_post_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("CREATE ENTITY test_select_when_all")
+        .uri("/wql/tx")
+        .to_request();
+
+    let _ = test::call_service(&mut app, req).await;
+    let before = Utc::now();
+
+    let mut uuids = Vec::new();
+    for _ in 0..2 {
+        let req = test::TestRequest::post()
+            .header("Content-Type", "application/wql")
+            .set_payload("INSERT {a: 123,} INTO test_select_when_all")
+            .uri("/wql/tx")
+            .to_request();
+
+        let mut resp = test::call_service(&mut app, req).await;
+        let body = resp.take_body().as_str().to_string();
+        let response: TxResponse = ron::de::from_str(&body).unwrap();
+        uuids.push(response.uuid.unwrap());
+    }
 
     let payload = format!(
-        "Select * FROM test_ok WHEN AT {}",
-        "2021-01-08T12:00:00+03:00"
+        "Select * FROM test_select_when_all WHEN AT {}",
+        Utc::now().to_rfc3339()
     );
     let req = test::TestRequest::post()
         .header("Content-Type", "application/wql")
@@ -432,17 +448,53 @@ async fn test_select_when_all_post_ok() {
     let body = resp.take_body().as_str().to_string();
 
     assert!(body.contains("\"a\": Integer(123)"));
-    assert!(body.contains("30d2b740-e791-4ff6-8471-215d38b1ff5c"));
-    assert!(body.contains("bcab53d9-1ef0-4eb3-9b99-f00259d8725b"));
+    assert!(body.contains(&uuids[0].to_string()));
+    assert!(body.contains(&uuids[1].to_string()));
+
+    let payload = format!(
+        "Select * FROM test_select_when_all WHEN AT {}",
+        before.to_rfc3339()
+    );
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload(payload)
+        .uri("/wql/query")
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success());
+    let body = resp.take_body().as_str().to_string();
+
+    assert!(!body.contains(&uuids[0].to_string()));
+    assert!(!body.contains(&uuids[1].to_string()));
 }
 
 #[actix_rt::test]
 async fn test_select_when_args_post_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("CREATE ENTITY test_select_when_args")
+        .uri("/wql/tx")
+        .to_request();
+
+    let _ = test::call_service(&mut app, req).await;
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("INSERT {a: 123, g: Nil,} INTO test_select_when_args")
+        .uri("/wql/tx")
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+    let body = resp.take_body().as_str().to_string();
+    let response: TxResponse = ron::de::from_str(&body).unwrap();
+    let uuid = response.uuid.unwrap();
 
     let payload = format!(
-        "Select #{{g,}} FROM test_update WHEN AT {}",
-        "2021-01-08T12:00:00+03:00"
+        "Select #{{g,}} FROM test_select_when_args WHEN AT {}",
+        Utc::now().to_rfc3339()
     );
     let req = test::TestRequest::post()
         .header("Content-Type", "application/wql")
@@ -457,17 +509,35 @@ async fn test_select_when_args_post_ok() {
 
     assert!(!body.contains("\"a\": Integer(123)"));
     assert!(body.contains("\"g\": Nil"));
-    assert!(body.contains("0a1b16ed-886c-4c99-97c9-0b977778ec13"));
-    assert!(body.contains("41ede07f-e98b-41dd-9ff2-8dce99af4e96"));
+    assert!(body.contains(&uuid.to_string()));
 }
 
 #[actix_rt::test]
 async fn test_select_when_args_id_post_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("CREATE ENTITY test_select_when_args_id")
+        .uri("/wql/tx")
+        .to_request();
+
+    let _ = test::call_service(&mut app, req).await;
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("INSERT {a: 123, g: Nil,} INTO test_select_when_args_id")
+        .uri("/wql/tx")
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+    let body = resp.take_body().as_str().to_string();
+    let response: TxResponse = ron::de::from_str(&body).unwrap();
+    let uuid = response.uuid.unwrap();
 
     let payload = format!(
-        "Select #{{g,}} FROM test_update ID 0a1b16ed-886c-4c99-97c9-0b977778ec13 WHEN AT {}",
-        "2021-01-08T12:00:00+03:00"
+        "Select #{{g,}} FROM test_select_when_args_id ID {} WHEN AT {}",
+        uuid,
+        Utc::now().to_rfc3339()
     );
     let req = test::TestRequest::post()
         .header("Content-Type", "application/wql")
@@ -482,17 +552,47 @@ async fn test_select_when_args_id_post_ok() {
 
     assert!(!body.contains("\"a\": Integer(123)"));
     assert!(body.contains("\"g\": Nil"));
-    assert!(!body.contains("0a1b16ed-886c-4c99-97c9-0b977778ec13"));
-    assert!(!body.contains("41ede07f-e98b-41dd-9ff2-8dce99af4e96"));
+    assert!(!body.contains(&uuid.to_string()));
 }
 
 #[actix_rt::test]
 async fn test_select_when_all_id_post_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("CREATE ENTITY test_select_when_all_id")
+        .uri("/wql/tx")
+        .to_request();
+
+    let _ = test::call_service(&mut app, req).await;
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("INSERT {a: 123, g: Nil,} INTO test_select_when_all_id")
+        .uri("/wql/tx")
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+    let body = resp.take_body().as_str().to_string();
+    let response: TxResponse = ron::de::from_str(&body).unwrap();
+    let uuid = response.uuid.unwrap();
+    let inserted = Utc::now();
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload(format!(
+            "UPDATE test_select_when_all_id SET {{a: 321,}} INTO {}",
+            uuid
+        ))
+        .uri("/wql/tx")
+        .to_request();
+
+    let _ = test::call_service(&mut app, req).await;
 
     let payload = format!(
-        "Select * FROM test_update ID 0a1b16ed-886c-4c99-97c9-0b977778ec13 WHEN AT {}",
-        "2021-01-08T12:00:00+03:00"
+        "Select * FROM test_select_when_all_id ID {} WHEN AT {}",
+        uuid,
+        inserted.to_rfc3339()
     );
     let req = test::TestRequest::post()
         .header("Content-Type", "application/wql")
@@ -507,17 +607,68 @@ async fn test_select_when_all_id_post_ok() {
 
     assert!(body.contains("\"a\": Integer(123)"));
     assert!(body.contains("\"g\": Nil"));
-    assert!(!body.contains("0a1b16ed-886c-4c99-97c9-0b977778ec13"));
-    assert!(!body.contains("41ede07f-e98b-41dd-9ff2-8dce99af4e96"));
+    assert!(!body.contains(&uuid.to_string()));
+
+    let payload = format!(
+        "Select * FROM test_select_when_all_id ID {} WHEN AT {}",
+        uuid,
+        Utc::now().to_rfc3339()
+    );
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload(payload)
+        .uri("/wql/query")
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success());
+    let body = resp.take_body().as_str().to_string();
+
+    assert!(body.contains("\"a\": Integer(321)"));
 }
 
 #[actix_rt::test]
 async fn test_select_when_range_all_post_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("CREATE ENTITY test_select_when_range")
+        .uri("/wql/tx")
+        .to_request();
+
+    let _ = test::call_service(&mut app, req).await;
+    let start = Utc::now();
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("INSERT {f: \"hello\",} INTO test_select_when_range")
+        .uri("/wql/tx")
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+    let body = resp.take_body().as_str().to_string();
+    let response: TxResponse = ron::de::from_str(&body).unwrap();
+    let uuid = response.uuid.unwrap();
+
+    for f in &["helloworld", "JULIA"] {
+        let req = test::TestRequest::post()
+            .header("Content-Type", "application/wql")
+            .set_payload(format!(
+                "UPDATE test_select_when_range SET {{f: \"{}\",}} INTO {}",
+                f, uuid
+            ))
+            .uri("/wql/tx")
+            .to_request();
+
+        let _ = test::call_service(&mut app, req).await;
+    }
 
     let payload = format!(
-        "Select * FROM test_update ID fb1ccddb-2465-4504-a4a4-e28ee75c7981 WHEN START {} END {}",
-        "2021-02-09T16:30:00Z", "2021-02-09T17:00:00Z"
+        "Select * FROM test_select_when_range ID {} WHEN START {} END {}",
+        uuid,
+        start.to_rfc3339(),
+        Utc::now().to_rfc3339()
     );
     let req = test::TestRequest::post()
         .header("Content-Type", "application/wql")
@@ -530,15 +681,245 @@ async fn test_select_when_range_all_post_ok() {
     assert!(resp.status().is_success());
     let body = resp.take_body().as_str().to_string();
 
-    assert!(body.contains("{\n \"2021-02-09T16:44:03.236333Z\":"));
+    assert_eq!(body.matches("\"f\": ").count(), 3);
     assert!(body.contains("\"f\": String(\"hello\")"));
-    assert!(body.contains("\"2021-02-09T16:54:06.237774Z\":"));
     assert!(body.contains("\"f\": String(\"helloworld\")"));
-    assert!(body.contains("\"2021-02-09T16:57:06.237774Z\":"));
     assert!(body.contains("\"f\": String(\"JULIA\")"));
+
+    let payload = format!(
+        "Select * FROM test_select_when_range ID {} WHEN START {} END {}",
+        uuid,
+        Utc::now().to_rfc3339(),
+        (Utc::now() + chrono::Duration::days(2)).to_rfc3339()
+    );
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload(payload)
+        .uri("/wql/query")
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success());
+    let body = resp.take_body().as_str().to_string();
+
+    assert!(!body.contains("\"f\": "));
+}
+
+#[actix_rt::test]
+async fn test_select_when_tx_post_ok() {
+    let mut app = test::init_service(App::new().configure(routes)).await;
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("CREATE ENTITY test_select_when_tx")
+        .uri("/wql/tx")
+        .to_request();
+
+    let _ = test::call_service(&mut app, req).await;
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("INSERT {a: 1,} INTO test_select_when_tx")
+        .uri("/wql/tx")
+        .to_request();
+
+    let mut resp_insert = test::call_service(&mut app, req).await;
+    let body = resp_insert.take_body().as_str().to_string();
+    let response: TxResponse = ron::de::from_str(&body).unwrap();
+    let uuid = response.uuid.unwrap();
+
+    let mut tx_seqs = Vec::new();
+    for a in 2..4 {
+        let req = test::TestRequest::post()
+            .header("Content-Type", "application/wql")
+            .set_payload(format!(
+                "UPDATE test_select_when_tx SET {{a: {},}} INTO {}",
+                a, uuid
+            ))
+            .uri("/wql/tx")
+            .to_request();
+
+        let mut resp = test::call_service(&mut app, req).await;
+        let body = resp.take_body().as_str().to_string();
+        let response: TxResponse = ron::de::from_str(&body).unwrap();
+        tx_seqs.push(response.tx_seq);
+    }
+    assert!(tx_seqs[0] < tx_seqs[1]);
+
+    let payload = format!(
+        "Select * FROM test_select_when_tx ID {} WHEN AT TX {}",
+        uuid, tx_seqs[0]
+    );
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload(payload)
+        .uri("/wql/query")
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success());
+    let body = resp.take_body().as_str().to_string();
+    assert!(body.contains("\"a\": Integer(2)"));
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("Select * FROM test_select_when_tx WHEN AT TX 18446744073709551615")
+        .uri("/wql/query")
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_client_error());
+    let body = resp.take_body().as_str().to_string();
+    assert_eq!(body, "(\n error_type: \"TxNotCommitted\",\n error_message: \"Transaction 18446744073709551615 was not committed\",\n)");
+}
+
+#[actix_rt::test]
+async fn test_select_when_tx_after_delete_and_evict() {
+    let mut app = test::init_service(App::new().configure(routes)).await;
+    let tx = |payload: String| {
+        test::TestRequest::post()
+            .header("Content-Type", "application/wql")
+            .set_payload(payload)
+            .uri("/wql/tx")
+            .to_request()
+    };
+    let query = |payload: String| {
+        test::TestRequest::post()
+            .header("Content-Type", "application/wql")
+            .set_payload(payload)
+            .uri("/wql/query")
+            .to_request()
+    };
+
+    let _ = test::call_service(&mut app, tx("CREATE ENTITY test_when_deleted".to_owned())).await;
+    let mut resp = test::call_service(
+        &mut app,
+        tx("INSERT {a: 1,} INTO test_when_deleted".to_owned()),
+    )
+    .await;
+    let body = resp.take_body().as_str().to_string();
+    let uuid = ron::de::from_str::<TxResponse>(&body)
+        .unwrap()
+        .uuid
+        .unwrap();
+
+    let mut tx_seqs = Vec::new();
+    for payload in &[
+        format!("UPDATE test_when_deleted SET {{a: 2,}} INTO {}", uuid),
+        format!("DELETE {} FROM test_when_deleted", uuid),
+        format!("EVICT {} FROM test_when_deleted", uuid),
+    ] {
+        let mut resp = test::call_service(&mut app, tx(payload.to_owned())).await;
+        assert!(resp.status().is_success());
+        let body = resp.take_body().as_str().to_string();
+        tx_seqs.push(ron::de::from_str::<TxResponse>(&body).unwrap().tx_seq);
+    }
+
+    let when_at = |tx_seq: u64| {
+        query(format!(
+            "Select * FROM test_when_deleted ID {} WHEN AT TX {}",
+            uuid, tx_seq
+        ))
+    };
+    let mut resp = test::call_service(&mut app, when_at(tx_seqs[0])).await;
+    assert!(resp.take_body().as_str().contains("\"a\": Integer(2)"));
+
+    let mut resp = test::call_service(&mut app, when_at(tx_seqs[1])).await;
+    let body = resp.take_body().as_str().to_string();
+    assert!(body.contains("\"a\": Integer(1)"));
+    assert!(!body.contains("Integer(2)"));
+
+    let mut resp = test::call_service(&mut app, when_at(tx_seqs[2])).await;
+    assert!(resp.status().is_success());
+    assert!(!resp.take_body().as_str().contains("\"a\""));
+
+    let mut resp = test::call_service(
+        &mut app,
+        query(format!(
+            "Select * FROM test_when_deleted WHEN AT TX {}",
+            tx_seqs[2]
+        )),
+    )
+    .await;
+    assert!(!resp.take_body().as_str().contains(&uuid.to_string()));
+}
+
+#[actix_rt::test]
+async fn test_select_when_as_of_valid_post_ok() {
+    let mut app = test::init_service(App::new().configure(routes)).await;
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("CREATE ENTITY test_select_valid")
+        .uri("/wql/tx")
+        .to_request();
+
+    let _ = test::call_service(&mut app, req).await;
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("INSERT {price: 10,} INTO test_select_valid")
+        .uri("/wql/tx")
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+    let body = resp.take_body().as_str().to_string();
+    let response: TxResponse = ron::de::from_str(&body).unwrap();
+    let uuid = response.uuid.unwrap();
+
+    let monday = Utc::now() + chrono::Duration::days(3);
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload(format!(
+            "UPDATE test_select_valid SET {{price: 12,}} INTO {} VALID FROM {}",
+            uuid,
+            monday.to_rfc3339()
+        ))
+        .uri("/wql/tx")
+        .to_request();
+
+    let resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_success());
+
+    for (valid, price) in &[
+        (Utc::now() + chrono::Duration::days(1), "Integer(10)"),
+        (monday + chrono::Duration::days(1), "Integer(12)"),
+    ] {
+        let payload = format!(
+            "Select * FROM test_select_valid ID {} WHEN AT {} AS OF VALID {}",
+            uuid,
+            Utc::now().to_rfc3339(),
+            valid.to_rfc3339()
+        );
+        let req = test::TestRequest::post()
+            .header("Content-Type", "application/wql")
+            .set_payload(payload)
+            .uri("/wql/query")
+            .to_request();
+
+        let mut resp = test::call_service(&mut app, req).await;
+
+        assert!(resp.status().is_success());
+        let body = resp.take_body().as_str().to_string();
+        assert!(body.contains(&format!("\"price\": {}", price)));
+    }
+
+    let payload = format!("Select * FROM test_select_valid ID {}", uuid);
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload(payload)
+        .uri("/wql/query")
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success());
+    let body = resp.take_body().as_str().to_string();
+    assert!(body.contains("\"price\": Integer(12)"));
+    assert!(body.contains("\"valid_from\": DateTime("));
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_check_encrypt_post_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -3,7 +3,7 @@ use std::collections::HashMap;
 use wql::{RelationType, ToSelect, Wql};
 
 use crate::{
-    model::{error::Error, DataExecutor, DataLocalContext},
+    model::{error::Error, DataExecutor, DataIndexContext, DataLocalContext},
     schemas::query::Response,
 };
 
@@ -129,10 +129,11 @@ pub async fn join(
     entity_b: (String, String),
     queries: Vec<Wql>,
     local_data: DataLocalContext,
+    indexes: DataIndexContext,
 ) -> Result<Response, Error> {
     let mut result = Vec::new();
-    let a = get_join_query_value(queries[0].clone(), local_data.clone()).await?;
-    let b = get_join_query_value(queries[1].clone(), local_data).await?;
+    let a = get_join_query_value(queries[0].clone(), local_data.clone(), indexes.clone()).await?;
+    let b = get_join_query_value(queries[1].clone(), local_data, indexes).await?;
 
     let b_hash = b
         .hash(&entity_b.1)
@@ -158,17 +159,21 @@ async fn get_query_value(
         Wql::Select(entity, ToSelect::Keys(keys), Some(uuid), _) => {
             select_keys_with_id(entity, uuid, keys, local_data).await
         }
-        Wql::SelectWhen(entity, ToSelect::All, Some(uuid), date) => {
-            select_all_id_when_controller(entity, date, uuid, actor).await
+        Wql::SelectWhen(entity, ToSelect::All, Some(uuid), when, valid) => {
+            select_all_id_when_controller(entity, when, valid, uuid, actor).await
         }
-        Wql::SelectWhen(entity, ToSelect::Keys(keys), Some(uuid), date) => {
-            select_keys_id_when_controller(entity, date, keys, uuid, actor).await
+        Wql::SelectWhen(entity, ToSelect::Keys(keys), Some(uuid), when, valid) => {
+            select_keys_id_when_controller(entity, when, valid, keys, uuid, actor).await
         }
         _ => Err(Error::QueryFormat(String::from(ERROR))),
     }
 }
 
-async fn get_join_query_value(query: Wql, local_data: DataLocalContext) -> Result<Response, Error> {
+async fn get_join_query_value(
+    query: Wql,
+    local_data: DataLocalContext,
+    indexes: DataIndexContext,
+) -> Result<Response, Error> {
     match query {
         Wql::Select(entity, ToSelect::All, None, functions) => {
             select_all(entity, local_data, functions).await
@@ -183,8 +188,15 @@ async fn get_join_query_value(query: Wql, local_data: DataLocalContext) -> Resul
             select_keys_with_ids(entity, keys, uuids, local_data, functions).await
         }
         Wql::SelectWhere(entity_name, args_to_select, clauses, functions) => {
-            select_where_controller(entity_name, args_to_select, clauses, local_data, functions)
-                .await
+            select_where_controller(
+                entity_name,
+                args_to_select,
+                clauses,
+                local_data,
+                indexes,
+                functions,
+            )
+            .await
         }
         _ => Err(Error::QueryFormat(String::from(ERROR_JOIN))),
     }
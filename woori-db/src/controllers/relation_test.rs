@@ -378,7 +378,6 @@ async fn test_union_key_value() {
     clear();
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_join() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -409,7 +408,6 @@ async fn test_join() {
     assert!(!body.contains("\"tx_time:entity_B\""));
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_join2() {
     let mut app = test::init_service(App::new().configure(routes)).await;
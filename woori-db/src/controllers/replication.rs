@@ -0,0 +1,36 @@
+use crate::model::error::error_to_http;
+use crate::{
+    actors::replication::{ReadLogChunk, LOG_HEADER, NEXT_LOG_HEADER},
+    model::DataExecutor,
+};
+
+use actix_web::{web, HttpResponse, Responder};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LogPosition {
+    /// Day log to read, the first one when unset.
+    pub log: Option<String>,
+    #[serde(default)]
+    pub offset: usize,
+}
+
+/// Streams the bytes of a day log from a position to the followers.
+pub async fn log_handler(position: web::Query<LogPosition>, actor: DataExecutor) -> impl Responder {
+    let LogPosition { log, offset } = position.into_inner();
+    let chunk = match actor.send(ReadLogChunk { log, offset }).await {
+        Ok(Ok(chunk)) => chunk,
+        Ok(Err(e)) => return error_to_http(&e),
+        Err(e) => return error_to_http(&e.into()),
+    };
+
+    let mut response = HttpResponse::Ok();
+    response.content_type("application/octet-stream");
+    if let Some(log) = chunk.log {
+        response.header(LOG_HEADER, log);
+    }
+    if let Some(next_log) = chunk.next_log {
+        response.header(NEXT_LOG_HEADER, next_log);
+    }
+    response.body(chunk.bytes)
+}
@@ -0,0 +1,279 @@
+use crate::{
+    actors::{
+        replication::{Follower, FollowerContext, LogChunk, ReadLogChunk, CHUNK_SIZE, LOG_HEADER},
+        wql::Executor,
+    },
+    http::routes,
+    io::{
+        record::encode,
+        storage::{MemoryStorage, Storage},
+    },
+    model::{error::Error, log::LogRecord, wql::Action},
+    repository::{index::IndexContext, local::LocalContext},
+    schemas::tx::TxResponse,
+};
+use actix::Actor;
+use actix_http::body::ResponseBody;
+use actix_rt::time::delay_for;
+use actix_web::{body::Body, client::Client, test, App};
+use chrono::Utc;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::broadcast;
+
+#[actix_rt::test]
+async fn test_replicate_log_ok() {
+    let mut app = test::init_service(App::new().configure(routes)).await;
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("CREATE ENTITY replicated")
+        .uri("/wql/tx")
+        .to_request();
+
+    let _ = test::call_service(&mut app, req).await;
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("INSERT {a: 1,} INTO replicated")
+        .uri("/wql/tx")
+        .to_request();
+
+    let mut resp_insert = test::call_service(&mut app, req).await;
+    let body = resp_insert.take_body().as_str().to_string();
+    let insert: TxResponse = ron::de::from_str(&body).unwrap();
+    let uuid = insert.uuid.unwrap();
+
+    let log = Utc::now().format("%Y_%m_%d.log").to_string();
+    let req = test::TestRequest::get()
+        .uri(&format!("/replication/log?log={}&offset=0", log))
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_success());
+    assert_eq!(resp.headers().get(LOG_HEADER).unwrap(), log.as_str());
+    let bytes = resp.take_body().as_bytes().to_vec();
+    let records = crate::io::record::Records::<LogRecord>::new(&bytes)
+        .filter_map(|(_, record)| record.ok())
+        .collect::<Vec<_>>();
+    assert!(records.iter().any(|record| record.uuid == Some(uuid)));
+
+    let storage = Arc::new(MemoryStorage::new());
+    let local_data = Arc::new(Mutex::new(Default::default()));
+    let context = FollowerContext {
+        local_data: local_data.clone(),
+        uniqueness: Arc::new(Mutex::new(Default::default())),
+        encryption: Arc::new(Mutex::new(Default::default())),
+        indexes: Arc::new(Mutex::new(IndexContext::default())),
+    };
+    let executor = Executor::new(storage.clone()).start();
+    let mut follower = Follower::new(
+        "http://localhost:1438",
+        None,
+        Duration::from_millis(500),
+        storage.clone(),
+        executor,
+        context,
+    )
+    .unwrap();
+
+    let replicated = follower
+        .replicate(LogChunk {
+            log: Some(log.clone()),
+            next_log: None,
+            bytes: bytes.clone(),
+        })
+        .unwrap();
+    assert!(replicated);
+    assert_eq!(storage.read_log(&log).unwrap(), bytes);
+    assert!(local_data.lock().unwrap()["replicated"].contains_key(&uuid));
+
+    let replicated = follower
+        .replicate(LogChunk {
+            log: Some(log),
+            next_log: None,
+            bytes: Vec::new(),
+        })
+        .unwrap();
+    assert!(!replicated);
+    clear();
+}
+
+#[actix_rt::test]
+async fn test_replicate_missing_log_is_empty() {
+    let mut app = test::init_service(App::new().configure(routes)).await;
+    let req = test::TestRequest::get()
+        .uri("/replication/log?log=1999_01_01.log&offset=0")
+        .to_request();
+
+    let mut resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_success());
+    assert!(resp.take_body().as_bytes().is_empty());
+}
+
+#[actix_rt::test]
+async fn test_read_log_chunk_ok() {
+    let storage = Arc::new(MemoryStorage::new());
+    let record = |content: String| {
+        encode(&LogRecord {
+            content: Some(content),
+            ..LogRecord::new(Action::Insert, "chunks")
+        })
+        .unwrap()
+    };
+    let small = record("small".to_string());
+    let large = record("l".repeat(CHUNK_SIZE));
+    storage.append_log("2021_01_01.log", &small).unwrap();
+    storage.append_log("2021_01_01.log", &large).unwrap();
+    storage.append_log("2021_01_01.log", &small).unwrap();
+    let executor = Executor::new(storage).start();
+    let read = |offset| {
+        executor.send(ReadLogChunk {
+            log: Some("2021_01_01.log".to_string()),
+            offset,
+        })
+    };
+
+    let chunk = read(0).await.unwrap().unwrap();
+    assert_eq!(chunk.bytes, small);
+    let chunk = read(small.len()).await.unwrap().unwrap();
+    assert_eq!(chunk.bytes, large);
+    let chunk = read(small.len() + large.len()).await.unwrap().unwrap();
+    assert_eq!(chunk.bytes, small);
+    let chunk = read(2 * small.len() + large.len()).await.unwrap().unwrap();
+    assert!(chunk.bytes.is_empty());
+}
+
+#[actix_rt::test]
+async fn test_pending_commit_is_not_read() {
+    let storage = Arc::new(MemoryStorage::new());
+    let record = |tx_seq| {
+        encode(&LogRecord {
+            tx_seq: Some(tx_seq),
+            ..LogRecord::new(Action::Insert, "pending")
+        })
+        .unwrap()
+    };
+    let (committed, failed) = (record(1), record(2));
+    storage.append_log("2021_01_01.log", &committed).unwrap();
+    storage.append_log("2021_01_01.log", &failed).unwrap();
+    storage.append_log("2021_01_02.log", &record(3)).unwrap();
+    let executor = Executor::new(storage.clone());
+    let commits = executor.commits.clone();
+    let (id, _outcome) =
+        commits
+            .lock()
+            .unwrap()
+            .append(2, "2021_01_01.log", committed.len(), Vec::new());
+    let executor = executor.start();
+    let read = |offset| {
+        executor.send(ReadLogChunk {
+            log: Some("2021_01_01.log".to_string()),
+            offset,
+        })
+    };
+
+    let chunk = read(0).await.unwrap().unwrap();
+    assert_eq!(chunk.bytes, committed);
+    assert!(chunk.next_log.is_none());
+    let chunk = read(committed.len()).await.unwrap().unwrap();
+    assert!(chunk.bytes.is_empty());
+    assert!(chunk.next_log.is_none());
+
+    commits.lock().unwrap().resolve(
+        &*storage,
+        &broadcast::channel(1).0,
+        id,
+        Err(Error::LockData),
+    );
+    let chunk = read(0).await.unwrap().unwrap();
+    assert_eq!(chunk.bytes, committed);
+    let chunk = read(committed.len()).await.unwrap().unwrap();
+    assert!(chunk.bytes.is_empty());
+    assert_eq!(chunk.next_log.as_deref(), Some("2021_01_02.log"));
+}
+
+#[actix_rt::test]
+async fn test_follower_polls_the_leader() {
+    let leader = test::start(|| App::new().configure(routes));
+    let client = Client::new();
+    let _ = client
+        .post(leader.url("/wql/tx"))
+        .header("Content-Type", "application/wql")
+        .send_body("CREATE ENTITY polled")
+        .await
+        .unwrap();
+    let body = client
+        .post(leader.url("/wql/tx"))
+        .header("Content-Type", "application/wql")
+        .send_body("INSERT {a: 1,} INTO polled")
+        .await
+        .unwrap()
+        .body()
+        .await
+        .unwrap();
+    let insert: TxResponse = ron::de::from_str(std::str::from_utf8(&body).unwrap()).unwrap();
+    let uuid = insert.uuid.unwrap();
+
+    let storage = Arc::new(MemoryStorage::new());
+    let local_data = Arc::new(Mutex::new(LocalContext::new()));
+    let context = FollowerContext {
+        local_data: local_data.clone(),
+        uniqueness: Arc::new(Mutex::new(Default::default())),
+        encryption: Arc::new(Mutex::new(Default::default())),
+        indexes: Arc::new(Mutex::new(IndexContext::default())),
+    };
+    let executor = Executor::new(storage.clone()).start();
+    let _follower = Follower::new(
+        &leader.url("/"),
+        None,
+        Duration::from_millis(10),
+        storage,
+        executor,
+        context,
+    )
+    .unwrap()
+    .start();
+
+    let replicated = || {
+        local_data
+            .lock()
+            .unwrap()
+            .get("polled")
+            .is_some_and(|ids| ids.contains_key(&uuid))
+    };
+    for _ in 0..500 {
+        if replicated() {
+            break;
+        }
+        delay_for(Duration::from_millis(10)).await;
+    }
+    assert!(replicated());
+}
+
+trait BodyTest {
+    fn as_str(&self) -> &str;
+    fn as_bytes(&self) -> &[u8];
+}
+
+impl BodyTest for ResponseBody<Body> {
+    fn as_str(&self) -> &str {
+        std::str::from_utf8(self.as_bytes()).unwrap()
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        match self {
+            ResponseBody::Body(Body::Bytes(by)) | ResponseBody::Other(Body::Bytes(by)) => by,
+            _ => panic!(),
+        }
+    }
+}
+
+pub fn clear() {
+    std::process::Command::new("rm")
+        .arg("-rf")
+        .arg("data/*.log")
+        .output()
+        .expect("failed to execute process");
+}
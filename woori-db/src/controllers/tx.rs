@@ -1,34 +1,37 @@
-use crate::core::tx_time;
-use crate::schemas::tx::{TxResponse, TxType};
+use crate::core::{next_tx_version, set_valid_time, tx_time, tx_version, TX_VERSION};
+use crate::schemas::tx::{TransactionResponse, TxResponse, TxType};
 use crate::{
     actors::{
         encrypts::{CreateWithEncryption, EncryptContent, WriteWithEncryption},
         recovery::{LocalData, OffsetCounter},
         state::{MatchUpdate, PreviousRegistry, State},
         uniques::{CreateWithUniqueKeys, WriteWithUniqueKeys},
-        wql::{DeleteId, InsertEntityContent, UpdateContentEntityContent, UpdateSetEntityContent},
+        wql::{
+            AbortBatch, BeginBatch, CommitBatch, DeleteId, IndexData, InsertEntityContent,
+            UpdateContentEntityContent, UpdateSetEntityContent,
+        },
     },
     core::{pretty_config_inner, wql::update_content_state},
     model::{
         error::error_to_http,
         wql::{InsertArgs, MatchUpdateArgs, UpdateArgs},
-        DataAtomicUsize, DataEncryptContext, DataExecutor, DataLocalContext, DataU32,
-        DataUniquenessContext,
+        DataAtomicUsize, DataEncryptContext, DataExecutor, DataIdLocks, DataIndexContext,
+        DataLocalContext, DataTxGate, DataU32, DataUniquenessContext,
     },
 };
 use crate::{
     actors::{
         uniques::CheckForUniqueKeys,
-        wql::{CreateEntity, EvictEntity, EvictEntityId},
+        wql::{CreateEntity, CreateIndex, EvictEntity, EvictEntityId},
     },
-    schemas::tx::CreateEntityResponse,
+    schemas::tx::{CreateEntityResponse, CreateIndexResponse},
 };
 use crate::{
     model::{error::Error, DataRegister},
     schemas::tx::InsertEntityResponse,
 };
 use crate::{
-    repository::local::LocalContext,
+    repository::local::{LocalContext, LocalDelta, UniquenessContext},
     schemas::tx::{DeleteOrEvictEntityResponse, UpdateEntityResponse},
 };
 
@@ -36,36 +39,134 @@ use actix_web::{HttpResponse, Responder};
 use rayon::prelude::*;
 use ron::ser::to_string_pretty;
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{HashMap, HashSet},
     str::FromStr,
     sync::{atomic::Ordering, Arc, Mutex},
 };
 use uuid::Uuid;
 use wql::{Types, Wql};
 
+#[allow(clippy::too_many_arguments)]
 pub async fn wql_handler(
     body: String,
     local_data: DataLocalContext,
+    indexes: DataIndexContext,
     uniqueness: DataUniquenessContext,
     encryption: DataEncryptContext,
     bytes_counter: DataAtomicUsize,
     hashing_cost: DataU32,
     actor: DataExecutor,
+    tx_gate: DataTxGate,
+    id_locks: DataIdLocks,
 ) -> impl Responder {
+    let config = crate::config::config();
+    if config.read_only || config.leader.is_some() {
+        return error_to_http(&Error::ReadOnly);
+    }
     let query = wql::Wql::from_str(&body);
+    if let Ok(Wql::Transaction(statements)) = query {
+        let _tx = tx_gate.write().await;
+        let response = transaction_controller(
+            statements,
+            local_data,
+            indexes,
+            uniqueness,
+            encryption,
+            bytes_counter,
+            hashing_cost,
+            actor,
+            id_locks,
+        )
+        .await;
+        return match response {
+            Err(e) => error_to_http(&e),
+            Ok(resp) => HttpResponse::Ok().body(resp.write()),
+        };
+    }
+
+    let _tx = tx_gate.read().await;
     let response = match query {
-        Ok(Wql::CreateEntity(entity, uniques, encrypts)) => {
-            let _ = create_unique_controller(&entity, uniques, uniqueness, &actor).await;
-            let _ = create_encrypts_controller(&entity, encrypts, encryption, &actor).await;
-            create_controller(entity, local_data.into_inner(), bytes_counter, actor).await
+        Ok(query) => {
+            statement_controller(
+                query,
+                local_data,
+                indexes,
+                uniqueness,
+                encryption,
+                bytes_counter,
+                hashing_cost,
+                actor,
+                id_locks,
+            )
+            .await
         }
-        Ok(Wql::Delete(entity, uuid)) => {
-            delete_controller(entity, uuid, local_data.into_inner(), bytes_counter, actor).await
+        Err(e) => Err(Error::QueryFormat(e)),
+    };
+
+    match response {
+        Err(e) => error_to_http(&e),
+        Ok(resp) => HttpResponse::Ok().body(resp.write()),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn statement_controller(
+    query: Wql,
+    local_data: DataLocalContext,
+    indexes: DataIndexContext,
+    uniqueness: DataUniquenessContext,
+    encryption: DataEncryptContext,
+    bytes_counter: DataAtomicUsize,
+    hashing_cost: DataU32,
+    actor: DataExecutor,
+    id_locks: DataIdLocks,
+) -> Result<TxResponse, Error> {
+    let id = match &query {
+        Wql::UpdateContent(entity, _, id, ..)
+        | Wql::UpdateSet(entity, _, id, ..)
+        | Wql::MatchUpdate(entity, _, id, ..) => Some((entity, *id)),
+        Wql::Insert(entity, _, id, _) => id.map(|id| (entity, id)),
+        Wql::Delete(entity, id) => Uuid::from_str(id).ok().map(|id| (entity, id)),
+        _ => None,
+    };
+    let _id = match id {
+        Some((entity, id)) => Some(id_locks.lock(entity, id).await),
+        None => None,
+    };
+
+    match query {
+        Wql::CreateEntity(entity, uniques, encrypts) => {
+            let _ = create_unique_controller(&entity, uniques.clone(), uniqueness, &actor).await;
+            let _ = create_encrypts_controller(&entity, encrypts.clone(), encryption, &actor).await;
+            create_controller(
+                entity,
+                uniques,
+                encrypts,
+                local_data.into_inner(),
+                bytes_counter,
+                actor,
+            )
+            .await
+        }
+        Wql::CreateIndex(entity, keys) => {
+            create_index_controller(entity, keys, local_data, indexes, bytes_counter, actor).await
+        }
+        Wql::Delete(entity, uuid) => {
+            delete_controller(
+                entity,
+                uuid,
+                local_data.into_inner(),
+                indexes,
+                bytes_counter,
+                actor,
+            )
+            .await
         }
-        Ok(Wql::Insert(entity, content, uuid)) => {
+        Wql::Insert(entity, content, uuid, valid) => {
             insert_controller(
-                InsertArgs::new(entity, content, uuid),
+                InsertArgs::new(entity, content, uuid, valid),
                 local_data.into_inner(),
+                indexes,
                 bytes_counter,
                 uniqueness,
                 encryption,
@@ -74,10 +175,11 @@ pub async fn wql_handler(
             )
             .await
         }
-        Ok(Wql::UpdateContent(entity, content, uuid)) => {
+        Wql::UpdateContent(entity, content, uuid, version, valid) => {
             update_content_controller(
-                UpdateArgs::new(entity, content, uuid),
+                UpdateArgs::new(entity, content, uuid, version, valid),
                 local_data.into_inner(),
+                indexes,
                 bytes_counter,
                 uniqueness,
                 encryption,
@@ -85,10 +187,11 @@ pub async fn wql_handler(
             )
             .await
         }
-        Ok(Wql::UpdateSet(entity, content, uuid)) => {
+        Wql::UpdateSet(entity, content, uuid, version, valid) => {
             update_set_controller(
-                UpdateArgs::new(entity, content, uuid),
+                UpdateArgs::new(entity, content, uuid, version, valid),
                 local_data.into_inner(),
+                indexes,
                 bytes_counter,
                 uniqueness,
                 encryption,
@@ -97,10 +200,11 @@ pub async fn wql_handler(
             )
             .await
         }
-        Ok(Wql::MatchUpdate(entity, content, uuid, conditions)) => {
+        Wql::MatchUpdate(entity, content, uuid, conditions, valid) => {
             match_update_set_controller(
-                MatchUpdateArgs::new(entity, content, uuid, conditions),
+                MatchUpdateArgs::new(entity, content, uuid, conditions, valid),
                 local_data.into_inner(),
+                indexes,
                 bytes_counter,
                 uniqueness,
                 encryption,
@@ -109,26 +213,174 @@ pub async fn wql_handler(
             )
             .await
         }
-        Ok(Wql::Evict(entity, uuid)) => {
-            evict_controller(entity, uuid, local_data.into_inner(), bytes_counter, actor).await
+        Wql::Evict(entity, uuid) => {
+            evict_controller(
+                entity,
+                uuid,
+                local_data.into_inner(),
+                indexes,
+                bytes_counter,
+                actor,
+            )
+            .await
+        }
+        _ => Err(Error::SelectBadRequest),
+    }
+}
+
+/// Runs the statements of a `BEGIN ... COMMIT` block one after the other, while the
+/// executor holds their writes back. They are committed with a single log append once
+/// all of them succeed, otherwise the state they changed is restored and nothing is
+/// written. Callers must keep other transactions out while it runs.
+#[allow(clippy::too_many_arguments)]
+pub async fn transaction_controller(
+    statements: Vec<Wql>,
+    local_data: DataLocalContext,
+    indexes: DataIndexContext,
+    uniqueness: DataUniquenessContext,
+    encryption: DataEncryptContext,
+    bytes_counter: DataAtomicUsize,
+    hashing_cost: DataU32,
+    actor: DataExecutor,
+    id_locks: DataIdLocks,
+) -> Result<TransactionResponse, Error> {
+    let entities = statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Wql::Insert(entity, ..)
+            | Wql::UpdateContent(entity, ..)
+            | Wql::UpdateSet(entity, ..)
+            | Wql::MatchUpdate(entity, ..)
+            | Wql::Delete(entity, ..) => Some(entity.to_owned()),
+            _ => None,
+        })
+        .collect::<HashSet<String>>();
+    let snapshot = TxSnapshot::take(entities, &local_data, &uniqueness)?;
+    let offset = bytes_counter.load(Ordering::SeqCst);
+
+    let (begin, begin_offset, tx_seq) = actor.send(BeginBatch).await??;
+    if begin_offset == 0 {
+        bytes_counter.store(0, Ordering::SeqCst);
+    }
+    bytes_counter.fetch_max(begin_offset + begin, Ordering::SeqCst);
+
+    let mut results = Vec::with_capacity(statements.len());
+    for (i, statement) in statements.into_iter().enumerate() {
+        let result = statement_controller(
+            statement,
+            local_data.clone(),
+            indexes.clone(),
+            uniqueness.clone(),
+            encryption.clone(),
+            bytes_counter.clone(),
+            hashing_cost.clone(),
+            actor.clone(),
+            id_locks.clone(),
+        )
+        .await;
+        match result {
+            Ok(response) => results.push(response),
+            Err(e) => {
+                actor.send(AbortBatch).await?;
+                snapshot.restore(&local_data, &indexes, &uniqueness)?;
+                bytes_counter.store(offset, Ordering::SeqCst);
+                return Err(Error::TransactionAborted(i + 1, Box::new(e)));
+            }
+        }
+    }
+
+    let commit = actor
+        .send(CommitBatch {
+            data: local_data.as_ref().clone(),
+        })
+        .await
+        .map_err(Error::from)
+        .and_then(|commit| commit);
+    match commit {
+        Ok(commit) => bytes_counter.fetch_add(commit, Ordering::SeqCst),
+        Err(e) => {
+            snapshot.restore(&local_data, &indexes, &uniqueness)?;
+            bytes_counter.store(offset, Ordering::SeqCst);
+            return Err(e);
         }
-        Ok(_) => Err(Error::SelectBadRequest),
-        Err(e) => Err(Error::QueryFormat(e)),
     };
+    actor
+        .send(OffsetCounter::new(bytes_counter.load(Ordering::SeqCst)))
+        .await??;
 
-    match response {
-        Err(e) => error_to_http(&e),
-        Ok(resp) => HttpResponse::Ok().body(resp.write()),
+    Ok(TransactionResponse::new(results, tx_seq))
+}
+
+/// Registers and unique values of the entities a transaction block touches, as they were
+/// before it ran.
+struct TxSnapshot {
+    entities: HashSet<String>,
+    local_data: LocalContext,
+    uniqueness: UniquenessContext,
+}
+
+impl TxSnapshot {
+    fn take(
+        entities: HashSet<String>,
+        local_data: &DataLocalContext,
+        uniqueness: &DataUniquenessContext,
+    ) -> Result<Self, Error> {
+        let (local_data, uniqueness) = match (local_data.lock(), uniqueness.lock()) {
+            (Ok(local_data), Ok(uniqueness)) => (local_data, uniqueness),
+            _ => return Err(Error::LockData),
+        };
+
+        Ok(Self {
+            local_data: local_data
+                .iter()
+                .filter(|(entity, _)| entities.contains(*entity))
+                .map(|(entity, registers)| (entity.to_owned(), registers.to_owned()))
+                .collect(),
+            uniqueness: uniqueness
+                .iter()
+                .filter(|(entity, _)| entities.contains(*entity))
+                .map(|(entity, uniques)| (entity.to_owned(), uniques.to_owned()))
+                .collect(),
+            entities,
+        })
+    }
+
+    fn restore(
+        mut self,
+        local_data: &DataLocalContext,
+        indexes: &DataIndexContext,
+        uniqueness: &DataUniquenessContext,
+    ) -> Result<(), Error> {
+        let (mut local_data, mut indexes, mut uniqueness) =
+            match (local_data.lock(), indexes.lock(), uniqueness.lock()) {
+                (Ok(local_data), Ok(indexes), Ok(uniqueness)) => (local_data, indexes, uniqueness),
+                _ => return Err(Error::LockData),
+            };
+
+        for entity in self.entities {
+            match self.local_data.remove(&entity) {
+                Some(registers) => local_data.insert(entity.clone(), registers),
+                None => local_data.remove(&entity),
+            };
+            indexes.rebuild(&entity, local_data.get(&entity));
+            match self.uniqueness.remove(&entity) {
+                Some(uniques) => uniqueness.insert(entity, uniques),
+                None => uniqueness.remove(&entity),
+            };
+        }
+        Ok(())
     }
 }
 
 pub async fn create_controller(
     entity: String,
+    uniques: Vec<String>,
+    encrypts: Vec<String>,
     local_data: Arc<Arc<Mutex<LocalContext>>>,
     bytes_counter: DataAtomicUsize,
     actor: DataExecutor,
 ) -> Result<TxResponse, Error> {
-    let local_data = {
+    let delta = {
         let mut local_data = if let Ok(guard) = local_data.lock() {
             guard
         } else {
@@ -138,84 +390,193 @@ pub async fn create_controller(
             return Err(Error::EntityAlreadyCreated(entity));
         }
 
-        local_data.insert(entity.clone(), BTreeMap::new());
-        local_data.clone()
+        let delta = LocalDelta::CreateEntity(entity.clone());
+        delta.apply(&mut local_data);
+        delta
     };
-    actor.send(LocalData::new(local_data)).await??;
+    actor
+        .send(LocalData::new(delta, local_data.as_ref().clone()))
+        .await??;
 
     let message = format!("Entity `{}` created", &entity);
-    let (offset, is_empty) = actor.send(CreateEntity::new(&entity)).await??;
+    let (bytes_written, offset, tx_seq) = actor
+        .send(CreateEntity::new(&entity, uniques, encrypts))
+        .await??;
 
-    if is_empty {
+    if offset == 0 {
         bytes_counter.store(0, Ordering::SeqCst);
     }
-    bytes_counter.fetch_add(offset, Ordering::SeqCst);
+    bytes_counter.fetch_max(offset + bytes_written, Ordering::SeqCst);
     actor
         .send(OffsetCounter::new(bytes_counter.load(Ordering::SeqCst)))
         .await??;
 
-    Ok(CreateEntityResponse::new(entity, message).into())
+    Ok(CreateEntityResponse::new(entity, tx_seq, message).into())
+}
+
+pub async fn create_index_controller(
+    entity: String,
+    keys: Vec<String>,
+    local_data: DataLocalContext,
+    indexes: DataIndexContext,
+    bytes_counter: DataAtomicUsize,
+    actor: DataExecutor,
+) -> Result<TxResponse, Error> {
+    let definitions = {
+        let local_data = if let Ok(guard) = local_data.lock() {
+            guard
+        } else {
+            return Err(Error::LockData);
+        };
+        let registers = if let Some(registers) = local_data.get(&entity) {
+            registers
+        } else {
+            return Err(Error::EntityNotCreated(entity));
+        };
+        let mut indexes = if let Ok(guard) = indexes.lock() {
+            guard
+        } else {
+            return Err(Error::LockData);
+        };
+        indexes.create(&entity, keys.clone(), registers);
+        to_string_pretty(&indexes.definitions(), pretty_config_inner())
+            .map_err(Error::Serialization)?
+    };
+
+    let message = format!(
+        "Index on `{}` of entity `{}` created",
+        keys.join(", "),
+        &entity
+    );
+    let (bytes_written, offset, tx_seq) = actor
+        .send(CreateIndex {
+            name: entity.clone(),
+            keys,
+            definitions,
+        })
+        .await??;
+
+    if offset == 0 {
+        bytes_counter.store(0, Ordering::SeqCst);
+    }
+    bytes_counter.fetch_max(offset + bytes_written, Ordering::SeqCst);
+    actor
+        .send(OffsetCounter::new(bytes_counter.load(Ordering::SeqCst)))
+        .await??;
+
+    Ok(CreateIndexResponse::new(entity, tx_seq, message).into())
 }
 
 pub async fn evict_controller(
     entity: String,
     uuid: Option<Uuid>,
     local_data: Arc<Arc<Mutex<LocalContext>>>,
+    indexes: DataIndexContext,
     bytes_counter: DataAtomicUsize,
     actor: DataExecutor,
 ) -> Result<TxResponse, Error> {
     if uuid.is_none() {
         let message = format!("Entity {} evicted", &entity);
-        let (offset, is_empty) = actor.send(EvictEntity::new(&entity)).await??;
+        let (date, bytes_written, offset, tx_seq) = actor.send(EvictEntity::new(&entity)).await??;
 
-        if is_empty {
+        if offset == 0 {
             bytes_counter.store(0, Ordering::SeqCst);
         }
-        bytes_counter.fetch_add(offset, Ordering::SeqCst);
+        bytes_counter.fetch_max(offset + bytes_written, Ordering::SeqCst);
         actor
             .send(OffsetCounter::new(bytes_counter.load(Ordering::SeqCst)))
             .await??;
 
-        let local_data = {
+        let (delta, definitions) = {
             let mut local_data = if let Ok(guard) = local_data.lock() {
                 guard
             } else {
                 return Err(Error::LockData);
             };
-            local_data.remove(&entity);
-            local_data.clone()
+            let mut indexes = if let Ok(guard) = indexes.lock() {
+                guard
+            } else {
+                return Err(Error::LockData);
+            };
+            let delta = LocalDelta::EvictEntity(entity.clone());
+            let indexed = indexes.contains(&entity);
+            delta.apply(&mut local_data);
+            indexes.apply(&delta);
+            (delta, indexed.then(|| indexes.definitions()))
         };
 
-        actor.send(LocalData::new(local_data)).await??;
-        Ok(DeleteOrEvictEntityResponse::new(entity, None, message, TxType::EvictEntityTree).into())
+        let register = DataRegister {
+            offset,
+            bytes_length: bytes_written,
+            file_name: date.format("%Y_%m_%d.log").to_string(),
+        };
+        actor
+            .send(
+                LocalData::new(delta, local_data.as_ref().clone())
+                    .at(date, tx_seq)
+                    .recorded_at(register),
+            )
+            .await??;
+        if let Some(definitions) = definitions {
+            let definitions = to_string_pretty(&definitions, pretty_config_inner())
+                .map_err(Error::Serialization)?;
+            actor.send(IndexData { definitions }).await??;
+        }
+        Ok(DeleteOrEvictEntityResponse::new(
+            entity,
+            None,
+            None,
+            tx_seq,
+            message,
+            TxType::EvictEntityTree,
+        )
+        .into())
     } else {
         let id = uuid.unwrap();
-        let (offset, is_empty) = actor.send(EvictEntityId::new(&entity, id)).await??;
+        let (date, bytes_written, offset, tx_seq) =
+            actor.send(EvictEntityId::new(&entity, id)).await??;
 
-        if is_empty {
+        if offset == 0 {
             bytes_counter.store(0, Ordering::SeqCst);
         }
-
-        bytes_counter.fetch_add(offset, Ordering::SeqCst);
+        bytes_counter.fetch_max(offset + bytes_written, Ordering::SeqCst);
         actor
             .send(OffsetCounter::new(bytes_counter.load(Ordering::SeqCst)))
             .await??;
 
-        let local_data = {
+        let delta = {
             let mut local_data = if let Ok(guard) = local_data.lock() {
                 guard
             } else {
                 return Err(Error::LockData);
             };
-            if let Some(d) = local_data.get_mut(&entity) {
-                d.remove(&id);
-            }
-            local_data.clone()
+            let delta = LocalDelta::EvictId(entity.clone(), id);
+            apply_delta(&delta, &mut local_data, &indexes)?;
+            delta
+        };
+        let register = DataRegister {
+            offset,
+            bytes_length: bytes_written,
+            file_name: date.format("%Y_%m_%d.log").to_string(),
         };
-        actor.send(LocalData::new(local_data)).await??;
+        actor
+            .send(
+                LocalData::new(delta, local_data.as_ref().clone())
+                    .at(date, tx_seq)
+                    .recorded_at(register),
+            )
+            .await??;
 
         let message = format!("Entity {} with id {} evicted", &entity, &id);
-        Ok(DeleteOrEvictEntityResponse::new(entity, uuid, message, TxType::EvictEntity).into())
+        Ok(DeleteOrEvictEntityResponse::new(
+            entity,
+            uuid,
+            None,
+            tx_seq,
+            message,
+            TxType::EvictEntity,
+        )
+        .into())
     }
 }
 
@@ -273,9 +634,11 @@ pub async fn create_encrypts_controller(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn insert_controller(
     args: InsertArgs,
     local_data: Arc<Arc<Mutex<LocalContext>>>,
+    indexes: DataIndexContext,
     bytes_counter: DataAtomicUsize,
     uniqueness: DataUniquenessContext,
     encryption: DataEncryptContext,
@@ -283,8 +646,7 @@ pub async fn insert_controller(
     actor: DataExecutor,
 ) -> Result<TxResponse, Error> {
     let datetime = tx_time(&args.content)?;
-    let mut offset = bytes_counter.load(Ordering::SeqCst);
-    let encrypted_content = actor
+    let mut encrypted_content = actor
         .send(EncryptContent::new(
             &args.entity,
             args.content,
@@ -294,9 +656,6 @@ pub async fn insert_controller(
         ))
         .await??;
 
-    let content_log = to_string_pretty(&encrypted_content, pretty_config_inner())
-        .map_err(Error::Serialization)?;
-
     {
         let local_data = if let Ok(guard) = local_data.lock() {
             guard
@@ -306,7 +665,22 @@ pub async fn insert_controller(
         if !local_data.contains_key(&args.entity) {
             return Err(Error::EntityNotCreated(args.entity));
         }
+        // Inserting over an existing id keeps counting from its version
+        let entity = &local_data[&args.entity];
+        if let Some((_, state)) = args.uuid.and_then(|id| entity.get(&id)) {
+            let state: HashMap<String, Types> =
+                bincode::deserialize(state).map_err(|_| Error::FailedToParseState)?;
+            encrypted_content.insert(
+                TX_VERSION.to_owned(),
+                Types::Integer(tx_version(&state) as isize),
+            );
+        }
     }
+    let version = next_tx_version(&mut encrypted_content);
+    set_valid_time(&mut encrypted_content, args.valid);
+
+    let content_log = to_string_pretty(&encrypted_content, pretty_config_inner())
+        .map_err(Error::Serialization)?;
 
     let uniqueness = uniqueness.into_inner();
     actor
@@ -326,33 +700,40 @@ pub async fn insert_controller(
         ))
         .await??;
 
-    if content_value.3 {
+    let offset = content_value.3;
+    if offset == 0 {
         bytes_counter.store(0, Ordering::SeqCst);
-        offset = 0;
     }
 
     let local_data_register = DataRegister {
         offset,
         bytes_length: content_value.2,
-        file_name: content_value.0.format("data/%Y_%m_%d.log").to_string(),
+        file_name: content_value.0.format("%Y_%m_%d.log").to_string(),
     };
 
-    let local_data = {
+    let delta = {
         let mut local_data = if let Ok(guard) = local_data.lock() {
             guard
         } else {
             return Err(Error::LockData);
         };
-        if let Some(map) = local_data.get_mut(&args.entity) {
-            let encoded: Vec<u8> = bincode::serialize(&encrypted_content).unwrap();
-            map.insert(content_value.1, (local_data_register, encoded));
-        }
-        local_data.clone()
+        let encoded: Vec<u8> = bincode::serialize(&encrypted_content).unwrap();
+        let delta = LocalDelta::Upsert(
+            args.entity.clone(),
+            content_value.1,
+            (local_data_register, encoded),
+        );
+        apply_delta(&delta, &mut local_data, &indexes)?;
+        delta
     };
 
-    actor.send(LocalData::new(local_data)).await??;
+    actor
+        .send(
+            LocalData::new(delta, local_data.as_ref().clone()).at(content_value.0, content_value.4),
+        )
+        .await??;
 
-    bytes_counter.fetch_add(content_value.2, Ordering::SeqCst);
+    bytes_counter.fetch_max(offset + content_value.2, Ordering::SeqCst);
     actor
         .send(OffsetCounter::new(bytes_counter.load(Ordering::SeqCst)))
         .await??;
@@ -361,12 +742,21 @@ pub async fn insert_controller(
         "Entity {} inserted with Uuid {}",
         &args.entity, &content_value.1
     );
-    Ok(InsertEntityResponse::new(args.entity, content_value.1, message).into())
+    Ok(InsertEntityResponse::new(
+        args.entity,
+        content_value.1,
+        version,
+        content_value.4,
+        message,
+    )
+    .into())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn update_set_controller(
     args: UpdateArgs,
     local_data: Arc<Arc<Mutex<LocalContext>>>,
+    indexes: DataIndexContext,
     bytes_counter: DataAtomicUsize,
     uniqueness: DataUniquenessContext,
     encryption: DataEncryptContext,
@@ -374,7 +764,6 @@ pub async fn update_set_controller(
     actor: DataExecutor,
 ) -> Result<TxResponse, Error> {
     let datetime = tx_time(&args.content)?;
-    let mut offset = bytes_counter.load(Ordering::SeqCst);
     let encrypted_content = actor
         .send(EncryptContent::new(
             &args.entity,
@@ -400,6 +789,7 @@ pub async fn update_set_controller(
         {
             return Err(Error::UuidNotCreatedForEntity(args.entity, args.id));
         }
+        check_version(&local_data, &args.entity, args.id, args.version)?;
     }
 
     let uniqueness = uniqueness.into_inner();
@@ -423,11 +813,12 @@ pub async fn update_set_controller(
 
     let mut previous_state: HashMap<String, Types> =
         bincode::deserialize(&previous_entry.1.clone()).unwrap();
-    let encrypted_content_clone = encrypted_content.clone();
     encrypted_content.into_iter().for_each(|(k, v)| {
         let local_state = previous_state.entry(k).or_insert_with(|| v.clone());
         *local_state = v;
     });
+    let version = next_tx_version(&mut previous_state);
+    set_valid_time(&mut previous_state, args.valid);
 
     let state_log =
         to_string_pretty(&previous_state, pretty_config_inner()).map_err(Error::Serialization)?;
@@ -444,54 +835,62 @@ pub async fn update_set_controller(
         ))
         .await??;
 
-    if content_value.2 {
+    let offset = content_value.2;
+    if offset == 0 {
         bytes_counter.store(0, Ordering::SeqCst);
-        offset = 0;
     }
 
     let local_data_register = DataRegister {
         offset,
         bytes_length: content_value.1,
-        file_name: content_value.0.format("data/%Y_%m_%d.log").to_string(),
+        file_name: content_value.0.format("%Y_%m_%d.log").to_string(),
     };
 
-    let local_data = {
+    let delta = {
         let mut local_data = if let Ok(guard) = local_data.lock() {
             guard
         } else {
             return Err(Error::LockData);
         };
-        if let Some(map) = local_data.get_mut(&args.entity) {
-            if let Some(reg) = map.get_mut(&args.id) {
-                let encoded: Vec<u8> = bincode::serialize(&encrypted_content_clone).unwrap();
-                *reg = (local_data_register, encoded);
-            }
-        }
-        local_data.clone()
+        let encoded: Vec<u8> = bincode::serialize(&previous_state).unwrap();
+        let delta =
+            LocalDelta::Upsert(args.entity.clone(), args.id, (local_data_register, encoded));
+        apply_delta(&delta, &mut local_data, &indexes)?;
+        delta
     };
-    actor.send(LocalData::new(local_data)).await??;
+    actor
+        .send(
+            LocalData::new(delta, local_data.as_ref().clone()).at(content_value.0, content_value.3),
+        )
+        .await??;
 
-    bytes_counter.fetch_add(content_value.1, Ordering::SeqCst);
+    bytes_counter.fetch_max(offset + content_value.1, Ordering::SeqCst);
     actor
         .send(OffsetCounter::new(bytes_counter.load(Ordering::SeqCst)))
         .await??;
     let message = format!("Entity {} with Uuid {} updated", &args.entity, &args.id);
-    Ok(
-        UpdateEntityResponse::new(args.entity, args.id, state_log, message, TxType::UpdateSet)
-            .into(),
+    Ok(UpdateEntityResponse::new(
+        args.entity,
+        args.id,
+        version,
+        content_value.3,
+        state_log,
+        message,
+        TxType::UpdateSet,
     )
+    .into())
 }
 
 pub async fn update_content_controller(
     args: UpdateArgs,
     local_data: Arc<Arc<Mutex<LocalContext>>>,
+    indexes: DataIndexContext,
     bytes_counter: DataAtomicUsize,
     uniqueness: DataUniquenessContext,
     encryption: DataEncryptContext,
     actor: DataExecutor,
 ) -> Result<TxResponse, Error> {
     let datetime = tx_time(&args.content)?;
-    let mut offset = bytes_counter.load(Ordering::SeqCst);
     if let Ok(guard) = encryption.lock() {
         if guard.contains_key(&args.entity) {
             let keys = args
@@ -523,6 +922,7 @@ pub async fn update_content_controller(
         {
             return Err(Error::UuidNotCreatedForEntity(args.entity, args.id));
         }
+        check_version(&local_data, &args.entity, args.id, args.version)?;
     }
 
     let uniqueness = uniqueness.into_inner();
@@ -550,6 +950,8 @@ pub async fn update_content_controller(
     content
         .into_iter()
         .for_each(|(k, v)| update_content_state(&mut previous_state, k, v));
+    let version = next_tx_version(&mut previous_state);
+    set_valid_time(&mut previous_state, args.valid);
 
     let state_log =
         to_string_pretty(&previous_state, pretty_config_inner()).map_err(Error::Serialization)?;
@@ -565,32 +967,34 @@ pub async fn update_content_controller(
         ))
         .await??;
 
-    if content_value.2 {
+    let offset = content_value.2;
+    if offset == 0 {
         bytes_counter.store(0, Ordering::SeqCst);
-        offset = 0;
     }
     let local_data_register = DataRegister {
         offset,
         bytes_length: content_value.1,
-        file_name: content_value.0.format("data/%Y_%m_%d.log").to_string(),
+        file_name: content_value.0.format("%Y_%m_%d.log").to_string(),
     };
-    let local_data = {
+    let delta = {
         let mut local_data = if let Ok(guard) = local_data.lock() {
             guard
         } else {
             return Err(Error::LockData);
         };
-        if let Some(map) = local_data.get_mut(&args.entity) {
-            if let Some(reg) = map.get_mut(&args.id) {
-                let encoded: Vec<u8> = bincode::serialize(&previous_state).unwrap();
-                *reg = (local_data_register, encoded);
-            }
-        }
-        local_data.clone()
+        let encoded: Vec<u8> = bincode::serialize(&previous_state).unwrap();
+        let delta =
+            LocalDelta::Upsert(args.entity.clone(), args.id, (local_data_register, encoded));
+        apply_delta(&delta, &mut local_data, &indexes)?;
+        delta
     };
-    actor.send(LocalData::new(local_data)).await??;
+    actor
+        .send(
+            LocalData::new(delta, local_data.as_ref().clone()).at(content_value.0, content_value.3),
+        )
+        .await??;
 
-    bytes_counter.fetch_add(content_value.1, Ordering::SeqCst);
+    bytes_counter.fetch_max(offset + content_value.1, Ordering::SeqCst);
     actor
         .send(OffsetCounter::new(bytes_counter.load(Ordering::SeqCst)))
         .await??;
@@ -599,6 +1003,8 @@ pub async fn update_content_controller(
     Ok(UpdateEntityResponse::new(
         args.entity,
         args.id,
+        version,
+        content_value.3,
         state_log,
         message,
         TxType::UpdateContent,
@@ -610,12 +1016,12 @@ pub async fn delete_controller(
     entity: String,
     id: String,
     local_data: Arc<Arc<Mutex<LocalContext>>>,
+    indexes: DataIndexContext,
     bytes_counter: DataAtomicUsize,
     actor: DataExecutor,
 ) -> Result<TxResponse, Error> {
     let uuid = Uuid::from_str(&id)?;
     let message = format!("Entity {} with Uuid {} deleted", &entity, id);
-    let mut offset = bytes_counter.load(Ordering::SeqCst);
 
     let previous_entry = {
         let local_data = if let Ok(guard) = local_data.lock() {
@@ -632,13 +1038,16 @@ pub async fn delete_controller(
         }
 
         let previous_entry = local_data.get(&entity).unwrap().get(&uuid).unwrap();
-        previous_entry.clone().0
+        previous_entry.clone()
     };
+    let (previous_entry, previous_state) = previous_entry;
+    let previous_state: HashMap<String, Types> =
+        bincode::deserialize(&previous_state).map_err(|_| Error::FailedToParseState)?;
 
     let previous_state_str = actor.send(previous_entry.to_owned()).await??;
     let two_registries_ago = actor.send(PreviousRegistry(previous_state_str)).await??;
 
-    let state_to_be = if let Some((reg, _)) = two_registries_ago {
+    let mut state_to_be = if let Some((reg, _)) = two_registries_ago {
         let state_str = actor.send(reg.to_owned()).await??;
         (actor.send(State(state_str)).await??, reg.to_owned())
     } else {
@@ -651,6 +1060,12 @@ pub async fn delete_controller(
         (HashMap::new(), insert_reg.0.to_owned())
     };
 
+    state_to_be.0.insert(
+        TX_VERSION.to_owned(),
+        Types::Integer(tx_version(&previous_state) as isize),
+    );
+    let version = next_tx_version(&mut state_to_be.0);
+
     let content_log =
         to_string_pretty(&state_to_be.0, pretty_config_inner()).map_err(Error::Serialization)?;
 
@@ -666,44 +1081,55 @@ pub async fn delete_controller(
         ))
         .await??;
 
-    if content_value.2 {
+    let offset = content_value.2;
+    if offset == 0 {
         bytes_counter.store(0, Ordering::SeqCst);
-        offset = 0;
     }
     let local_data_register = DataRegister {
         offset,
         bytes_length: content_value.1,
-        file_name: content_value.0.format("data/%Y_%m_%d.log").to_string(),
+        file_name: content_value.0.format("%Y_%m_%d.log").to_string(),
     };
 
-    let local_data = {
+    let delta = {
         let mut local_data = if let Ok(guard) = local_data.lock() {
             guard
         } else {
             return Err(Error::LockData);
         };
-        if let Some(map) = local_data.get_mut(&entity) {
-            if let Some(reg) = map.get_mut(&uuid) {
-                let encoded: Vec<u8> = bincode::serialize(&state_to_be.0).unwrap();
-                *reg = (local_data_register, encoded);
-            }
-        }
-        local_data.clone()
+        let encoded: Vec<u8> = bincode::serialize(&state_to_be.0).unwrap();
+        let delta = LocalDelta::Upsert(entity.clone(), uuid, (local_data_register, encoded));
+        apply_delta(&delta, &mut local_data, &indexes)?;
+        delta
     };
 
-    actor.send(LocalData::new(local_data)).await??;
+    actor
+        .send(
+            LocalData::new(delta, local_data.as_ref().clone()).at(content_value.0, content_value.3),
+        )
+        .await??;
 
-    bytes_counter.fetch_add(content_value.1, Ordering::SeqCst);
+    bytes_counter.fetch_max(offset + content_value.1, Ordering::SeqCst);
     actor
         .send(OffsetCounter::new(bytes_counter.load(Ordering::SeqCst)))
         .await??;
 
-    Ok(DeleteOrEvictEntityResponse::new(entity, Some(uuid), message, TxType::Delete).into())
+    Ok(DeleteOrEvictEntityResponse::new(
+        entity,
+        Some(uuid),
+        Some(version),
+        content_value.3,
+        message,
+        TxType::Delete,
+    )
+    .into())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn match_update_set_controller(
     args: MatchUpdateArgs,
     local_data: Arc<Arc<Mutex<LocalContext>>>,
+    indexes: DataIndexContext,
     bytes_counter: DataAtomicUsize,
     uniqueness: DataUniquenessContext,
     encryption: DataEncryptContext,
@@ -740,8 +1166,6 @@ pub async fn match_update_set_controller(
         })
         .await??;
 
-    let mut offset = bytes_counter.load(Ordering::SeqCst);
-
     let encrypted_content = actor
         .send(EncryptContent::new(
             &args.entity,
@@ -763,10 +1187,12 @@ pub async fn match_update_set_controller(
         })
         .await??;
 
-    args.content.into_iter().for_each(|(k, v)| {
+    encrypted_content.into_iter().for_each(|(k, v)| {
         let local_state = previous_state.entry(k).or_insert_with(|| v.clone());
         *local_state = v;
     });
+    let version = next_tx_version(&mut previous_state);
+    set_valid_time(&mut previous_state, args.valid);
 
     let state_log =
         to_string_pretty(&previous_state, pretty_config_inner()).map_err(Error::Serialization)?;
@@ -783,41 +1209,93 @@ pub async fn match_update_set_controller(
         })
         .await??;
 
-    if content_value.2 {
+    let offset = content_value.2;
+    if offset == 0 {
         bytes_counter.store(0, Ordering::SeqCst);
-        offset = 0;
     }
     let local_data_register = DataRegister {
         offset,
         bytes_length: content_value.1,
-        file_name: content_value.0.format("data/%Y_%m_%d.log").to_string(),
+        file_name: content_value.0.format("%Y_%m_%d.log").to_string(),
     };
 
-    let local_data = {
+    let delta = {
         let mut local_data = if let Ok(guard) = local_data.lock() {
             guard
         } else {
             return Err(Error::LockData);
         };
-        if let Some(map) = local_data.get_mut(&args.entity) {
-            if let Some(reg) = map.get_mut(&args.id) {
-                let encoded: Vec<u8> = bincode::serialize(&encrypted_content).unwrap();
-                *reg = (local_data_register, encoded);
-            }
-        }
-        local_data.clone()
+        let encoded: Vec<u8> = bincode::serialize(&previous_state).unwrap();
+        let delta =
+            LocalDelta::Upsert(args.entity.clone(), args.id, (local_data_register, encoded));
+        apply_delta(&delta, &mut local_data, &indexes)?;
+        delta
     };
 
-    actor.send(LocalData::new(local_data)).await??;
+    actor
+        .send(
+            LocalData::new(delta, local_data.as_ref().clone()).at(content_value.0, content_value.3),
+        )
+        .await??;
 
-    bytes_counter.fetch_add(content_value.1, Ordering::SeqCst);
+    bytes_counter.fetch_max(offset + content_value.1, Ordering::SeqCst);
     actor
         .send(OffsetCounter::new(bytes_counter.load(Ordering::SeqCst)))
         .await??;
 
     let message = format!("Entity {} with Uuid {} updated", &args.entity, &args.id);
-    Ok(
-        UpdateEntityResponse::new(args.entity, args.id, state_log, message, TxType::UpdateSet)
-            .into(),
+    Ok(UpdateEntityResponse::new(
+        args.entity,
+        args.id,
+        version,
+        content_value.3,
+        state_log,
+        message,
+        TxType::UpdateSet,
     )
+    .into())
+}
+
+/// Applies `delta` to `local_data` and to the indexes of its entity.
+fn apply_delta(
+    delta: &LocalDelta,
+    local_data: &mut LocalContext,
+    indexes: &DataIndexContext,
+) -> Result<(), Error> {
+    let mut indexes = if let Ok(guard) = indexes.lock() {
+        guard
+    } else {
+        return Err(Error::LockData);
+    };
+    delta.apply(local_data);
+    indexes.apply(delta);
+    Ok(())
+}
+
+/// Fails when the update requires a version the entity id is not at.
+fn check_version(
+    local_data: &LocalContext,
+    entity: &str,
+    id: Uuid,
+    expected: Option<usize>,
+) -> Result<(), Error> {
+    let expected = match expected {
+        Some(expected) => expected,
+        None => return Ok(()),
+    };
+    let (_, state) = &local_data[entity][&id];
+    let state: HashMap<String, Types> =
+        bincode::deserialize(state).map_err(|_| Error::FailedToParseState)?;
+    let version = tx_version(&state);
+
+    if version == expected {
+        Ok(())
+    } else {
+        Err(Error::VersionConflict(
+            entity.to_owned(),
+            id,
+            expected,
+            version,
+        ))
+    }
 }
@@ -1,7 +1,15 @@
-use crate::io::read;
-use crate::{http::routes, schemas::tx::TxResponse};
+use crate::io::{read, storage::default_storage};
+use crate::{
+    actors::wql::Executor,
+    config::Durability,
+    http::routes,
+    schemas::tx::{TransactionResponse, TxResponse},
+};
+use actix::Actor;
 use actix_http::body::ResponseBody;
-use actix_web::{body::Body, test, App};
+use actix_web::{body::Body, dev::Service, test, App};
+use chrono::Utc;
+use std::{collections::HashMap, time::Duration};
 use uuid::Uuid;
 
 #[actix_rt::test]
@@ -15,15 +23,17 @@ async fn test_create_post_ok() {
 
     let mut resp = test::call_service(&mut app, req).await;
     assert!(resp.status().is_success());
-    let body = resp.take_body();
-    let body = body.as_ref().unwrap();
+    let body = resp.take_body().as_str().to_string();
+    let response: TxResponse = ron::de::from_str(&body).unwrap();
     assert_eq!(
-        &Body::from(
-            "(\n tx_type: Create,\n entity: \"crete_test_ok\",\n uuid: None,\n state: \"\",\n message: \"Entity `crete_test_ok` created\",\n)"
-        ),
-        body
+        body,
+        format!(
+            "(\n tx_type: Create,\n entity: \"crete_test_ok\",\n uuid: None,\n version: None,\n tx_seq: {},\n state: \"\",\n message: \"Entity `crete_test_ok` created\",\n)",
+            response.tx_seq
+        )
     );
-    read::assert_content("CREATE_ENTITY|crete_test_ok;");
+    read::assert_content("CREATE_ENTITY|");
+    read::assert_content("|crete_test_ok;");
     clear();
 }
 
@@ -58,7 +68,8 @@ async fn test_create_uniques_post_ok() {
 
     let resp = test::call_service(&mut app, req).await;
     assert!(resp.status().is_success());
-    read::assert_content("CREATE_ENTITY|test_uniques;");
+    read::assert_content("CREATE_ENTITY|");
+    read::assert_content("|test_uniques|(uniques: [\"name\",\"ssn\",\"id\",],encrypts: [],);");
     read::assert_uniques("test_uniques");
     read::assert_uniques("uniques: [\"name\",\"ssn\",\"id\",]");
     clear();
@@ -75,7 +86,8 @@ async fn test_create_encrypts_post_ok() {
 
     let resp = test::call_service(&mut app, req).await;
     assert!(resp.status().is_success());
-    read::assert_content("CREATE_ENTITY|test_encrypt;");
+    read::assert_content("CREATE_ENTITY|");
+    read::assert_content("|test_encrypt|(uniques: [],encrypts: [\"name\",\"ssn\",\"id\",],);");
     read::assert_encrypt("test_encrypt");
     read::assert_encrypt("encrypts: [\"name\",\"ssn\",\"id\",]");
     clear();
@@ -289,7 +301,6 @@ async fn test_insert_entity_not_created() {
     clear();
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_update_set_post_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -337,7 +348,6 @@ async fn test_update_set_post_ok() {
     clear();
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_update_uniqueness_set_post_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -391,7 +401,6 @@ async fn test_update_uniqueness_set_post_ok() {
     );
     clear();
 }
-#[ignore]
 #[actix_rt::test]
 async fn test_update_content_post_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -551,7 +560,6 @@ async fn test_update_any_uuid() {
     clear();
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_delete_post_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -600,7 +608,6 @@ async fn test_delete_post_ok() {
     clear();
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_delete_without_update() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -632,16 +639,17 @@ async fn test_delete_without_update() {
 
     let mut resp = test::call_service(&mut app, req).await;
     let body = resp.take_body().as_str().to_string();
-    assert_eq!(body, format!("(\n tx_type: Delete,\n entity: \"test_delete\",\n uuid: Some(\"{}\"),\n state: \"\",\n message: \"Entity test_delete with Uuid {} deleted\",\n)", uuid.unwrap(), uuid.unwrap()));
+    let tx_seq = ron::de::from_str::<TxResponse>(&body).unwrap().tx_seq;
+    assert!(tx_seq > response.tx_seq);
+    assert_eq!(body, format!("(\n tx_type: Delete,\n entity: \"test_delete\",\n uuid: Some(\"{}\"),\n version: Some(2),\n tx_seq: {},\n state: \"\",\n message: \"Entity test_delete with Uuid {} deleted\",\n)", uuid.unwrap(), tx_seq, uuid.unwrap()));
 
     assert!(resp.status().is_success());
 
     read::assert_content("DELETE");
-    read::assert_content("|{}|");
+    read::assert_content("|{\"tx_version\": Integer(2),}|");
     clear();
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_match_all_update_post_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -680,7 +688,6 @@ async fn test_match_all_update_post_ok() {
     clear();
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_match_any_update_post_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -719,7 +726,6 @@ async fn test_match_any_update_post_ok() {
     clear();
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_match_any_update_fail() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -761,7 +767,6 @@ async fn test_match_any_update_fail() {
     clear();
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_match_any_update_fake_key() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -800,7 +805,6 @@ async fn test_match_any_update_fake_key() {
     clear();
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_match_all_update_fake_key() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -955,7 +959,6 @@ async fn test_insert_encrypt_post_ok() {
     clear();
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_update_set_encrypt_post_ok() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -1002,7 +1005,6 @@ async fn test_update_set_encrypt_post_ok() {
     clear();
 }
 
-#[ignore]
 #[actix_rt::test]
 async fn test_update_content_encrypt_post_err() {
     let mut app = test::init_service(App::new().configure(routes)).await;
@@ -1045,6 +1047,370 @@ async fn test_update_content_encrypt_post_err() {
     clear();
 }
 
+#[actix_rt::test]
+async fn test_transaction_post_ok() {
+    let mut app = test::init_service(App::new().configure(routes)).await;
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("CREATE ENTITY test_transaction_ok")
+        .uri("/wql/tx")
+        .to_request();
+    let _ = test::call_service(&mut app, req).await;
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload(
+            "BEGIN
+                INSERT {a: 123, b: \"first;statement\",} INTO test_transaction_ok;
+                INSERT {a: 456,} INTO test_transaction_ok;
+            COMMIT",
+        )
+        .uri("/wql/tx")
+        .to_request();
+    let mut resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_success());
+    let body = resp.take_body().as_str().to_string();
+    let response: TransactionResponse = ron::de::from_str(&body).unwrap();
+    assert!(body.contains(&format!(
+        "message: \"Transaction {} with 2 statements committed\"",
+        response.tx_seq
+    )));
+    assert_eq!(body.matches("tx_type: Insert").count(), 2);
+    assert!(response
+        .results
+        .iter()
+        .all(|result| result.tx_seq == response.tx_seq));
+    read::assert_content("BEGIN|");
+    read::assert_content("first;statement");
+    read::assert_content("COMMIT|");
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("SELECT * FROM test_transaction_ok")
+        .uri("/wql/query")
+        .to_request();
+    let mut resp = test::call_service(&mut app, req).await;
+    let body = resp.take_body().as_str().to_string();
+    assert!(body.contains("Integer(123)"));
+    assert!(body.contains("Integer(456)"));
+    clear();
+}
+
+#[actix_rt::test]
+async fn test_transaction_post_rolls_back() {
+    let mut app = test::init_service(App::new().configure(routes)).await;
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("CREATE ENTITY test_transaction_err UNIQUES #{id,}")
+        .uri("/wql/tx")
+        .to_request();
+    let _ = test::call_service(&mut app, req).await;
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload(
+            "BEGIN
+                INSERT {id: 1, a: \"never_committed\",} INTO test_transaction_err;
+                INSERT {id: 1, a: \"duplicated\",} INTO test_transaction_err;
+            COMMIT",
+        )
+        .uri("/wql/tx")
+        .to_request();
+    let mut resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_client_error());
+    let body = resp.take_body().as_str().to_string();
+    assert!(body.contains("error_type: \"TransactionAborted\""));
+    assert!(body.contains("Statement 2 failed"));
+    read::assert_not_content("never_committed");
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("SELECT * FROM test_transaction_err")
+        .uri("/wql/query")
+        .to_request();
+    let mut resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.take_body().as_str(), "{}");
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("INSERT {id: 1,} INTO test_transaction_err")
+        .uri("/wql/tx")
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_success());
+    clear();
+}
+
+#[actix_rt::test]
+async fn test_transaction_insert_update_same_id() {
+    let mut app = test::init_service(App::new().configure(routes)).await;
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("CREATE ENTITY test_transaction_update")
+        .uri("/wql/tx")
+        .to_request();
+    let _ = test::call_service(&mut app, req).await;
+
+    let uuid = uuid::Uuid::new_v4();
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload(format!(
+            "BEGIN
+                INSERT {{a: 1, b: 2,}} INTO test_transaction_update WITH {uuid};
+                UPDATE test_transaction_update SET {{a: 3,}} INTO {uuid};
+            COMMIT",
+            uuid = uuid
+        ))
+        .uri("/wql/tx")
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_success());
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload(format!("DELETE {} FROM test_transaction_update", uuid))
+        .uri("/wql/tx")
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_success());
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload(format!("SELECT * FROM test_transaction_update ID {}", uuid))
+        .uri("/wql/query")
+        .to_request();
+    let mut resp = test::call_service(&mut app, req).await;
+    let body = resp.take_body().as_str().to_string();
+    assert!(body.contains("\"a\": Integer(1)"));
+    assert!(body.contains("\"b\": Integer(2)"));
+    clear();
+}
+
+#[actix_rt::test]
+async fn test_transaction_insert_delete_same_id() {
+    let mut app = test::init_service(App::new().configure(routes)).await;
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("CREATE ENTITY test_transaction_delete")
+        .uri("/wql/tx")
+        .to_request();
+    let _ = test::call_service(&mut app, req).await;
+
+    let (deleted, restored) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload(format!(
+            "BEGIN
+                INSERT {{a: 1,}} INTO test_transaction_delete WITH {deleted};
+                DELETE {deleted} FROM test_transaction_delete;
+                INSERT {{a: 2,}} INTO test_transaction_delete WITH {restored};
+                UPDATE test_transaction_delete SET {{a: 3,}} INTO {restored};
+                DELETE {restored} FROM test_transaction_delete;
+            COMMIT",
+            deleted = deleted,
+            restored = restored
+        ))
+        .uri("/wql/tx")
+        .to_request();
+    let resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_success());
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload(format!(
+            "SELECT * FROM test_transaction_delete ID {}",
+            deleted
+        ))
+        .uri("/wql/query")
+        .to_request();
+    let mut resp = test::call_service(&mut app, req).await;
+    assert!(!resp.take_body().as_str().contains("\"a\""));
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload(format!(
+            "SELECT * FROM test_transaction_delete ID {}",
+            restored
+        ))
+        .uri("/wql/query")
+        .to_request();
+    let mut resp = test::call_service(&mut app, req).await;
+    assert!(resp.take_body().as_str().contains("\"a\": Integer(2)"));
+    clear();
+}
+
+#[actix_rt::test]
+async fn test_update_if_version_post() {
+    let mut app = test::init_service(App::new().configure(routes)).await;
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("CREATE ENTITY test_update_version")
+        .uri("/wql/tx")
+        .to_request();
+    let _ = test::call_service(&mut app, req).await;
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("INSERT {a: 123,} INTO test_update_version")
+        .uri("/wql/tx")
+        .to_request();
+    let mut resp = test::call_service(&mut app, req).await;
+    let body = resp.take_body().as_str().to_string();
+    assert!(body.contains("version: Some(1)"));
+    let uuid = ron::de::from_str::<TxResponse>(&body)
+        .unwrap()
+        .uuid
+        .unwrap();
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload(format!(
+            "UPDATE test_update_version SET {{a: 456,}} INTO {} IF VERSION 1",
+            uuid
+        ))
+        .uri("/wql/tx")
+        .to_request();
+    let mut resp = test::call_service(&mut app, req).await;
+    assert!(resp.status().is_success());
+    assert!(resp.take_body().as_str().contains("version: Some(2)"));
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload(format!(
+            "UPDATE test_update_version CONTENT {{a: 1,}} INTO {} IF VERSION 1",
+            uuid
+        ))
+        .uri("/wql/tx")
+        .to_request();
+    let mut resp = test::call_service(&mut app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::CONFLICT);
+    assert_eq!(
+        resp.take_body().as_str(),
+        format!("(\n error_type: \"VersionConflict\",\n error_message: \"Entity test_update_version with Uuid {} is at version 2, not 1\",\n)", uuid)
+    );
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload(format!("SELECT * FROM test_update_version ID {}", uuid))
+        .uri("/wql/query")
+        .to_request();
+    let mut resp = test::call_service(&mut app, req).await;
+    let body = resp.take_body().as_str().to_string();
+    assert!(body.contains("\"a\": Integer(456)"));
+    assert!(body.contains("\"tx_version\": Integer(2)"));
+    clear();
+}
+
+#[actix_rt::test]
+async fn test_insert_with_tx_version_err() {
+    let mut app = test::init_service(App::new().configure(routes)).await;
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("CREATE ENTITY test_insert_tx_version")
+        .uri("/wql/tx")
+        .to_request();
+    let _ = test::call_service(&mut app, req).await;
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("INSERT {a: 123, tx_version: 7,} INTO test_insert_tx_version")
+        .uri("/wql/tx")
+        .to_request();
+    let mut resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_client_error());
+    assert_eq!(
+        resp.take_body().as_str(),
+        "(\n error_type: \"KeyTxVersionNotAllowed\",\n error_message: \"Key `tx_version` is not allowed\",\n)"
+    );
+    clear();
+}
+
+#[actix_rt::test]
+async fn test_insert_with_valid_from_err() {
+    let mut app = test::init_service(App::new().configure(routes)).await;
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("CREATE ENTITY test_insert_valid_from")
+        .uri("/wql/tx")
+        .to_request();
+    let _ = test::call_service(&mut app, req).await;
+
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("INSERT {a: 123, valid_from: 7,} INTO test_insert_valid_from")
+        .uri("/wql/tx")
+        .to_request();
+    let mut resp = test::call_service(&mut app, req).await;
+
+    assert!(resp.status().is_client_error());
+    assert_eq!(
+        resp.take_body().as_str(),
+        "(\n error_type: \"KeyValidTimeNotAllowed\",\n error_message: \"Keys `valid_from` and `valid_to` are not allowed, use VALID FROM ... TO ...\",\n)"
+    );
+    clear();
+}
+
+#[actix_rt::test]
+async fn test_concurrent_inserts_group_commit_ok() {
+    let executor = Executor::with_durability(
+        default_storage(),
+        Durability::GroupCommit,
+        Duration::from_millis(50),
+    )
+    .start();
+    let mut app = test::init_service(App::new().data(executor).configure(routes)).await;
+    let req = test::TestRequest::post()
+        .header("Content-Type", "application/wql")
+        .set_payload("CREATE ENTITY test_concurrent_inserts")
+        .uri("/wql/tx")
+        .to_request();
+    let _ = test::call_service(&mut app, req).await;
+
+    let inserts = (0..8)
+        .map(|i| {
+            let req = test::TestRequest::post()
+                .header("Content-Type", "application/wql")
+                .set_payload(format!("INSERT {{a: {},}} INTO test_concurrent_inserts", i))
+                .uri("/wql/tx")
+                .to_request();
+            app.call(req)
+        })
+        .collect::<Vec<_>>();
+    let mut inserted = HashMap::new();
+    for (i, resp) in futures::future::join_all(inserts)
+        .await
+        .into_iter()
+        .enumerate()
+    {
+        let mut resp = resp.unwrap();
+        assert!(resp.status().is_success());
+        let body = resp.take_body().as_str().to_string();
+        let response: TxResponse = ron::de::from_str(&body).unwrap();
+        inserted.insert(response.uuid.unwrap(), i);
+    }
+
+    for (uuid, i) in inserted {
+        let req = test::TestRequest::post()
+            .header("Content-Type", "application/wql")
+            .set_payload(format!(
+                "Select * FROM test_concurrent_inserts ID {} WHEN AT {}",
+                uuid,
+                Utc::now().to_rfc3339()
+            ))
+            .uri("/wql/query")
+            .to_request();
+        let mut resp = test::call_service(&mut app, req).await;
+
+        assert!(resp.status().is_success());
+        let body = resp.take_body().as_str().to_string();
+        assert!(body.contains(&format!("\"a\": Integer({})", i)));
+    }
+    clear();
+}
+
 trait BodyTest {
     fn as_str(&self) -> &str;
 }
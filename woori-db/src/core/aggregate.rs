@@ -0,0 +1,414 @@
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+
+use bigdecimal::{BigDecimal, FromPrimitive, ToPrimitive, Zero};
+use chrono::{DateTime, TimeZone, Utc};
+use wql::{Algebra, Clause, Function, Types};
+
+use crate::{core::query::get_path, schemas::query::Response as QueryResponse};
+
+/// Aggregate functions requested in `functions`, with the key of their result, like
+/// `sum_a` for `SUM a`.
+pub(crate) fn aggregates(functions: &HashMap<String, Algebra>) -> Vec<(String, &Algebra)> {
+    functions
+        .values()
+        .filter_map(|function| Some((function.aggregate_name()?, function)))
+        .collect()
+}
+
+/// Whether the states of a `GROUP BY` bucket of `count` entities satisfy the `HAVING`
+/// clauses in `functions`, `true` when there are none. `count` includes the entities
+/// without a state, which aren't aggregated.
+pub(crate) fn having(
+    states: &[&HashMap<String, Types>],
+    count: usize,
+    functions: &HashMap<String, Algebra>,
+) -> bool {
+    match functions.get("HAVING") {
+        Some(Algebra::Having(clauses)) => clauses
+            .iter()
+            .all(|clause| satisfies(states, count, clause)),
+        _ => true,
+    }
+}
+
+fn satisfies(states: &[&HashMap<String, Types>], count: usize, clause: &Clause) -> bool {
+    let value = |name: &str| {
+        if name == "count" {
+            Types::Integer(count as isize)
+        } else {
+            Algebra::from_aggregate_name(name)
+                .map_or(Types::Nil, |function| compute(&function, states))
+        }
+    };
+    let is_within = |value: &Types, start: &Types, end: &Types| {
+        matches!(
+            compare(value, start),
+            Some(Ordering::Greater) | Some(Ordering::Equal)
+        ) && matches!(
+            compare(value, end),
+            Some(Ordering::Less) | Some(Ordering::Equal)
+        )
+    };
+
+    match clause {
+        Clause::SimpleComparisonFunction(function, name, expected) => {
+            let ordering = compare(&value(name), expected);
+            match function {
+                Function::Eq => ordering == Some(Ordering::Equal),
+                Function::NotEq => ordering != Some(Ordering::Equal),
+                Function::G => ordering == Some(Ordering::Greater),
+                Function::GEq => {
+                    matches!(ordering, Some(Ordering::Greater) | Some(Ordering::Equal))
+                }
+                Function::L => ordering == Some(Ordering::Less),
+                Function::LEq => matches!(ordering, Some(Ordering::Less) | Some(Ordering::Equal)),
+                _ => false,
+            }
+        }
+        Clause::ComplexComparisonFunctions(Function::In, name, set) => {
+            let value = value(name);
+            set.iter()
+                .any(|expected| compare(&value, expected) == Some(Ordering::Equal))
+        }
+        Clause::ComplexComparisonFunctions(Function::Between, name, start_end)
+            if start_end.len() == 2 =>
+        {
+            is_within(&value(name), &start_end[0], &start_end[1])
+        }
+        Clause::Or(_, clauses) => clauses
+            .iter()
+            .any(|clause| satisfies(states, count, clause)),
+        _ => false,
+    }
+}
+
+/// Order of two values, comparing numbers of any type by their value.
+fn compare(a: &Types, b: &Types) -> Option<Ordering> {
+    match (number(a), number(b), a, b) {
+        (Some((a, _)), Some((b, _)), _, _) => Some(a.cmp(&b)),
+        (_, _, Types::DateTime(a), Types::DateTime(b)) => Some(a.cmp(b)),
+        _ if a == b => Some(Ordering::Equal),
+        _ => a.partial_cmp(b),
+    }
+}
+
+/// Results of `aggregates` over `states`, or over each of their `GROUP BY` buckets that
+/// satisfy `HAVING`. With `COUNT` the results also have the `count` of states aggregated.
+pub(crate) fn aggregate_states<'a>(
+    states: impl Iterator<Item = &'a HashMap<String, Types>>,
+    functions: &HashMap<String, Algebra>,
+    aggregates: &[(String, &Algebra)],
+    should_count: bool,
+) -> QueryResponse {
+    if let Some(Algebra::GroupBy(k)) = functions.get("GROUP") {
+        let mut groups: HashMap<String, Vec<&HashMap<String, Types>>> = HashMap::new();
+        for state in states {
+            let key = get_path(state, k).unwrap_or(&Types::Nil);
+            groups.entry(format!("{:?}", key)).or_default().push(state);
+        }
+        QueryResponse::GroupAggregate(
+            groups
+                .into_iter()
+                .filter(|(_, states)| having(states, states.len(), functions))
+                .map(|(key, states)| (key, aggregate(&states, aggregates, should_count)))
+                .collect(),
+        )
+    } else {
+        let states = states.collect::<Vec<&HashMap<String, Types>>>();
+        QueryResponse::Aggregate(aggregate(&states, aggregates, should_count))
+    }
+}
+
+fn aggregate(
+    states: &[&HashMap<String, Types>],
+    aggregates: &[(String, &Algebra)],
+    should_count: bool,
+) -> HashMap<String, Types> {
+    let mut results = aggregates
+        .iter()
+        .map(|(label, function)| (label.to_owned(), compute(function, states)))
+        .collect::<HashMap<String, Types>>();
+    if should_count {
+        results.insert("count".to_string(), Types::Integer(states.len() as isize));
+    }
+    results
+}
+
+fn compute(function: &Algebra, states: &[&HashMap<String, Types>]) -> Types {
+    match function {
+        Algebra::Sum(key) => sum(values(states, key)),
+        Algebra::Avg(key) => avg(values(states, key)),
+        Algebra::Min(key) => extreme(values(states, key), Ordering::Less),
+        Algebra::Max(key) => extreme(values(states, key), Ordering::Greater),
+        Algebra::CountDistinct(key) => {
+            let distinct = values(states, key)
+                .filter(|value| **value != Types::Nil)
+                .collect::<HashSet<&Types>>();
+            Types::Integer(distinct.len() as isize)
+        }
+        _ => Types::Nil,
+    }
+}
+
+fn values<'a>(
+    states: &'a [&'a HashMap<String, Types>],
+    key: &'a str,
+) -> impl Iterator<Item = &'a Types> + Clone + 'a {
+    states.iter().filter_map(move |state| get_path(state, key))
+}
+
+/// Widest type among the numbers aggregated, which is the type of their sum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    Integer,
+    Float,
+    Precise,
+}
+
+/// `Integer`, `Float` and `Precise` values as exact decimals. Values of other types, and
+/// floats that aren't finite, are `None`.
+fn number(value: &Types) -> Option<(BigDecimal, Kind)> {
+    Some(match value {
+        Types::Integer(i) => (BigDecimal::from_i64(*i as i64)?, Kind::Integer),
+        Types::Float(f) => (BigDecimal::from_f64(*f)?, Kind::Float),
+        Types::Precise(p) => (BigDecimal::from_str(p).ok()?, Kind::Precise),
+        _ => return None,
+    })
+}
+
+fn numbers<'a>(values: impl Iterator<Item = &'a Types>) -> (Vec<(BigDecimal, &'a Types)>, Kind) {
+    let mut kind = Kind::Integer;
+    let numbers = values
+        .filter_map(|value| {
+            let (number, value_kind) = number(value)?;
+            kind = kind.max(value_kind);
+            Some((number, value))
+        })
+        .collect();
+    (numbers, kind)
+}
+
+fn dates<'a>(values: impl Iterator<Item = &'a Types>) -> Vec<(DateTime<Utc>, &'a Types)> {
+    values
+        .filter_map(|value| match value {
+            Types::DateTime(date) => Some((*date, value)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Sum of the numbers, `Nil` when there are none.
+fn sum<'a>(values: impl Iterator<Item = &'a Types>) -> Types {
+    let (numbers, kind) = numbers(values);
+    if numbers.is_empty() {
+        return Types::Nil;
+    }
+    let total = numbers
+        .into_iter()
+        .fold(BigDecimal::zero(), |total, (number, _)| total + number);
+
+    match kind {
+        Kind::Integer => total
+            .to_isize()
+            .map_or_else(|| Types::Precise(total.to_string()), Types::Integer),
+        Kind::Float => total.to_f64().map_or(Types::Nil, Types::Float),
+        Kind::Precise => Types::Precise(total.to_string()),
+    }
+}
+
+/// Mean of the numbers, `Precise` when one of them is and `Float` otherwise. Without
+/// numbers, it is the mean of the dates.
+fn avg<'a>(values: impl Iterator<Item = &'a Types> + Clone) -> Types {
+    let (numbers, kind) = numbers(values.clone());
+    if !numbers.is_empty() {
+        let len = BigDecimal::from(numbers.len() as u64);
+        let total = numbers
+            .into_iter()
+            .fold(BigDecimal::zero(), |total, (number, _)| total + number);
+        let mean = total / len;
+        return match kind {
+            Kind::Precise => Types::Precise(mean.to_string()),
+            Kind::Integer | Kind::Float => mean.to_f64().map_or(Types::Nil, Types::Float),
+        };
+    }
+
+    let dates = dates(values);
+    if dates.is_empty() {
+        return Types::Nil;
+    }
+    let micros = dates
+        .iter()
+        .map(|(date, _)| i128::from(date.timestamp_micros()))
+        .sum::<i128>()
+        / dates.len() as i128;
+    let (secs, micros) = (micros.div_euclid(1_000_000), micros.rem_euclid(1_000_000));
+    Utc.timestamp_opt(secs as i64, micros as u32 * 1_000)
+        .single()
+        .map_or(Types::Nil, Types::DateTime)
+}
+
+/// Smallest (`Ordering::Less`) or largest (`Ordering::Greater`) of the numbers, or of the
+/// dates when there are no numbers.
+fn extreme<'a>(values: impl Iterator<Item = &'a Types> + Clone, ordering: Ordering) -> Types {
+    let (numbers, _) = numbers(values.clone());
+    let extreme = if numbers.is_empty() {
+        dates(values)
+            .into_iter()
+            .reduce(|a, b| if b.0.cmp(&a.0) == ordering { b } else { a })
+            .map(|(_, value)| value)
+    } else {
+        numbers
+            .into_iter()
+            .reduce(|a, b| if b.0.cmp(&a.0) == ordering { b } else { a })
+            .map(|(_, value)| value)
+    };
+    extreme.cloned().unwrap_or(Types::Nil)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn state(key: &str, value: Types) -> HashMap<String, Types> {
+        [(key.to_string(), value)].iter().cloned().collect()
+    }
+
+    #[test]
+    fn aggregates_numbers_by_their_widest_type() {
+        let integers = [
+            Types::Integer(1),
+            Types::Integer(4),
+            Types::String("a".into()),
+        ];
+        assert_eq!(sum(integers.iter()), Types::Integer(5));
+        assert_eq!(avg(integers.iter()), Types::Float(2.5));
+        assert_eq!(extreme(integers.iter(), Ordering::Less), Types::Integer(1));
+
+        let floats = [Types::Integer(1), Types::Float(0.5)];
+        assert_eq!(sum(floats.iter()), Types::Float(1.5));
+        assert_eq!(extreme(floats.iter(), Ordering::Greater), Types::Integer(1));
+
+        let precise = [
+            Types::Precise(
+                "98347883122138743294728345738925783257325789353593473247832493483478935673.5"
+                    .into(),
+            ),
+            Types::Integer(1),
+        ];
+        assert_eq!(
+            sum(precise.iter()),
+            Types::Precise(
+                "98347883122138743294728345738925783257325789353593473247832493483478935674.5"
+                    .into()
+            )
+        );
+        assert_eq!(sum([Types::Nil].iter()), Types::Nil);
+    }
+
+    #[test]
+    fn aggregates_dates() {
+        let dates = [
+            Types::DateTime("2021-01-01T00:00:00Z".parse().unwrap()),
+            Types::DateTime("2021-01-03T00:00:00Z".parse().unwrap()),
+        ];
+        assert_eq!(
+            avg(dates.iter()),
+            Types::DateTime("2021-01-02T00:00:00Z".parse().unwrap())
+        );
+        assert_eq!(extreme(dates.iter(), Ordering::Greater), dates[1]);
+        assert_eq!(sum(dates.iter()), Types::Nil);
+    }
+
+    #[test]
+    fn aggregates_per_group() {
+        let states = [
+            state("a", Types::Integer(1)),
+            state("a", Types::Integer(1)),
+            state("a", Types::Integer(3)),
+        ];
+        let functions: HashMap<String, Algebra> = [
+            ("sum_a".to_string(), Algebra::Sum("a".to_string())),
+            (
+                "count_distinct_a".to_string(),
+                Algebra::CountDistinct("a".to_string()),
+            ),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        let aggregates = aggregates(&functions);
+
+        match aggregate_states(states.iter(), &functions, &aggregates, true) {
+            QueryResponse::Aggregate(results) => {
+                assert_eq!(results["sum_a"], Types::Integer(5));
+                assert_eq!(results["count_distinct_a"], Types::Integer(2));
+                assert_eq!(results["count"], Types::Integer(3));
+            }
+            _ => panic!(),
+        }
+
+        let mut group_functions = functions.clone();
+        group_functions.insert("GROUP".to_string(), Algebra::GroupBy("a".to_string()));
+        match aggregate_states(states.iter(), &group_functions, &aggregates, false) {
+            QueryResponse::GroupAggregate(groups) => {
+                assert_eq!(groups["Integer(1)"]["sum_a"], Types::Integer(2));
+                assert_eq!(groups["Integer(3)"]["sum_a"], Types::Integer(3));
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn having_compares_count_and_aggregates() {
+        let states = [state("a", Types::Integer(1)), state("a", Types::Float(2.5))];
+        let states = states.iter().collect::<Vec<_>>();
+        let having_functions = |clauses: Vec<Clause>| -> HashMap<String, Algebra> {
+            [("HAVING".to_string(), Algebra::Having(clauses))]
+                .iter()
+                .cloned()
+                .collect()
+        };
+
+        assert!(having(&states, states.len(), &HashMap::new()));
+        assert!(having(
+            &states,
+            states.len(),
+            &having_functions(vec![
+                Clause::SimpleComparisonFunction(Function::Eq, "count".into(), Types::Integer(2)),
+                Clause::SimpleComparisonFunction(Function::GEq, "sum_a".into(), Types::Integer(3)),
+            ])
+        ));
+        assert!(!having(
+            &states,
+            states.len(),
+            &having_functions(vec![Clause::ComplexComparisonFunctions(
+                Function::Between,
+                "max_a".into(),
+                vec![Types::Integer(3), Types::Integer(5)],
+            )])
+        ));
+        assert!(having(
+            &states,
+            states.len(),
+            &having_functions(vec![Clause::Or(
+                Function::Or,
+                vec![
+                    Clause::SimpleComparisonFunction(
+                        Function::G,
+                        "count".into(),
+                        Types::Integer(5)
+                    ),
+                    Clause::ComplexComparisonFunctions(
+                        Function::In,
+                        "min_a".into(),
+                        vec![Types::Float(1.0)],
+                    ),
+                ],
+            )])
+        ));
+    }
+}
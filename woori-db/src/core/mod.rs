@@ -2,13 +2,14 @@ extern crate wql as ewql;
 use std::collections::HashMap;
 
 use chrono::{DateTime, Utc};
-use ewql::Types;
+use ewql::{Types, Valid};
 use ron::ser::PrettyConfig;
 
 use crate::model::error::Error;
 
+pub(crate) mod aggregate;
 pub(crate) mod query;
-pub(crate) mod registry;
+pub(crate) mod replay;
 pub(crate) mod wql;
 
 pub fn pretty_config_output() -> PrettyConfig {
@@ -24,9 +25,63 @@ pub fn pretty_config_inner() -> PrettyConfig {
         .new_line("".to_string())
 }
 
+/// Key of the entity map holding the version of the entity id, incremented by every
+/// transaction on it.
+pub const TX_VERSION: &str = "tx_version";
+
+/// Keys of the entity map holding the valid-time interval of the state, set by
+/// `VALID FROM ... TO ...`.
+pub const VALID_FROM: &str = "valid_from";
+pub const VALID_TO: &str = "valid_to";
+
 pub fn tx_time(content: &HashMap<String, Types>) -> Result<DateTime<Utc>, Error> {
     if content.contains_key("tx_time") {
         return Err(Error::KeyTxTimeNotAllowed);
     }
+    if content.contains_key(TX_VERSION) {
+        return Err(Error::KeyTxVersionNotAllowed);
+    }
+    if content.contains_key(VALID_FROM) || content.contains_key(VALID_TO) {
+        return Err(Error::KeyValidTimeNotAllowed);
+    }
     Ok(Utc::now())
 }
+
+/// Version of an entity state, `0` for states written before versions existed.
+pub fn tx_version(state: &HashMap<String, Types>) -> usize {
+    match state.get(TX_VERSION) {
+        Some(Types::Integer(version)) => *version as usize,
+        _ => 0,
+    }
+}
+
+/// Increments the version of `state`, returning the new one.
+pub fn next_tx_version(state: &mut HashMap<String, Types>) -> usize {
+    let version = tx_version(state) + 1;
+    state.insert(TX_VERSION.to_owned(), Types::Integer(version as isize));
+    version
+}
+
+/// Replaces the valid-time interval of `state`. A write without one is valid from its
+/// `tx_time` on, so it doesn't keep the interval of the previous state.
+pub fn set_valid_time(state: &mut HashMap<String, Types>, valid: Option<Valid>) {
+    state.remove(VALID_FROM);
+    state.remove(VALID_TO);
+    if let Some(Valid { from, to }) = valid {
+        state.insert(VALID_FROM.to_owned(), Types::DateTime(from));
+        if let Some(to) = to {
+            state.insert(VALID_TO.to_owned(), Types::DateTime(to));
+        }
+    }
+}
+
+/// Valid-time interval of `state`, without bounds when it was written without one.
+pub fn valid_time(
+    state: &HashMap<String, Types>,
+) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+    let bound = |key| match state.get(key) {
+        Some(Types::DateTime(date)) => Some(*date),
+        _ => None,
+    };
+    (bound(VALID_FROM), bound(VALID_TO))
+}
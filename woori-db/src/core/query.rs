@@ -8,19 +8,43 @@ use uuid::Uuid;
 use wql::{Algebra, Types};
 
 use crate::{
+    core::aggregate::{aggregate_states, aggregates, having},
     model::DataRegister,
     schemas::query::{CountResponse, Response as QueryResponse},
 };
 
+/// Value at `path` in `state`. A key equal to the whole path comes first, so that states
+/// already projected by path answer it, otherwise every `.` goes one level down a `Map`
+/// key or a `Vector` index, like `address.city` or `tags.0`.
+pub(crate) fn get_path<'a>(state: &'a HashMap<String, Types>, path: &str) -> Option<&'a Types> {
+    if let Some(value) = state.get(path) {
+        return Some(value);
+    }
+    let mut segments = path.split('.');
+    let first = state.get(segments.next()?)?;
+    segments.try_fold(first, |value, segment| match value {
+        Types::Map(map) => map.get(segment),
+        Types::Vector(vec) => vec.get(segment.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
 pub(crate) fn filter_keys_and_hash(
     state: HashMap<String, Types>,
     keys: Option<HashSet<String>>,
 ) -> HashMap<String, Types> {
-    let filtered = state.into_par_iter().filter(|(_, v)| !v.is_hash());
     if let Some(keys) = keys {
-        filtered.filter(|(k, _)| keys.contains(k)).collect()
+        keys.into_par_iter()
+            .filter_map(|k| {
+                let value = get_path(&state, &k).filter(|v| !v.is_hash())?.to_owned();
+                Some((k, value))
+            })
+            .collect()
     } else {
-        filtered.collect()
+        state
+            .into_par_iter()
+            .filter(|(_, v)| !v.is_hash())
+            .collect()
     }
 }
 
@@ -71,7 +95,7 @@ pub(crate) fn dedup_states(
         let mut set: HashSet<String> = HashSet::new();
         let mut new_states: BTreeMap<Uuid, HashMap<String, Types>> = BTreeMap::new();
         for (id, state) in states {
-            let k_value = state.get(&key);
+            let k_value = get_path(&state, &key);
 
             if k.starts_with("NIL(")
                 && k_value.is_some()
@@ -81,9 +105,9 @@ pub(crate) fn dedup_states(
                 set.insert(format!("{:?}", k_value.unwrap()));
                 new_states.insert(id.to_owned(), state.to_owned());
             } else if !k.starts_with("NIL(")
-                && !set.contains(&format!("{:?}", state.get(k).unwrap_or(&Types::Nil)))
+                && !set.contains(&format!("{:?}", get_path(&state, k).unwrap_or(&Types::Nil)))
             {
-                set.insert(format!("{:?}", state.get(k).unwrap_or(&Types::Nil)));
+                set.insert(format!("{:?}", get_path(&state, k).unwrap_or(&Types::Nil)));
                 new_states.insert(id, state);
             }
         }
@@ -109,7 +133,7 @@ pub(crate) fn dedup_option_states(
         let mut new_states: BTreeMap<Uuid, Option<HashMap<String, Types>>> = BTreeMap::new();
         for (id, state) in states.iter().filter(|(_, s)| s.is_some()) {
             let some_state = state.clone().unwrap();
-            let k_value = some_state.get(&key);
+            let k_value = get_path(&some_state, &key);
 
             if k.starts_with("NIL(")
                 && k_value.is_some()
@@ -129,12 +153,18 @@ pub(crate) fn dedup_option_states(
     }
 }
 
+/// Answers `states`, already filtered by `DEDUP`, `LIMIT` and `OFFSET`, ordered, grouped
+/// or aggregated as `functions` ask.
 pub(crate) fn get_result_after_manipulation(
     states: BTreeMap<Uuid, HashMap<String, Types>>,
     functions: &HashMap<String, wql::Algebra>,
     should_count: bool,
 ) -> QueryResponse {
-    if let (Some(Algebra::OrderBy(k, ord)), None) = (functions.get("ORDER"), functions.get("GROUP"))
+    let aggregates = aggregates(functions);
+    if !aggregates.is_empty() {
+        aggregate_states(states.values(), functions, &aggregates, should_count)
+    } else if let (Some(Algebra::OrderBy(k, ord)), None) =
+        (functions.get("ORDER"), functions.get("GROUP"))
     {
         let mut states = states
             .into_par_iter()
@@ -142,14 +172,14 @@ pub(crate) fn get_result_after_manipulation(
             .collect::<Vec<(Uuid, HashMap<String, Types>)>>();
         if ord == &wql::Order::Asc {
             states.sort_by(|a, b| {
-                a.1.get(k)
-                    .partial_cmp(&b.1.get(k))
+                get_path(&a.1, k)
+                    .partial_cmp(&get_path(&b.1, k))
                     .unwrap_or(Ordering::Less)
             });
         } else {
             states.sort_by(|a, b| {
-                b.1.get(k)
-                    .partial_cmp(&a.1.get(k))
+                get_path(&b.1, k)
+                    .partial_cmp(&get_path(&a.1, k))
                     .unwrap_or(Ordering::Less)
             });
         }
@@ -162,12 +192,19 @@ pub(crate) fn get_result_after_manipulation(
     } else if let Some(Algebra::GroupBy(k)) = functions.get("GROUP") {
         let mut groups: HashMap<String, BTreeMap<Uuid, HashMap<String, Types>>> = HashMap::new();
         for (id, state) in states {
-            let key = state.get(k).unwrap_or(&Types::Nil);
+            let key = get_path(&state, k).unwrap_or(&Types::Nil);
             let g = groups
                 .entry(format!("{:?}", key))
                 .or_insert_with(BTreeMap::new);
             (*g).insert(id, state);
         }
+        groups.retain(|_, states| {
+            having(
+                &states.values().collect::<Vec<_>>(),
+                states.len(),
+                functions,
+            )
+        });
         if let Some(Algebra::OrderBy(k, ord)) = functions.get("ORDER") {
             let mut group_states = groups
                 .into_par_iter()
@@ -187,8 +224,8 @@ pub(crate) fn get_result_after_manipulation(
                     .iter_mut()
                     .map(|(key, states)| {
                         states.sort_by(|a, b| {
-                            a.1.get(k)
-                                .partial_cmp(&b.1.get(k))
+                            get_path(&a.1, k)
+                                .partial_cmp(&get_path(&b.1, k))
                                 .unwrap_or(Ordering::Less)
                         });
                         (key.to_owned(), states.to_owned())
@@ -201,8 +238,8 @@ pub(crate) fn get_result_after_manipulation(
                     .iter_mut()
                     .map(|(key, states)| {
                         states.sort_by(|a, b| {
-                            b.1.get(k)
-                                .partial_cmp(&a.1.get(k))
+                            get_path(&b.1, k)
+                                .partial_cmp(&get_path(&a.1, k))
                                 .unwrap_or(Ordering::Less)
                         });
                         (key.to_owned(), states.to_owned())
@@ -229,7 +266,16 @@ pub(crate) fn get_result_after_manipulation_for_options(
     functions: &HashMap<String, wql::Algebra>,
     should_count: bool,
 ) -> QueryResponse {
-    if let (Some(Algebra::OrderBy(k, ord)), None) = (functions.get("ORDER"), functions.get("GROUP"))
+    let aggregates = aggregates(functions);
+    if !aggregates.is_empty() {
+        aggregate_states(
+            states.values().flatten(),
+            functions,
+            &aggregates,
+            should_count,
+        )
+    } else if let (Some(Algebra::OrderBy(k, ord)), None) =
+        (functions.get("ORDER"), functions.get("GROUP"))
     {
         let states = states
             .into_par_iter()
@@ -242,14 +288,14 @@ pub(crate) fn get_result_after_manipulation_for_options(
             .collect::<Vec<(Uuid, HashMap<String, Types>)>>();
         if ord == &wql::Order::Asc {
             states.sort_by(|a, b| {
-                a.1.get(k)
-                    .partial_cmp(&b.1.get(k))
+                get_path(&a.1, k)
+                    .partial_cmp(&get_path(&b.1, k))
                     .unwrap_or(Ordering::Less)
             });
         } else {
             states.sort_by(|a, b| {
-                b.1.get(k)
-                    .partial_cmp(&a.1.get(k))
+                get_path(&b.1, k)
+                    .partial_cmp(&get_path(&a.1, k))
                     .unwrap_or(Ordering::Less)
             });
         }
@@ -264,7 +310,7 @@ pub(crate) fn get_result_after_manipulation_for_options(
             HashMap::new();
         for (id, state) in states {
             if let Some(s) = state {
-                let key = s.get(k).unwrap_or(&Types::Nil);
+                let key = get_path(&s, k).unwrap_or(&Types::Nil);
 
                 let g = groups
                     .entry(format!("{:?}", key))
@@ -279,6 +325,13 @@ pub(crate) fn get_result_after_manipulation_for_options(
                 (*g).insert(id, None);
             }
         }
+        groups.retain(|_, states| {
+            having(
+                &states.values().flatten().collect::<Vec<_>>(),
+                states.len(),
+                functions,
+            )
+        });
         if let Some(Algebra::OrderBy(k, ord)) = functions.get("ORDER") {
             let mut group_states = groups
                 .into_par_iter()
@@ -299,8 +352,8 @@ pub(crate) fn get_result_after_manipulation_for_options(
                     .iter_mut()
                     .map(|(key, states)| {
                         states.sort_by(|a, b| {
-                            a.1.get(k)
-                                .partial_cmp(&b.1.get(k))
+                            get_path(&a.1, k)
+                                .partial_cmp(&get_path(&b.1, k))
                                 .unwrap_or(Ordering::Less)
                         });
                         (key.to_owned(), states.to_owned())
@@ -313,8 +366,8 @@ pub(crate) fn get_result_after_manipulation_for_options(
                     .iter_mut()
                     .map(|(key, states)| {
                         states.sort_by(|a, b| {
-                            b.1.get(k)
-                                .partial_cmp(&a.1.get(k))
+                            get_path(&b.1, k)
+                                .partial_cmp(&get_path(&a.1, k))
                                 .unwrap_or(Ordering::Less)
                         });
                         (key.to_owned(), states.to_owned())
@@ -335,3 +388,65 @@ pub(crate) fn get_result_after_manipulation_for_options(
         states.into()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn values_at_key_paths() {
+        let address: HashMap<String, Types> =
+            vec![("city".to_string(), Types::String("Seoul".to_string()))]
+                .into_iter()
+                .collect();
+        let state: HashMap<String, Types> = vec![
+            ("address".to_string(), Types::Map(address)),
+            (
+                "tags".to_string(),
+                Types::Vector(vec![Types::Integer(1), Types::Integer(2)]),
+            ),
+            ("a.b".to_string(), Types::Boolean(true)),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(
+            get_path(&state, "address.city"),
+            Some(&Types::String("Seoul".to_string()))
+        );
+        assert_eq!(get_path(&state, "tags.1"), Some(&Types::Integer(2)));
+        assert_eq!(get_path(&state, "a.b"), Some(&Types::Boolean(true)));
+        assert_eq!(get_path(&state, "tags.2"), None);
+        assert_eq!(get_path(&state, "address.city.name"), None);
+        assert_eq!(get_path(&state, "address.street"), None);
+    }
+
+    #[test]
+    fn having_counts_ids_without_state() {
+        let state: HashMap<String, Types> = vec![("a".to_string(), Types::Integer(1))]
+            .into_iter()
+            .collect();
+        let states: BTreeMap<Uuid, Option<HashMap<String, Types>>> =
+            vec![(Uuid::new_v4(), Some(state)), (Uuid::new_v4(), None)]
+                .into_iter()
+                .collect();
+        let functions: HashMap<String, Algebra> = vec![
+            ("GROUP".to_string(), Algebra::GroupBy("c".to_string())),
+            (
+                "HAVING".to_string(),
+                Algebra::Having(vec![wql::Clause::SimpleComparisonFunction(
+                    wql::Function::Eq,
+                    "count".to_string(),
+                    Types::Integer(2),
+                )]),
+            ),
+        ]
+        .into_iter()
+        .collect();
+
+        match get_result_after_manipulation_for_options(states, &functions, false) {
+            QueryResponse::OptionGroupBy(groups) => assert_eq!(groups["Nil"].len(), 2),
+            _ => panic!(),
+        }
+    }
+}
@@ -0,0 +1,565 @@
+use std::collections::HashMap;
+
+use ron::ser::to_string_pretty;
+use wql::Types;
+
+use crate::{
+    actors::encrypts::WriteWithEncryption,
+    core::pretty_config_inner,
+    io::{
+        read,
+        record::{encode, Records},
+        storage::Storage,
+        write,
+    },
+    model::{
+        error::Error,
+        log::{EntitySchema, LogRecord},
+        wql::Action,
+        DataRegister,
+    },
+    repository::{
+        index::IndexDefinitions,
+        local::{EncryptContext, LocalContext, LocalDelta, UniquenessContext},
+    },
+};
+
+/// Records of an open `BEGIN ... COMMIT` block with their offsets and registers.
+type PendingRecords = Vec<(usize, LogRecord, DataRegister)>;
+
+/// When set to `true`, the server rebuilds its state from the day logs at startup.
+pub const RECOVER_FROM_LOGS: &str = "RECOVER_FROM_LOGS";
+
+#[derive(Debug, Default)]
+pub struct RecoveredContext {
+    pub local_data: LocalContext,
+    pub uniqueness: UniquenessContext,
+    pub encryption: EncryptContext,
+    pub indexes: IndexDefinitions,
+    pub offset: usize,
+}
+
+/// Loads the state the server starts with. It comes from the persisted state files, plus
+/// the records they miss, unless `RECOVER_FROM_LOGS=true`, in which case it is rebuilt
+/// from the day logs and persisted.
+pub fn startup_context(storage: &dyn Storage) -> RecoveredContext {
+    // Followers always rebuild their state from the logs they replicated.
+    let recover = std::env::var(RECOVER_FROM_LOGS).is_ok_and(|flag| flag == "true")
+        || crate::config::config().leader.is_some();
+    if recover {
+        match replay_logs(storage).and_then(|context| persist(storage, &context).map(|_| context)) {
+            Ok(context) => return context,
+            Err(e) => log::error!("Failed to recover state from logs: {}", e),
+        }
+    }
+
+    let local_data = read::local_data(storage).unwrap_or_else(|e| {
+        log::warn!(
+            "Failed to read local data, starting with an empty state: {}. Set {}=true to rebuild it from the logs",
+            e,
+            RECOVER_FROM_LOGS
+        );
+        LocalContext::new()
+    });
+    let mut context = RecoveredContext {
+        local_data,
+        uniqueness: read::unique_data(storage).unwrap_or_default(),
+        encryption: read::encryption(storage).unwrap_or_default(),
+        indexes: read::index_data(storage).unwrap_or_default(),
+        offset: read::offset(storage).unwrap_or(0),
+    };
+    if let Err(e) = replay_unjournaled(storage, &mut context) {
+        log::error!(
+            "Failed to replay the records past the journaled offset: {}",
+            e
+        );
+    }
+    context
+}
+
+/// Applies the records of the latest day log written after `context.offset`, the last
+/// offset journaled, which a crash between the append and the journal write leaves out
+/// of the persisted state. An offset past the end of the log belongs to an earlier day,
+/// so the whole log is replayed.
+fn replay_unjournaled(storage: &dyn Storage, context: &mut RecoveredContext) -> Result<(), Error> {
+    let file_name = match storage.list_logs()?.pop() {
+        Some((file_name, false)) => file_name,
+        _ => return Ok(()),
+    };
+    let bytes = storage.read_log(&file_name)?;
+    if context.offset == bytes.len() {
+        return Ok(());
+    }
+    let from = if context.offset < bytes.len() {
+        context.offset
+    } else {
+        0
+    };
+
+    log::warn!(
+        "Replaying {} bytes of {} that were not journaled",
+        bytes.len() - from,
+        file_name
+    );
+    let valid_len = replay_log(context, &file_name, &bytes, from);
+    truncate_torn_tail(storage, &file_name, &bytes, valid_len)?;
+    context.offset = valid_len;
+    persist(storage, context)
+}
+
+/// Replays every day log, oldest first. Records that cannot be read or applied are logged
+/// and skipped, and a torn tail of the latest log is truncated so writes can resume.
+/// Records of a `BEGIN ... COMMIT` block only apply once its `COMMIT` is read.
+pub fn replay_logs(storage: &dyn Storage) -> Result<RecoveredContext, Error> {
+    let logs = storage.list_logs()?;
+    let mut context = RecoveredContext::default();
+
+    for (i, (file_name, is_archived)) in logs.iter().enumerate() {
+        let bytes = storage.read_log(file_name)?;
+        let valid_len = replay_log(&mut context, file_name, &bytes, 0);
+        if i == logs.len() - 1 && !is_archived {
+            truncate_torn_tail(storage, file_name, &bytes, valid_len)?;
+            context.offset = valid_len;
+        }
+    }
+
+    Ok(context)
+}
+
+/// Applies the committed records of `bytes` from `from` onwards to `context`, returning
+/// the length of the log that holds complete and committed records.
+fn replay_log(context: &mut RecoveredContext, file_name: &str, bytes: &[u8], from: usize) -> usize {
+    let mut records = Records::<LogRecord>::starting_at(bytes, from);
+    let mut block: Option<(usize, PendingRecords)> = None;
+
+    while let Some((offset, record)) = records.next() {
+        let register = DataRegister {
+            file_name: file_name.to_owned(),
+            offset,
+            bytes_length: records.valid_len() - offset,
+        };
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                unrecoverable(file_name, offset, &e);
+                continue;
+            }
+        };
+        match (&record.action, &mut block) {
+            (Action::Begin, _) => {
+                if let Some((start, _)) = block.replace((offset, Vec::new())) {
+                    uncommitted(file_name, start);
+                }
+            }
+            (Action::Commit, Some(_)) => {
+                let (_, pending) = block.take().unwrap_or_default();
+                for (offset, record, register) in pending {
+                    if let Err(e) = apply(context, record, register) {
+                        unrecoverable(file_name, offset, &e);
+                    }
+                }
+            }
+            (_, Some((_, pending))) => pending.push((offset, record, register)),
+            (_, None) => {
+                if let Err(e) = apply(context, record, register) {
+                    unrecoverable(file_name, offset, &e);
+                }
+            }
+        }
+    }
+
+    match block {
+        Some((start, _)) => {
+            uncommitted(file_name, start);
+            start
+        }
+        None => records.valid_len(),
+    }
+}
+
+fn truncate_torn_tail(
+    storage: &dyn Storage,
+    file_name: &str,
+    bytes: &[u8],
+    valid_len: usize,
+) -> Result<(), Error> {
+    if valid_len < bytes.len() {
+        log::warn!(
+            "Truncating {} torn bytes at the end of {}",
+            bytes.len() - valid_len,
+            file_name
+        );
+        storage.truncate_log(file_name, valid_len)?;
+    }
+    Ok(())
+}
+
+fn unrecoverable(file_name: &str, offset: usize, e: &Error) {
+    log::error!(
+        "Unrecoverable record at offset {} of {}: {}",
+        offset,
+        file_name,
+        e
+    );
+}
+
+fn uncommitted(file_name: &str, offset: usize) {
+    log::warn!(
+        "Discarding the transaction block at offset {} of {}, it was never committed",
+        offset,
+        file_name
+    );
+}
+
+/// Applies a committed record to `context`, returning the change it made to the local
+/// data. `CREATE INDEX` only changes the index definitions.
+pub(crate) fn apply(
+    context: &mut RecoveredContext,
+    record: LogRecord,
+    register: DataRegister,
+) -> Result<Option<LocalDelta>, Error> {
+    let delta = match record.action {
+        Action::CreateEntity => {
+            let EntitySchema { uniques, encrypts } = match &record.content {
+                Some(content) => {
+                    ron::de::from_str(content).map_err(|_| Error::FailedToParseState)?
+                }
+                None => EntitySchema::default(),
+            };
+            context.local_data.entry(record.entity.clone()).or_default();
+            if !uniques.is_empty() {
+                let entity_uniques = context.uniqueness.entry(record.entity.clone()).or_default();
+                for name in uniques {
+                    entity_uniques.entry(name).or_default();
+                }
+            }
+            if !encrypts.is_empty() {
+                context
+                    .encryption
+                    .entry(record.entity.clone())
+                    .or_insert_with(|| encrypts.into_iter().collect());
+            }
+            LocalDelta::CreateEntity(record.entity)
+        }
+        Action::Insert | Action::UpdateSet | Action::UpdateContent | Action::Delete => {
+            let uuid = record.uuid.ok_or(Error::FailedToParseState)?;
+            let state: HashMap<String, Types> = record
+                .state
+                .as_ref()
+                .and_then(|state| ron::de::from_str(state).ok())
+                .ok_or(Error::FailedToParseState)?;
+            let entity = context
+                .local_data
+                .get_mut(&record.entity)
+                .ok_or_else(|| Error::EntityNotCreated(record.entity.clone()))?;
+            if record.action != Action::Insert && !entity.contains_key(&uuid) {
+                return Err(Error::UuidNotCreatedForEntity(record.entity, uuid));
+            }
+            let encoded = bincode::serialize(&state).map_err(|_| Error::FailedToParseState)?;
+            entity.insert(uuid, (register.clone(), encoded.clone()));
+
+            if record.action != Action::Delete {
+                if let Some(uniques) = context.uniqueness.get_mut(&record.entity) {
+                    for (key, value) in &state {
+                        if let Some(values) = uniques.get_mut(key) {
+                            values.insert(format!("{:?}", value));
+                        }
+                    }
+                }
+            }
+            LocalDelta::Upsert(record.entity, uuid, (register, encoded))
+        }
+        Action::CreateIndex => {
+            let keys: Vec<String> = record
+                .content
+                .as_ref()
+                .and_then(|content| ron::de::from_str(content).ok())
+                .ok_or(Error::FailedToParseState)?;
+            if !context.local_data.contains_key(&record.entity) {
+                return Err(Error::EntityNotCreated(record.entity));
+            }
+            context
+                .indexes
+                .entry(record.entity)
+                .or_default()
+                .extend(keys);
+            return Ok(None);
+        }
+        Action::EvictEntity => {
+            context.local_data.remove(&record.entity);
+            context.indexes.remove(&record.entity);
+            LocalDelta::EvictEntity(record.entity)
+        }
+        Action::EvictEntityId => {
+            let uuid = record.uuid.ok_or(Error::FailedToParseState)?;
+            if let Some(entity) = context.local_data.get_mut(&record.entity) {
+                entity.remove(&uuid);
+            }
+            LocalDelta::EvictId(record.entity, uuid)
+        }
+        Action::Read | Action::Error | Action::Begin | Action::Commit => {
+            return Err(Error::CorruptedLogRecord)
+        }
+    };
+    Ok(Some(delta))
+}
+
+/// Overwrites the persisted state files with the recovered context.
+pub(crate) fn persist(storage: &dyn Storage, context: &RecoveredContext) -> Result<(), Error> {
+    write::local_checkpoint(storage, &encode(&context.local_data)?)?;
+    write::unique_data(
+        storage,
+        &to_string_pretty(&context.uniqueness, pretty_config_inner())?,
+    )?;
+    write::index_data(
+        storage,
+        &to_string_pretty(&context.indexes, pretty_config_inner())?,
+    )?;
+    write::offset_counter(storage, context.offset)?;
+    // An empty time index is rebuilt from the recovered logs when the executor starts.
+    storage.write_file("time_index.log", &[])?;
+
+    let encrypts = context
+        .encryption
+        .iter()
+        .map(|(entity, encrypts)| {
+            to_string_pretty(
+                &WriteWithEncryption {
+                    entity: entity.to_owned(),
+                    encrypts: encrypts.iter().cloned().collect(),
+                },
+                pretty_config_inner(),
+            )
+        })
+        .collect::<Result<String, _>>()?;
+    write::encrypt_data(storage, &encrypts)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::io::storage::MemoryStorage;
+
+    fn write_log(storage: &MemoryStorage, log: &str, records: &[LogRecord]) -> Vec<u8> {
+        let bytes = records
+            .iter()
+            .flat_map(|record| encode(record).unwrap())
+            .collect::<Vec<u8>>();
+        storage.append_log(log, &bytes).unwrap();
+        bytes
+    }
+
+    fn state_record(action: Action, entity: &str, uuid: Uuid, state: &str) -> LogRecord {
+        LogRecord {
+            tx_time: Some(Utc::now()),
+            uuid: Some(uuid),
+            state: Some(state.to_owned()),
+            ..LogRecord::new(action, entity)
+        }
+    }
+
+    fn state_of(context: &RecoveredContext, entity: &str, uuid: &Uuid) -> HashMap<String, Types> {
+        bincode::deserialize(&context.local_data[entity][uuid].1).unwrap()
+    }
+
+    #[test]
+    fn replays_state_across_days() {
+        let storage = MemoryStorage::new();
+        let (kept, evicted) = (Uuid::new_v4(), Uuid::new_v4());
+        let create = LogRecord {
+            content: Some("(uniques: [\"a\",],encrypts: [\"pswd\",],)".to_owned()),
+            ..LogRecord::new(Action::CreateEntity, "replay")
+        };
+        write_log(
+            &storage,
+            "2021_01_01.log",
+            &[
+                create,
+                LogRecord {
+                    content: Some("[\"a\",]".to_owned()),
+                    ..LogRecord::new(Action::CreateIndex, "replay")
+                },
+                state_record(Action::Insert, "replay", kept, "{\"a\": Integer(1),}"),
+                state_record(Action::Insert, "replay", evicted, "{\"a\": Integer(2),}"),
+            ],
+        );
+        let day_two = write_log(
+            &storage,
+            "2021_01_02.log",
+            &[
+                state_record(
+                    Action::UpdateSet,
+                    "replay",
+                    kept,
+                    "{\"a\": Integer(3),\"b\": Integer(4),}",
+                ),
+                LogRecord {
+                    uuid: Some(evicted),
+                    ..LogRecord::new(Action::EvictEntityId, "replay")
+                },
+            ],
+        );
+
+        let context = replay_logs(&storage).unwrap();
+
+        assert_eq!(context.local_data["replay"].len(), 1);
+        let state = state_of(&context, "replay", &kept);
+        assert_eq!(state["a"], Types::Integer(3));
+        assert_eq!(state["b"], Types::Integer(4));
+        let register = &context.local_data["replay"][&kept].0;
+        assert_eq!(register.file_name, "2021_01_02.log");
+        assert_eq!(register.offset, 0);
+        assert_eq!(context.uniqueness["replay"]["a"].len(), 3);
+        assert!(context.encryption["replay"].contains("pswd"));
+        assert!(context.indexes["replay"].contains("a"));
+        assert_eq!(context.offset, day_two.len());
+    }
+
+    #[test]
+    fn skips_bad_records_and_truncates_torn_tail() {
+        let storage = MemoryStorage::new();
+        let uuid = Uuid::new_v4();
+        let records = [
+            LogRecord::new(Action::CreateEntity, "replay"),
+            state_record(Action::Insert, "not_created", uuid, "{}"),
+            state_record(Action::Insert, "replay", uuid, "{\"a\": Integer(1),}"),
+        ];
+        let valid_len = write_log(&storage, "2021_01_01.log", &records).len();
+        storage
+            .append_log("2021_01_01.log", &encode(&records[2]).unwrap()[..5])
+            .unwrap();
+
+        let context = replay_logs(&storage).unwrap();
+        let file_len = storage.read_log("2021_01_01.log").unwrap().len();
+
+        assert!(!context.local_data.contains_key("not_created"));
+        assert_eq!(state_of(&context, "replay", &uuid)["a"], Types::Integer(1));
+        assert_eq!(context.offset, valid_len);
+        assert_eq!(file_len, valid_len);
+    }
+
+    #[test]
+    fn applies_committed_blocks_only() {
+        let storage = MemoryStorage::new();
+        let (committed, uncommitted) = (Uuid::new_v4(), Uuid::new_v4());
+        let marker = |action| LogRecord {
+            tx_time: Some(Utc::now()),
+            ..LogRecord::new(action, "")
+        };
+        let valid_len = write_log(
+            &storage,
+            "2021_01_01.log",
+            &[
+                LogRecord::new(Action::CreateEntity, "replay"),
+                marker(Action::Begin),
+                state_record(Action::Insert, "replay", committed, "{\"a\": Integer(1),}"),
+                marker(Action::Commit),
+            ],
+        )
+        .len();
+        write_log(
+            &storage,
+            "2021_01_01.log",
+            &[
+                marker(Action::Begin),
+                state_record(
+                    Action::Insert,
+                    "replay",
+                    uncommitted,
+                    "{\"a\": Integer(2),}",
+                ),
+            ],
+        );
+
+        let context = replay_logs(&storage).unwrap();
+        let file_len = storage.read_log("2021_01_01.log").unwrap().len();
+
+        assert_eq!(
+            state_of(&context, "replay", &committed)["a"],
+            Types::Integer(1)
+        );
+        assert!(!context.local_data["replay"].contains_key(&uncommitted));
+        assert_eq!(context.offset, valid_len);
+        assert_eq!(file_len, valid_len);
+    }
+
+    #[test]
+    fn startup_applies_records_past_the_journaled_offset() {
+        let storage = MemoryStorage::new();
+        let (journaled, lost) = (Uuid::new_v4(), Uuid::new_v4());
+        let offset = write_log(
+            &storage,
+            "2021_01_01.log",
+            &[
+                LogRecord::new(Action::CreateEntity, "replay"),
+                state_record(Action::Insert, "replay", journaled, "{\"a\": Integer(1),}"),
+            ],
+        )
+        .len();
+        let mut context = replay_logs(&storage).unwrap();
+        persist(&storage, &context).unwrap();
+        let tail = write_log(
+            &storage,
+            "2021_01_01.log",
+            &[state_record(
+                Action::Insert,
+                "replay",
+                lost,
+                "{\"a\": Integer(2),}",
+            )],
+        );
+
+        context = startup_context(&storage);
+
+        assert_eq!(
+            state_of(&context, "replay", &journaled)["a"],
+            Types::Integer(1)
+        );
+        assert_eq!(state_of(&context, "replay", &lost)["a"], Types::Integer(2));
+        assert_eq!(context.local_data["replay"][&lost].0.offset, offset);
+        assert_eq!(context.offset, offset + tail.len());
+        assert_eq!(read::offset(&storage).unwrap(), offset + tail.len());
+        assert!(read::local_data(&storage).unwrap()["replay"].contains_key(&lost));
+    }
+
+    #[test]
+    fn time_entries_of_committed_states() {
+        let storage = MemoryStorage::new();
+        let (single, committed, uncommitted) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let marker = |action| LogRecord {
+            tx_time: Some(Utc::now()),
+            ..LogRecord::new(action, "")
+        };
+        write_log(
+            &storage,
+            "2021_01_01.log",
+            &[
+                LogRecord::new(Action::CreateEntity, "replay"),
+                state_record(Action::Insert, "replay", single, "{\"a\": Integer(1),}"),
+                marker(Action::Begin),
+                state_record(Action::Insert, "replay", committed, "{\"a\": Integer(2),}"),
+                marker(Action::Commit),
+                marker(Action::Begin),
+                state_record(
+                    Action::Insert,
+                    "replay",
+                    uncommitted,
+                    "{\"a\": Integer(3),}",
+                ),
+            ],
+        );
+
+        let entries = read::time_entries(&storage).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].uuid, single);
+        assert_eq!(entries[1].uuid, committed);
+        let record = read::read_log(&storage, entries[1].register.clone()).unwrap();
+        assert_eq!(record.state.unwrap(), "{\"a\": Integer(2),}");
+    }
+}
@@ -10,97 +10,112 @@ use crate::{
         UpdateSetEntityContent,
     },
     core::pretty_config_inner,
-    model::wql::Action,
+    model::{
+        log::{EntitySchema, LogRecord},
+        wql::Action,
+    },
 };
-use ron::ser::to_string_pretty;
 
-pub fn create_entity(entity: &str) -> String {
-    format!("{}|{};", Action::CreateEntity, entity)
+pub fn create_entity(entity: &str, uniques: Vec<String>, encrypts: Vec<String>) -> LogRecord {
+    let content = if uniques.is_empty() && encrypts.is_empty() {
+        None
+    } else {
+        let schema = EntitySchema { uniques, encrypts };
+        ron::ser::to_string_pretty(&schema, pretty_config_inner()).ok()
+    };
+
+    LogRecord {
+        tx_time: Some(Utc::now()),
+        content,
+        ..LogRecord::new(Action::CreateEntity, entity)
+    }
 }
 
-pub fn evict_entity_content(entity: &str) -> String {
+pub fn create_index(entity: &str, keys: &[String]) -> LogRecord {
+    LogRecord {
+        tx_time: Some(Utc::now()),
+        content: ron::ser::to_string_pretty(keys, pretty_config_inner()).ok(),
+        ..LogRecord::new(Action::CreateIndex, entity)
+    }
+}
+
+pub fn evict_entity_content(entity: &str) -> (DateTime<Utc>, LogRecord) {
     let date: DateTime<Utc> = Utc::now();
-    let date = to_string_pretty(&date, pretty_config_inner()).unwrap();
-    format!("{}|{}|{};", Action::EvictEntity, date, entity)
+    let log = LogRecord {
+        tx_time: Some(date),
+        ..LogRecord::new(Action::EvictEntity, entity)
+    };
+    (date, log)
 }
 
-pub fn evict_entity_id_content(entity: &EvictEntityId) -> String {
+pub fn evict_entity_id_content(entity: &EvictEntityId) -> (DateTime<Utc>, LogRecord) {
     let date: DateTime<Utc> = Utc::now();
-    let date = to_string_pretty(&date, pretty_config_inner()).unwrap();
-    format!(
-        "{}|{}|{}|{};",
-        Action::EvictEntityId,
-        date,
-        entity.id,
-        entity.name
-    )
+    let log = LogRecord {
+        tx_time: Some(date),
+        uuid: Some(entity.id),
+        ..LogRecord::new(Action::EvictEntityId, &entity.name)
+    };
+    (date, log)
 }
 
-pub fn insert_entity_content(content: &InsertEntityContent) -> (DateTime<Utc>, Uuid, String) {
+/// `BEGIN` or `COMMIT` marker around the records of a transaction block.
+pub fn transaction_marker(action: Action) -> LogRecord {
+    LogRecord {
+        tx_time: Some(Utc::now()),
+        ..LogRecord::new(action, "")
+    }
+}
+
+pub fn insert_entity_content(content: &InsertEntityContent) -> (DateTime<Utc>, Uuid, LogRecord) {
     let uuid = content.uuid.map_or_else(Uuid::new_v4, |id| id);
 
     let date = content.datetime;
-    let date_str = to_string_pretty(&date, pretty_config_inner()).unwrap();
-    let log = format!(
-        "{}|{}|{}|{}|{};",
-        Action::Insert,
-        date_str,
-        uuid.to_string(),
-        content.name,
-        content.content
-    );
+    let log = LogRecord {
+        tx_time: Some(date),
+        uuid: Some(uuid),
+        state: Some(content.content.to_owned()),
+        ..LogRecord::new(Action::Insert, &content.name)
+    };
     (date, uuid, log)
 }
 
-pub fn update_set_entity_content(content: &UpdateSetEntityContent) -> (DateTime<Utc>, String) {
-    let uuid = content.id;
+pub fn update_set_entity_content(content: &UpdateSetEntityContent) -> (DateTime<Utc>, LogRecord) {
     let date = content.datetime;
-    let date_str = to_string_pretty(&date, pretty_config_inner()).unwrap();
-    let log = format!(
-        "{}|{}|{}|{}|{}|{}|{};",
-        Action::UpdateSet,
-        date_str,
-        uuid.to_string(),
-        content.name,
-        content.content_log,
-        content.current_state,
-        content.previous_registry
-    );
+    let log = LogRecord {
+        tx_time: Some(date),
+        uuid: Some(content.id),
+        content: Some(content.content_log.to_owned()),
+        state: Some(content.current_state.to_owned()),
+        previous_registry: Some(content.previous_registry.to_owned()),
+        ..LogRecord::new(Action::UpdateSet, &content.name)
+    };
     (date, log)
 }
 
 pub fn update_content_entity_content(
     content: &UpdateContentEntityContent,
-) -> (DateTime<Utc>, String) {
-    let uuid = content.id;
+) -> (DateTime<Utc>, LogRecord) {
     let date: DateTime<Utc> = Utc::now();
-    let date_str = to_string_pretty(&date, pretty_config_inner()).unwrap();
-    let log = format!(
-        "{}|{}|{}|{}|{}|{}|{};",
-        Action::UpdateContent,
-        date_str,
-        uuid.to_string(),
-        content.name,
-        content.content_log,
-        content.current_state,
-        content.previous_registry
-    );
+    let log = LogRecord {
+        tx_time: Some(date),
+        uuid: Some(content.id),
+        content: Some(content.content_log.to_owned()),
+        state: Some(content.current_state.to_owned()),
+        previous_registry: Some(content.previous_registry.to_owned()),
+        ..LogRecord::new(Action::UpdateContent, &content.name)
+    };
     (date, log)
 }
 
-pub fn delete_entity_content(content: &DeleteId) -> (DateTime<Utc>, String) {
+pub fn delete_entity_content(content: &DeleteId) -> (DateTime<Utc>, LogRecord) {
     let date: DateTime<Utc> = Utc::now();
-    let date_str = to_string_pretty(&date, pretty_config_inner()).unwrap();
-
-    let log = format!(
-        "{}|{}|{}|{}|{}|{};",
-        Action::Delete,
-        date_str,
-        content.uuid.to_string(),
-        content.name,
-        content.content_log,
-        content.previous_registry
-    );
+    let log = LogRecord {
+        tx_time: Some(date),
+        uuid: Some(content.uuid),
+        state: Some(content.content_log.to_owned()),
+        previous_registry: Some(content.previous_registry.to_owned()),
+        ..LogRecord::new(Action::Delete, &content.name)
+    };
     (date, log)
 }
 
@@ -180,8 +195,16 @@ mod test {
 
     #[test]
     fn create_entity_test() {
-        let s = create_entity(&"my_entity".to_string());
-        assert_eq!(s, "CREATE_ENTITY|my_entity;");
+        let s = create_entity(&"my_entity".to_string(), Vec::new(), Vec::new()).to_string();
+        assert!(s.starts_with("CREATE_ENTITY|"));
+        assert!(s.ends_with("|my_entity;"));
+    }
+
+    #[test]
+    fn create_entity_with_schema_test() {
+        let s = create_entity("my_entity", vec!["id".to_string()], Vec::new()).to_string();
+        assert!(s.starts_with("CREATE_ENTITY|"));
+        assert!(s.ends_with("|my_entity|(uniques: [\"id\",],encrypts: [],);"));
     }
 
     #[test]
@@ -193,6 +216,7 @@ mod test {
             datetime: Utc::now(),
         };
         let (_, _, s) = insert_entity_content(&entity);
+        let s = s.to_string();
 
         assert!(s.contains("INSERT"));
         assert!(s.contains("my_entity"));
@@ -209,6 +233,7 @@ mod test {
             datetime: Utc::now(),
         };
         let (_, _, s) = insert_entity_content(&entity);
+        let s = s.to_string();
 
         assert!(s.contains("INSERT"));
         assert!(s.contains("my_entity"));
@@ -229,6 +254,7 @@ mod test {
         };
 
         let (_, s) = update_set_entity_content(&entity);
+        let s = s.to_string();
         assert!(s.contains("UPDATE_SET"));
         assert!(s.contains("my-entity"));
         assert!(s.contains("state"));
@@ -248,6 +274,7 @@ mod test {
         };
 
         let (_, s) = update_content_entity_content(&entity);
+        let s = s.to_string();
         assert!(s.contains("UPDATE_CONTENT"));
         assert!(s.contains("my-entity"));
         assert!(s.contains("state"));
@@ -266,6 +293,7 @@ mod test {
         };
 
         let (_, s) = delete_entity_content(&entity);
+        let s = s.to_string();
         assert!(s.contains("DELETE"));
         assert!(s.contains("my-entity"));
         assert!(s.contains("log"));
@@ -276,7 +304,7 @@ mod test {
     fn evict_entity_test() {
         let entity = "hello";
 
-        let actual = evict_entity_content(&entity);
+        let actual = evict_entity_content(&entity).1.to_string();
 
         assert!(actual.starts_with("EVICT_ENTITY"));
         assert!(actual.contains("hello"))
@@ -290,7 +318,7 @@ mod test {
             id: uuid,
         };
 
-        let actual = evict_entity_id_content(&entity);
+        let actual = evict_entity_id_content(&entity).1.to_string();
 
         assert!(actual.starts_with("EVICT_ENTITY_ID"));
         assert!(actual.contains("hello"));
@@ -0,0 +1,361 @@
+use std::collections::HashMap;
+
+use uuid::Uuid;
+use wql::Types;
+
+use crate::{
+    config::config,
+    core::replay::{persist, RecoveredContext},
+    io::{
+        read,
+        record::{decode, Records},
+        storage::{FsStorage, Storage},
+    },
+    model::{
+        error::Error,
+        log::{EntitySchema, LogRecord},
+        wql::Action,
+        DataRegister,
+    },
+};
+
+/// Problems found by an integrity check.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub logs: usize,
+    pub records: usize,
+    /// `log@offset: reason` of every record that can't be read or breaks the grammar.
+    pub bad_records: Vec<String>,
+    /// `entity/uuid: reason` of every register not pointing to its record.
+    pub dangling_registers: Vec<String>,
+    /// Entities in `unique_data.log` that don't exist.
+    pub orphan_uniques: Vec<String>,
+    /// Entities in `encrypt.log` that don't exist.
+    pub orphan_encrypts: Vec<String>,
+    /// Entities in `index_data.log` that don't exist.
+    pub orphan_indexes: Vec<String>,
+    /// Whether the dangling registers and orphan entities were removed.
+    pub repaired: bool,
+}
+
+impl Report {
+    pub fn is_clean(&self) -> bool {
+        self.bad_records.is_empty() && !self.has_repairable()
+    }
+
+    fn has_repairable(&self) -> bool {
+        !self.dangling_registers.is_empty()
+            || !self.orphan_uniques.is_empty()
+            || !self.orphan_encrypts.is_empty()
+            || !self.orphan_indexes.is_empty()
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "{} records checked in {} logs", self.records, self.logs)?;
+        let sections = [
+            ("Bad records", &self.bad_records),
+            ("Dangling registers", &self.dangling_registers),
+            ("Orphan unique entities", &self.orphan_uniques),
+            ("Orphan encrypted entities", &self.orphan_encrypts),
+            ("Orphan indexed entities", &self.orphan_indexes),
+        ];
+        for (title, issues) in sections.iter().filter(|(_, issues)| !issues.is_empty()) {
+            writeln!(f, "{} ({}):", title, issues.len())?;
+            for issue in issues.iter() {
+                writeln!(f, "  {}", issue)?;
+            }
+        }
+        if self.repaired {
+            writeln!(f, "Dangling registers and orphan entities were removed")?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks the configured data directory, removing dangling registers and orphan
+/// entities when `repair` is set.
+pub fn run(repair: bool) -> Result<Report, String> {
+    let config = config();
+    let storage = FsStorage::new(&config.data_dir);
+    let storage = match &config.archive.archive_dir {
+        Some(archive_dir) => storage.with_archive_dir(archive_dir),
+        None => storage,
+    };
+    check(&storage, repair).map_err(|e| e.to_string())
+}
+
+pub(crate) fn check(storage: &dyn Storage, repair: bool) -> Result<Report, Error> {
+    let mut report = Report::default();
+    check_logs(storage, &mut report)?;
+
+    let mut context = RecoveredContext {
+        local_data: read::local_data(storage)?,
+        uniqueness: read::unique_data(storage).unwrap_or_default(),
+        encryption: read::encryption(storage).unwrap_or_default(),
+        indexes: read::index_data(storage).unwrap_or_default(),
+        offset: read::offset(storage).unwrap_or(0),
+    };
+
+    for (entity, registers) in context.local_data.iter_mut() {
+        registers.retain(|uuid, (register, state)| {
+            match check_register(storage, entity, uuid, register, state) {
+                Ok(()) => true,
+                Err(e) => {
+                    report
+                        .dangling_registers
+                        .push(format!("{}/{}: {}", entity, uuid, e));
+                    false
+                }
+            }
+        });
+    }
+
+    let local_data = &context.local_data;
+    context.uniqueness.retain(|entity, _| {
+        let exists = local_data.contains_key(entity);
+        if !exists {
+            report.orphan_uniques.push(entity.to_owned());
+        }
+        exists
+    });
+    context.encryption.retain(|entity, _| {
+        let exists = local_data.contains_key(entity);
+        if !exists {
+            report.orphan_encrypts.push(entity.to_owned());
+        }
+        exists
+    });
+    context.indexes.retain(|entity, _| {
+        let exists = local_data.contains_key(entity);
+        if !exists {
+            report.orphan_indexes.push(entity.to_owned());
+        }
+        exists
+    });
+
+    if repair && report.has_repairable() {
+        persist(storage, &context)?;
+        report.repaired = true;
+    }
+    Ok(report)
+}
+
+fn check_logs(storage: &dyn Storage, report: &mut Report) -> Result<(), Error> {
+    for (log, _) in storage.list_logs()? {
+        let bytes = storage.read_log(&log)?;
+        report.logs += 1;
+
+        let mut block = None;
+        for (offset, record) in Records::<LogRecord>::new(&bytes) {
+            report.records += 1;
+            let checked = record
+                .map_err(|e| e.to_string())
+                .and_then(|r| validate(&r).map(|_| r.action))
+                .and_then(|action| match (action, block) {
+                    (Action::Begin, Some(_)) => Err("BEGIN inside a transaction block".to_owned()),
+                    (Action::Commit, None) => Err("COMMIT without BEGIN".to_owned()),
+                    (Action::Begin, None) => Ok(Some(offset)),
+                    (Action::Commit, Some(_)) => Ok(None),
+                    _ => Ok(block),
+                });
+            match checked {
+                Ok(open_block) => block = open_block,
+                Err(e) => report
+                    .bad_records
+                    .push(format!("{}@{}: {}", log, offset, e)),
+            }
+        }
+        if let Some(offset) = block {
+            report.bad_records.push(format!(
+                "{}@{}: transaction block without COMMIT",
+                log, offset
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Checks the record has the fields its `Action` writes.
+fn validate(record: &LogRecord) -> Result<(), String> {
+    if matches!(record.action, Action::Begin | Action::Commit) {
+        return Ok(());
+    }
+    if record.entity.is_empty() {
+        return Err("record without entity".to_owned());
+    }
+    match record.action {
+        Action::CreateEntity => match &record.content {
+            Some(content) => ron::de::from_str::<EntitySchema>(content)
+                .map(|_| ())
+                .map_err(|e| format!("invalid entity schema: {}", e)),
+            None => Ok(()),
+        },
+        Action::Insert | Action::UpdateSet | Action::UpdateContent | Action::Delete => {
+            record.uuid.ok_or("record without uuid")?;
+            record_state(record).map(|_| ())
+        }
+        Action::CreateIndex => match &record.content {
+            Some(content) => ron::de::from_str::<Vec<String>>(content)
+                .map(|_| ())
+                .map_err(|e| format!("invalid index keys: {}", e)),
+            None => Err("record without index keys".to_owned()),
+        },
+        Action::EvictEntity => Ok(()),
+        Action::EvictEntityId => record
+            .uuid
+            .map(|_| ())
+            .ok_or_else(|| "record without uuid".to_owned()),
+        Action::Read | Action::Error => Err(format!("{} is not a transaction", record.action)),
+        Action::Begin | Action::Commit => Ok(()),
+    }
+}
+
+fn record_state(record: &LogRecord) -> Result<HashMap<String, Types>, String> {
+    let state = record.state.as_ref().ok_or("record without state")?;
+    ron::de::from_str(state).map_err(|e| format!("invalid state: {}", e))
+}
+
+fn check_register(
+    storage: &dyn Storage,
+    entity: &str,
+    uuid: &Uuid,
+    register: &DataRegister,
+    state: &[u8],
+) -> Result<(), String> {
+    let bytes = storage
+        .read_log_range(&register.file_name, register.offset, register.bytes_length)
+        .map_err(|e| e.to_string())?;
+    let (record, size) = decode::<LogRecord>(&bytes)
+        .map_err(|e| format!("{}@{}: {}", register.file_name, register.offset, e))?;
+
+    if size != register.bytes_length {
+        return Err(format!(
+            "register length is {} but the record has {} bytes",
+            register.bytes_length, size
+        ));
+    }
+    if record.entity != entity || record.uuid.as_ref() != Some(uuid) {
+        return Err(format!(
+            "register points to a record of {}/{}",
+            record.entity,
+            record.uuid.map(|uuid| uuid.to_string()).unwrap_or_default()
+        ));
+    }
+    let local_state: HashMap<String, Types> =
+        bincode::deserialize(state).map_err(|_| "unreadable local state".to_owned())?;
+    if record_state(&record)? != local_state {
+        return Err("local state differs from the record state".to_owned());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        http::routes,
+        io::{record::encode, storage::test_storage, storage::MemoryStorage, write},
+        model::log::LogRecord,
+        repository::local::LocalContext,
+        schemas::tx::TxResponse,
+    };
+    use actix_web::{test, App};
+
+    async fn tx<S>(app: &mut S, wql: &str) -> TxResponse
+    where
+        S: actix_web::dev::Service<
+            Request = actix_http::Request,
+            Response = actix_web::dev::ServiceResponse,
+            Error = actix_web::Error,
+        >,
+    {
+        let req = test::TestRequest::post()
+            .header("Content-Type", "application/wql")
+            .set_payload(wql.to_owned())
+            .uri("/wql/tx")
+            .to_request();
+        let body = test::read_response(app, req).await;
+        let body = std::str::from_utf8(&body).unwrap();
+        ron::de::from_str(body).unwrap_or_else(|_| panic!("{} failed: {}", wql, body))
+    }
+
+    #[actix_rt::test]
+    async fn transactions_are_consistent() {
+        let mut app = test::init_service(App::new().configure(routes)).await;
+        tx(&mut app, "CREATE ENTITY fsck_ok UNIQUES #{a,}").await;
+        tx(&mut app, "CREATE ENTITY fsck_encrypt ENCRYPT #{pswd,}").await;
+        tx(&mut app, "INSERT {pswd: \"secret\",} INTO fsck_encrypt").await;
+        let uuid = tx(&mut app, "INSERT {a: 1,} INTO fsck_ok")
+            .await
+            .uuid
+            .unwrap();
+        tx(
+            &mut app,
+            &format!("UPDATE fsck_ok SET {{a: 2,}} INTO {}", uuid),
+        )
+        .await;
+        tx(
+            &mut app,
+            &format!("UPDATE fsck_ok CONTENT {{b: 3,}} INTO {}", uuid),
+        )
+        .await;
+        let other = tx(&mut app, "INSERT {a: 3,} INTO fsck_ok")
+            .await
+            .uuid
+            .unwrap();
+        tx(&mut app, &format!("DELETE {} FROM fsck_ok", other)).await;
+
+        let report = check(&*test_storage(), false).unwrap();
+
+        assert!(report.is_clean(), "{}", report);
+        assert_eq!(report.records, 8);
+    }
+
+    #[test]
+    fn reports_and_repairs() {
+        let storage = MemoryStorage::new();
+        let uuid = Uuid::new_v4();
+        let create = encode(&LogRecord::new(Action::CreateEntity, "fsck")).unwrap();
+        let insert = encode(&LogRecord {
+            uuid: Some(uuid),
+            state: Some("{\"a\": Integer(1),}".to_owned()),
+            ..LogRecord::new(Action::Insert, "fsck")
+        })
+        .unwrap();
+        let read = encode(&LogRecord::new(Action::Read, "fsck")).unwrap();
+        for record in [&create, &insert, &read].iter() {
+            storage.append_log("2021_01_01.log", record).unwrap();
+        }
+
+        let state =
+            bincode::serialize(&HashMap::from([("a".to_owned(), Types::Integer(1))])).unwrap();
+        let register = |offset: usize, bytes_length: usize| DataRegister {
+            file_name: "2021_01_01.log".to_owned(),
+            offset,
+            bytes_length,
+        };
+        let mut local_data = LocalContext::new();
+        let registers = local_data.entry("fsck".to_owned()).or_default();
+        registers.insert(uuid, (register(create.len(), insert.len()), state.clone()));
+        registers.insert(Uuid::new_v4(), (register(0, create.len()), state));
+        write::local_checkpoint(&storage, &encode(&local_data).unwrap()).unwrap();
+        write::unique_data(&storage, "{\"fsck\": {}, \"evicted\": {},}").unwrap();
+
+        let report = check(&storage, true).unwrap();
+        assert_eq!(report.records, 3);
+        assert_eq!(report.bad_records.len(), 1);
+        assert!(report.bad_records[0]
+            .starts_with(&format!("2021_01_01.log@{}", create.len() + insert.len())));
+        assert_eq!(report.dangling_registers.len(), 1);
+        assert_eq!(report.orphan_uniques, vec!["evicted".to_string()]);
+        assert!(report.repaired);
+
+        let report = check(&storage, false).unwrap();
+        assert!(report.dangling_registers.is_empty());
+        assert!(report.orphan_uniques.is_empty());
+        assert_eq!(report.bad_records.len(), 1);
+        assert!(!report.repaired);
+    }
+}
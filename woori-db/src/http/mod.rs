@@ -2,20 +2,22 @@
 use crate::auth::{
     controllers as auth,
     io::read_admin_info,
-    middlewares::{history_validator, wql_validator},
+    middlewares::{changes_validator, history_validator, replication_validator, wql_validator},
 };
 #[cfg(not(debug_assertions))]
 use actix_web_httpauth::middleware::HttpAuthentication;
 
+use crate::controllers::{changes, query, replication, tx};
 use crate::{
-    actors::{scheduler::Scheduler, wql::Executor},
+    actors::{
+        replication::{Follower, FollowerContext},
+        scheduler::Scheduler,
+        wql::Executor,
+    },
     controllers::entity_history,
-    io::read::{encryption, local_data, offset, unique_data},
-    repository::local::{LocalContext, SessionContext, UniquenessContext},
-};
-use crate::{
-    controllers::{query, tx},
-    repository::local::EncryptContext,
+    core::replay::{startup_context, RecoveredContext},
+    io::storage::default_storage,
+    repository::{index::IndexContext, local::SessionContext, locks::IdLocks},
 };
 use actix::Actor;
 use actix_web::{get, guard, web, HttpResponse, Responder};
@@ -41,14 +43,40 @@ pub async fn readiness() -> impl Responder {
 }
 
 pub fn routes(config: &mut web::ServiceConfig) {
-    let local_context = local_data().map_or(LocalContext::new(), |map| map);
-    let encrypt_context = encryption().map_or(EncryptContext::new(), |e| e);
-    let uniqueness = unique_data().map_or(UniquenessContext::new(), |u| u);
-    let wql_context = Arc::new(Mutex::new(local_context));
+    let storage = default_storage();
+    let RecoveredContext {
+        local_data,
+        uniqueness,
+        encryption,
+        indexes,
+        offset,
+    } = startup_context(&*storage);
+    let index_context = Arc::new(Mutex::new(IndexContext::new(indexes, &local_data)));
+    let wql_context = Arc::new(Mutex::new(local_data));
     let unique_context = Arc::new(Mutex::new(uniqueness));
-    let encrypt_context = Arc::new(Mutex::new(encrypt_context));
-    let write_offset = AtomicUsize::new(offset().map_or(0_usize, |o| o));
-    let actor = Executor::new().start();
+    let encrypt_context = Arc::new(Mutex::new(encryption));
+    let write_offset = AtomicUsize::new(offset);
+    let actor = Executor::new(storage.clone()).start();
+    if let Some(leader) = &crate::config::config().leader {
+        let context = FollowerContext {
+            local_data: wql_context.clone(),
+            uniqueness: unique_context.clone(),
+            encryption: encrypt_context.clone(),
+            indexes: index_context.clone(),
+        };
+        let follower = Follower::new(
+            leader,
+            crate::config::config().replication_token.clone(),
+            std::time::Duration::from_millis(crate::config::config().replication_interval_ms),
+            storage.clone(),
+            actor.clone(),
+            context,
+        )
+        .expect("Failed to find the position to replicate the leader from");
+        follower.start();
+    }
+    let tx_gate = tokio::sync::RwLock::new(());
+    let id_locks = IdLocks::default();
     let env_cost = std::env::var("HASHING_COST").unwrap_or_else(|_| "14".to_owned());
     let cost = env_cost.parse::<u32>().expect("HASHING_COST must be a u32");
 
@@ -63,25 +91,38 @@ pub fn routes(config: &mut web::ServiceConfig) {
     #[cfg(not(debug_assertions))]
     let admin_info = read_admin_info().unwrap();
 
-    Scheduler.start();
+    #[cfg_attr(debug_assertions, allow(unused_variables))]
+    let scheduler =
+        Scheduler::new(storage.clone(), crate::config::config().archive.clone()).start();
 
     #[cfg(not(debug_assertions))]
     let wql_auth = HttpAuthentication::bearer(wql_validator);
     #[cfg(not(debug_assertions))]
     let history_auth = HttpAuthentication::bearer(history_validator);
+    #[cfg(not(debug_assertions))]
+    let changes_auth = HttpAuthentication::bearer(changes_validator);
+    #[cfg(not(debug_assertions))]
+    let replication_auth = HttpAuthentication::bearer(replication_validator);
 
     #[cfg(not(debug_assertions))]
     config
         .data(session_context)
         .data(wql_context)
+        .data(index_context)
         .data(actor)
+        .data(storage)
+        .data(tx_gate)
+        .data(id_locks)
         .service(
             web::scope("/auth")
                 .data(admin_info)
                 .data(exp_time)
+                .data(scheduler)
                 .route("/createUser", web::post().to(auth::create_user))
                 .route("/deleteUsers", web::post().to(auth::delete_users))
-                .route("/putUserSession", web::put().to(auth::put_user_session)),
+                .route("/putUserSession", web::put().to(auth::put_user_session))
+                .route("/archive", web::post().to(auth::archive))
+                .route("/backup", web::post().to(auth::backup)),
         )
         .service(
             web::scope("/wql")
@@ -99,13 +140,26 @@ pub fn routes(config: &mut web::ServiceConfig) {
                 .wrap(history_auth)
                 .route("", web::post().to(entity_history::history_handler)),
         )
+        .service(
+            web::scope("/changes")
+                .wrap(changes_auth)
+                .route("", web::get().to(changes::changes_handler)),
+        )
+        .service(
+            web::scope("/replication")
+                .wrap(replication_auth)
+                .route("/log", web::get().to(replication::log_handler)),
+        )
         .route("", web::get().to(HttpResponse::NotFound));
 
     #[cfg(debug_assertions)]
     config
         .data(session_context)
         .data(wql_context)
+        .data(index_context)
         .data(actor)
+        .data(tx_gate)
+        .data(id_locks)
         .service(
             web::scope("/wql")
                 .guard(guard::Header("Content-Type", "application/wql"))
@@ -120,6 +174,8 @@ pub fn routes(config: &mut web::ServiceConfig) {
             "/entity-history",
             web::post().to(entity_history::history_handler),
         )
+        .route("/changes", web::get().to(changes::changes_handler))
+        .route("/replication/log", web::get().to(replication::log_handler))
         .route("", web::get().to(HttpResponse::NotFound));
 }
 
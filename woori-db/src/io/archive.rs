@@ -0,0 +1,219 @@
+use std::{
+    convert::TryInto,
+    io::{Read, Seek, SeekFrom, Write},
+};
+
+use crate::model::error::Error;
+
+/// Uncompressed bytes of a day log held by each archive frame.
+pub const FRAME_SIZE: usize = 64 * 1024;
+
+/// The frame index follows the zstd seekable format: a skippable frame holding the
+/// compressed and decompressed size of every frame, so `zstd -d` still reads archives.
+const SKIPPABLE_MAGIC: u32 = 0x184D_2A5E;
+const SEEKABLE_MAGIC: u32 = 0x8F92_EAB1;
+const SKIPPABLE_HEADER_SIZE: u64 = 8;
+const ENTRY_SIZE: u64 = 8;
+const FOOTER_SIZE: u64 = 9;
+
+#[derive(Debug)]
+struct Frame {
+    compressed_offset: u64,
+    compressed_size: usize,
+    offset: usize,
+    size: usize,
+}
+
+/// Compresses `reader` at zstd `level` into independently decodable frames of
+/// `FRAME_SIZE` bytes, followed by the frame index.
+pub fn write_archive(
+    mut reader: impl Read,
+    mut writer: impl Write,
+    level: i32,
+) -> Result<(), Error> {
+    let mut table = Vec::new();
+    let mut frames = 0u32;
+    let mut buffer = Vec::with_capacity(FRAME_SIZE);
+    loop {
+        buffer.clear();
+        reader
+            .by_ref()
+            .take(FRAME_SIZE as u64)
+            .read_to_end(&mut buffer)?;
+        if buffer.is_empty() {
+            break;
+        }
+
+        let frame = zstd::bulk::compress(&buffer, level)?;
+        writer.write_all(&frame)?;
+        table.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        table.extend_from_slice(&(buffer.len() as u32).to_le_bytes());
+        frames += 1;
+    }
+
+    table.extend_from_slice(&frames.to_le_bytes());
+    table.push(0);
+    table.extend_from_slice(&SEEKABLE_MAGIC.to_le_bytes());
+    writer.write_all(&SKIPPABLE_MAGIC.to_le_bytes())?;
+    writer.write_all(&(table.len() as u32).to_le_bytes())?;
+    writer.write_all(&table)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Reads `length` bytes at `offset` of the archived log, decompressing only the frames
+/// holding them. Archives written before the frame index are decompressed whole.
+pub fn read_range(
+    mut reader: impl Read + Seek,
+    offset: usize,
+    length: usize,
+) -> Result<Vec<u8>, Error> {
+    let frames = match frames(&mut reader)? {
+        Some(frames) => frames,
+        None => {
+            reader.seek(SeekFrom::Start(0))?;
+            let bytes = zstd::stream::decode_all(reader)?;
+            return Ok(bytes.into_iter().skip(offset).take(length).collect());
+        }
+    };
+
+    let end = offset + length;
+    let mut res = Vec::with_capacity(length);
+    for frame in frames
+        .iter()
+        .filter(|frame| frame.offset < end && frame.offset + frame.size > offset)
+    {
+        let mut compressed = vec![0; frame.compressed_size];
+        reader.seek(SeekFrom::Start(frame.compressed_offset))?;
+        reader.read_exact(&mut compressed)?;
+        let bytes = zstd::bulk::decompress(&compressed, frame.size)?;
+
+        let start = offset.saturating_sub(frame.offset);
+        let stop = (end - frame.offset).min(bytes.len());
+        res.extend_from_slice(&bytes[start..stop]);
+    }
+
+    Ok(res)
+}
+
+/// Decompresses the whole archive, with or without a frame index.
+pub fn read_all(reader: impl Read) -> Result<Vec<u8>, Error> {
+    Ok(zstd::stream::decode_all(reader)?)
+}
+
+fn frames(reader: &mut (impl Read + Seek)) -> Result<Option<Vec<Frame>>, Error> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    if len < SKIPPABLE_HEADER_SIZE + FOOTER_SIZE {
+        return Ok(None);
+    }
+
+    let mut footer = [0u8; FOOTER_SIZE as usize];
+    reader.seek(SeekFrom::Start(len - FOOTER_SIZE))?;
+    reader.read_exact(&mut footer)?;
+    if u32::from_le_bytes(footer[5..9].try_into().unwrap()) != SEEKABLE_MAGIC {
+        return Ok(None);
+    }
+
+    let count = u64::from(u32::from_le_bytes(footer[..4].try_into().unwrap()));
+    let table_size = count * ENTRY_SIZE + FOOTER_SIZE;
+    let table_start = (len - SKIPPABLE_HEADER_SIZE)
+        .checked_sub(table_size)
+        .ok_or(Error::CorruptedLogRecord)?;
+    let mut table = vec![0u8; (SKIPPABLE_HEADER_SIZE + table_size) as usize];
+    reader.seek(SeekFrom::Start(table_start))?;
+    reader.read_exact(&mut table)?;
+    if u32::from_le_bytes(table[..4].try_into().unwrap()) != SKIPPABLE_MAGIC {
+        return Err(Error::CorruptedLogRecord);
+    }
+
+    let mut frames = Vec::with_capacity(count as usize);
+    let (mut compressed_offset, mut offset) = (0u64, 0usize);
+    for entry in table[SKIPPABLE_HEADER_SIZE as usize..]
+        .chunks_exact(ENTRY_SIZE as usize)
+        .take(count as usize)
+    {
+        let compressed_size = u32::from_le_bytes(entry[..4].try_into().unwrap()) as usize;
+        let size = u32::from_le_bytes(entry[4..].try_into().unwrap()) as usize;
+        frames.push(Frame {
+            compressed_offset,
+            compressed_size,
+            offset,
+            size,
+        });
+        compressed_offset += compressed_size as u64;
+        offset += size;
+    }
+    if compressed_offset != table_start {
+        return Err(Error::CorruptedLogRecord);
+    }
+
+    Ok(Some(frames))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn frame_count(archive: &[u8]) -> Option<usize> {
+        frames(&mut Cursor::new(archive)).unwrap().map(|f| f.len())
+    }
+
+    fn read_range_from(archive: &[u8], offset: usize, length: usize) -> Result<Vec<u8>, Error> {
+        read_range(Cursor::new(archive), offset, length)
+    }
+
+    fn log() -> Vec<u8> {
+        (0..FRAME_SIZE * 2 + 100).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn archive(bytes: &[u8]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        write_archive(bytes, &mut archive, 22).unwrap();
+        archive
+    }
+
+    #[test]
+    fn archive_is_indexed_by_frame() {
+        let log = log();
+        let archive = archive(&log);
+
+        assert_eq!(frame_count(&archive), Some(3));
+        assert_eq!(read_all(&archive[..]).unwrap(), log);
+    }
+
+    #[test]
+    fn read_range_within_and_across_frames() {
+        let log = log();
+        let archive = archive(&log);
+
+        assert_eq!(read_range_from(&archive, 10, 20).unwrap(), &log[10..30]);
+        let across = FRAME_SIZE - 5;
+        assert_eq!(
+            read_range_from(&archive, across, FRAME_SIZE + 10).unwrap(),
+            &log[across..across + FRAME_SIZE + 10]
+        );
+        assert_eq!(
+            read_range_from(&archive, log.len() - 4, 10).unwrap(),
+            &log[log.len() - 4..]
+        );
+    }
+
+    #[test]
+    fn empty_archive() {
+        let archive = archive(&[]);
+
+        assert_eq!(frame_count(&archive), Some(0));
+        assert!(read_range_from(&archive, 0, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn legacy_archive_without_index() {
+        let log = log();
+        let archive = zstd::stream::encode_all(&log[..], 3).unwrap();
+
+        assert_eq!(frame_count(&archive), None);
+        assert_eq!(read_range_from(&archive, 100, 5).unwrap(), &log[100..105]);
+    }
+}
@@ -0,0 +1,218 @@
+use std::{
+    collections::BTreeSet,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::Duration,
+};
+
+use futures::{channel::oneshot, future, FutureExt};
+
+use crate::{config::Durability, io::storage::Storage, model::error::Error};
+
+type Commit = Pin<Box<dyn Future<Output = Result<(), Error>>>>;
+
+/// Makes appended day log records as durable as the configured `Durability` requires.
+pub struct Committer {
+    storage: Arc<dyn Storage>,
+    durability: Durability,
+    group: Option<Arc<Group>>,
+}
+
+#[derive(Default)]
+struct Group {
+    batch: Mutex<Batch>,
+    arrived: Condvar,
+}
+
+#[derive(Default)]
+struct Batch {
+    waiting: Vec<(String, oneshot::Sender<Result<(), String>>)>,
+    closed: bool,
+}
+
+impl Committer {
+    pub fn new(storage: Arc<dyn Storage>, durability: Durability, window: Duration) -> Self {
+        let group = if durability == Durability::GroupCommit {
+            let group = Arc::new(Group::default());
+            let (storage, syncer) = (storage.clone(), group.clone());
+            thread::Builder::new()
+                .name("group-commit".to_owned())
+                .spawn(move || syncer.run(&*storage, window))
+                .expect("Failed to start the group commit thread");
+            Some(group)
+        } else {
+            None
+        };
+
+        Self {
+            storage,
+            durability,
+            group,
+        }
+    }
+
+    /// Resolves once everything appended to `log` so far holds the durability guarantee.
+    pub fn commit(&self, log: &str) -> Commit {
+        match (&self.durability, &self.group) {
+            (Durability::Fsync, _) => future::ready(self.storage.sync_log(log)).boxed_local(),
+            (Durability::GroupCommit, Some(group)) => {
+                let (sender, synced) = oneshot::channel();
+                match group.batch.lock() {
+                    Ok(mut batch) => batch.waiting.push((log.to_owned(), sender)),
+                    Err(_) => return future::ready(Err(Error::LockData)).boxed_local(),
+                }
+                group.arrived.notify_one();
+
+                synced
+                    .map(|synced| match synced {
+                        Ok(Ok(())) => Ok(()),
+                        Ok(Err(e)) => Err(Error::Io(std::io::Error::other(e))),
+                        Err(_) => Err(Error::LockData),
+                    })
+                    .boxed_local()
+            }
+            _ => future::ready(Ok(())).boxed_local(),
+        }
+    }
+}
+
+impl Drop for Committer {
+    fn drop(&mut self) {
+        if let Some(group) = &self.group {
+            if let Ok(mut batch) = group.batch.lock() {
+                batch.closed = true;
+            }
+            group.arrived.notify_one();
+        }
+    }
+}
+
+impl Group {
+    /// Waits for a transaction, lets others arrive for `window`, then syncs every log
+    /// the batch wrote to once and answers all of them.
+    fn run(&self, storage: &dyn Storage, window: Duration) {
+        loop {
+            let mut batch = match self.batch.lock() {
+                Ok(batch) => batch,
+                Err(_) => return,
+            };
+            while batch.waiting.is_empty() && !batch.closed {
+                batch = match self.arrived.wait(batch) {
+                    Ok(batch) => batch,
+                    Err(_) => return,
+                };
+            }
+            if batch.waiting.is_empty() {
+                return;
+            }
+            drop(batch);
+
+            thread::sleep(window);
+            let waiting = match self.batch.lock() {
+                Ok(mut batch) => std::mem::take(&mut batch.waiting),
+                Err(_) => return,
+            };
+            let logs = waiting
+                .iter()
+                .map(|(log, _)| log.as_str())
+                .collect::<BTreeSet<&str>>();
+            let synced = logs
+                .into_iter()
+                .try_for_each(|log| storage.sync_log(log))
+                .map_err(|e| e.to_string());
+            for (_, sender) in waiting {
+                let _ = sender.send(synced.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::io::storage::MemoryStorage;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    /// Storage counting its syncs, which fail while `fail_syncs` is set.
+    #[derive(Default)]
+    pub(crate) struct CountingStorage {
+        pub(crate) storage: MemoryStorage,
+        pub(crate) syncs: AtomicUsize,
+        pub(crate) fail_syncs: AtomicBool,
+    }
+
+    impl Storage for CountingStorage {
+        fn append_log(&self, log: &str, bytes: &[u8]) -> Result<usize, Error> {
+            self.storage.append_log(log, bytes)
+        }
+        fn log_len(&self, log: &str) -> Result<usize, Error> {
+            self.storage.log_len(log)
+        }
+        fn sync_log(&self, _log: &str) -> Result<(), Error> {
+            self.syncs.fetch_add(1, Ordering::SeqCst);
+            if self.fail_syncs.load(Ordering::SeqCst) {
+                return Err(Error::Io(std::io::Error::other("sync failed")));
+            }
+            Ok(())
+        }
+        fn read_log_range(
+            &self,
+            log: &str,
+            offset: usize,
+            length: usize,
+        ) -> Result<Vec<u8>, Error> {
+            self.storage.read_log_range(log, offset, length)
+        }
+        fn read_log(&self, log: &str) -> Result<Vec<u8>, Error> {
+            self.storage.read_log(log)
+        }
+        fn truncate_log(&self, log: &str, length: usize) -> Result<(), Error> {
+            self.storage.truncate_log(log, length)
+        }
+        fn list_logs(&self) -> Result<Vec<(String, bool)>, Error> {
+            self.storage.list_logs()
+        }
+        fn archive_log(&self, log: &str, level: i32) -> Result<(), Error> {
+            self.storage.archive_log(log, level)
+        }
+        fn read_archive(&self, log: &str) -> Result<Option<Vec<u8>>, Error> {
+            self.storage.read_archive(log)
+        }
+        fn write_checkpoint(&self, bytes: &[u8]) -> Result<(), Error> {
+            self.storage.write_checkpoint(bytes)
+        }
+        fn read_checkpoint(&self) -> Result<Option<Vec<u8>>, Error> {
+            self.storage.read_checkpoint()
+        }
+        fn append_file(&self, name: &str, bytes: &[u8]) -> Result<u64, Error> {
+            self.storage.append_file(name, bytes)
+        }
+        fn write_file(&self, name: &str, bytes: &[u8]) -> Result<(), Error> {
+            self.storage.write_file(name, bytes)
+        }
+        fn read_file(&self, name: &str) -> Result<Option<Vec<u8>>, Error> {
+            self.storage.read_file(name)
+        }
+    }
+
+    async fn commit_ten(durability: Durability) -> usize {
+        let storage = Arc::new(CountingStorage::default());
+        let committer = Committer::new(storage.clone(), durability, Duration::from_millis(20));
+        let commits = (0..10)
+            .map(|_| committer.commit("2021_01_01.log"))
+            .collect::<Vec<_>>();
+        for commit in future::join_all(commits).await {
+            commit.unwrap();
+        }
+        storage.syncs.load(Ordering::SeqCst)
+    }
+
+    #[actix_rt::test]
+    async fn syncs_per_durability() {
+        assert_eq!(commit_ten(Durability::None).await, 0);
+        assert_eq!(commit_ten(Durability::Fsync).await, 10);
+        assert_eq!(commit_ten(Durability::GroupCommit).await, 1);
+    }
+}
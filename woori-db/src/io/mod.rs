@@ -1,2 +1,6 @@
+pub(crate) mod archive;
+pub(crate) mod durability;
 pub(crate) mod read;
+pub(crate) mod record;
+pub(crate) mod storage;
 pub(crate) mod write;
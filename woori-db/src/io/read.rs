@@ -1,164 +1,242 @@
-use std::{
-    collections::{BTreeMap, HashMap, HashSet},
-    fs::OpenOptions,
-    io::{BufReader, Error, Read, Seek, SeekFrom},
-};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use rayon::prelude::*;
 
-use crate::{actors::encrypts::WriteWithEncryption, model::DataRegister};
-use crate::{model::error, repository::local::LocalContext};
+use crate::{
+    actors::encrypts::WriteWithEncryption,
+    io::{
+        record::{decode, Records},
+        storage::Storage,
+    },
+    model::{log::LogRecord, wql::Action, DataRegister},
+};
+use crate::{
+    model::error,
+    repository::{index::IndexDefinitions, local::LocalContext, time_index::TimeEntry},
+};
 
 #[cfg(test)]
-pub fn assert_content(pat: &str) {
+fn test_file(name: &str) -> String {
+    let bytes = crate::io::storage::test_storage()
+        .read_file(name)
+        .unwrap()
+        .unwrap();
+    String::from_utf8(bytes).unwrap()
+}
+
+#[cfg(test)]
+fn date_log_content() -> String {
     use chrono::prelude::*;
     let utc: DateTime<Utc> = Utc::now();
-    let date_log = utc.format("data/%Y_%m_%d.log").to_string();
+    let date_log = utc.format("%Y_%m_%d.log").to_string();
+    let bytes = crate::io::storage::test_storage()
+        .read_log(&date_log)
+        .unwrap();
+
+    Records::<LogRecord>::new(&bytes)
+        .filter_map(|(_, record)| record.ok())
+        .map(|record| record.to_string())
+        .collect()
+}
 
-    let mut file = OpenOptions::new().read(true).open(date_log).unwrap();
-    let mut s = String::new();
-    file.read_to_string(&mut s).unwrap();
+#[cfg(test)]
+pub fn assert_content(pat: &str) {
+    let s = date_log_content();
 
     assert!(s.contains(pat));
 }
 
 #[cfg(test)]
 pub fn assert_not_content(pat: &str) {
-    use chrono::prelude::*;
-    let utc: DateTime<Utc> = Utc::now();
-    let date_log = utc.format("data/%Y_%m_%d.log").to_string();
-
-    let mut file = OpenOptions::new().read(true).open(date_log).unwrap();
-    let mut s = String::new();
-    file.read_to_string(&mut s).unwrap();
+    let s = date_log_content();
 
     assert!(!s.contains(pat));
 }
 
 #[cfg(test)]
 pub fn assert_uniques(pat: &str) {
-    let mut file = OpenOptions::new()
-        .read(true)
-        .open("data/uniques.log")
-        .unwrap();
-    let mut s = String::new();
-    file.read_to_string(&mut s).unwrap();
-
-    assert!(s.contains(pat));
+    assert!(test_file("uniques.log").contains(pat));
 }
 
 #[cfg(test)]
 pub fn assert_offset(pat: &str) {
-    let mut file = OpenOptions::new()
-        .read(true)
-        .open("data/offset_counter.log")
-        .unwrap();
-    let mut s = String::new();
-    file.read_to_string(&mut s).unwrap();
+    assert!(test_file("offset_counter.log").contains(pat));
+}
 
-    assert!(s.contains(pat));
+#[cfg(test)]
+pub fn assert_unique_data(pat: &str) {
+    assert!(test_file("unique_data.log").contains(pat));
 }
 
 #[cfg(test)]
-pub fn assert_local_data(pat: &str) {
-    let mut file = OpenOptions::new()
-        .read(true)
-        .open("data/local_data.log")
-        .unwrap();
-    let mut s = String::new();
-    file.read_to_string(&mut s).unwrap();
+pub fn assert_encrypt(pat: &str) {
+    assert!(test_file("encrypt.log").contains(pat));
+}
 
-    assert!(s.contains(pat));
+pub fn read_log(storage: &dyn Storage, registry: DataRegister) -> Result<LogRecord, error::Error> {
+    let res =
+        storage.read_log_range(&registry.file_name, registry.offset, registry.bytes_length)?;
+
+    Ok(decode(&res)?.0)
 }
 
-#[cfg(test)]
-pub fn assert_unique_data(pat: &str) {
-    let mut file = OpenOptions::new()
-        .read(true)
-        .open("data/unique_data.log")
-        .unwrap();
-    let mut s = String::new();
-    file.read_to_string(&mut s).unwrap();
+/// Sequence numbers of the committed transactions of a day log, in order. Records of a
+/// `BEGIN ... COMMIT` block only count once its `COMMIT` is read.
+fn committed_tx_seqs(bytes: &[u8]) -> Vec<u64> {
+    let mut committed = Vec::new();
+    let mut block = None;
+    for record in Records::<LogRecord>::new(bytes).filter_map(|(_, record)| record.ok()) {
+        match (record.action, record.tx_seq) {
+            (Action::Begin, tx_seq) => block = tx_seq,
+            (Action::Commit, _) => committed.extend(block.take()),
+            (_, Some(tx_seq)) if block.is_none() => committed.push(tx_seq),
+            _ => (),
+        }
+    }
+    committed
+}
 
-    assert!(s.contains(pat));
+/// Sequence number of the last committed transaction, `0` when there is none.
+pub fn last_tx_seq(storage: &dyn Storage) -> Result<u64, error::Error> {
+    for (log, _) in storage.list_logs()?.iter().rev() {
+        if let Some(tx_seq) = committed_tx_seqs(&storage.read_log(log)?).last() {
+            return Ok(*tx_seq);
+        }
+    }
+    Ok(0)
 }
 
-#[cfg(test)]
-pub fn assert_encrypt(pat: &str) {
-    let mut file = OpenOptions::new()
-        .read(true)
-        .open("data/encrypt.log")
-        .unwrap();
-    let mut s = String::new();
-    file.read_to_string(&mut s).unwrap();
+/// Entries of the time index journal, empty when there is none.
+pub fn time_index(storage: &dyn Storage) -> Result<Vec<TimeEntry>, error::Error> {
+    let bytes = storage.read_file("time_index.log")?.unwrap_or_default();
 
-    assert!(s.contains(pat));
+    Ok(Records::<TimeEntry>::new(&bytes)
+        .filter_map(|(_, entry)| entry.ok())
+        .collect())
 }
 
-pub fn read_log(registry: DataRegister) -> Result<String, Error> {
-    let mut res = String::with_capacity(registry.bytes_length);
-    let file_name = registry.file_name;
-
-    if let Ok(mut file) = OpenOptions::new().read(true).open(&file_name) {
-        file.seek(SeekFrom::Start(registry.offset as u64))?;
-        file.take(registry.bytes_length as u64)
-            .read_to_string(&mut res)?;
-    } else {
-        let zst_date = file_name.replace(".log", ".zst");
-        let file = OpenOptions::new().read(true).open(zst_date)?;
-        let reader = BufReader::new(file);
-        let utf8 = zstd::stream::decode_all(reader)?;
-        res = std::str::from_utf8(&utf8)
-            .unwrap_or("")
-            .chars()
-            .skip(registry.offset as usize)
-            .take(registry.bytes_length as usize)
-            .collect::<String>();
-    };
+/// Time index entries of the committed states and evictions written to every day log. Records of a
+/// `BEGIN ... COMMIT` block only count once its `COMMIT` is read.
+pub fn time_entries(storage: &dyn Storage) -> Result<Vec<TimeEntry>, error::Error> {
+    let mut entries = Vec::new();
+    for (log, _) in storage.list_logs()? {
+        let bytes = storage.read_log(&log)?;
+        let mut records = Records::<LogRecord>::new(&bytes);
+        let mut block = None;
+        while let Some((offset, record)) = records.next() {
+            let record = match record {
+                Ok(record) => record,
+                Err(_) => continue,
+            };
+            let register = DataRegister {
+                file_name: log.clone(),
+                offset,
+                bytes_length: records.valid_len() - offset,
+            };
+            match record.action {
+                Action::Begin => block = Some(Vec::new()),
+                Action::Commit => entries.extend(block.take().unwrap_or_default()),
+                _ => match (&mut block, TimeEntry::of(&record, register)) {
+                    (Some(block), Some(entry)) => block.push(entry),
+                    (None, Some(entry)) => entries.push(entry),
+                    _ => (),
+                },
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Records of the committed transactions with sequence numbers in `from..until`, in
+/// order and without the `BEGIN` and `COMMIT` markers of their blocks. Day logs are read
+/// newest first, down to the one that starts at or before `from`, since older ones only
+/// have records below it.
+pub fn committed_records(
+    storage: &dyn Storage,
+    from: u64,
+    until: u64,
+) -> Result<Vec<LogRecord>, error::Error> {
+    let mut logs = Vec::new();
+    for (log, _) in storage.list_logs()?.into_iter().rev() {
+        let bytes = storage.read_log(&log)?;
+        let first_tx_seq = Records::<LogRecord>::new(&bytes)
+            .find_map(|(_, record)| record.ok())
+            .map(|record| record.tx_seq);
+        logs.push(bytes);
+        // Records written before sequence numbers have none.
+        if first_tx_seq.is_some_and(|tx_seq| tx_seq.is_none_or(|tx_seq| tx_seq <= from)) {
+            break;
+        }
+    }
 
-    Ok(res)
+    let mut committed = Vec::new();
+    for bytes in logs.iter().rev() {
+        let mut block = None;
+        for record in Records::<LogRecord>::new(bytes).filter_map(|(_, record)| record.ok()) {
+            match (&record.action, record.tx_seq) {
+                (_, Some(tx_seq)) if tx_seq < from || tx_seq >= until => (),
+                (Action::Begin, _) => block = Some(Vec::new()),
+                (Action::Commit, _) => committed.extend(block.take().unwrap_or_default()),
+                (_, Some(_)) => match &mut block {
+                    Some(block) => block.push(record),
+                    None => committed.push(record),
+                },
+                _ => (),
+            }
+        }
+    }
+    Ok(committed)
 }
 
-pub fn read_date_log(date_log: String) -> Result<String, Error> {
-    let mut res = String::new();
-
-    if let Ok(mut file) = OpenOptions::new().read(true).open(&date_log) {
-        file.seek(SeekFrom::Start(0))?;
-        file.read_to_string(&mut res)?;
-    } else {
-        let zst_date = date_log.replace(".log", ".zst");
-        let file = OpenOptions::new().read(true).open(zst_date)?;
-        let reader = BufReader::new(file);
-        let utf8 = zstd::stream::decode_all(reader)?;
-        res = std::str::from_utf8(&utf8).unwrap_or("").to_owned();
-    };
+/// Content of an auxiliary file, failing with `NotFound` when it doesn't exist.
+fn read_file(storage: &dyn Storage, name: &str) -> Result<String, error::Error> {
+    let bytes = storage
+        .read_file(name)?
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, name.to_owned()))?;
 
-    Ok(res)
+    String::from_utf8(bytes).map_err(|_| error::Error::FailedToParseState)
 }
 
-pub fn offset() -> Result<usize, error::Error> {
+pub fn offset(storage: &dyn Storage) -> Result<usize, error::Error> {
     #[cfg(not(feature = "test_read"))]
-    let path = "data/offset_counter.log";
+    let s = read_file(storage, "offset_counter.log")?;
     #[cfg(feature = "test_read")]
-    let path = "data/offset_counter.txt";
-    let mut file = OpenOptions::new().read(true).open(path)?;
-    let mut s = String::new();
-    file.read_to_string(&mut s)?;
+    let s = read_file(storage, "offset_counter.txt")?;
 
     Ok(s.parse::<usize>()
         .map_err(|_| error::Error::FailedToParseState)?)
 }
 
-pub fn local_data() -> Result<LocalContext, error::Error> {
-    #[cfg(not(feature = "test_read"))]
-    let path = "data/local_data.log";
-    #[cfg(feature = "test_read")]
-    let path = "data/local_data.txt";
-    let mut file = OpenOptions::new().read(true).open(path)?;
-    let mut s = String::new();
-    file.read_to_string(&mut s)?;
+#[cfg(feature = "test_read")]
+pub fn local_data(storage: &dyn Storage) -> Result<LocalContext, error::Error> {
+    ron_local_data(&read_file(storage, "local_data.txt")?)
+}
+
+/// Loads the latest checkpoint, or the legacy `local_data.log` when there is none, and
+/// applies the delta journal on top of it.
+#[cfg(not(feature = "test_read"))]
+pub fn local_data(storage: &dyn Storage) -> Result<LocalContext, error::Error> {
+    use crate::repository::local::LocalDelta;
+
+    let mut data = match storage.read_checkpoint()? {
+        Some(bytes) => decode::<LocalContext>(&bytes)?.0,
+        None => match storage.read_file("local_data.log")? {
+            Some(bytes) => ron_local_data(&String::from_utf8_lossy(&bytes))?,
+            None => LocalContext::new(),
+        },
+    };
+
+    if let Some(bytes) = storage.read_file("delta.log")? {
+        Records::<LocalDelta>::new(&bytes)
+            .filter_map(|(_, delta)| delta.ok())
+            .for_each(|delta| delta.apply(&mut data));
+    }
+
+    Ok(data)
+}
 
-    let data: Result<LocalContext, error::Error> = match ron::de::from_str(&s) {
+fn ron_local_data(s: &str) -> Result<LocalContext, error::Error> {
+    let data: Result<LocalContext, error::Error> = match ron::de::from_str(s) {
         Ok(x) => Ok(x),
         Err(_) => Err(error::Error::FailedToParseState),
     };
@@ -166,14 +244,13 @@ pub fn local_data() -> Result<LocalContext, error::Error> {
     data
 }
 
-pub fn unique_data() -> Result<BTreeMap<String, HashMap<String, HashSet<String>>>, error::Error> {
+pub fn unique_data(
+    storage: &dyn Storage,
+) -> Result<BTreeMap<String, HashMap<String, HashSet<String>>>, error::Error> {
     #[cfg(not(feature = "test_read"))]
-    let path = "data/unique_data.log";
+    let s = read_file(storage, "unique_data.log")?;
     #[cfg(feature = "test_read")]
-    let path = "data/unique_data.txt";
-    let mut file = OpenOptions::new().read(true).open(path)?;
-    let mut s = String::new();
-    file.read_to_string(&mut s)?;
+    let s = read_file(storage, "unique_data.txt")?;
 
     let data: Result<BTreeMap<String, HashMap<String, HashSet<String>>>, error::Error> =
         match ron::de::from_str(&s) {
@@ -184,14 +261,21 @@ pub fn unique_data() -> Result<BTreeMap<String, HashMap<String, HashSet<String>>
     data
 }
 
-pub fn encryption() -> Result<BTreeMap<String, HashSet<String>>, error::Error> {
+pub fn index_data(storage: &dyn Storage) -> Result<IndexDefinitions, error::Error> {
+    let s = read_file(storage, "index_data.log")?;
+
+    ron::de::from_str(&s).map_err(|_| error::Error::FailedToParseState)
+}
+
+pub fn encryption(
+    storage: &dyn Storage,
+) -> Result<BTreeMap<String, HashSet<String>>, error::Error> {
     #[cfg(not(feature = "test_read"))]
-    let path = "data/encrypt.log";
+    let content = read_file(storage, "encrypt.log")?;
     #[cfg(feature = "test_read")]
-    let path = "data/encrypt.txt";
-    let mut file = OpenOptions::new().read(true).open(path)?;
+    let content = read_file(storage, "encrypt.txt")?;
     let mut s = String::from('[');
-    file.read_to_string(&mut s)?;
+    s.push_str(&content);
     s.push(']');
     let s = s.replace(")(", "),(");
 
@@ -216,39 +300,94 @@ pub fn encryption() -> Result<BTreeMap<String, HashSet<String>>, error::Error> {
 #[cfg(test)]
 #[cfg(feature = "test_read")]
 mod test {
-    use std::{fs::OpenOptions, io::Write};
-
     use super::*;
-    use crate::model::DataRegister;
+    use crate::{
+        io::{record::encode, storage::test_storage},
+        model::wql::Action,
+        model::DataRegister,
+    };
 
     #[test]
     fn read_log_range() {
-        let log_size = write_new();
+        let (offset, log_size) = write_new();
         let data = DataRegister {
-            file_name: "data/read_test.log".to_string(),
-            offset: 30,
-            bytes_length: log_size - 58,
+            file_name: "read_test.log".to_string(),
+            offset,
+            bytes_length: log_size,
         };
 
-        let log = read_log(data).unwrap();
-        assert_eq!(log, "i am too lazy to create.");
+        let log = read_log(&*test_storage(), data).unwrap();
+        assert_eq!(log.to_string(), "CREATE_ENTITY|i am too lazy to create;");
     }
 
-    fn write_new() -> usize {
-        let log =
-            "this is a very long text that i am too lazy to create. Guess it is enough already.";
-        let mut file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open("data/read_test.log")
-            .unwrap();
+    fn write_new() -> (usize, usize) {
+        let storage = test_storage();
+        storage.append_log("read_test.log", b"previous").unwrap();
+        let record = encode(&LogRecord::new(
+            Action::CreateEntity,
+            "i am too lazy to create",
+        ))
+        .unwrap();
 
-        file.write(log.as_bytes()).unwrap()
+        storage.append_log("read_test.log", &record).unwrap();
+        (8, record.len())
+    }
+
+    #[test]
+    fn committed_records_in_range() {
+        let storage = crate::io::storage::MemoryStorage::default();
+        let record = |action, tx_seq| {
+            encode(&LogRecord {
+                tx_seq: Some(tx_seq),
+                ..LogRecord::new(action, "committed")
+            })
+            .unwrap()
+        };
+        let log = [
+            record(Action::CreateEntity, 1),
+            record(Action::Begin, 2),
+            record(Action::Insert, 2),
+            record(Action::Commit, 2),
+            record(Action::UpdateSet, 3),
+            record(Action::Begin, 4),
+            record(Action::Insert, 4),
+        ]
+        .concat();
+        storage.append_log("2021_01_08.log", &log).unwrap();
+        let actions = |from, until| {
+            committed_records(&storage, from, until)
+                .unwrap()
+                .into_iter()
+                .map(|record| (record.action, record.tx_seq.unwrap()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            actions(2, 5),
+            vec![(Action::Insert, 2), (Action::UpdateSet, 3)]
+        );
+        assert_eq!(
+            actions(1, 3),
+            vec![(Action::CreateEntity, 1), (Action::Insert, 2)]
+        );
+
+        // The older log is only read when `from` is below the first record of the newer one.
+        storage
+            .append_log("2021_01_07.log", &record(Action::UpdateSet, 2))
+            .unwrap();
+        assert_eq!(
+            actions(2, 5),
+            vec![(Action::Insert, 2), (Action::UpdateSet, 3)]
+        );
+        assert_eq!(
+            actions(0, 2),
+            vec![(Action::UpdateSet, 2), (Action::CreateEntity, 1)]
+        );
     }
 
     #[test]
     fn encryption_test() {
-        let encrypt = encryption().unwrap();
+        let encrypt = encryption(&*test_storage()).unwrap();
         let s = format!("{:?}", encrypt);
 
         assert!(s.contains("encrypt_ent"));
@@ -259,14 +398,14 @@ mod test {
 
     #[test]
     fn offset_test() {
-        let offset = offset();
+        let offset = offset(&*test_storage());
 
         assert_eq!(offset.unwrap(), 701);
     }
 
     #[test]
     fn local_data_test() {
-        let local_data = local_data();
+        let local_data = local_data(&*test_storage());
 
         assert!(local_data.is_ok());
         assert_eq!(
@@ -277,7 +416,7 @@ mod test {
 
     #[test]
     fn unique_data_test() {
-        let unique_data = unique_data();
+        let unique_data = unique_data(&*test_storage());
 
         assert!(unique_data.is_ok());
         let body = format!("{:?}", unique_data);
@@ -0,0 +1,283 @@
+use std::{convert::TryInto, marker::PhantomData};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::model::error::Error;
+
+/// Every record starts with `RECORD_MAGIC`, the format version, the payload length and
+/// the payload CRC32, all little endian.
+pub const RECORD_MAGIC: [u8; 2] = *b"WR";
+pub const RECORD_VERSION: u8 = 3;
+pub const HEADER_SIZE: usize = 11;
+
+/// Payload of a record. Types whose layout changed between format versions read the
+/// payloads of older versions by overriding `from_version`.
+pub trait Payload: Serialize + DeserializeOwned {
+    fn from_version(version: u8, payload: &[u8]) -> Result<Self, Error> {
+        let _ = version;
+        bincode::deserialize(payload).map_err(|_| Error::CorruptedLogRecord)
+    }
+}
+
+pub fn encode<T: Payload>(record: &T) -> Result<Vec<u8>, Error> {
+    let payload = bincode::serialize(record).map_err(|_| Error::CorruptedLogRecord)?;
+    let length = payload.len() as u32;
+    let crc = crc32fast::hash(&payload);
+
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + payload.len());
+    bytes.extend_from_slice(&RECORD_MAGIC);
+    bytes.push(RECORD_VERSION);
+    bytes.extend_from_slice(&length.to_le_bytes());
+    bytes.extend_from_slice(&crc.to_le_bytes());
+    bytes.extend_from_slice(&payload);
+    Ok(bytes)
+}
+
+/// Decodes the record at the start of `bytes`, returning it with its encoded size.
+pub fn decode<T: Payload>(bytes: &[u8]) -> Result<(T, usize), Error> {
+    let (version, payload, crc) = frame(bytes)?;
+    Ok((verify(version, payload, crc)?, HEADER_SIZE + payload.len()))
+}
+
+/// Encoded size of the record framed at the start of `bytes`, which may hold only part
+/// of its payload. `None` when `bytes` don't start with a valid header.
+pub fn record_len(bytes: &[u8]) -> Option<usize> {
+    if bytes.len() < HEADER_SIZE || bytes[..2] != RECORD_MAGIC {
+        return None;
+    }
+    let length = u32::from_le_bytes(bytes[3..7].try_into().ok()?) as usize;
+    Some(HEADER_SIZE + length)
+}
+
+fn frame(bytes: &[u8]) -> Result<(u8, &[u8], u32), Error> {
+    if bytes.len() < HEADER_SIZE {
+        return Err(Error::TornLogRecord);
+    }
+    if bytes[..2] != RECORD_MAGIC {
+        return Err(Error::CorruptedLogRecord);
+    }
+    if bytes[2] == 0 || bytes[2] > RECORD_VERSION {
+        return Err(Error::UnsupportedLogVersion(bytes[2]));
+    }
+
+    let length = u32::from_le_bytes(bytes[3..7].try_into().unwrap()) as usize;
+    let crc = u32::from_le_bytes(bytes[7..11].try_into().unwrap());
+    let payload = bytes
+        .get(HEADER_SIZE..HEADER_SIZE + length)
+        .ok_or(Error::TornLogRecord)?;
+    Ok((bytes[2], payload, crc))
+}
+
+fn verify<T: Payload>(version: u8, payload: &[u8], crc: u32) -> Result<T, Error> {
+    if crc32fast::hash(payload) != crc {
+        return Err(Error::CorruptedLogRecord);
+    }
+    T::from_version(version, payload)
+}
+
+/// Iterates over every record of a log, yielding each record with its offset.
+/// A record with a bad checksum is yielded as an error and skipped. A torn record
+/// or an invalid header ends the iteration, since nothing after it can be framed.
+pub struct Records<'a, T> {
+    bytes: &'a [u8],
+    offset: usize,
+    valid_len: usize,
+    record: PhantomData<T>,
+}
+
+impl<'a, T: Payload> Records<'a, T> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            offset: 0,
+            valid_len: 0,
+            record: PhantomData,
+        }
+    }
+
+    /// Iterates over the records that follow the one ending at `offset`.
+    pub fn starting_at(bytes: &'a [u8], offset: usize) -> Self {
+        Self {
+            offset,
+            valid_len: offset,
+            ..Self::new(bytes)
+        }
+    }
+
+    /// Length of the prefix made of complete records read so far.
+    pub fn valid_len(&self) -> usize {
+        self.valid_len
+    }
+}
+
+impl<'a, T: Payload> Iterator for Records<'a, T> {
+    type Item = (usize, Result<T, Error>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.bytes.len() {
+            return None;
+        }
+
+        let offset = self.offset;
+        match frame(&self.bytes[offset..]) {
+            Ok((version, payload, crc)) => {
+                self.offset += HEADER_SIZE + payload.len();
+                self.valid_len = self.offset;
+                Some((offset, verify(version, payload, crc)))
+            }
+            Err(e) => {
+                self.offset = self.bytes.len();
+                Some((offset, Err(e)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::{log::LogRecord, wql::Action};
+
+    fn record() -> LogRecord {
+        LogRecord {
+            content: Some("{\"a\": String(\"pipe | and ; semicolon\"),}".to_string()),
+            ..LogRecord::new(Action::Insert, "my_entity")
+        }
+    }
+
+    #[test]
+    fn encode_decode() {
+        let bytes = encode(&record()).unwrap();
+        let (decoded, size) = decode::<LogRecord>(&bytes).unwrap();
+
+        assert_eq!(decoded, record());
+        assert_eq!(size, bytes.len());
+    }
+
+    #[test]
+    fn torn_record() {
+        let bytes = encode(&record()).unwrap();
+
+        assert!(matches!(
+            decode::<LogRecord>(&bytes[..bytes.len() - 3]),
+            Err(Error::TornLogRecord)
+        ));
+        assert!(matches!(
+            decode::<LogRecord>(&bytes[..5]),
+            Err(Error::TornLogRecord)
+        ));
+    }
+
+    #[test]
+    fn corrupted_record() {
+        let mut bytes = encode(&record()).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+
+        assert!(matches!(
+            decode::<LogRecord>(&bytes),
+            Err(Error::CorruptedLogRecord)
+        ));
+    }
+
+    #[test]
+    fn unsupported_version() {
+        let mut bytes = encode(&record()).unwrap();
+        bytes[2] = 0;
+
+        assert!(matches!(
+            decode::<LogRecord>(&bytes),
+            Err(Error::UnsupportedLogVersion(0))
+        ));
+
+        bytes[2] = RECORD_VERSION + 1;
+        assert!(matches!(
+            decode::<LogRecord>(&bytes),
+            Err(Error::UnsupportedLogVersion(version)) if version == RECORD_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn decodes_version_1_log_records() {
+        let v1 = (
+            Action::Insert,
+            None::<chrono::DateTime<chrono::Utc>>,
+            None::<uuid::Uuid>,
+            "my_entity",
+            record().content,
+            None::<String>,
+            None::<String>,
+        );
+        let payload = bincode::serialize(&v1).unwrap();
+        let mut bytes = RECORD_MAGIC.to_vec();
+        bytes.push(1);
+        bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+
+        let (decoded, size) = decode::<LogRecord>(&bytes).unwrap();
+        assert_eq!(decoded, record());
+        assert_eq!(size, bytes.len());
+    }
+
+    #[test]
+    fn decodes_version_2_time_entries() {
+        use crate::{model::DataRegister, repository::time_index::TimeEntry};
+
+        let tx_time = chrono::Utc::now();
+        let register = DataRegister {
+            file_name: "2021_01_01.log".to_string(),
+            offset: 10,
+            bytes_length: 20,
+        };
+        let v2 = (
+            "my_entity",
+            uuid::Uuid::nil(),
+            tx_time,
+            Some(3u64),
+            None::<chrono::DateTime<chrono::Utc>>,
+            None::<chrono::DateTime<chrono::Utc>>,
+            register,
+        );
+        let payload = bincode::serialize(&v2).unwrap();
+        let mut bytes = RECORD_MAGIC.to_vec();
+        bytes.push(2);
+        bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+
+        let (decoded, _) = decode::<TimeEntry>(&bytes).unwrap();
+        assert_eq!(decoded.tx_time, tx_time);
+        assert_eq!(decoded.tx_seq, Some(3));
+        assert_eq!(decoded.register.offset, 10);
+        assert!(!decoded.evicted);
+    }
+
+    #[test]
+    fn records_stop_at_torn_tail() {
+        let mut bytes = encode(&record()).unwrap();
+        let size = bytes.len();
+        bytes.extend(encode(&record()).unwrap());
+        bytes.truncate(bytes.len() - 1);
+
+        let mut iter = Records::<LogRecord>::new(&bytes);
+        let records = iter.by_ref().collect::<Vec<_>>();
+        assert_eq!(iter.valid_len(), size);
+        assert_eq!(records.len(), 2);
+        assert!(matches!(records[0], (0, Ok(_))));
+        assert!(matches!(records[1], (offset, Err(Error::TornLogRecord)) if offset == size));
+    }
+
+    #[test]
+    fn records_skip_corrupted() {
+        let mut bytes = encode(&record()).unwrap();
+        let size = bytes.len();
+        bytes[size - 1] ^= 0xff;
+        bytes.extend(encode(&record()).unwrap());
+
+        let records = Records::<LogRecord>::new(&bytes).collect::<Vec<_>>();
+        assert_eq!(records.len(), 2);
+        assert!(matches!(records[0], (0, Err(Error::CorruptedLogRecord))));
+        assert!(matches!(records[1], (offset, Ok(_)) if offset == size));
+    }
+}
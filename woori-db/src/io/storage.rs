@@ -0,0 +1,541 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use chrono::NaiveDate;
+
+#[cfg(not(test))]
+use crate::config::StorageBackend;
+use crate::{io::archive, model::error::Error};
+
+/// Where logs and state are persisted. Logs and files are named relative to the storage
+/// root, like `2021_01_08.log` or `uniques.log`.
+pub trait Storage: Send + Sync {
+    /// Appends `bytes` to the day log `log`, returning the offset they were appended at,
+    /// which is 0 when this append created it.
+    fn append_log(&self, log: &str, bytes: &[u8]) -> Result<usize, Error>;
+    /// Length of the day log `log` being appended to, 0 when it doesn't exist yet.
+    fn log_len(&self, log: &str) -> Result<usize, Error>;
+    /// Flushes what was appended to the day log `log` to durable storage.
+    fn sync_log(&self, log: &str) -> Result<(), Error>;
+    /// Reads `length` bytes at `offset` of the day log `log`, archived or not.
+    fn read_log_range(&self, log: &str, offset: usize, length: usize) -> Result<Vec<u8>, Error>;
+    /// Reads the whole day log `log`, archived or not.
+    fn read_log(&self, log: &str) -> Result<Vec<u8>, Error>;
+    /// Shrinks the day log `log` to `length` bytes.
+    fn truncate_log(&self, log: &str, length: usize) -> Result<(), Error>;
+    /// Sorted names of the day logs, each with whether it is only available archived.
+    fn list_logs(&self) -> Result<Vec<(String, bool)>, Error>;
+    /// Compresses the day log `log` into its archive at zstd `level`, removing the log.
+    fn archive_log(&self, log: &str, level: i32) -> Result<(), Error>;
+    /// Reads the compressed archive of the day log `log`, `None` when it isn't archived.
+    fn read_archive(&self, log: &str) -> Result<Option<Vec<u8>>, Error>;
+    /// Atomically replaces the local data checkpoint.
+    fn write_checkpoint(&self, bytes: &[u8]) -> Result<(), Error>;
+    /// Reads the local data checkpoint, `None` when there is none yet.
+    fn read_checkpoint(&self) -> Result<Option<Vec<u8>>, Error>;
+    /// Appends `bytes` to the auxiliary file `name`, returning its new size.
+    fn append_file(&self, name: &str, bytes: &[u8]) -> Result<u64, Error>;
+    /// Replaces the content of the auxiliary file `name`.
+    fn write_file(&self, name: &str, bytes: &[u8]) -> Result<(), Error>;
+    /// Reads the auxiliary file `name`, `None` when it doesn't exist.
+    fn read_file(&self, name: &str) -> Result<Option<Vec<u8>>, Error>;
+}
+
+const CHECKPOINT: &str = "checkpoint.log";
+
+/// Registers written before the storage root was configurable carry a `data/` prefix.
+fn file_name(name: &str) -> &str {
+    Path::new(name)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(name)
+}
+
+fn day_log(name: &str) -> Option<(&str, &str)> {
+    let path = Path::new(name);
+    let date = path.file_stem()?.to_str()?;
+    let extension = path.extension()?.to_str()?;
+    NaiveDate::parse_from_str(date, "%Y_%m_%d")
+        .ok()
+        .map(|_| (date, extension))
+}
+
+fn not_found(name: &str) -> Error {
+    std::io::Error::new(ErrorKind::NotFound, name.to_owned()).into()
+}
+
+/// Stores every log and file in a directory, archiving day logs as seekable `.zst`.
+pub struct FsStorage {
+    root: PathBuf,
+    archive_root: PathBuf,
+    /// Day log being appended to, kept open across transactions.
+    current_log: Mutex<Option<(String, File)>>,
+}
+
+impl FsStorage {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_owned(),
+            archive_root: root.to_owned(),
+            current_log: Mutex::new(None),
+        }
+    }
+
+    /// Writes new archives to `archive_dir` instead of the storage root. Archives
+    /// already in the root are still read.
+    pub fn with_archive_dir(mut self, archive_dir: &Path) -> Self {
+        self.archive_root = archive_dir.to_owned();
+        self
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.root.join(file_name(name))
+    }
+
+    fn archive_path(&self, log: &str) -> PathBuf {
+        let archive = self.archive_root.join(file_name(log)).with_extension("zst");
+        if archive.exists() {
+            archive
+        } else {
+            self.path(log).with_extension("zst")
+        }
+    }
+
+    fn current_log(&self) -> Result<std::sync::MutexGuard<'_, Option<(String, File)>>, Error> {
+        self.current_log.lock().map_err(|_| Error::LockData)
+    }
+
+    /// Closes the cached handle of `log` before it is truncated or removed.
+    fn close_log(&self, log: &str) -> Result<(), Error> {
+        let mut current = self.current_log()?;
+        if matches!(&*current, Some((name, _)) if name == file_name(log)) {
+            *current = None;
+        }
+        Ok(())
+    }
+
+    fn open_archive(&self, log: &str) -> Result<BufReader<fs::File>, Error> {
+        let file = OpenOptions::new().read(true).open(self.archive_path(log))?;
+        Ok(BufReader::new(file))
+    }
+}
+
+impl Storage for FsStorage {
+    fn append_log(&self, log: &str, bytes: &[u8]) -> Result<usize, Error> {
+        let mut current = self.current_log()?;
+        let file = match &mut *current {
+            Some((name, file)) if name == file_name(log) => file,
+            current => {
+                let file = OpenOptions::new()
+                    .append(true)
+                    .create(true)
+                    .open(self.path(log))?;
+                &mut current.insert((file_name(log).to_owned(), file)).1
+            }
+        };
+        let offset = file.metadata()?.len() as usize;
+        file.write_all(bytes)?;
+
+        Ok(offset)
+    }
+
+    fn log_len(&self, log: &str) -> Result<usize, Error> {
+        match fs::metadata(self.path(log)) {
+            Ok(metadata) => Ok(metadata.len() as usize),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn sync_log(&self, log: &str) -> Result<(), Error> {
+        if let Some((name, file)) = &*self.current_log()? {
+            if name == file_name(log) {
+                return Ok(file.sync_data()?);
+            }
+        }
+        Ok(OpenOptions::new()
+            .append(true)
+            .open(self.path(log))?
+            .sync_data()?)
+    }
+
+    fn read_log_range(&self, log: &str, offset: usize, length: usize) -> Result<Vec<u8>, Error> {
+        let mut res = Vec::with_capacity(length);
+        if let Ok(mut file) = OpenOptions::new().read(true).open(self.path(log)) {
+            file.seek(SeekFrom::Start(offset as u64))?;
+            file.take(length as u64).read_to_end(&mut res)?;
+        } else {
+            res = archive::read_range(self.open_archive(log)?, offset, length)?;
+        }
+
+        Ok(res)
+    }
+
+    fn read_log(&self, log: &str) -> Result<Vec<u8>, Error> {
+        match fs::read(self.path(log)) {
+            Ok(bytes) => Ok(bytes),
+            Err(_) => archive::read_all(self.open_archive(log)?),
+        }
+    }
+
+    fn truncate_log(&self, log: &str, length: usize) -> Result<(), Error> {
+        self.close_log(log)?;
+        OpenOptions::new()
+            .write(true)
+            .open(self.path(log))?
+            .set_len(length as u64)?;
+        Ok(())
+    }
+
+    fn list_logs(&self) -> Result<Vec<(String, bool)>, Error> {
+        let mut logs = BTreeMap::new();
+        let mut names = Vec::new();
+        for entry in fs::read_dir(&self.root)? {
+            names.push(entry?.file_name());
+        }
+        if self.archive_root != self.root && self.archive_root.is_dir() {
+            for entry in fs::read_dir(&self.archive_root)? {
+                names.push(entry?.file_name());
+            }
+        }
+
+        for name in names {
+            let name = name.to_string_lossy();
+            match day_log(&name) {
+                Some((date, "log")) => {
+                    logs.insert(format!("{}.log", date), false);
+                }
+                Some((date, "zst")) => {
+                    logs.entry(format!("{}.log", date)).or_insert(true);
+                }
+                _ => (),
+            }
+        }
+
+        Ok(logs.into_iter().collect())
+    }
+
+    fn archive_log(&self, log: &str, level: i32) -> Result<(), Error> {
+        self.close_log(log)?;
+        let file = OpenOptions::new().read(true).open(self.path(log))?;
+        fs::create_dir_all(&self.archive_root)?;
+        let archive = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(self.archive_root.join(file_name(log)).with_extension("zst"))?;
+        archive::write_archive(BufReader::new(file), BufWriter::new(archive), level)?;
+
+        Ok(fs::remove_file(self.path(log))?)
+    }
+
+    fn read_archive(&self, log: &str) -> Result<Option<Vec<u8>>, Error> {
+        match fs::read(self.archive_path(log)) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write_checkpoint(&self, bytes: &[u8]) -> Result<(), Error> {
+        let tmp = self.path("checkpoint_tmp.log");
+        let mut file = OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .create(true)
+            .open(&tmp)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+
+        Ok(fs::rename(tmp, self.path(CHECKPOINT))?)
+    }
+
+    fn read_checkpoint(&self) -> Result<Option<Vec<u8>>, Error> {
+        self.read_file(CHECKPOINT)
+    }
+
+    fn append_file(&self, name: &str, bytes: &[u8]) -> Result<u64, Error> {
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(self.path(name))?;
+        file.write_all(bytes)?;
+        file.flush()?;
+
+        Ok(file.metadata()?.len())
+    }
+
+    fn write_file(&self, name: &str, bytes: &[u8]) -> Result<(), Error> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .create(true)
+            .open(self.path(name))?;
+        file.write_all(bytes)?;
+        file.flush()?;
+
+        Ok(())
+    }
+
+    fn read_file(&self, name: &str) -> Result<Option<Vec<u8>>, Error> {
+        match fs::read(self.path(name)) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Keeps every log and file in memory. Archiving is a no-op.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    files: Mutex<BTreeMap<String, Vec<u8>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn files(&self) -> Result<std::sync::MutexGuard<'_, BTreeMap<String, Vec<u8>>>, Error> {
+        self.files.lock().map_err(|_| Error::LockData)
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn append_log(&self, log: &str, bytes: &[u8]) -> Result<usize, Error> {
+        let mut files = self.files()?;
+        let file = files.entry(file_name(log).to_owned()).or_default();
+        let offset = file.len();
+        file.extend_from_slice(bytes);
+
+        Ok(offset)
+    }
+
+    fn log_len(&self, log: &str) -> Result<usize, Error> {
+        Ok(self.files()?.get(file_name(log)).map_or(0, Vec::len))
+    }
+
+    fn sync_log(&self, _log: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn read_log_range(&self, log: &str, offset: usize, length: usize) -> Result<Vec<u8>, Error> {
+        let files = self.files()?;
+        let bytes = files.get(file_name(log)).ok_or_else(|| not_found(log))?;
+
+        Ok(bytes.iter().skip(offset).take(length).cloned().collect())
+    }
+
+    fn read_log(&self, log: &str) -> Result<Vec<u8>, Error> {
+        self.read_file(log)?.ok_or_else(|| not_found(log))
+    }
+
+    fn truncate_log(&self, log: &str, length: usize) -> Result<(), Error> {
+        let mut files = self.files()?;
+        let bytes = files
+            .get_mut(file_name(log))
+            .ok_or_else(|| not_found(log))?;
+        bytes.truncate(length);
+
+        Ok(())
+    }
+
+    fn list_logs(&self) -> Result<Vec<(String, bool)>, Error> {
+        Ok(self
+            .files()?
+            .keys()
+            .filter(|name| matches!(day_log(name), Some((_, "log"))))
+            .map(|name| (name.to_owned(), false))
+            .collect())
+    }
+
+    fn archive_log(&self, _log: &str, _level: i32) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn read_archive(&self, _log: &str) -> Result<Option<Vec<u8>>, Error> {
+        Ok(None)
+    }
+
+    fn write_checkpoint(&self, bytes: &[u8]) -> Result<(), Error> {
+        self.write_file(CHECKPOINT, bytes)
+    }
+
+    fn read_checkpoint(&self) -> Result<Option<Vec<u8>>, Error> {
+        self.read_file(CHECKPOINT)
+    }
+
+    fn append_file(&self, name: &str, bytes: &[u8]) -> Result<u64, Error> {
+        let mut files = self.files()?;
+        let file = files.entry(file_name(name).to_owned()).or_default();
+        file.extend_from_slice(bytes);
+
+        Ok(file.len() as u64)
+    }
+
+    fn write_file(&self, name: &str, bytes: &[u8]) -> Result<(), Error> {
+        self.files()?
+            .insert(file_name(name).to_owned(), bytes.to_vec());
+        Ok(())
+    }
+
+    fn read_file(&self, name: &str) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.files()?.get(file_name(name)).cloned())
+    }
+}
+
+/// The storage the server runs on, as configured.
+#[cfg(not(test))]
+pub fn default_storage() -> Arc<dyn Storage> {
+    let config = crate::config::config();
+    match config.storage {
+        StorageBackend::Fs => {
+            let storage = FsStorage::new(&config.data_dir);
+            match &config.archive.archive_dir {
+                Some(archive_dir) => Arc::new(storage.with_archive_dir(archive_dir)),
+                None => Arc::new(storage),
+            }
+        }
+        StorageBackend::Memory => Arc::new(MemoryStorage::new()),
+    }
+}
+
+/// Every test gets its own in-memory storage, seeded with the `data/*.txt` fixtures.
+#[cfg(test)]
+pub fn default_storage() -> Arc<dyn Storage> {
+    test_storage()
+}
+
+#[cfg(test)]
+thread_local! {
+    static TEST_STORAGE: Arc<MemoryStorage> = {
+        let storage = MemoryStorage::new();
+        for entry in fs::read_dir("data").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|extension| extension == "txt") {
+                let name = path.file_name().unwrap().to_string_lossy();
+                storage.write_file(&name, &fs::read(&path).unwrap()).unwrap();
+            }
+        }
+        Arc::new(storage)
+    };
+}
+
+/// In-memory storage of the current test, shared by everything the test starts.
+#[cfg(test)]
+pub fn test_storage() -> Arc<MemoryStorage> {
+    TEST_STORAGE.with(Arc::clone)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn round_trip(storage: &dyn Storage) {
+        assert_eq!(storage.log_len("2021_01_01.log").unwrap(), 0);
+        assert_eq!(storage.append_log("2021_01_01.log", b"first").unwrap(), 0);
+        assert_eq!(
+            storage
+                .append_log("data/2021_01_01.log", b"second")
+                .unwrap(),
+            5
+        );
+        assert_eq!(storage.log_len("2021_01_01.log").unwrap(), 11);
+        assert_eq!(
+            storage.read_log_range("2021_01_01.log", 5, 6).unwrap(),
+            b"second"
+        );
+        storage.sync_log("2021_01_01.log").unwrap();
+        storage.truncate_log("2021_01_01.log", 5).unwrap();
+        assert_eq!(storage.read_log("2021_01_01.log").unwrap(), b"first");
+        assert!(storage.read_log("2021_01_02.log").is_err());
+
+        assert_eq!(storage.append_file("uniques.log", b"ab").unwrap(), 2);
+        assert_eq!(storage.append_file("uniques.log", b"c").unwrap(), 3);
+        storage.write_file("offset_counter.log", b"10").unwrap();
+        storage.write_file("offset_counter.log", b"5").unwrap();
+        assert_eq!(
+            storage.read_file("offset_counter.log").unwrap(),
+            Some(b"5".to_vec())
+        );
+        assert_eq!(storage.read_file("encrypt.log").unwrap(), None);
+
+        assert_eq!(storage.read_checkpoint().unwrap(), None);
+        storage.write_checkpoint(b"checkpoint").unwrap();
+        assert_eq!(
+            storage.read_checkpoint().unwrap(),
+            Some(b"checkpoint".to_vec())
+        );
+    }
+
+    #[test]
+    fn memory_storage() {
+        let storage = MemoryStorage::new();
+        round_trip(&storage);
+        assert_eq!(
+            storage.list_logs().unwrap(),
+            vec![("2021_01_01.log".to_string(), false)]
+        );
+    }
+
+    #[test]
+    fn fs_storage() {
+        let dir = Path::new("data/fs_storage_test");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir).unwrap();
+        let storage = FsStorage::new(dir);
+
+        round_trip(&storage);
+        storage.append_log("2021_01_02.log", b"archived").unwrap();
+        storage.archive_log("2021_01_02.log", 3).unwrap();
+        let logs = storage.list_logs().unwrap();
+        let archived = storage.read_log_range("2021_01_02.log", 0, 4).unwrap();
+        let _ = fs::remove_dir_all(dir);
+
+        assert_eq!(
+            logs,
+            vec![
+                ("2021_01_01.log".to_string(), false),
+                ("2021_01_02.log".to_string(), true)
+            ]
+        );
+        assert_eq!(archived, b"arch");
+    }
+
+    #[test]
+    fn fs_storage_with_archive_dir() {
+        let dir = Path::new("data/fs_storage_archive_test");
+        let archive_dir = dir.join("archive");
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir).unwrap();
+        let storage = FsStorage::new(dir);
+        storage.append_log("2021_01_01.log", b"old root").unwrap();
+        storage.archive_log("2021_01_01.log", 3).unwrap();
+        let storage = FsStorage::new(dir).with_archive_dir(&archive_dir);
+        storage.append_log("2021_01_02.log", b"moved").unwrap();
+        storage.archive_log("2021_01_02.log", 3).unwrap();
+
+        let logs = storage.list_logs().unwrap();
+        let moved = archive_dir.join("2021_01_02.zst").exists();
+        let old = storage.read_log("2021_01_01.log").unwrap();
+        let new = storage.read_log_range("2021_01_02.log", 1, 3).unwrap();
+        let _ = fs::remove_dir_all(dir);
+
+        assert!(moved);
+        assert_eq!(
+            logs,
+            vec![
+                ("2021_01_01.log".to_string(), true),
+                ("2021_01_02.log".to_string(), true)
+            ]
+        );
+        assert_eq!(old, b"old root");
+        assert_eq!(new, b"ove");
+    }
+}
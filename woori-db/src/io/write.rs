@@ -1,121 +1,116 @@
 use chrono::{DateTime, Utc};
-use std::io::{Error, Seek, SeekFrom};
-use std::path::Path;
-use std::{fs::OpenOptions, io::Write};
 
-pub fn write_to_log(log: &str) -> Result<(usize, bool), Error> {
+use crate::{
+    io::{record::encode, storage::Storage},
+    model::error::Error,
+    model::log::LogRecord,
+};
+
+/// Appends `log` to today's day log, returning the log name, the bytes written and the
+/// offset they were written at.
+pub fn write_to_log(
+    storage: &dyn Storage,
+    log: &LogRecord,
+) -> Result<(String, usize, usize), Error> {
     let utc: DateTime<Utc> = Utc::now();
-    let date_log = utc.format("data/%Y_%m_%d.log").to_string();
-    let is_empty = !Path::new(&date_log).exists();
+    let date_log = utc.format("%Y_%m_%d.log").to_string();
+    let record = encode(log)?;
+    let offset = storage.append_log(&date_log, &record)?;
 
-    let mut file = OpenOptions::new()
-        .append(true)
-        .create(true)
-        .open(date_log)?;
-    let written_bytes = file.write(log.as_bytes())?;
-    file.flush()?;
-
-    Ok((written_bytes, is_empty))
+    Ok((date_log, record.len(), offset))
 }
-pub fn write_to_uniques(log: &str) -> Result<(), Error> {
-    let mut file = OpenOptions::new()
-        .append(true)
-        .create(true)
-        .open("data/uniques.log")?;
-
-    let _ = file.write(log.as_bytes())?;
-    file.flush()?;
 
+pub fn write_to_uniques(storage: &dyn Storage, log: &str) -> Result<(), Error> {
+    storage.append_file("uniques.log", log.as_bytes())?;
     Ok(())
 }
 
-pub fn local_data(log: &str) -> Result<(), Error> {
-    let mut file = OpenOptions::new()
-        .write(true)
-        .append(false)
-        .create(true)
-        .open("data/local_data.log")?;
-
-    let _ = file.seek(SeekFrom::Start(0));
-    file.write_all(log.as_bytes())?;
-    file.flush()?;
+/// Appends an encoded `LocalDelta` to the journal, returning the journal size.
+pub fn local_delta(storage: &dyn Storage, delta: &[u8]) -> Result<u64, Error> {
+    storage.append_file("delta.log", delta)
+}
 
-    Ok(())
+/// Replaces the checkpoint with an encoded `LocalContext` and empties the delta journal.
+/// Deltas journaled after the snapshot was taken are replayed again, which is harmless.
+pub fn local_checkpoint(storage: &dyn Storage, checkpoint: &[u8]) -> Result<(), Error> {
+    storage.write_checkpoint(checkpoint)?;
+    storage.write_file("delta.log", &[])
 }
 
-pub fn unique_data(log: &str) -> Result<(), Error> {
-    let mut file = OpenOptions::new()
-        .write(true)
-        .append(false)
-        .create(true)
-        .open("data/unique_data.log")?;
+pub fn unique_data(storage: &dyn Storage, log: &str) -> Result<(), Error> {
+    storage.write_file("unique_data.log", log.as_bytes())
+}
 
-    let _ = file.seek(SeekFrom::Start(0));
-    file.write_all(log.as_bytes())?;
-    file.flush()?;
+pub fn index_data(storage: &dyn Storage, log: &str) -> Result<(), Error> {
+    storage.write_file("index_data.log", log.as_bytes())
+}
 
+/// Appends encoded `TimeEntry`s to the time index journal.
+pub fn time_index(storage: &dyn Storage, entries: &[u8]) -> Result<(), Error> {
+    storage.append_file("time_index.log", entries)?;
     Ok(())
 }
 
-pub fn offset_counter(log: usize) -> Result<(), Error> {
-    let mut file = OpenOptions::new()
-        .write(true)
-        .append(false)
-        .create(true)
-        .open("data/offset_counter.log")?;
-
-    let _ = file.seek(SeekFrom::Start(0));
-    file.write_all(log.to_string().as_bytes())?;
-    file.flush()?;
+pub fn offset_counter(storage: &dyn Storage, log: usize) -> Result<(), Error> {
+    storage.write_file("offset_counter.log", log.to_string().as_bytes())
+}
 
+pub fn write_to_encrypts(storage: &dyn Storage, log: &str) -> Result<(), Error> {
+    storage.append_file("encrypt.log", log.as_bytes())?;
     Ok(())
 }
 
-pub fn write_to_encrypts(log: &str) -> Result<(), Error> {
-    let mut file = OpenOptions::new()
-        .append(true)
-        .create(true)
-        .open("data/encrypt.log")?;
-
-    let _ = file.write(log.as_bytes())?;
-    file.flush()?;
-
-    Ok(())
+pub fn encrypt_data(storage: &dyn Storage, log: &str) -> Result<(), Error> {
+    storage.write_file("encrypt.log", log.as_bytes())
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::io::read::{
-        assert_content, assert_local_data, assert_offset, assert_unique_data, assert_uniques,
+    use crate::io::{
+        read::{assert_content, assert_offset, assert_unique_data, assert_uniques},
+        storage::test_storage,
     };
     #[test]
     fn write_unique() {
-        let _ = write_to_uniques("oh crazy unique log");
+        let _ = write_to_uniques(&*test_storage(), "oh crazy unique log");
         assert_uniques("oh crazy unique log");
     }
 
     #[test]
     fn write_log() {
-        let _ = write_to_log("oh crazy log");
-        assert_content("oh crazy log");
+        use crate::model::wql::Action;
+        let _ = write_to_log(
+            &*test_storage(),
+            &LogRecord::new(Action::CreateEntity, "oh crazy log"),
+        );
+        assert_content("CREATE_ENTITY|oh crazy log;");
     }
 
     #[test]
     fn offset_counter_test() {
-        let _ = offset_counter(5_usize);
+        let _ = offset_counter(&*test_storage(), 5_usize);
         assert_offset("5");
     }
 
     #[test]
-    fn local_data_test() {
-        let _ = local_data("some crazy date here");
-        assert_local_data("some crazy date here");
+    fn local_checkpoint_test() {
+        use crate::{io::record::decode, repository::local::LocalContext};
+        let storage = test_storage();
+        let mut context = LocalContext::new();
+        context.insert("checkpoint_entity".to_string(), Default::default());
+        let _ = local_delta(&*storage, b"delta");
+        let _ = local_checkpoint(&*storage, &encode(&context).unwrap());
+
+        let bytes = storage.read_checkpoint().unwrap().unwrap();
+        let (checkpoint, _) = decode::<LocalContext>(&bytes).unwrap();
+        assert!(checkpoint.contains_key("checkpoint_entity"));
+        assert_eq!(storage.read_file("delta.log").unwrap(), Some(Vec::new()));
     }
 
     #[test]
     fn unique_data_test() {
-        let _ = unique_data("some crazy date here");
+        let _ = unique_data(&*test_storage(), "some crazy date here");
         assert_unique_data("some crazy date here");
     }
 }
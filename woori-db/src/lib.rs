@@ -0,0 +1,14 @@
+mod actors;
+#[allow(dead_code)]
+mod auth;
+pub mod backup;
+pub mod config;
+mod controllers;
+mod core;
+pub mod fsck;
+pub mod http;
+mod io;
+mod model;
+pub mod pitr;
+mod repository;
+mod schemas;
@@ -3,22 +3,15 @@ use actix_web::{
     web, App, HttpResponse, HttpServer,
 };
 
-mod actors;
-#[allow(dead_code)]
-mod auth;
-mod controllers;
-mod core;
-mod http;
-mod io;
-mod model;
-mod repository;
-mod schemas;
-
-use http::{ping, readiness, routes};
+use std::path::Path;
+use woori_db::{
+    backup, config,
+    http::{ping, readiness, routes},
+};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    std::env::set_var("RUST_LOG", "actix_web=info");
+    std::env::set_var("RUST_LOG", "actix_web=info,wooridb=info,woori_db=info");
     env_logger::init();
     let env_port = std::env::var("PORT").unwrap_or_else(|_| "1438".to_owned());
     let port = env_port.parse::<u16>().expect("PORT must be a u16");
@@ -34,6 +27,24 @@ async fn main() -> std::io::Result<()> {
         .parse::<u64>()
         .expect("CLIENT_SHUTDOWN must be a u64");
 
+    let config = config::config();
+    if let Ok(backup) = std::env::var(backup::RESTORE_FROM) {
+        assert!(
+            config.storage == config::StorageBackend::Fs,
+            "{} requires the fs storage",
+            backup::RESTORE_FROM
+        );
+        let manifest = backup::restore(Path::new(&backup), &config.data_dir)
+            .unwrap_or_else(|e| panic!("Failed to restore backup {}: {}", backup, e));
+        log::info!(
+            "Restored {} files backed up at {} from {}",
+            manifest.files.len(),
+            manifest.created_at,
+            backup
+        );
+    }
+    std::fs::create_dir_all(&config.data_dir)?;
+
     HttpServer::new(move || {
         App::new()
             .wrap(Logger::default())
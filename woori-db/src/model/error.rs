@@ -21,14 +21,27 @@ pub enum Error {
     UuidNotCreatedForEntity(String, Uuid),
     FailedToParseState,
     FailedToParseRegistry,
+    TornLogRecord,
+    CorruptedLogRecord,
+    UnsupportedLogVersion(u8),
+    InvalidBackup(String),
     UnknownCondition,
     FailedMatchCondition,
     DuplicatedUnique(String, String, Types),
     SelectBadRequest,
+    ReadOnly,
+    TransactionAborted(usize, Box<Error>),
+    TransactionSpansDays,
+    TransactionLogMoved,
     NonSelectQuery,
     ActixMailbox(MailboxError),
     LockData,
     KeyTxTimeNotAllowed,
+    KeyTxVersionNotAllowed,
+    KeyValidTimeNotAllowed,
+    VersionConflict(String, Uuid, usize, usize),
+    TxNotCommitted(u64),
+    HistoryCursorNotValid,
     Ron(ron::Error),
     InvalidUuid(uuid::Error),
     UpdateContentEncryptKeys(Vec<String>),
@@ -51,6 +64,10 @@ pub fn error_to_http(e: &Error) -> HttpResponse {
         | Error::Serialization(_)
         | Error::FailedToParseState
         | Error::FailedToParseRegistry
+        | Error::TornLogRecord
+        | Error::CorruptedLogRecord
+        | Error::UnsupportedLogVersion(_)
+        | Error::InvalidBackup(_)
         | Error::UnknownCondition
         | Error::ActixMailbox(_)
         | Error::Ron(_)
@@ -67,20 +84,32 @@ pub fn error_to_http(e: &Error) -> HttpResponse {
         | Error::CheckNonEncryptedKeys(_)
         | Error::FailedToCreateUser
         | Error::FailedToDeleteUsers
-        | Error::KeyTxTimeNotAllowed => HttpResponse::BadRequest().body(e.to_string()),
+        | Error::KeyTxTimeNotAllowed
+        | Error::KeyTxVersionNotAllowed
+        | Error::KeyValidTimeNotAllowed
+        | Error::TxNotCommitted(_)
+        | Error::HistoryCursorNotValid => HttpResponse::BadRequest().body(e.to_string()),
+        Error::VersionConflict(..) => HttpResponse::Conflict().body(e.to_string()),
         Error::EntityAlreadyCreated(_) => HttpResponse::UnprocessableEntity().body(e.to_string()),
         #[cfg(feature = "json")]
         Error::SerdeJson(_) => HttpResponse::InternalServerError().body(e.to_string()),
         Error::FailedMatchCondition => HttpResponse::PreconditionFailed().body(e.to_string()),
         Error::NonSelectQuery => HttpResponse::MethodNotAllowed().body(e.to_string()),
-        Error::LockData => HttpResponse::ServiceUnavailable().body(e.to_string()),
+        Error::LockData | Error::TransactionSpansDays | Error::TransactionLogMoved => {
+            HttpResponse::ServiceUnavailable().body(e.to_string())
+        }
+        Error::TransactionAborted(_, inner) => {
+            HttpResponse::build(error_to_http(inner).status()).body(e.to_string())
+        }
         Error::AdminNotConfigured | Error::AuthorizationBadRequest | Error::Unknown => {
             HttpResponse::Unauthorized().body(e.to_string())
         }
         Error::AuthenticationBadRequest | Error::AuthenticationBadRequestBody(_) => {
             HttpResponse::Forbidden().body(e.to_string())
         }
-        Error::SelectBadRequest => HttpResponse::MethodNotAllowed().body(e.to_string()),
+        Error::SelectBadRequest | Error::ReadOnly => {
+            HttpResponse::MethodNotAllowed().body(e.to_string())
+        }
     }
 }
 
@@ -134,6 +163,26 @@ impl std::fmt::Display for Error {
                 "Failed to parse registry".to_string(),
             )
             .write(f),
+            Error::TornLogRecord => Response::new(
+                String::from("TornLogRecord"),
+                "Log record is incomplete, probably due to a partial write".to_string(),
+            )
+            .write(f),
+            Error::CorruptedLogRecord => Response::new(
+                String::from("CorruptedLogRecord"),
+                "Log record header or checksum is invalid".to_string(),
+            )
+            .write(f),
+            Error::UnsupportedLogVersion(version) => Response::new(
+                String::from("UnsupportedLogVersion"),
+                format!("Log record version `{}` is not supported", version),
+            )
+            .write(f),
+            Error::InvalidBackup(reason) => Response::new(
+                String::from("InvalidBackup"),
+                format!("Backup doesn't match its manifest: {}", reason),
+            )
+            .write(f),
             Error::DuplicatedUnique(entity, key, t) => Response::new(
                 String::from("DuplicatedUnique"),
                 format!(
@@ -157,6 +206,30 @@ impl std::fmt::Display for Error {
                 "SELECT expressions are handled by `/wql/query` endpoint".to_string(),
             )
             .write(f),
+            Error::ReadOnly => Response::new(
+                String::from("ReadOnly"),
+                "Transactions are disabled on read-only instances".to_string(),
+            )
+            .write(f),
+            Error::TransactionAborted(statement, e) => Response::new(
+                String::from("TransactionAborted"),
+                format!(
+                    "Statement {} failed, so no statement of the transaction was committed: {}",
+                    statement, e
+                ),
+            )
+            .write(f),
+            Error::TransactionSpansDays => Response::new(
+                String::from("TransactionSpansDays"),
+                "Transaction started before midnight and can't commit after it, retry it"
+                    .to_string(),
+            )
+            .write(f),
+            Error::TransactionLogMoved => Response::new(
+                String::from("TransactionLogMoved"),
+                "The day log changed length while the transaction ran, retry it".to_string(),
+            )
+            .write(f),
             Error::NonSelectQuery => Response::new(
                 String::from("NonSelectQuery"),
                 "Non-SELECT expressions are handled by `/wql/tx` endpoint".to_string(),
@@ -237,6 +310,35 @@ impl std::fmt::Display for Error {
                 "Key `tx_time` is not allowed".to_string(),
             )
             .write(f),
+            Error::KeyTxVersionNotAllowed => Response::new(
+                String::from("KeyTxVersionNotAllowed"),
+                "Key `tx_version` is not allowed".to_string(),
+            )
+            .write(f),
+            Error::KeyValidTimeNotAllowed => Response::new(
+                String::from("KeyValidTimeNotAllowed"),
+                "Keys `valid_from` and `valid_to` are not allowed, use VALID FROM ... TO ..."
+                    .to_string(),
+            )
+            .write(f),
+            Error::VersionConflict(entity, id, expected, version) => Response::new(
+                String::from("VersionConflict"),
+                format!(
+                    "Entity {} with Uuid {} is at version {}, not {}",
+                    entity, id, version, expected
+                ),
+            )
+            .write(f),
+            Error::TxNotCommitted(tx_seq) => Response::new(
+                String::from("TxNotCommitted"),
+                format!("Transaction {} was not committed", tx_seq),
+            )
+            .write(f),
+            Error::HistoryCursorNotValid => Response::new(
+                String::from("HistoryCursorNotValid"),
+                "Entity history cursor is not from this entity id or order".to_string(),
+            )
+            .write(f),
         }
     }
 }
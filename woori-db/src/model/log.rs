@@ -0,0 +1,134 @@
+use chrono::{DateTime, Utc};
+use ron::ser::to_string_pretty;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    core::pretty_config_inner,
+    io::record::Payload,
+    model::{error::Error, wql::Action},
+};
+
+/// A single transaction log record. Every field is stored length-prefixed by the
+/// record codec, so values may contain any character, including `|` and `;`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogRecord {
+    pub action: Action,
+    pub tx_time: Option<DateTime<Utc>>,
+    pub uuid: Option<Uuid>,
+    pub entity: String,
+    /// Content sent by the transaction, as `ron`.
+    pub content: Option<String>,
+    /// Entity state after this transaction, as `ron`.
+    pub state: Option<String>,
+    /// Registry of the state before this transaction, as `ron`.
+    pub previous_registry: Option<String>,
+    /// Sequence number of the transaction, shared by every record of a `BEGIN ... COMMIT`
+    /// block. Records written before sequence numbers existed have none.
+    pub tx_seq: Option<u64>,
+}
+
+impl LogRecord {
+    pub fn new(action: Action, entity: &str) -> Self {
+        Self {
+            action,
+            tx_time: None,
+            uuid: None,
+            entity: entity.to_owned(),
+            content: None,
+            state: None,
+            previous_registry: None,
+            tx_seq: None,
+        }
+    }
+}
+
+/// Layout of `LogRecord` in format version 1, before `tx_seq`.
+#[derive(Deserialize)]
+struct LogRecordV1 {
+    action: Action,
+    tx_time: Option<DateTime<Utc>>,
+    uuid: Option<Uuid>,
+    entity: String,
+    content: Option<String>,
+    state: Option<String>,
+    previous_registry: Option<String>,
+}
+
+impl Payload for LogRecord {
+    fn from_version(version: u8, payload: &[u8]) -> Result<Self, Error> {
+        if version > 1 {
+            return bincode::deserialize(payload).map_err(|_| Error::CorruptedLogRecord);
+        }
+        let record: LogRecordV1 =
+            bincode::deserialize(payload).map_err(|_| Error::CorruptedLogRecord)?;
+        Ok(Self {
+            action: record.action,
+            tx_time: record.tx_time,
+            uuid: record.uuid,
+            entity: record.entity,
+            content: record.content,
+            state: record.state,
+            previous_registry: record.previous_registry,
+            tx_seq: None,
+        })
+    }
+}
+
+/// Keys declared by `CREATE ENTITY`, stored as the content of its record.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EntitySchema {
+    pub uniques: Vec<String>,
+    pub encrypts: Vec<String>,
+}
+
+/// Human readable `ACTION|date|uuid|entity|...;` form of the record.
+impl std::fmt::Display for LogRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut fields = vec![self.action.to_string()];
+        if let Some(date) = &self.tx_time {
+            fields
+                .push(to_string_pretty(date, pretty_config_inner()).map_err(|_| std::fmt::Error)?);
+        }
+        if let Some(uuid) = &self.uuid {
+            fields.push(uuid.to_string());
+        }
+        fields.push(self.entity.to_owned());
+        fields.extend(
+            [&self.content, &self.state, &self.previous_registry]
+                .iter()
+                .filter_map(|field| field.as_ref().map(ToOwned::to_owned)),
+        );
+
+        write!(f, "{};", fields.join("|"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn display_create_entity() {
+        let record = LogRecord::new(Action::CreateEntity, "my_entity");
+
+        assert_eq!(record.to_string(), "CREATE_ENTITY|my_entity;");
+    }
+
+    #[test]
+    fn display_update_set() {
+        let uuid = Uuid::new_v4();
+        let record = LogRecord {
+            tx_time: Some(Utc::now()),
+            uuid: Some(uuid),
+            content: Some("content".to_string()),
+            state: Some("state".to_string()),
+            previous_registry: Some("registry".to_string()),
+            ..LogRecord::new(Action::UpdateSet, "my_entity")
+        };
+        let display = record.to_string();
+
+        assert!(display.starts_with("UPDATE_SET|\""));
+        assert!(display.ends_with(&format!("|{}|my_entity|content|state|registry;", uuid)));
+    }
+}
@@ -1,26 +1,36 @@
 pub(crate) mod error;
+pub(crate) mod log;
 pub(crate) mod wql;
 
 use actix::prelude::*;
 use actix_web::web;
 use serde::{Deserialize, Serialize};
-use std::{
-    io::Error,
-    sync::{atomic::AtomicUsize, Arc, Mutex},
-};
+use std::sync::{atomic::AtomicUsize, Arc, Mutex};
 
 use crate::{
     actors::wql::Executor,
-    repository::local::{EncryptContext, LocalContext, UniquenessContext},
+    io::storage::Storage,
+    model::{error::Error, log::LogRecord},
+    repository::{
+        index::IndexContext,
+        local::{EncryptContext, LocalContext, UniquenessContext},
+        locks::IdLocks,
+    },
 };
 
 pub type DataLocalContext = web::Data<Arc<Mutex<LocalContext>>>;
 pub type DataUniquenessContext = web::Data<Arc<Mutex<UniquenessContext>>>;
 pub type DataEncryptContext = web::Data<Arc<Mutex<EncryptContext>>>;
+pub type DataIndexContext = web::Data<Arc<Mutex<IndexContext>>>;
 pub type DataAtomicUsize = web::Data<AtomicUsize>;
 pub type DataU32 = web::Data<u32>;
 pub type DataI64 = web::Data<i64>;
 pub type DataExecutor = web::Data<Addr<Executor>>;
+pub type DataStorage = web::Data<Arc<dyn Storage>>;
+/// Held shared by every transaction and exclusively by backups, so a backup never sees
+/// a transaction half written.
+pub type DataTxGate = web::Data<tokio::sync::RwLock<()>>;
+pub type DataIdLocks = web::Data<IdLocks>;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct DataRegister {
@@ -30,14 +40,17 @@ pub struct DataRegister {
 }
 
 impl Message for DataRegister {
-    type Result = Result<String, Error>;
+    type Result = Result<LogRecord, Error>;
 }
 
 impl Handler<DataRegister> for Executor {
-    type Result = Result<String, Error>;
+    type Result = Result<LogRecord, Error>;
 
     fn handle(&mut self, msg: DataRegister, _: &mut Self::Context) -> Self::Result {
         use crate::io::read::read_log;
-        read_log(msg)
+        if let Some(record) = self.batch.as_ref().and_then(|batch| batch.read(&msg)) {
+            return record;
+        }
+        read_log(&*self.storage, msg)
     }
 }
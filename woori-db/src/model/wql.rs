@@ -1,9 +1,10 @@
 use std::collections::HashMap;
 
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
-use wql::{MatchCondition, Types};
+use wql::{MatchCondition, Types, Valid};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Action {
     CreateEntity,
     Insert,
@@ -14,6 +15,11 @@ pub enum Action {
     EvictEntity,
     EvictEntityId,
     Error,
+    /// Opens a `BEGIN ... COMMIT` block, whose records only apply once it is closed.
+    Begin,
+    /// Closes a `BEGIN ... COMMIT` block.
+    Commit,
+    CreateIndex,
 }
 
 impl std::fmt::Display for Action {
@@ -28,6 +34,9 @@ impl std::fmt::Display for Action {
             Action::EvictEntity => write!(f, "EVICT_ENTITY"),
             Action::EvictEntityId => write!(f, "EVICT_ENTITY_ID"),
             Action::Error => write!(f, "Error"),
+            Action::Begin => write!(f, "BEGIN"),
+            Action::Commit => write!(f, "COMMIT"),
+            Action::CreateIndex => write!(f, "CREATE_INDEX"),
         }
     }
 }
@@ -43,6 +52,9 @@ impl From<String> for Action {
             "UPDATE_CONTENT" => Action::UpdateContent,
             "EVICT_ENTITY" => Action::EvictEntity,
             "EVICT_ENTITY_ID" => Action::EvictEntityId,
+            "BEGIN" => Action::Begin,
+            "COMMIT" => Action::Commit,
+            "CREATE_INDEX" => Action::CreateIndex,
             _ => Action::Error,
         }
     }
@@ -53,6 +65,7 @@ pub struct MatchUpdateArgs {
     pub content: HashMap<String, Types>,
     pub id: uuid::Uuid,
     pub conditions: MatchCondition,
+    pub valid: Option<Valid>,
 }
 
 impl MatchUpdateArgs {
@@ -61,12 +74,14 @@ impl MatchUpdateArgs {
         content: HashMap<String, Types>,
         id: uuid::Uuid,
         conditions: MatchCondition,
+        valid: Option<Valid>,
     ) -> Self {
         Self {
             entity,
             content,
             id,
             conditions,
+            valid,
         }
     }
 }
@@ -75,14 +90,25 @@ pub struct UpdateArgs {
     pub entity: String,
     pub content: HashMap<String, Types>,
     pub id: Uuid,
+    /// Version the entity id must be at for the update to apply.
+    pub version: Option<usize>,
+    pub valid: Option<Valid>,
 }
 
 impl UpdateArgs {
-    pub fn new(entity: String, content: HashMap<String, Types>, id: Uuid) -> Self {
+    pub fn new(
+        entity: String,
+        content: HashMap<String, Types>,
+        id: Uuid,
+        version: Option<usize>,
+        valid: Option<Valid>,
+    ) -> Self {
         Self {
             entity,
             content,
             id,
+            version,
+            valid,
         }
     }
 }
@@ -91,14 +117,21 @@ pub struct InsertArgs {
     pub entity: String,
     pub content: HashMap<String, Types>,
     pub uuid: Option<Uuid>,
+    pub valid: Option<Valid>,
 }
 
 impl InsertArgs {
-    pub fn new(entity: String, content: HashMap<String, Types>, uuid: Option<Uuid>) -> Self {
+    pub fn new(
+        entity: String,
+        content: HashMap<String, Types>,
+        uuid: Option<Uuid>,
+        valid: Option<Valid>,
+    ) -> Self {
         Self {
             entity,
             content,
             uuid,
+            valid,
         }
     }
 }
@@ -121,5 +154,11 @@ mod test {
             Action::from(String::from("UPDATE_CONTENT")),
             Action::UpdateContent
         );
+        assert_eq!(Action::from(String::from("BEGIN")), Action::Begin);
+        assert_eq!(Action::from(String::from("COMMIT")), Action::Commit);
+        assert_eq!(
+            Action::from(String::from("CREATE_INDEX")),
+            Action::CreateIndex
+        );
     }
 }
@@ -0,0 +1,372 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    mem::discriminant,
+};
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+use wql::Types;
+
+use crate::repository::local::{LocalContext, LocalDelta, StateInfo};
+
+/// Keys indexed by `CREATE INDEX`, per entity.
+pub type IndexDefinitions = BTreeMap<String, BTreeSet<String>>;
+
+/// Secondary indexes of the entities, kept in step with `LocalContext`.
+#[derive(Debug, Default)]
+pub struct IndexContext {
+    entities: BTreeMap<String, BTreeMap<String, Index>>,
+}
+
+/// Values a `WHERE` clause looks up in an index. Ranges are inclusive.
+pub enum Lookup<'a> {
+    Eq(&'a Types),
+    In(&'a [Types]),
+    From(&'a Types),
+    To(&'a Types),
+    Between(&'a Types, &'a Types),
+}
+
+impl IndexContext {
+    /// Builds the indexes of `definitions` from `data`, skipping entities that don't
+    /// exist.
+    pub fn new(definitions: IndexDefinitions, data: &LocalContext) -> Self {
+        let mut context = Self::default();
+        for (entity, keys) in definitions {
+            if let Some(registers) = data.get(&entity) {
+                context.create(&entity, keys.into_iter().collect(), registers);
+            }
+        }
+        context
+    }
+
+    pub fn definitions(&self) -> IndexDefinitions {
+        self.entities
+            .iter()
+            .map(|(entity, indexes)| (entity.to_owned(), indexes.keys().cloned().collect()))
+            .collect()
+    }
+
+    pub fn contains(&self, entity: &str) -> bool {
+        self.entities.contains_key(entity)
+    }
+
+    /// Indexes `keys` of `entity` that aren't indexed yet.
+    pub fn create(
+        &mut self,
+        entity: &str,
+        keys: Vec<String>,
+        registers: &BTreeMap<Uuid, StateInfo>,
+    ) {
+        let indexes = self.entities.entry(entity.to_owned()).or_default();
+        for key in keys {
+            indexes.entry(key).or_insert_with_key(|key| {
+                let mut index = Index::default();
+                for (id, (_, state)) in registers {
+                    index.insert(*id, decode(state).get(key));
+                }
+                index
+            });
+        }
+    }
+
+    /// Applies a change already applied to `LocalContext`. Evicting an entity drops its
+    /// indexes.
+    pub fn apply(&mut self, delta: &LocalDelta) {
+        match delta {
+            LocalDelta::CreateEntity(_) => (),
+            LocalDelta::EvictEntity(entity) => {
+                self.entities.remove(entity);
+            }
+            LocalDelta::EvictId(entity, id) => {
+                if let Some(indexes) = self.entities.get_mut(entity) {
+                    indexes.values_mut().for_each(|index| index.remove(id));
+                }
+            }
+            LocalDelta::Upsert(entity, id, (_, state)) => {
+                if let Some(indexes) = self.entities.get_mut(entity) {
+                    let state = decode(state);
+                    for (key, index) in indexes.iter_mut() {
+                        index.insert(*id, state.get(key));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Rebuilds the indexes of `entity` from its registers, after they were restored.
+    pub fn rebuild(&mut self, entity: &str, registers: Option<&BTreeMap<Uuid, StateInfo>>) {
+        if let Some(indexes) = self.entities.get_mut(entity) {
+            let empty = BTreeMap::new();
+            let registers = registers.unwrap_or(&empty);
+            for (key, index) in indexes.iter_mut() {
+                *index = Index::default();
+                for (id, (_, state)) in registers {
+                    index.insert(*id, decode(state).get(key));
+                }
+            }
+        }
+    }
+
+    /// Ids of `entity` that may match `lookup` on `key`: the ids whose value matches it
+    /// and the ids whose value can't be indexed. `None` when `key` isn't indexed or
+    /// `lookup` has values that can't be indexed.
+    pub fn candidates(&self, entity: &str, key: &str, lookup: &Lookup) -> Option<HashSet<Uuid>> {
+        let index = self.entities.get(entity)?.get(key)?;
+        let mut ids = index.unindexed.clone();
+        match lookup {
+            Lookup::Eq(value) => ids.extend(index.eq(&IndexKey::new(value)?)),
+            Lookup::In(values) => {
+                let keys = values
+                    .iter()
+                    .map(IndexKey::new)
+                    .collect::<Option<Vec<IndexKey>>>()?;
+                for key in keys {
+                    ids.extend(index.eq(&key));
+                }
+            }
+            Lookup::From(value) => ids.extend(index.from(&IndexKey::new(value)?, None)),
+            Lookup::To(value) => ids.extend(index.to(&IndexKey::new(value)?)),
+            Lookup::Between(start, end) => {
+                let end = IndexKey::new(end)?;
+                ids.extend(index.from(&IndexKey::new(start)?, Some(&end)));
+            }
+        }
+        Some(ids)
+    }
+}
+
+fn decode(state: &[u8]) -> HashMap<String, Types> {
+    bincode::deserialize(state).unwrap_or_default()
+}
+
+/// Ids by the value of one key. Ids without the key or with a value that can't be
+/// ordered, like vectors, maps and hashes, are kept apart so lookups can return them.
+#[derive(Debug, Default)]
+struct Index {
+    values: BTreeMap<IndexKey, HashSet<Uuid>>,
+    keys: HashMap<Uuid, IndexKey>,
+    unindexed: HashSet<Uuid>,
+}
+
+impl Index {
+    fn insert(&mut self, id: Uuid, value: Option<&Types>) {
+        self.remove(&id);
+        match value.and_then(IndexKey::new) {
+            Some(key) => {
+                self.values.entry(key.clone()).or_default().insert(id);
+                self.keys.insert(id, key);
+            }
+            None => {
+                self.unindexed.insert(id);
+            }
+        }
+    }
+
+    fn remove(&mut self, id: &Uuid) {
+        self.unindexed.remove(id);
+        if let Some(key) = self.keys.remove(id) {
+            if let Some(ids) = self.values.get_mut(&key) {
+                ids.remove(id);
+                if ids.is_empty() {
+                    self.values.remove(&key);
+                }
+            }
+        }
+    }
+
+    fn eq<'a>(&'a self, key: &IndexKey) -> impl Iterator<Item = &'a Uuid> {
+        self.values.get(key).into_iter().flatten()
+    }
+
+    /// Ids from `start` up to `end`, among the values of the same kind as `start`.
+    fn from<'a>(
+        &'a self,
+        start: &IndexKey,
+        end: Option<&'a IndexKey>,
+    ) -> impl Iterator<Item = &'a Uuid> {
+        let kind = discriminant(start);
+        self.values
+            .range(start..)
+            .take_while(move |(key, _)| {
+                discriminant(*key) == kind && end.is_none_or(|end| *key <= end)
+            })
+            .flat_map(|(_, ids)| ids)
+    }
+
+    /// Ids up to `end`, among the values of the same kind as `end`.
+    fn to<'a>(&'a self, end: &IndexKey) -> impl Iterator<Item = &'a Uuid> {
+        let kind = discriminant(end);
+        self.values
+            .range(..=end)
+            .rev()
+            .take_while(move |(key, _)| discriminant(*key) == kind)
+            .flat_map(|(_, ids)| ids)
+    }
+}
+
+/// Totally ordered form of an indexed value. Integers and floats share one kind, as
+/// `Types` compares them with each other.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum IndexKey {
+    Nil,
+    Boolean(bool),
+    Number(Number),
+    Char(char),
+    String(String),
+    Precise(String),
+    Uuid(Uuid),
+    DateTime(DateTime<Utc>),
+}
+
+impl IndexKey {
+    fn new(value: &Types) -> Option<Self> {
+        Some(match value {
+            Types::Nil => Self::Nil,
+            Types::Boolean(b) => Self::Boolean(*b),
+            Types::Integer(i) => Self::Number(Number::new(*i as f64)?),
+            Types::Float(f) => Self::Number(Number::new(*f)?),
+            Types::Char(c) => Self::Char(*c),
+            Types::String(s) => Self::String(s.to_owned()),
+            Types::Precise(p) => Self::Precise(p.to_owned()),
+            Types::Uuid(id) => Self::Uuid(*id),
+            Types::DateTime(date) => Self::DateTime(*date),
+            Types::Vector(_) | Types::Map(_) | Types::Hash(_) => return None,
+        })
+    }
+}
+
+/// A float that is never `NaN` nor `-0.0`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Number(f64);
+
+impl Number {
+    fn new(n: f64) -> Option<Self> {
+        if n.is_nan() {
+            None
+        } else {
+            Some(Self(n + 0.0))
+        }
+    }
+}
+
+impl Eq for Number {}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Number {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::model::DataRegister;
+
+    fn state(value: Types) -> StateInfo {
+        let register = DataRegister {
+            file_name: "data/2021_01_01.log".to_string(),
+            offset: 0,
+            bytes_length: 10,
+        };
+        let mut state = HashMap::new();
+        state.insert("a".to_string(), value);
+        (register, bincode::serialize(&state).unwrap())
+    }
+
+    fn upsert(context: &mut IndexContext, id: Uuid, value: Types) {
+        context.apply(&LocalDelta::Upsert("entity".to_string(), id, state(value)));
+    }
+
+    fn candidates(context: &IndexContext, lookup: Lookup) -> HashSet<Uuid> {
+        context.candidates("entity", "a", &lookup).unwrap()
+    }
+
+    #[test]
+    fn looks_up_values_and_ranges() {
+        let mut context = IndexContext::default();
+        let ids = (0..5).map(|_| Uuid::new_v4()).collect::<Vec<Uuid>>();
+        context.create("entity", vec!["a".to_string()], &BTreeMap::new());
+        upsert(&mut context, ids[0], Types::Integer(1));
+        upsert(&mut context, ids[1], Types::Float(2.5));
+        upsert(&mut context, ids[2], Types::Integer(4));
+        upsert(&mut context, ids[3], Types::String("4".to_string()));
+        upsert(&mut context, ids[4], Types::Vector(vec![]));
+
+        let expected = |i: &[usize]| i.iter().map(|i| ids[*i]).collect::<HashSet<Uuid>>();
+        assert_eq!(
+            candidates(&context, Lookup::Eq(&Types::Float(1.0))),
+            expected(&[0, 4])
+        );
+        assert_eq!(
+            candidates(&context, Lookup::From(&Types::Integer(2))),
+            expected(&[1, 2, 4])
+        );
+        assert_eq!(
+            candidates(&context, Lookup::To(&Types::Float(2.5))),
+            expected(&[0, 1, 4])
+        );
+        assert_eq!(
+            candidates(
+                &context,
+                Lookup::Between(&Types::Integer(2), &Types::Integer(1))
+            ),
+            expected(&[4])
+        );
+        assert_eq!(
+            candidates(
+                &context,
+                Lookup::In(&[Types::Integer(4), Types::String("4".to_string())])
+            ),
+            expected(&[2, 3, 4])
+        );
+        assert!(context
+            .candidates("entity", "a", &Lookup::Eq(&Types::Vector(vec![])))
+            .is_none());
+        assert!(context
+            .candidates("entity", "b", &Lookup::Eq(&Types::Nil))
+            .is_none());
+    }
+
+    #[test]
+    fn follows_local_deltas() {
+        let id = Uuid::new_v4();
+        let mut data = LocalContext::new();
+        data.entry("entity".to_string())
+            .or_default()
+            .insert(id, state(Types::Integer(1)));
+        let mut definitions = IndexDefinitions::new();
+        definitions.insert(
+            "entity".to_string(),
+            vec!["a".to_string()].into_iter().collect(),
+        );
+        definitions.insert(
+            "evicted".to_string(),
+            vec!["a".to_string()].into_iter().collect(),
+        );
+        let mut context = IndexContext::new(definitions, &data);
+
+        assert!(!context.contains("evicted"));
+        assert!(candidates(&context, Lookup::Eq(&Types::Integer(1))).contains(&id));
+
+        upsert(&mut context, id, Types::Integer(2));
+        assert!(candidates(&context, Lookup::Eq(&Types::Integer(1))).is_empty());
+        assert!(candidates(&context, Lookup::Eq(&Types::Integer(2))).contains(&id));
+
+        context.apply(&LocalDelta::EvictId("entity".to_string(), id));
+        assert!(candidates(&context, Lookup::Eq(&Types::Integer(2))).is_empty());
+
+        context.rebuild("entity", data.get("entity"));
+        assert!(candidates(&context, Lookup::Eq(&Types::Integer(1))).contains(&id));
+
+        context.apply(&LocalDelta::EvictEntity("entity".to_string()));
+        assert!(context.definitions().is_empty());
+    }
+}
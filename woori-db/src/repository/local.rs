@@ -4,7 +4,7 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::{auth::schemas::Role, model::DataRegister};
+use crate::{auth::schemas::Role, io::record::Payload, model::DataRegister};
 
 pub type StateInfo = (DataRegister, Vec<u8>);
 pub type LocalContext = BTreeMap<String, BTreeMap<Uuid, StateInfo>>;
@@ -34,3 +34,67 @@ impl SessionInfo {
         self.expiration > now
     }
 }
+
+/// A change to `LocalContext`, appended to the delta journal between checkpoints.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum LocalDelta {
+    CreateEntity(String),
+    EvictEntity(String),
+    EvictId(String, Uuid),
+    Upsert(String, Uuid, StateInfo),
+}
+
+impl LocalDelta {
+    pub fn apply(&self, data: &mut LocalContext) {
+        match self {
+            LocalDelta::CreateEntity(entity) => {
+                data.entry(entity.to_owned()).or_default();
+            }
+            LocalDelta::EvictEntity(entity) => {
+                data.remove(entity);
+            }
+            LocalDelta::EvictId(entity, uuid) => {
+                if let Some(map) = data.get_mut(entity) {
+                    map.remove(uuid);
+                }
+            }
+            LocalDelta::Upsert(entity, uuid, state) => {
+                if let Some(map) = data.get_mut(entity) {
+                    map.insert(*uuid, state.to_owned());
+                }
+            }
+        }
+    }
+}
+
+impl Payload for LocalDelta {}
+
+impl Payload for LocalContext {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn local_delta_apply() {
+        let mut data = LocalContext::new();
+        let uuid = Uuid::new_v4();
+        let register = DataRegister {
+            file_name: "data/2021_01_01.log".to_string(),
+            offset: 0,
+            bytes_length: 10,
+        };
+
+        LocalDelta::Upsert("ignored".to_string(), uuid, (register.clone(), vec![1]))
+            .apply(&mut data);
+        LocalDelta::CreateEntity("entity".to_string()).apply(&mut data);
+        LocalDelta::Upsert("entity".to_string(), uuid, (register, vec![1])).apply(&mut data);
+        assert!(!data.contains_key("ignored"));
+        assert_eq!(data["entity"][&uuid].1, vec![1]);
+
+        LocalDelta::EvictId("entity".to_string(), uuid).apply(&mut data);
+        assert!(data["entity"].is_empty());
+        LocalDelta::EvictEntity("entity".to_string()).apply(&mut data);
+        assert!(data.is_empty());
+    }
+}
@@ -0,0 +1,76 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+use uuid::Uuid;
+
+type IdKey = (String, Uuid);
+
+/// Serializes transactions that read and then rewrite the state of the same entity id,
+/// so none of them works on a state another one is replacing.
+#[derive(Default)]
+pub struct IdLocks {
+    locks: Arc<Mutex<HashMap<IdKey, Arc<AsyncMutex<()>>>>>,
+}
+
+impl IdLocks {
+    /// Waits until no other transaction holds the id, and holds it until the guard drops.
+    pub async fn lock(&self, entity: &str, id: Uuid) -> IdGuard {
+        let key = (entity.to_owned(), id);
+        let lock = self
+            .locks
+            .lock()
+            .map(|mut locks| locks.entry(key.clone()).or_default().clone())
+            .unwrap_or_default();
+
+        IdGuard {
+            guard: Some(lock.lock_owned().await),
+            locks: self.locks.clone(),
+            key,
+        }
+    }
+}
+
+pub struct IdGuard {
+    guard: Option<OwnedMutexGuard<()>>,
+    locks: Arc<Mutex<HashMap<IdKey, Arc<AsyncMutex<()>>>>>,
+    key: IdKey,
+}
+
+impl Drop for IdGuard {
+    fn drop(&mut self) {
+        self.guard.take();
+        if let Ok(mut locks) = self.locks.lock() {
+            let is_unused = locks
+                .get(&self.key)
+                .is_some_and(|lock| Arc::strong_count(lock) == 1);
+            if is_unused {
+                locks.remove(&self.key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[actix_rt::test]
+    async fn serializes_same_id_only() {
+        let locks = IdLocks::default();
+        let id = Uuid::new_v4();
+
+        let guard = locks.lock("entity", id).await;
+        let other = locks.lock("entity", Uuid::new_v4()).await;
+        let mut waiting = Box::pin(locks.lock("entity", id));
+        assert!(futures::poll!(waiting.as_mut()).is_pending());
+
+        drop(guard);
+        assert!(futures::poll!(waiting.as_mut()).is_ready());
+        drop(waiting);
+        drop(other);
+        assert!(locks.locks.lock().unwrap().is_empty());
+    }
+}
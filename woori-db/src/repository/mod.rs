@@ -1 +1,4 @@
+pub(crate) mod index;
 pub(crate) mod local;
+pub(crate) mod locks;
+pub(crate) mod time_index;
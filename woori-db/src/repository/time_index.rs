@@ -0,0 +1,421 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use wql::Types;
+
+use crate::{
+    core::valid_time,
+    io::record::Payload,
+    model::{error::Error, log::LogRecord, wql::Action, DataRegister},
+};
+
+/// A state of an entity id and where it was written, journaled to `time_index.log`.
+/// Evictions are journaled as tombstones, with the register of their record.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TimeEntry {
+    pub entity: String,
+    /// Evicted id, or nil when the whole entity was evicted.
+    pub uuid: Uuid,
+    pub tx_time: DateTime<Utc>,
+    pub tx_seq: Option<u64>,
+    pub valid_from: Option<DateTime<Utc>>,
+    pub valid_to: Option<DateTime<Utc>>,
+    pub register: DataRegister,
+    /// Whether the id has no state from `tx_time` on.
+    pub evicted: bool,
+}
+
+/// Layout of `TimeEntry` in format version 2, before tombstones.
+#[derive(Deserialize)]
+struct TimeEntryV2 {
+    entity: String,
+    uuid: Uuid,
+    tx_time: DateTime<Utc>,
+    tx_seq: Option<u64>,
+    valid_from: Option<DateTime<Utc>>,
+    valid_to: Option<DateTime<Utc>>,
+    register: DataRegister,
+}
+
+impl Payload for TimeEntry {
+    fn from_version(version: u8, payload: &[u8]) -> Result<Self, Error> {
+        if version > 2 {
+            return bincode::deserialize(payload).map_err(|_| Error::CorruptedLogRecord);
+        }
+        let entry: TimeEntryV2 =
+            bincode::deserialize(payload).map_err(|_| Error::CorruptedLogRecord)?;
+        Ok(Self {
+            entity: entry.entity,
+            uuid: entry.uuid,
+            tx_time: entry.tx_time,
+            tx_seq: entry.tx_seq,
+            valid_from: entry.valid_from,
+            valid_to: entry.valid_to,
+            register: entry.register,
+            evicted: false,
+        })
+    }
+}
+
+impl TimeEntry {
+    /// Entry of a record that writes a state of an id or evicts it, `None` for every
+    /// other record.
+    pub fn of(record: &LogRecord, register: DataRegister) -> Option<Self> {
+        match record.action {
+            Action::Insert | Action::UpdateSet | Action::UpdateContent | Action::Delete => {
+                let state: HashMap<String, Types> =
+                    ron::de::from_str(record.state.as_ref()?).ok()?;
+                let (valid_from, valid_to) = valid_time(&state);
+
+                Some(Self {
+                    entity: record.entity.clone(),
+                    uuid: record.uuid?,
+                    tx_time: record.tx_time?,
+                    tx_seq: record.tx_seq,
+                    valid_from,
+                    valid_to,
+                    register,
+                    evicted: false,
+                })
+            }
+            Action::EvictEntityId => Some(Self::evicted(
+                &record.entity,
+                record.uuid?,
+                record.tx_time?,
+                record.tx_seq,
+                register,
+            )),
+            Action::EvictEntity => Some(Self::evicted(
+                &record.entity,
+                Uuid::nil(),
+                record.tx_time?,
+                record.tx_seq,
+                register,
+            )),
+            _ => None,
+        }
+    }
+
+    /// Tombstone of `uuid`, or of every id of `entity` when it is nil, evicted by the
+    /// record at `register`.
+    pub fn evicted(
+        entity: &str,
+        uuid: Uuid,
+        tx_time: DateTime<Utc>,
+        tx_seq: Option<u64>,
+        register: DataRegister,
+    ) -> Self {
+        Self {
+            entity: entity.to_owned(),
+            uuid,
+            tx_time,
+            tx_seq,
+            valid_from: None,
+            valid_to: None,
+            register,
+            evicted: true,
+        }
+    }
+}
+
+/// Last version a `WHEN AT` query sees.
+#[derive(Debug, Clone, Copy)]
+pub enum Until {
+    Time(DateTime<Utc>),
+    Tx(u64),
+}
+
+#[derive(Debug, Clone)]
+struct Version {
+    tx_time: DateTime<Utc>,
+    tx_seq: Option<u64>,
+    valid_from: Option<DateTime<Utc>>,
+    valid_to: Option<DateTime<Utc>>,
+    register: DataRegister,
+    evicted: bool,
+}
+
+impl Version {
+    /// Whether the state is valid at `date`. Without a valid-time interval a state is
+    /// valid from its `tx_time` on.
+    fn is_valid_at(&self, date: DateTime<Utc>) -> bool {
+        self.valid_from.unwrap_or(self.tx_time) <= date
+            && self.valid_to.is_none_or(|valid_to| date < valid_to)
+    }
+}
+
+/// Versions of every entity id ordered by `tx_time`, so that reading an id as of some
+/// instant is a binary search and a single ranged read. Writes to an id are serialized
+/// by its lock, so the versions are ordered by `tx_seq` as well.
+#[derive(Debug, Default)]
+pub struct TimeIndex {
+    entities: HashMap<String, HashMap<Uuid, Vec<Version>>>,
+}
+
+impl TimeIndex {
+    pub fn new(entries: impl IntoIterator<Item = TimeEntry>) -> Self {
+        let mut index = Self::default();
+        entries.into_iter().for_each(|entry| index.insert(entry));
+        index
+    }
+
+    /// Adds a version of an id. A tombstone of the whole entity ends every id it has.
+    pub fn insert(&mut self, entry: TimeEntry) {
+        let TimeEntry {
+            entity,
+            uuid,
+            tx_time,
+            tx_seq,
+            valid_from,
+            valid_to,
+            register,
+            evicted,
+        } = entry;
+        let version = Version {
+            tx_time,
+            tx_seq,
+            valid_from,
+            valid_to,
+            register,
+            evicted,
+        };
+        let ids = self.entities.entry(entity).or_default();
+        if evicted && uuid.is_nil() {
+            ids.values_mut()
+                .for_each(|versions| insert_version(versions, version.clone()));
+        } else {
+            insert_version(ids.entry(uuid).or_default(), version);
+        }
+    }
+
+    /// Ids of `entity` with at least one version.
+    pub fn ids(&self, entity: &str) -> Vec<Uuid> {
+        self.entities
+            .get(entity)
+            .map(|ids| ids.keys().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Register of the latest version of the id no later than `until`, whatever day it was
+    /// written, and valid at `valid` when set. `None` when the id was evicted after that
+    /// version. Versions without a sequence number predate every numbered one.
+    pub fn at(
+        &self,
+        entity: &str,
+        uuid: &Uuid,
+        until: Until,
+        valid: Option<DateTime<Utc>>,
+    ) -> Option<&DataRegister> {
+        let versions = self.entities.get(entity)?.get(uuid)?;
+        let end = match until {
+            Until::Time(time) => versions.partition_point(|version| version.tx_time <= time),
+            Until::Tx(tx_seq) => {
+                versions.partition_point(|version| version.tx_seq.is_none_or(|seq| seq <= tx_seq))
+            }
+        };
+
+        versions[..end]
+            .iter()
+            .rev()
+            .find(|version| version.evicted || valid.is_none_or(|valid| version.is_valid_at(valid)))
+            .filter(|version| !version.evicted)
+            .map(|version| &version.register)
+    }
+
+    /// Register of the version of the id written at `tx_time`, if it wasn't evicted.
+    pub fn version(
+        &self,
+        entity: &str,
+        uuid: &Uuid,
+        tx_time: DateTime<Utc>,
+    ) -> Option<&DataRegister> {
+        let versions = self.entities.get(entity)?.get(uuid)?;
+        let end = versions.partition_point(|version| version.tx_time <= tx_time);
+
+        versions[..end]
+            .last()
+            .filter(|version| version.tx_time == tx_time && !version.evicted)
+            .map(|version| &version.register)
+    }
+
+    /// Versions of the id written strictly between `start` and `end`, oldest first.
+    pub fn between(
+        &self,
+        entity: &str,
+        uuid: &Uuid,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Vec<(DateTime<Utc>, &DataRegister)> {
+        let versions = match self.entities.get(entity).and_then(|ids| ids.get(uuid)) {
+            Some(versions) => versions,
+            None => return Vec::new(),
+        };
+        let first = versions.partition_point(|version| version.tx_time <= start);
+        let last = versions.partition_point(|version| version.tx_time < end);
+
+        versions[first..last.max(first)]
+            .iter()
+            .filter(|version| !version.evicted)
+            .map(|version| (version.tx_time, &version.register))
+            .collect()
+    }
+}
+
+fn insert_version(versions: &mut Vec<Version>, version: Version) {
+    let position = versions.partition_point(|existing| existing.tx_time <= version.tx_time);
+    versions.insert(position, version);
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn time(day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2021, 1, day, hour, 0, 0).unwrap()
+    }
+
+    fn entry(uuid: Uuid, hour: u32, tx_seq: u64) -> TimeEntry {
+        TimeEntry {
+            entity: "time".to_string(),
+            uuid,
+            tx_time: time(8, hour),
+            tx_seq: Some(tx_seq),
+            valid_from: None,
+            valid_to: None,
+            register: DataRegister {
+                file_name: "2021_01_08.log".to_string(),
+                offset: tx_seq as usize * 100,
+                bytes_length: 100,
+            },
+            evicted: false,
+        }
+    }
+
+    #[test]
+    fn latest_version_at_time_and_tx() {
+        let uuid = Uuid::new_v4();
+        let index = TimeIndex::new(vec![
+            entry(uuid, 14, 3),
+            entry(uuid, 10, 1),
+            entry(uuid, 12, 2),
+        ]);
+        let offset = |until| {
+            index
+                .at("time", &uuid, until, None)
+                .map(|register| register.offset)
+        };
+
+        assert_eq!(index.ids("time"), vec![uuid]);
+        assert_eq!(offset(Until::Time(time(8, 9))), None);
+        assert_eq!(offset(Until::Time(time(8, 12))), Some(200));
+        assert_eq!(offset(Until::Time(time(9, 0))), Some(300));
+        assert_eq!(offset(Until::Tx(1)), Some(100));
+        assert_eq!(offset(Until::Tx(0)), None);
+    }
+
+    #[test]
+    fn versions_across_days() {
+        let uuid = Uuid::new_v4();
+        let mut next_day = entry(uuid, 9, 4);
+        next_day.tx_time = time(9, 9);
+        let index = TimeIndex::new(vec![entry(uuid, 10, 1), entry(uuid, 12, 2), next_day]);
+        let offsets = |start, end| {
+            index
+                .between("time", &uuid, start, end)
+                .into_iter()
+                .map(|(_, register)| register.offset)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            index
+                .at("time", &uuid, Until::Time(time(9, 8)), None)
+                .map(|register| register.offset),
+            Some(200)
+        );
+        assert_eq!(offsets(time(8, 10), time(9, 10)), vec![200, 400]);
+        assert_eq!(offsets(time(8, 0), time(8, 12)), vec![100]);
+        assert!(offsets(time(9, 10), time(8, 0)).is_empty());
+        assert!(index.ids("other").is_empty());
+    }
+
+    #[test]
+    fn latest_version_valid_at() {
+        let uuid = Uuid::new_v4();
+        let mut monday_price = entry(uuid, 12, 2);
+        monday_price.valid_from = Some(time(11, 0));
+        let mut holiday_price = entry(uuid, 14, 3);
+        holiday_price.valid_from = Some(time(12, 0));
+        holiday_price.valid_to = Some(time(13, 0));
+        let index = TimeIndex::new(vec![entry(uuid, 10, 1), monday_price, holiday_price]);
+        let offset = |until, valid| {
+            index
+                .at("time", &uuid, until, Some(valid))
+                .map(|register| register.offset)
+        };
+
+        assert_eq!(offset(Until::Tx(3), time(8, 9)), None);
+        assert_eq!(offset(Until::Tx(3), time(9, 0)), Some(100));
+        assert_eq!(offset(Until::Tx(3), time(11, 0)), Some(200));
+        assert_eq!(offset(Until::Tx(3), time(12, 0)), Some(300));
+        assert_eq!(offset(Until::Tx(3), time(13, 0)), Some(200));
+        assert_eq!(offset(Until::Time(time(8, 13)), time(12, 0)), Some(200));
+        assert_eq!(offset(Until::Tx(1), time(12, 0)), Some(100));
+    }
+
+    #[test]
+    fn version_written_at_a_date() {
+        let uuid = Uuid::new_v4();
+        let index = TimeIndex::new(vec![entry(uuid, 10, 1), entry(uuid, 12, 2)]);
+
+        assert_eq!(
+            index.version("time", &uuid, time(8, 12)).map(|r| r.offset),
+            Some(200)
+        );
+        assert!(index.version("time", &uuid, time(8, 11)).is_none());
+        assert!(index
+            .version("time", &Uuid::new_v4(), time(8, 10))
+            .is_none());
+    }
+
+    #[test]
+    fn evicted_ids_have_no_version() {
+        let (uuid, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let tombstone = |mut entry: TimeEntry| {
+            entry.evicted = true;
+            entry
+        };
+        let index = TimeIndex::new(vec![
+            entry(uuid, 10, 1),
+            entry(other, 11, 2),
+            tombstone(entry(uuid, 12, 3)),
+            entry(uuid, 14, 4),
+            tombstone(entry(Uuid::nil(), 16, 5)),
+        ]);
+        let offset = |uuid, until, valid| {
+            index
+                .at("time", &uuid, until, valid)
+                .map(|register| register.offset)
+        };
+
+        assert_eq!(offset(uuid, Until::Time(time(8, 11)), None), Some(100));
+        assert_eq!(offset(uuid, Until::Time(time(8, 13)), None), None);
+        assert_eq!(offset(uuid, Until::Tx(3), Some(time(8, 10))), None);
+        assert_eq!(offset(uuid, Until::Time(time(8, 15)), None), Some(400));
+        assert_eq!(offset(uuid, Until::Time(time(8, 17)), None), None);
+        assert_eq!(offset(other, Until::Tx(4), None), Some(200));
+        assert_eq!(offset(other, Until::Tx(5), None), None);
+        assert_eq!(
+            index
+                .between("time", &uuid, time(8, 0), time(9, 0))
+                .into_iter()
+                .map(|(_, register)| register.offset)
+                .collect::<Vec<_>>(),
+            vec![100, 400]
+        );
+    }
+}
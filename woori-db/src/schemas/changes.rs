@@ -0,0 +1,49 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+use wql::Types;
+
+use crate::model::{error::Error, log::LogRecord, wql::Action};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChangesQuery {
+    /// Only changes of this entity tree key.
+    pub entity: Option<String>,
+    /// Sequence number of the first transaction to stream.
+    pub from: Option<u64>,
+}
+
+/// A write of a committed transaction, as streamed by `/changes`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangeEvent {
+    pub tx_seq: u64,
+    pub action: Action,
+    pub entity: String,
+    pub uuid: Option<Uuid>,
+    pub tx_time: Option<DateTime<Utc>>,
+    /// Entity map after the transaction, without encrypted values.
+    pub state: Option<HashMap<String, Types>>,
+}
+
+impl ChangeEvent {
+    pub fn new(record: &LogRecord) -> Result<Self, Error> {
+        let state = match &record.state {
+            Some(state) => {
+                let state: HashMap<String, Types> =
+                    ron::de::from_str(state).map_err(|_| Error::FailedToParseState)?;
+                Some(state.into_iter().filter(|(_, v)| !v.is_hash()).collect())
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            tx_seq: record.tx_seq.unwrap_or_default(),
+            action: record.action.clone(),
+            entity: record.entity.clone(),
+            uuid: record.uuid,
+            tx_time: record.tx_time,
+            state,
+        })
+    }
+}
@@ -1,6 +1,10 @@
 use chrono::{DateTime, Utc};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use uuid::Uuid;
+use wql::Types;
+
+use crate::{core::TX_VERSION, model::wql::Action};
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct EntityHistoryInfo {
@@ -8,4 +12,150 @@ pub struct EntityHistoryInfo {
     pub entity_id: Uuid,
     pub start_datetime: Option<DateTime<Utc>>,
     pub end_datetime: Option<DateTime<Utc>>,
+    /// Answers with what each version changed instead of its whole state.
+    #[serde(default)]
+    pub diff: bool,
+    /// Maximum number of versions answered. Requests with a `limit`, a `cursor` or an
+    /// `order` are answered with a `HistoryPage`.
+    pub limit: Option<usize>,
+    /// `cursor` of the previous page, to continue after its last version.
+    pub cursor: Option<HistoryCursor>,
+    pub order: Option<HistoryOrder>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum HistoryOrder {
+    OldestFirst,
+    NewestFirst,
+}
+
+/// Where a page of the entity history stopped.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum HistoryCursor {
+    /// Date of the next older version, so that newest first pages don't walk the
+    /// versions already answered.
+    Before(DateTime<Utc>),
+    /// Date of the last version answered in oldest first pages.
+    After(DateTime<Utc>),
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum HistoryResponse {
+    States(BTreeMap<DateTime<Utc>, HashMap<String, Types>>),
+    Diffs(BTreeMap<DateTime<Utc>, StateDiff>),
+    Page(HistoryPage),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryPage {
+    /// Versions in the requested order.
+    pub versions: Vec<(DateTime<Utc>, HistoryVersion)>,
+    /// Cursor to send for the next page, `None` on the last one.
+    pub cursor: Option<HistoryCursor>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum HistoryVersion {
+    State(HashMap<String, Types>),
+    Diff(StateDiff),
+}
+
+/// Keys a version changed from the previous one, and the action that wrote it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StateDiff {
+    pub action: Action,
+    pub added: HashMap<String, Types>,
+    pub removed: HashMap<String, Types>,
+    pub changed: HashMap<String, Change>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Change {
+    pub old: Types,
+    pub new: Types,
+}
+
+impl StateDiff {
+    /// Diff from `previous` to `state`. `tx_time` and `tx_version` change with every
+    /// version, so they are left out.
+    pub fn new(
+        action: Action,
+        previous: &HashMap<String, Types>,
+        state: &HashMap<String, Types>,
+    ) -> Self {
+        let is_diffed = |key: &String| key != "tx_time" && key != TX_VERSION;
+        let added = state
+            .iter()
+            .filter(|(k, _)| is_diffed(k) && !previous.contains_key(*k))
+            .map(|(k, v)| (k.to_owned(), v.to_owned()))
+            .collect();
+        let removed = previous
+            .iter()
+            .filter(|(k, _)| is_diffed(k) && !state.contains_key(*k))
+            .map(|(k, v)| (k.to_owned(), v.to_owned()))
+            .collect();
+        let changed = state
+            .iter()
+            .filter(|(k, _)| is_diffed(k))
+            .filter_map(|(k, new)| match previous.get(k) {
+                Some(old) if old != new => Some((
+                    k.to_owned(),
+                    Change {
+                        old: old.to_owned(),
+                        new: new.to_owned(),
+                    },
+                )),
+                _ => None,
+            })
+            .collect();
+
+        Self {
+            action,
+            added,
+            removed,
+            changed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn diff_between_states() {
+        let previous: HashMap<String, Types> = vec![
+            ("a".to_string(), Types::Integer(123)),
+            ("b".to_string(), Types::Float(12.3)),
+            ("tx_version".to_string(), Types::Integer(1)),
+        ]
+        .into_iter()
+        .collect();
+        let state: HashMap<String, Types> = vec![
+            ("a".to_string(), Types::Integer(34)),
+            ("c".to_string(), Types::Boolean(true)),
+            ("tx_version".to_string(), Types::Integer(2)),
+        ]
+        .into_iter()
+        .collect();
+
+        let diff = StateDiff::new(Action::UpdateSet, &previous, &state);
+
+        assert_eq!(diff.action, Action::UpdateSet);
+        assert_eq!(diff.added.get("c"), Some(&Types::Boolean(true)));
+        assert_eq!(diff.removed.get("b"), Some(&Types::Float(12.3)));
+        assert_eq!(
+            diff.changed.get("a"),
+            Some(&Change {
+                old: Types::Integer(123),
+                new: Types::Integer(34),
+            })
+        );
+        assert_eq!(
+            diff.added.len() + diff.removed.len() + diff.changed.len(),
+            3
+        );
+    }
 }
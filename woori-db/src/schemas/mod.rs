@@ -1,3 +1,4 @@
+pub mod changes;
 pub mod error;
 pub mod history;
 pub mod query;
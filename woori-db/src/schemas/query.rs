@@ -1,6 +1,9 @@
 use std::collections::{BTreeMap, HashMap};
 
-use crate::{core::pretty_config_output, model::error::Error};
+use crate::{
+    core::{pretty_config_output, TX_VERSION, VALID_FROM, VALID_TO},
+    model::error::Error,
+};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -220,6 +223,11 @@ pub enum Response {
     WithCount(CountResponse),
     DateSelect(HashMap<String, HashMap<String, Types>>),
     Join(Vec<HashMap<String, Types>>),
+    /// Results of the aggregate functions by their key, like `sum_a`, which is also the
+    /// name `HAVING` clauses use.
+    Aggregate(HashMap<String, Types>),
+    /// Results of the aggregate functions for each `GROUP BY` bucket.
+    GroupAggregate(HashMap<String, HashMap<String, Types>>),
 }
 
 impl From<CountResponse> for Response {
@@ -314,6 +322,8 @@ impl Response {
             | Response::GroupBy(_)
             | Response::OrderedGroupBy(_)
             | Response::Join(_)
+            | Response::Aggregate(_)
+            | Response::GroupAggregate(_)
             | Response::DateSelect(_) => {
                 return false;
             }
@@ -324,10 +334,13 @@ impl Response {
                     if let Some(v) = entities {
                         for ent in v {
                             let mut s = s.clone();
-                            for entry in ent
-                                .iter()
-                                .filter(|(k, _)| *k != "tx_time" && *k != &ent_b.1)
-                            {
+                            for entry in ent.iter().filter(|(k, _)| {
+                                *k != "tx_time"
+                                    && *k != TX_VERSION
+                                    && *k != VALID_FROM
+                                    && *k != VALID_TO
+                                    && *k != &ent_b.1
+                            }) {
                                 let entry_name = if s.contains_key(entry.0) {
                                     format!("{}:{}", entry.0, ent_b.0)
                                 } else {
@@ -347,10 +360,13 @@ impl Response {
                     if let Some(v) = entities {
                         for ent in v {
                             let mut s = s.clone();
-                            for entry in ent
-                                .iter()
-                                .filter(|(k, _)| *k != "tx_time" && *k != &ent_b.1)
-                            {
+                            for entry in ent.iter().filter(|(k, _)| {
+                                *k != "tx_time"
+                                    && *k != TX_VERSION
+                                    && *k != VALID_FROM
+                                    && *k != VALID_TO
+                                    && *k != &ent_b.1
+                            }) {
                                 let entry_name = if s.contains_key(entry.0) {
                                     format!("{}:{}", entry.0, ent_b.0)
                                 } else {
@@ -371,10 +387,13 @@ impl Response {
                         if let Some(v) = entities {
                             for ent in v {
                                 let mut s = s.clone();
-                                for entry in ent
-                                    .iter()
-                                    .filter(|(k, _)| *k != "tx_time" && *k != &ent_b.1)
-                                {
+                                for entry in ent.iter().filter(|(k, _)| {
+                                    *k != "tx_time"
+                                        && *k != TX_VERSION
+                                        && *k != VALID_FROM
+                                        && *k != VALID_TO
+                                        && *k != &ent_b.1
+                                }) {
                                     let entry_name = if s.contains_key(entry.0) {
                                         format!("{}:{}", entry.0, ent_b.0)
                                     } else {
@@ -396,10 +415,13 @@ impl Response {
                         if let Some(v) = entities {
                             for ent in v {
                                 let mut s = s.clone();
-                                for entry in ent
-                                    .iter()
-                                    .filter(|(k, _)| *k != "tx_time" && *k != &ent_b.1)
-                                {
+                                for entry in ent.iter().filter(|(k, _)| {
+                                    *k != "tx_time"
+                                        && *k != TX_VERSION
+                                        && *k != VALID_FROM
+                                        && *k != VALID_TO
+                                        && *k != &ent_b.1
+                                }) {
                                     let entry_name = if s.contains_key(entry.0) {
                                         entry.0.to_owned()
                                     } else {
@@ -467,6 +489,8 @@ impl Response {
             | Response::Union(_)
             | Response::GroupBy(_)
             | Response::OrderedGroupBy(_)
+            | Response::Aggregate(_)
+            | Response::GroupAggregate(_)
             | Response::DateSelect(_) => {
                 return None;
             }
@@ -519,6 +543,12 @@ impl Response {
             Response::Join(state) => {
                 Ok(ron::ser::to_string_pretty(&state, pretty_config_output())?)
             }
+            Response::Aggregate(state) => {
+                Ok(ron::ser::to_string_pretty(&state, pretty_config_output())?)
+            }
+            Response::GroupAggregate(state) => {
+                Ok(ron::ser::to_string_pretty(&state, pretty_config_output())?)
+            }
         }
     }
 }
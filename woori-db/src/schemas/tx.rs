@@ -12,6 +12,7 @@ pub enum TxType {
     Delete,
     EvictEntity,
     EvictEntityTree,
+    CreateIndex,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,6 +20,10 @@ pub struct TxResponse {
     tx_type: TxType,
     entity: String,
     pub(crate) uuid: Option<Uuid>,
+    /// Version of the entity id after the transaction.
+    version: Option<usize>,
+    /// Sequence number of the transaction.
+    pub(crate) tx_seq: u64,
     state: String,
     message: String,
 }
@@ -36,12 +41,17 @@ impl TxResponse {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateEntityResponse {
     entity: String,
+    tx_seq: u64,
     message: String,
 }
 
 impl CreateEntityResponse {
-    pub fn new(entity: String, message: String) -> Self {
-        Self { entity, message }
+    pub fn new(entity: String, tx_seq: u64, message: String) -> Self {
+        Self {
+            entity,
+            tx_seq,
+            message,
+        }
     }
 }
 
@@ -51,6 +61,39 @@ impl From<CreateEntityResponse> for TxResponse {
             tx_type: TxType::Create,
             entity: tx.entity,
             uuid: None,
+            version: None,
+            tx_seq: tx.tx_seq,
+            state: String::new(),
+            message: tx.message,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateIndexResponse {
+    entity: String,
+    tx_seq: u64,
+    message: String,
+}
+
+impl CreateIndexResponse {
+    pub fn new(entity: String, tx_seq: u64, message: String) -> Self {
+        Self {
+            entity,
+            tx_seq,
+            message,
+        }
+    }
+}
+
+impl From<CreateIndexResponse> for TxResponse {
+    fn from(tx: CreateIndexResponse) -> Self {
+        Self {
+            tx_type: TxType::CreateIndex,
+            entity: tx.entity,
+            uuid: None,
+            version: None,
+            tx_seq: tx.tx_seq,
             state: String::new(),
             message: tx.message,
         }
@@ -61,6 +104,8 @@ impl From<CreateEntityResponse> for TxResponse {
 pub struct InsertEntityResponse {
     entity: String,
     pub(crate) uuid: Uuid,
+    version: usize,
+    tx_seq: u64,
     message: String,
 }
 
@@ -70,6 +115,8 @@ impl From<InsertEntityResponse> for TxResponse {
             tx_type: TxType::Insert,
             entity: tx.entity,
             uuid: Some(tx.uuid),
+            version: Some(tx.version),
+            tx_seq: tx.tx_seq,
             state: String::new(),
             message: tx.message,
         }
@@ -77,10 +124,12 @@ impl From<InsertEntityResponse> for TxResponse {
 }
 
 impl InsertEntityResponse {
-    pub fn new(entity: String, uuid: Uuid, message: String) -> Self {
+    pub fn new(entity: String, uuid: Uuid, version: usize, tx_seq: u64, message: String) -> Self {
         Self {
             entity,
             uuid,
+            version,
+            tx_seq,
             message,
         }
     }
@@ -90,6 +139,8 @@ impl InsertEntityResponse {
 pub struct DeleteOrEvictEntityResponse {
     entity: String,
     uuid: Option<Uuid>,
+    version: Option<usize>,
+    tx_seq: u64,
     message: String,
     tx_type: TxType,
 }
@@ -100,6 +151,8 @@ impl From<DeleteOrEvictEntityResponse> for TxResponse {
             tx_type: tx.tx_type,
             entity: tx.entity,
             uuid: tx.uuid,
+            version: tx.version,
+            tx_seq: tx.tx_seq,
             state: String::new(),
             message: tx.message,
         }
@@ -107,10 +160,19 @@ impl From<DeleteOrEvictEntityResponse> for TxResponse {
 }
 
 impl DeleteOrEvictEntityResponse {
-    pub fn new(entity: String, uuid: Option<Uuid>, message: String, tx_type: TxType) -> Self {
+    pub fn new(
+        entity: String,
+        uuid: Option<Uuid>,
+        version: Option<usize>,
+        tx_seq: u64,
+        message: String,
+        tx_type: TxType,
+    ) -> Self {
         Self {
             entity,
             uuid,
+            version,
+            tx_seq,
             message,
             tx_type,
         }
@@ -121,6 +183,8 @@ impl DeleteOrEvictEntityResponse {
 pub struct UpdateEntityResponse {
     entity: String,
     uuid: Uuid,
+    version: usize,
+    tx_seq: u64,
     state: String,
     message: String,
     tx_type: TxType,
@@ -132,6 +196,8 @@ impl From<UpdateEntityResponse> for TxResponse {
             tx_type: tx.tx_type,
             entity: tx.entity,
             uuid: Some(tx.uuid),
+            version: Some(tx.version),
+            tx_seq: tx.tx_seq,
             state: tx.state,
             message: tx.message,
         }
@@ -142,6 +208,8 @@ impl UpdateEntityResponse {
     pub fn new(
         entity: String,
         uuid: Uuid,
+        version: usize,
+        tx_seq: u64,
         state: String,
         message: String,
         tx_type: TxType,
@@ -149,9 +217,41 @@ impl UpdateEntityResponse {
         Self {
             entity,
             uuid,
+            version,
+            tx_seq,
             state,
             message,
             tx_type,
         }
     }
 }
+
+/// Results of the statements of a `BEGIN ... COMMIT` block, in order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionResponse {
+    pub(crate) results: Vec<TxResponse>,
+    pub(crate) tx_seq: u64,
+    message: String,
+}
+
+impl TransactionResponse {
+    pub fn new(results: Vec<TxResponse>, tx_seq: u64) -> Self {
+        Self {
+            message: format!(
+                "Transaction {} with {} statements committed",
+                tx_seq,
+                results.len()
+            ),
+            results,
+            tx_seq,
+        }
+    }
+
+    pub fn write(&self) -> String {
+        #[cfg(feature = "json")]
+        return serde_json::to_string(self).unwrap_or_else(|_| "SERVER ERROR".to_string());
+        #[cfg(not(feature = "json"))]
+        ron::ser::to_string_pretty(self, pretty_config_output())
+            .unwrap_or_else(|_| "SERVER ERROR".to_string())
+    }
+}
@@ -3,9 +3,12 @@ use crate::{
     logic::{read_args, read_map_as_str},
     relation::{relation, Relation},
     select::{select_all, select_args},
+    transaction::transaction,
 };
 
-use super::{read_map, read_match_args, FromStr, MatchCondition, Uuid, Wql};
+use super::{read_map, read_match_args, FromStr, MatchCondition, Uuid, Valid, Wql};
+use chrono::{DateTime, Utc};
+use std::{iter::Peekable, str::SplitWhitespace};
 
 #[allow(clippy::redundant_pub_crate)]
 pub(crate) fn read_symbol(a: char, chars: &mut std::str::Chars) -> Result<Wql, String> {
@@ -24,6 +27,7 @@ pub(crate) fn read_symbol(a: char, chars: &mut std::str::Chars) -> Result<Wql, S
         ('d', "IFFERENCE") | ('D', "IFFERENCE") => relation(chars, Relation::Difference),
         ('u', "NION") | ('U', "NION") => relation(chars, Relation::Union),
         ('j', "OIN") | ('J', "OIN") => join(chars),
+        ('b', "EGIN") | ('B', "EGIN") => transaction(chars),
         _ => Err(format!("Symbol `{}{}` not implemented", a, symbol)),
     }
 }
@@ -31,6 +35,9 @@ pub(crate) fn read_symbol(a: char, chars: &mut std::str::Chars) -> Result<Wql, S
 fn create_entity(chars: &mut std::str::Chars) -> Result<Wql, String> {
     let entity_symbol = chars.take_while(|c| !c.is_whitespace()).collect::<String>();
 
+    if entity_symbol.to_uppercase() == "INDEX" {
+        return create_index(chars);
+    }
     if entity_symbol.to_uppercase() != "ENTITY" {
         return Err(String::from("Keyword ENTITY is required for CREATE"));
     }
@@ -59,6 +66,29 @@ fn create_entity(chars: &mut std::str::Chars) -> Result<Wql, String> {
     }
 }
 
+fn create_index(chars: &mut std::str::Chars) -> Result<Wql, String> {
+    let entity_name = chars
+        .take_while(|c| c.is_alphanumeric() || c == &'_')
+        .collect::<String>()
+        .trim()
+        .to_string();
+    if entity_name.is_empty() {
+        return Err(String::from("Entity name is required for CREATE INDEX"));
+    }
+
+    if chars.find(|c| !c.is_whitespace()) != Some('#') {
+        return Err(String::from(
+            "Arguments set should start with `#{` and end with `}`",
+        ));
+    }
+    let keys = read_args(chars)?;
+    if keys.is_empty() {
+        return Err(String::from("CREATE INDEX requires at least one key"));
+    }
+
+    Ok(Wql::CreateIndex(entity_name, keys))
+}
+
 fn create_uniques_and_encrypts(
     chars: &mut std::str::Chars,
     next_element: &str,
@@ -154,34 +184,70 @@ fn insert(chars: &mut std::str::Chars) -> Result<Wql, String> {
         return Err(String::from("Entity name is required after INTO"));
     }
 
-    let with_symbol = chars
-        .skip_while(|c| c.is_whitespace())
-        .take_while(|c| !c.is_whitespace())
-        .collect::<String>();
+    let rest = chars.collect::<String>();
+    let mut words = rest.split_whitespace().peekable();
+    let uuid = match words.peek() {
+        Some(with_symbol) if with_symbol.eq_ignore_ascii_case("WITH") => {
+            words.next();
+            let entity_id = words
+                .next()
+                .ok_or_else(|| String::from("Entity UUID is required for INSERT WITH id"))?;
+            Uuid::parse_str(entity_id).ok()
+        }
+        Some(valid_symbol) if valid_symbol.eq_ignore_ascii_case("VALID") => None,
+        Some(_) => {
+            return Err(String::from(
+                "Keyword WITH is required for INSERT with Uuid",
+            ))
+        }
+        None => None,
+    };
+    let valid = valid(&mut words)?;
 
-    if with_symbol.is_empty() {
-        Ok(Wql::Insert(entity_name, entity_map, None))
-    } else if with_symbol.to_uppercase() != "WITH" {
-        Err(String::from(
-            "Keyword WITH is required for INSERT with Uuid",
-        ))
-    } else {
-        let entity_id = chars
-            .take_while(|c| c.is_alphanumeric() || c == &'-')
-            .collect::<String>()
-            .trim()
-            .to_string();
+    if words.next().is_some() {
+        return Err(String::from(
+            "Only WITH <uuid> and VALID FROM <date> TO <date> are allowed after the INSERT entity",
+        ));
+    }
+    Ok(Wql::Insert(entity_name, entity_map, uuid, valid))
+}
 
-        if entity_id.is_empty() {
-            return Err(String::from("Entity UUID is required for INSERT WITH id"));
+/// Reads the optional `VALID FROM <date> TO <date>` of a write, where `TO <date>` is
+/// optional as well.
+fn valid(words: &mut Peekable<SplitWhitespace>) -> Result<Option<Valid>, String> {
+    match words.peek() {
+        Some(valid_symbol) if valid_symbol.eq_ignore_ascii_case("VALID") => words.next(),
+        _ => return Ok(None),
+    };
+    if !words
+        .next()
+        .is_some_and(|from_symbol| from_symbol.eq_ignore_ascii_case("FROM"))
+    {
+        return Err(String::from("Keyword FROM is required after VALID"));
+    }
+    let from = valid_date(words.next())?;
+    let to = match words.peek() {
+        Some(to_symbol) if to_symbol.eq_ignore_ascii_case("TO") => {
+            words.next();
+            Some(valid_date(words.next())?)
         }
+        _ => None,
+    };
 
-        Ok(Wql::Insert(
-            entity_name,
-            entity_map,
-            Uuid::parse_str(&entity_id).ok(),
-        ))
+    if to.is_some_and(|to| to <= from) {
+        return Err(String::from(
+            "VALID TO date should be after VALID FROM date",
+        ));
     }
+    Ok(Some(Valid { from, to }))
+}
+
+#[allow(clippy::redundant_pub_crate)]
+pub(crate) fn valid_date(date: Option<&str>) -> Result<DateTime<Utc>, String> {
+    let date = date.ok_or_else(|| String::from("A date is required for VALID"))?;
+
+    date.parse::<DateTime<Utc>>()
+        .map_err(|e| format!("Couldn't parse VALID date {}. Error: {:?}", date, e))
 }
 
 fn check(chars: &mut std::str::Chars) -> Result<Wql, String> {
@@ -265,13 +331,50 @@ fn update(chars: &mut std::str::Chars) -> Result<Wql, String> {
     let uuid = Uuid::from_str(&uuid_str)
         .map_err(|e| format!("Couldn't create uuid from {}. Error: {:?}", uuid_str, e))?;
 
+    let rest = chars.collect::<String>();
+    let mut words = rest.split_whitespace().peekable();
+    let valid = valid(&mut words)?;
+    let version = if_version(words)?;
+
     match &entity_symbol.to_uppercase()[..] {
-        "SET" => Ok(Wql::UpdateSet(entity_name, entity_map, uuid)),
-        "CONTENT" => Ok(Wql::UpdateContent(entity_name, entity_map, uuid)),
+        "SET" => Ok(Wql::UpdateSet(
+            entity_name,
+            entity_map,
+            uuid,
+            version,
+            valid,
+        )),
+        "CONTENT" => Ok(Wql::UpdateContent(
+            entity_name,
+            entity_map,
+            uuid,
+            version,
+            valid,
+        )),
         _ => Err("Couldn't parse UPDATE query".to_string()),
     }
 }
 
+/// Reads the optional `IF VERSION <version>` that ends an `UPDATE`. Any other trailing
+/// words are an error rather than ignored.
+fn if_version(mut words: Peekable<SplitWhitespace>) -> Result<Option<usize>, String> {
+    match (words.next(), words.next(), words.next(), words.next()) {
+        (None, ..) => Ok(None),
+        (Some(if_symbol), Some(version_symbol), Some(version), None)
+            if if_symbol.eq_ignore_ascii_case("IF")
+                && version_symbol.eq_ignore_ascii_case("VERSION") =>
+        {
+            version
+                .parse::<usize>()
+                .map(Some)
+                .map_err(|e| format!("Couldn't parse version {}. Error: {:?}", version, e))
+        }
+        _ => Err(String::from(
+            "Only VALID FROM <date> TO <date> and IF VERSION <version> are allowed after the UPDATE uuid",
+        )),
+    }
+}
+
 fn match_update(chars: &mut std::str::Chars) -> Result<Wql, String> {
     let match_arg_symbol = chars
         .skip_while(|c| c.is_whitespace())
@@ -342,8 +445,23 @@ fn match_update(chars: &mut std::str::Chars) -> Result<Wql, String> {
     let uuid = Uuid::from_str(&uuid_str)
         .map_err(|e| format!("Couldn't create uuid from {}, Error: {:?}", uuid_str, e))?;
 
+    let rest = chars.collect::<String>();
+    let mut words = rest.split_whitespace().peekable();
+    let valid = valid(&mut words)?;
+    if words.next().is_some() {
+        return Err(String::from(
+            "Only VALID FROM <date> TO <date> is allowed after the MATCH UPDATE uuid",
+        ));
+    }
+
     match &entity_symbol.to_uppercase()[..] {
-        "SET" => Ok(Wql::MatchUpdate(entity_name, entity_map, uuid, match_args?)),
+        "SET" => Ok(Wql::MatchUpdate(
+            entity_name,
+            entity_map,
+            uuid,
+            match_args?,
+            valid,
+        )),
         _ => Err("Couldn't parse MATCH UPDATE query".to_string()),
     }
 }
@@ -11,6 +11,7 @@ mod relation;
 mod select;
 #[cfg(test)]
 mod test;
+mod transaction;
 mod where_clause;
 
 pub use logic::parse_value as parse_types;
@@ -21,20 +22,22 @@ pub use where_clause::{Clause, Function, Value};
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub enum Wql {
     CreateEntity(String, Vec<String>, Vec<String>),
-    Insert(String, Entity, Option<Uuid>),
-    UpdateContent(String, Entity, Uuid),
-    UpdateSet(String, Entity, Uuid),
+    CreateIndex(String, Vec<String>),
+    Insert(String, Entity, Option<Uuid>, Option<Valid>),
+    UpdateContent(String, Entity, Uuid, Option<usize>, Option<Valid>),
+    UpdateSet(String, Entity, Uuid, Option<usize>, Option<Valid>),
     Delete(String, String),
-    MatchUpdate(String, Entity, Uuid, MatchCondition),
+    MatchUpdate(String, Entity, Uuid, MatchCondition, Option<Valid>),
     Evict(String, Option<Uuid>),
     Select(String, ToSelect, Option<Uuid>, HashMap<String, Algebra>),
-    SelectWhen(String, ToSelect, Option<Uuid>, String),
+    SelectWhen(String, ToSelect, Option<Uuid>, When, Option<DateTime<Utc>>),
     SelectWhenRange(String, Uuid, String, String),
     SelectIds(String, ToSelect, Vec<Uuid>, HashMap<String, Algebra>),
     SelectWhere(String, ToSelect, Vec<Clause>, HashMap<String, Algebra>),
     CheckValue(String, Uuid, HashMap<String, String>),
     RelationQuery(Vec<Wql>, Relation, RelationType),
     Join((String, String), (String, String), Vec<Wql>),
+    Transaction(Vec<Wql>),
 }
 
 pub use select::{Algebra, Order};
@@ -45,6 +48,21 @@ pub enum ToSelect {
     Keys(Vec<String>),
 }
 
+/// Point in time `WHEN AT` reads at, a date or a transaction sequence number.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub enum When {
+    Date(String),
+    Tx(u64),
+}
+
+/// Valid-time interval of a write, `VALID FROM <date> TO <date>`. Without `TO` the
+/// state stays valid until another write says otherwise.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Valid {
+    pub from: DateTime<Utc>,
+    pub to: Option<DateTime<Utc>>,
+}
+
 pub type Entity = HashMap<String, Types>;
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
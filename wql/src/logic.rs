@@ -249,7 +249,7 @@ pub(crate) fn read_select_args(chars: &mut std::str::Chars) -> Result<Vec<String
             Some('}') => return Ok(res),
             Some(c) if !c.is_whitespace() && c != ',' => {
                 let key_rest = chars
-                    .take_while(|c| c.is_alphanumeric() || c == &'_')
+                    .take_while(|c| c.is_alphanumeric() || c == &'_' || c == &'.')
                     .collect::<String>();
 
                 let key = format!("{}{}", c, key_rest);
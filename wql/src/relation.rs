@@ -60,7 +60,7 @@ pub fn relation(chars: &mut std::str::Chars, relation: Relation) -> Result<Wql,
             match &q {
                 Wql::Select(_, _, Some(_), hm) if hm.is_empty()
                     => Ok(q),
-                Wql::SelectWhen(_, _, Some(_), _)
+                Wql::SelectWhen(_, _, Some(_), _, _)
                     => Ok(q),
                 _ => Err(String::from("Only single value queries are allowed, so key `ID` is required and keys `WHEN AT` are optional"))
             }
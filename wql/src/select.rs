@@ -1,12 +1,19 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, str::FromStr};
 
 use uuid::Uuid;
 
-const ALGEBRA: [&str; 6] = ["DEDUP", "GROUP", "ORDER", "OFFSET", "LIMIT", "COUNT"];
-const OPERATORS: [&str; 10] = [
-    "ID", "IDS", "WHERE", "WHEN", "DEDUP", "GROUP", "ORDER", "OFFSET", "LIMIT", "COUNT",
+use crate::language_parser::valid_date;
+
+const ALGEBRA: [&str; 11] = [
+    "DEDUP", "GROUP", "ORDER", "OFFSET", "LIMIT", "COUNT", "SUM", "AVG", "MIN", "MAX", "HAVING",
+];
+const OPERATORS: [&str; 15] = [
+    "ID", "IDS", "WHERE", "WHEN", "DEDUP", "GROUP", "ORDER", "OFFSET", "LIMIT", "COUNT", "SUM",
+    "AVG", "MIN", "MAX", "HAVING",
 ];
+const FUNCTIONS_ERROR: &str = "Available functions are DEDUP, GROUP BY, ORDER BY, OFFSET, LIMIT, COUNT, COUNT DISTINCT, SUM, AVG, MIN, MAX, HAVING";
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub enum Order {
     Asc,
@@ -34,13 +41,59 @@ pub enum Algebra {
     Limit(usize),
     Offset(usize),
     Count,
+    /// Aggregates of the values of a key, over all the states or over each `GROUP BY`
+    /// bucket.
+    Sum(String),
+    Avg(String),
+    Min(String),
+    Max(String),
+    CountDistinct(String),
+    /// Clauses on the `count` and the aggregates of each `GROUP BY` bucket, keeping only
+    /// the buckets that satisfy all of them.
+    Having(Vec<Clause>),
 }
 
-use crate::where_clause::where_selector;
+impl Algebra {
+    /// Key of the result of an aggregate function, like `sum_a` for `SUM a`. `HAVING`
+    /// clauses name aggregates by it too.
+    pub fn aggregate_name(&self) -> Option<String> {
+        Some(match self {
+            Self::Sum(key) => format!("sum_{}", key),
+            Self::Avg(key) => format!("avg_{}", key),
+            Self::Min(key) => format!("min_{}", key),
+            Self::Max(key) => format!("max_{}", key),
+            Self::CountDistinct(key) => format!("count_distinct_{}", key),
+            _ => return None,
+        })
+    }
+
+    /// Aggregate function named `name` by `aggregate_name`.
+    pub fn from_aggregate_name(name: &str) -> Option<Self> {
+        let (function, key): (fn(String) -> Self, &str) =
+            if let Some(key) = name.strip_prefix("count_distinct_") {
+                (Self::CountDistinct, key)
+            } else if let Some(key) = name.strip_prefix("sum_") {
+                (Self::Sum, key)
+            } else if let Some(key) = name.strip_prefix("avg_") {
+                (Self::Avg, key)
+            } else if let Some(key) = name.strip_prefix("min_") {
+                (Self::Min, key)
+            } else {
+                (Self::Max, name.strip_prefix("max_")?)
+            };
+        if key.is_empty() {
+            None
+        } else {
+            Some(function(key.to_owned()))
+        }
+    }
+}
+
+use crate::where_clause::{having_selector, where_selector, Clause};
 
 use super::{
     logic::{read_select_args, read_uuids},
-    ToSelect, Wql,
+    ToSelect, When, Wql,
 };
 
 #[allow(clippy::redundant_pub_crate)]
@@ -158,7 +211,17 @@ pub fn algebra_functions(
     let mut next_symbol = next;
 
     loop {
-        if ALGEBRA.contains(&&next_symbol[..]) {
+        if next_symbol == "HAVING" {
+            functions.insert(
+                "HAVING".to_string(),
+                Algebra::Having(having_selector(chars)?),
+            );
+            next_symbol = chars
+                .skip_while(|c| c.is_whitespace())
+                .take_while(|c| !c.is_whitespace())
+                .collect::<String>()
+                .to_uppercase();
+        } else if ALGEBRA.contains(&&next_symbol[..]) {
             if next_symbol == "GROUP" || next_symbol == "ORDER" {
                 let by = chars
                     .skip_while(|c| c.is_whitespace())
@@ -200,12 +263,19 @@ pub fn algebra_functions(
                         .map_err(|e| format!("Error parsing value: {:?}", e))?;
                     functions.insert("LIMIT".to_string(), Algebra::Limit(value))
                 }
-                "COUNT" => functions.insert("COUNT".to_string(), Algebra::Count),
-                _ => {
-                    return Err(String::from(
-                        "Available functions are DEDUP, GROUP BY, ORDER BY, OFFSET, LIMIT, COUNT",
-                    ))
+                "COUNT" if next_value.eq_ignore_ascii_case("DISTINCT") => {
+                    let key = chars
+                        .skip_while(|c| c.is_whitespace())
+                        .take_while(|c| !c.is_whitespace())
+                        .collect::<String>();
+                    insert_aggregate(&mut functions, Algebra::CountDistinct(aggregate_key(key)?))
                 }
+                "COUNT" => functions.insert("COUNT".to_string(), Algebra::Count),
+                "SUM" => insert_aggregate(&mut functions, Algebra::Sum(aggregate_key(next_value)?)),
+                "AVG" => insert_aggregate(&mut functions, Algebra::Avg(aggregate_key(next_value)?)),
+                "MIN" => insert_aggregate(&mut functions, Algebra::Min(aggregate_key(next_value)?)),
+                "MAX" => insert_aggregate(&mut functions, Algebra::Max(aggregate_key(next_value)?)),
+                _ => return Err(String::from(FUNCTIONS_ERROR)),
             };
 
             next_symbol = chars
@@ -216,15 +286,46 @@ pub fn algebra_functions(
         } else if chars.count() == 0 {
             break;
         } else {
-            return Err(String::from(
-                "Available functions are DEDUP, GROUP BY, ORDER BY, OFFSET, LIMIT, COUNT",
-            ));
+            return Err(String::from(FUNCTIONS_ERROR));
         }
     }
 
+    if functions.contains_key("HAVING") && !functions.contains_key("GROUP") {
+        return Err(String::from("HAVING requires GROUP BY"));
+    }
+    if functions.contains_key("ORDER")
+        && functions
+            .values()
+            .any(|function| function.aggregate_name().is_some())
+    {
+        return Err(String::from(
+            "ORDER BY can't be used with aggregate functions, which answer their results instead of the entities",
+        ));
+    }
     Ok(functions)
 }
 
+/// Inserts an aggregate function by the key of its result, so that the same function can
+/// be applied to several keys, like `SUM a SUM b`.
+fn insert_aggregate(
+    functions: &mut HashMap<String, Algebra>,
+    function: Algebra,
+) -> Option<Algebra> {
+    let name = function.aggregate_name()?;
+    functions.insert(name, function)
+}
+
+/// Key an aggregate function is applied to.
+fn aggregate_key(key: String) -> Result<String, String> {
+    if key.is_empty() {
+        Err(String::from(
+            "Aggregate functions COUNT DISTINCT, SUM, AVG, MIN and MAX require a key",
+        ))
+    } else {
+        Ok(key)
+    }
+}
+
 fn when_selector(
     entity_name: String,
     arg: ToSelect,
@@ -249,8 +350,50 @@ fn when_selector(
         .skip_while(|c| c.is_whitespace())
         .take_while(|c| !c.is_whitespace())
         .collect::<String>();
+    let when = if date.to_uppercase() == "TX" {
+        let tx_seq = chars
+            .skip_while(|c| c.is_whitespace())
+            .take_while(|c| !c.is_whitespace())
+            .collect::<String>()
+            .parse::<u64>()
+            .map_err(|e| format!("Error parsing transaction sequence number: {:?}", e))?;
+        When::Tx(tx_seq)
+    } else {
+        When::Date(date)
+    };
 
-    Ok(Wql::SelectWhen(entity_name, arg, uuid, date))
+    Ok(Wql::SelectWhen(
+        entity_name,
+        arg,
+        uuid,
+        when,
+        as_of_valid(chars)?,
+    ))
+}
+
+/// Reads the optional `AS OF VALID <date>` that ends a `WHEN AT` query.
+fn as_of_valid(chars: &mut std::str::Chars) -> Result<Option<DateTime<Utc>>, String> {
+    let rest = chars.collect::<String>();
+    let mut words = rest.split_whitespace();
+    match (words.next(), words.next(), words.next()) {
+        (None, ..) => Ok(None),
+        (Some(as_symbol), Some(of_symbol), Some(valid_symbol))
+            if as_symbol.eq_ignore_ascii_case("AS")
+                && of_symbol.eq_ignore_ascii_case("OF")
+                && valid_symbol.eq_ignore_ascii_case("VALID") =>
+        {
+            let date = valid_date(words.next())?;
+            if words.next().is_some() {
+                return Err(String::from(
+                    "Only AS OF VALID <date> is allowed after WHEN AT",
+                ));
+            }
+            Ok(Some(date))
+        }
+        _ => Err(String::from(
+            "Only AS OF VALID <date> is allowed after WHEN AT",
+        )),
+    }
 }
 
 fn when_time_range(
@@ -279,12 +422,6 @@ fn when_time_range(
         .take_while(|c| !c.is_whitespace())
         .collect::<String>();
 
-    if !end_date.starts_with(&start_date[0..10]) {
-        return Err(String::from(
-            "START date and END date should be the same date.",
-        ));
-    }
-
     Ok(Wql::SelectWhenRange(
         entity_name,
         uuid,
@@ -297,7 +434,7 @@ fn when_time_range(
 mod test {
     use uuid::Uuid;
 
-    use crate::{ToSelect, Wql};
+    use crate::{ToSelect, When, Wql};
     use std::{collections::HashMap, str::FromStr};
 
     #[test]
@@ -364,6 +501,25 @@ mod test {
         );
     }
 
+    #[test]
+    fn select_key_paths() {
+        let wql = Wql::from_str("SelEct #{name, address.city, tags.0,} FROM my_entity");
+
+        assert_eq!(
+            wql.unwrap(),
+            Wql::Select(
+                "my_entity".to_string(),
+                ToSelect::Keys(vec![
+                    "name".to_string(),
+                    "address.city".to_string(),
+                    "tags.0".to_string()
+                ]),
+                None,
+                HashMap::new()
+            )
+        );
+    }
+
     #[test]
     fn select_all_id() {
         let wql = Wql::from_str("SelEct * FROM my_entity ID 2df2b8cf-49da-474d-8a00-c596c0bb6fd1");
@@ -462,7 +618,8 @@ mod test {
                 "my_entity".to_string(),
                 ToSelect::All,
                 Some(uuid),
-                "2020-01-01T00:00:00Z".to_string()
+                When::Date("2020-01-01T00:00:00Z".to_string()),
+                None
             )
         );
     }
@@ -477,7 +634,8 @@ mod test {
                 "my_entity".to_string(),
                 ToSelect::Keys(vec!["a".to_string(), "b".to_string(), "c".to_string()]),
                 Some(uuid),
-                "2020-01-01T00:00:00Z".to_string()
+                When::Date("2020-01-01T00:00:00Z".to_string()),
+                None
             )
         );
     }
@@ -492,14 +650,68 @@ mod test {
                 "my_entity".to_string(),
                 ToSelect::Keys(vec!["a".to_string(), "b".to_string(), "c".to_string()]),
                 None,
-                "2020-01-01T00:00:00Z".to_string()
+                When::Date("2020-01-01T00:00:00Z".to_string()),
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn when_at_tx() {
+        let wql = Wql::from_str(
+            "SelEct * FROM my_entity ID 2df2b8cf-49da-474d-8a00-c596c0bb6fd1 WHEN AT tx 42",
+        );
+        let uuid = Uuid::from_str("2df2b8cf-49da-474d-8a00-c596c0bb6fd1").unwrap();
+        assert_eq!(
+            wql.unwrap(),
+            Wql::SelectWhen(
+                "my_entity".to_string(),
+                ToSelect::All,
+                Some(uuid),
+                When::Tx(42),
+                None
             )
         );
     }
 
+    #[test]
+    fn when_at_tx_err() {
+        let wql = Wql::from_str("SelEct * FROM my_entity WHEN AT TX 2020-01-01T00:00:00Z");
+
+        assert!(wql
+            .unwrap_err()
+            .starts_with("Error parsing transaction sequence number"));
+    }
+
+    #[test]
+    fn when_as_of_valid() {
+        let wql = Wql::from_str("SelEct * FROM my_entity ID 2df2b8cf-49da-474d-8a00-c596c0bb6fd1 WHEN AT TX 42 as of valid 2021-01-04T00:00:00Z");
+        let uuid = Uuid::from_str("2df2b8cf-49da-474d-8a00-c596c0bb6fd1").unwrap();
+
+        assert_eq!(
+            wql.unwrap(),
+            Wql::SelectWhen(
+                "my_entity".to_string(),
+                ToSelect::All,
+                Some(uuid),
+                When::Tx(42),
+                Some("2021-01-04T00:00:00Z".parse().unwrap())
+            )
+        );
+        assert_eq!(
+            Wql::from_str(
+                "SelEct * FROM my_entity WHEN AT 2020-01-01T00:00:00Z AS OF 2021-01-04T00:00:00Z"
+            )
+            .err(),
+            Some(String::from(
+                "Only AS OF VALID <date> is allowed after WHEN AT"
+            ))
+        );
+    }
+
     #[test]
     fn when_range_all() {
-        let wql = Wql::from_str("SelEct * FROM my_entity ID 2df2b8cf-49da-474d-8a00-c596c0bb6fd1 WHEN START 2020-01-01T00:00:00Z END 2020-01-01T03:00:00Z");
+        let wql = Wql::from_str("SelEct * FROM my_entity ID 2df2b8cf-49da-474d-8a00-c596c0bb6fd1 WHEN START 2020-01-01T00:00:00Z END 2020-01-03T03:00:00Z");
         let uuid = Uuid::from_str("2df2b8cf-49da-474d-8a00-c596c0bb6fd1").unwrap();
         assert_eq!(
             wql.unwrap(),
@@ -507,7 +719,7 @@ mod test {
                 "my_entity".to_string(),
                 uuid,
                 "2020-01-01T00:00:00Z".to_string(),
-                "2020-01-01T03:00:00Z".to_string()
+                "2020-01-03T03:00:00Z".to_string()
             )
         );
     }
@@ -528,7 +740,7 @@ mod test {
 #[cfg(test)]
 mod functions_test {
     use super::*;
-    use crate::{ToSelect, Wql};
+    use crate::{Function, ToSelect, Types, Wql};
 
     use std::str::FromStr;
 
@@ -598,6 +810,148 @@ mod functions_test {
         );
     }
 
+    #[test]
+    fn select_all_aggregates() {
+        let wql = Wql::from_str(
+            "SelEct * FROM my_entity GROUP BY c SUM a AVG b MIN a MAX d COUNT DISTINCT e",
+        );
+        let hm: HashMap<String, Algebra> = [
+            ("GROUP".to_string(), Algebra::GroupBy("c".to_string())),
+            ("sum_a".to_string(), Algebra::Sum("a".to_string())),
+            ("avg_b".to_string(), Algebra::Avg("b".to_string())),
+            ("min_a".to_string(), Algebra::Min("a".to_string())),
+            ("max_d".to_string(), Algebra::Max("d".to_string())),
+            (
+                "count_distinct_e".to_string(),
+                Algebra::CountDistinct("e".to_string()),
+            ),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+
+        assert_eq!(
+            wql.unwrap(),
+            Wql::Select("my_entity".to_string(), ToSelect::All, None, hm)
+        );
+    }
+
+    #[test]
+    fn same_aggregate_of_several_keys() {
+        let wql = Wql::from_str("SelEct * FROM my_entity SUM a SUM b");
+        let hm: HashMap<String, Algebra> = [
+            ("sum_a".to_string(), Algebra::Sum("a".to_string())),
+            ("sum_b".to_string(), Algebra::Sum("b".to_string())),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+
+        assert_eq!(
+            wql.unwrap(),
+            Wql::Select("my_entity".to_string(), ToSelect::All, None, hm)
+        );
+    }
+
+    #[test]
+    fn aggregate_with_order_by() {
+        let wql = Wql::from_str("SelEct * FROM my_entity ORDER BY a :asc SUM b");
+
+        assert_eq!(
+            wql.err(),
+            Some(String::from(
+                "ORDER BY can't be used with aggregate functions, which answer their results instead of the entities"
+            ))
+        );
+    }
+
+    #[test]
+    fn aggregate_without_key() {
+        let wql = Wql::from_str("SelEct * FROM my_entity COUNT DISTINCT");
+
+        assert_eq!(
+            wql.err(),
+            Some(String::from(
+                "Aggregate functions COUNT DISTINCT, SUM, AVG, MIN and MAX require a key"
+            ))
+        );
+    }
+
+    #[test]
+    fn select_all_having() {
+        let wql = Wql::from_str(
+            "SelEct * FROM my_entity GROUP BY c SUM amount HAVING { (> count 10), (or (>= sum_amount 1000) (between max_a 1 3)) }",
+        );
+        let hm: HashMap<String, Algebra> = [
+            ("GROUP".to_string(), Algebra::GroupBy("c".to_string())),
+            ("sum_amount".to_string(), Algebra::Sum("amount".to_string())),
+            (
+                "HAVING".to_string(),
+                Algebra::Having(vec![
+                    Clause::SimpleComparisonFunction(
+                        Function::G,
+                        "count".to_string(),
+                        Types::Integer(10),
+                    ),
+                    Clause::Or(
+                        Function::Or,
+                        vec![
+                            Clause::SimpleComparisonFunction(
+                                Function::GEq,
+                                "sum_amount".to_string(),
+                                Types::Integer(1000),
+                            ),
+                            Clause::ComplexComparisonFunctions(
+                                Function::Between,
+                                "max_a".to_string(),
+                                vec![Types::Integer(1), Types::Integer(3)],
+                            ),
+                        ],
+                    ),
+                ]),
+            ),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+
+        assert_eq!(
+            wql.unwrap(),
+            Wql::Select("my_entity".to_string(), ToSelect::All, None, hm)
+        );
+    }
+
+    #[test]
+    fn having_errors() {
+        assert_eq!(
+            Wql::from_str("SelEct * FROM my_entity HAVING { (> count 10) }").err(),
+            Some(String::from("HAVING requires GROUP BY"))
+        );
+        assert_eq!(
+            Wql::from_str("SelEct * FROM my_entity GROUP BY c HAVING { (> amount 10) }").err(),
+            Some(String::from(
+                "HAVING clauses compare `count` or aggregates like `sum_key` with ==, >, >=, <, <=, in, between or or"
+            ))
+        );
+        assert_eq!(
+            Wql::from_str("SelEct * FROM my_entity GROUP BY c HAVING {}").err(),
+            Some(String::from("HAVING clause cannot be empty"))
+        );
+    }
+
+    #[test]
+    fn aggregate_names() {
+        let sum = Algebra::Sum("address.city".to_string());
+        assert_eq!(sum.aggregate_name(), Some("sum_address.city".to_string()));
+        assert_eq!(Algebra::from_aggregate_name("sum_address.city"), Some(sum));
+        assert_eq!(
+            Algebra::from_aggregate_name("count_distinct_a"),
+            Some(Algebra::CountDistinct("a".to_string()))
+        );
+        assert_eq!(Algebra::from_aggregate_name("sum_"), None);
+        assert_eq!(Algebra::Count.aggregate_name(), None);
+    }
+
     #[test]
     fn select_all_ids_order() {
         let wql = Wql::from_str("SelEct * FROM my_entity IDS IN #{2df2b8cf-49da-474d-8a00-c596c0bb6fd1, 53315090-e14d-4738-a4d2-f1ec2a93664c,} ORDER BY my_key :desc DEDUP ley");
@@ -45,6 +45,37 @@ mod test_create {
         );
     }
 
+    #[test]
+    fn create_index() {
+        let wql = Wql::from_str("CREATE INDEX entity #{name, age,}");
+
+        assert_eq!(
+            wql.unwrap(),
+            Wql::CreateIndex(
+                String::from("entity"),
+                vec!["name".to_string(), "age".to_string()]
+            )
+        );
+    }
+
+    #[test]
+    fn create_index_errors() {
+        assert_eq!(
+            Wql::from_str("CREATE INDEX #{name,}").err(),
+            Some(String::from("Entity name is required for CREATE INDEX"))
+        );
+        assert_eq!(
+            Wql::from_str("CREATE INDEX entity name").err(),
+            Some(String::from(
+                "Arguments set should start with `#{` and end with `}`"
+            ))
+        );
+        assert_eq!(
+            Wql::from_str("CREATE INDEX entity #{}").err(),
+            Some(String::from("CREATE INDEX requires at least one key"))
+        );
+    }
+
     #[test]
     fn create_entity_with_uniques() {
         let wql = Wql::from_str("CREATE ENTITY entity UNIQUES #{name, ssn,something,}");
@@ -207,7 +238,7 @@ mod test_insert {
 
         assert_eq!(
             wql.unwrap(),
-            Wql::Insert("my_entity".to_string(), hashmap(), None)
+            Wql::Insert("my_entity".to_string(), hashmap(), None, None)
         );
     }
 
@@ -222,7 +253,10 @@ mod test_insert {
         let mut hm = HashMap::new();
         hm.insert("a".to_string(), Types::Precise("98347883122138743294728345738925783257325789353593473247832493483478935673.9347324783249348347893567393473247832493483478935673".to_string()));
 
-        assert_eq!(wql.unwrap(), Wql::Insert("my_entity".to_string(), hm, None));
+        assert_eq!(
+            wql.unwrap(),
+            Wql::Insert("my_entity".to_string(), hm, None, None)
+        );
     }
 
     #[test]
@@ -288,11 +322,181 @@ mod test_update {
             Wql::UpdateSet(
                 "this_entity".to_string(),
                 hashmap(),
-                Uuid::from_str("d6ca73c0-41ff-4975-8a60-fc4a061ce536").unwrap()
+                Uuid::from_str("d6ca73c0-41ff-4975-8a60-fc4a061ce536").unwrap(),
+                None,
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn update_set_entity_if_version() {
+        let wql = Wql::from_str(
+            "UPDATE this_entity 
+        SET {
+            a: 123,
+            g: NiL
+        } 
+        INTO d6ca73c0-41ff-4975-8a60-fc4a061ce536 if version 3",
+        );
+
+        assert_eq!(
+            wql.unwrap(),
+            Wql::UpdateSet(
+                "this_entity".to_string(),
+                hashmap(),
+                Uuid::from_str("d6ca73c0-41ff-4975-8a60-fc4a061ce536").unwrap(),
+                Some(3),
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn update_content_entity_if_version() {
+        let wql = Wql::from_str(
+            "UPDATE this_entity 
+        CONTENT {
+            a: 123,
+            g: NiL
+        } 
+        INTO d6ca73c0-41ff-4975-8a60-fc4a061ce536 IF VERSION 12",
+        );
+
+        assert_eq!(
+            wql.unwrap(),
+            Wql::UpdateContent(
+                "this_entity".to_string(),
+                hashmap(),
+                Uuid::from_str("d6ca73c0-41ff-4975-8a60-fc4a061ce536").unwrap(),
+                Some(12),
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn update_set_entity_bad_version() {
+        let wql = Wql::from_str(
+            "UPDATE this_entity SET {a: 123,} INTO d6ca73c0-41ff-4975-8a60-fc4a061ce536 IF VERSION",
+        );
+
+        assert_eq!(
+            wql.err(),
+            Some(String::from(
+                "Only VALID FROM <date> TO <date> and IF VERSION <version> are allowed after the UPDATE uuid"
+            ))
+        );
+    }
+
+    #[test]
+    fn update_set_entity_trailing_text() {
+        let wql = Wql::from_str(
+            "UPDATE this_entity SET {a: 123, g: NiL,} INTO d6ca73c0-41ff-4975-8a60-fc4a061ce536 \n\t ",
+        );
+
+        assert_eq!(
+            wql.unwrap(),
+            Wql::UpdateSet(
+                "this_entity".to_string(),
+                hashmap(),
+                Uuid::from_str("d6ca73c0-41ff-4975-8a60-fc4a061ce536").unwrap(),
+                None,
+                None
+            )
+        );
+
+        let wql = Wql::from_str(
+            "UPDATE this_entity SET {a: 123,} INTO d6ca73c0-41ff-4975-8a60-fc4a061ce536 IF VERSON 2",
+        );
+
+        assert_eq!(
+            wql.err(),
+            Some(String::from(
+                "Only VALID FROM <date> TO <date> and IF VERSION <version> are allowed after the UPDATE uuid"
+            ))
+        );
+    }
+
+    #[test]
+    fn update_set_entity_valid() {
+        let wql = Wql::from_str(
+            "UPDATE this_entity SET {a: 123, g: NiL,}
+        INTO d6ca73c0-41ff-4975-8a60-fc4a061ce536
+        VALID FROM 2021-01-04T00:00:00Z TO 2021-01-11T00:00:00Z IF VERSION 3",
+        );
+
+        assert_eq!(
+            wql.unwrap(),
+            Wql::UpdateSet(
+                "this_entity".to_string(),
+                hashmap(),
+                Uuid::from_str("d6ca73c0-41ff-4975-8a60-fc4a061ce536").unwrap(),
+                Some(3),
+                Some(Valid {
+                    from: "2021-01-04T00:00:00Z".parse().unwrap(),
+                    to: Some("2021-01-11T00:00:00Z".parse().unwrap()),
+                })
+            )
+        );
+    }
+
+    #[test]
+    fn insert_entity_valid() {
+        let wql = Wql::from_str(
+            "INSERT {a: 123, g: NiL,} INTO my_entity
+            WITH 13ca62fc-241b-4af6-87c3-0ae4015f9967 VALID FROM 2021-01-04T00:00:00Z",
+        );
+
+        assert_eq!(
+            wql.unwrap(),
+            Wql::Insert(
+                "my_entity".to_string(),
+                hashmap(),
+                Uuid::parse_str("13ca62fc-241b-4af6-87c3-0ae4015f9967").ok(),
+                Some(Valid {
+                    from: "2021-01-04T00:00:00Z".parse().unwrap(),
+                    to: None,
+                })
             )
         );
     }
 
+    #[test]
+    fn valid_errors() {
+        let errors = [
+            (
+                "INSERT {a: 1,} INTO my_entity VALID 2021-01-04T00:00:00Z",
+                "Keyword FROM is required after VALID",
+            ),
+            (
+                "INSERT {a: 1,} INTO my_entity VALID FROM",
+                "A date is required for VALID",
+            ),
+            (
+                "INSERT {a: 1,} INTO my_entity VALID FROM 2021-01-04T00:00:00Z TO 2021-01-04T00:00:00Z",
+                "VALID TO date should be after VALID FROM date",
+            ),
+            (
+                "INSERT {a: 1,} INTO my_entity VALID FROM 2021-01-04T00:00:00Z NOW",
+                "Only WITH <uuid> and VALID FROM <date> TO <date> are allowed after the INSERT entity",
+            ),
+            (
+                "MATCH ALL(a == 1) UPDATE my_entity SET {a: 2,} INTO d6ca73c0-41ff-4975-8a60-fc4a061ce536 IF VERSION 1",
+                "Only VALID FROM <date> TO <date> is allowed after the MATCH UPDATE uuid",
+            ),
+        ];
+
+        for (wql, error) in errors.iter() {
+            assert_eq!(Wql::from_str(wql).err(), Some(error.to_string()));
+        }
+        assert!(
+            Wql::from_str("INSERT {a: 1,} INTO my_entity VALID FROM monday")
+                .unwrap_err()
+                .starts_with("Couldn't parse VALID date monday")
+        );
+    }
+
     #[test]
     fn update_content_entity() {
         let wql = Wql::from_str(
@@ -309,7 +513,9 @@ mod test_update {
             Wql::UpdateContent(
                 "this_entity".to_string(),
                 hashmap(),
-                Uuid::from_str("d6ca73c0-41ff-4975-8a60-fc4a061ce536").unwrap()
+                Uuid::from_str("d6ca73c0-41ff-4975-8a60-fc4a061ce536").unwrap(),
+                None,
+                None
             )
         );
     }
@@ -435,7 +641,8 @@ mod test_match {
                     MatchCondition::GEq("b".to_string(), Types::Integer(3)),
                     MatchCondition::NotEq("c".to_string(), Types::String("hello".to_string())),
                     MatchCondition::L("d".to_string(), Types::Integer(7)),
-                ])
+                ]),
+                None
             )
         );
     }
@@ -643,7 +850,7 @@ mod test_data_sructures {
 
         assert_eq!(
             wql.unwrap(),
-            Wql::Insert("my_entity".to_string(), hashmap(), None)
+            Wql::Insert("my_entity".to_string(), hashmap(), None, None)
         );
     }
 
@@ -663,7 +870,10 @@ mod test_data_sructures {
         .iter()
         .cloned()
         .collect();
-        assert_eq!(wql.unwrap(), Wql::Insert("my_entity".to_string(), hm, None));
+        assert_eq!(
+            wql.unwrap(),
+            Wql::Insert("my_entity".to_string(), hm, None, None)
+        );
     }
 
     #[test]
@@ -677,7 +887,7 @@ mod test_data_sructures {
 
         assert_eq!(
             wql.unwrap(),
-            Wql::Insert("my_entity".to_string(), hashmap2(), None)
+            Wql::Insert("my_entity".to_string(), hashmap2(), None, None)
         );
     }
 
@@ -725,7 +935,7 @@ mod test_data_sructures {
 
         assert_eq!(
             wql.unwrap(),
-            Wql::Insert("my_entity".to_string(), hashmap3(), None)
+            Wql::Insert("my_entity".to_string(), hashmap3(), None, None)
         );
     }
 
@@ -742,7 +952,7 @@ mod test_data_sructures {
 
         assert_eq!(
             wql.unwrap(),
-            Wql::Insert("my_entity".to_string(), hashmap3(), uuid)
+            Wql::Insert("my_entity".to_string(), hashmap3(), uuid, None)
         );
     }
 
@@ -1003,7 +1213,8 @@ mod diff_intersect {
                         "my_entity".to_string(),
                         ToSelect::All,
                         s_uuid,
-                        "2020-01-01T00:00:00Z".to_string()
+                        When::Date("2020-01-01T00:00:00Z".to_string()),
+                        None
                     ),
                 ],
                 Relation::Difference,
@@ -1032,7 +1243,8 @@ mod diff_intersect {
                         "my_entity".to_string(),
                         ToSelect::All,
                         s_uuid,
-                        "2020-01-01T00:00:00Z".to_string()
+                        When::Date("2020-01-01T00:00:00Z".to_string()),
+                        None
                     ),
                 ],
                 Relation::Union,
@@ -1041,3 +1253,73 @@ mod diff_intersect {
         );
     }
 }
+
+#[cfg(test)]
+mod test_transaction {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn transaction_statements() {
+        let query = "BEGIN
+            INSERT {a: 1, b: \"semi;colon\",} INTO my_entity;
+            UPDATE my_entity SET {a: 2,} INTO 2df2b8cf-49da-474d-8a00-c596c0bb6fd1;
+            DELETE 2df2b8cf-49da-474d-8a00-c596c0bb6fd1 FROM my_entity;
+        COMMIT";
+        let wql = Wql::from_str(query);
+        let uuid = Uuid::from_str("2df2b8cf-49da-474d-8a00-c596c0bb6fd1").unwrap();
+        let mut insert = HashMap::new();
+        insert.insert("a".to_string(), Types::Integer(1));
+        insert.insert("b".to_string(), Types::String("semi;colon".to_string()));
+        let mut update = HashMap::new();
+        update.insert("a".to_string(), Types::Integer(2));
+
+        assert_eq!(
+            wql.unwrap(),
+            Wql::Transaction(vec![
+                Wql::Insert("my_entity".to_string(), insert, None, None),
+                Wql::UpdateSet("my_entity".to_string(), update, uuid, None, None),
+                Wql::Delete(
+                    "my_entity".to_string(),
+                    "2df2b8cf-49da-474d-8a00-c596c0bb6fd1".to_string()
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn transaction_without_commit() {
+        let wql = Wql::from_str("BEGIN INSERT {a: 1,} INTO my_entity;");
+
+        assert_eq!(
+            wql.err(),
+            Some(String::from(
+                "Keyword COMMIT is required to end a BEGIN block"
+            ))
+        );
+    }
+
+    #[test]
+    fn transaction_without_statements() {
+        let wql = Wql::from_str("BEGIN ; commit");
+
+        assert_eq!(
+            wql.err(),
+            Some(String::from(
+                "BEGIN ... COMMIT requires at least one statement"
+            ))
+        );
+    }
+
+    #[test]
+    fn transaction_with_create() {
+        let wql = Wql::from_str("BEGIN CREATE ENTITY my_entity; COMMIT");
+
+        assert_eq!(
+            wql.err(),
+            Some(String::from(
+                "Only INSERT, UPDATE, MATCH UPDATE and DELETE are allowed inside BEGIN ... COMMIT"
+            ))
+        );
+    }
+}
@@ -0,0 +1,74 @@
+use std::str::FromStr;
+
+use crate::Wql;
+
+/// Parses the statements of a `BEGIN ... COMMIT` block, separated by `;`.
+pub fn transaction(chars: &mut std::str::Chars) -> Result<Wql, String> {
+    let block = chars.collect::<String>();
+    let block = block.trim_end();
+    let commit_start = block.len().saturating_sub("COMMIT".len());
+    let body = match block.get(commit_start..) {
+        Some(commit) if commit.eq_ignore_ascii_case("COMMIT") => &block[..commit_start],
+        _ => {
+            return Err(String::from(
+                "Keyword COMMIT is required to end a BEGIN block",
+            ))
+        }
+    };
+
+    let statements = split_statements(body)?
+        .into_iter()
+        .map(|statement| match Wql::from_str(statement)? {
+            wql @ Wql::Insert(..)
+            | wql @ Wql::UpdateContent(..)
+            | wql @ Wql::UpdateSet(..)
+            | wql @ Wql::MatchUpdate(..)
+            | wql @ Wql::Delete(..) => Ok(wql),
+            _ => Err(String::from(
+                "Only INSERT, UPDATE, MATCH UPDATE and DELETE are allowed inside BEGIN ... COMMIT",
+            )),
+        })
+        .collect::<Result<Vec<Wql>, String>>()?;
+
+    if statements.is_empty() {
+        return Err(String::from(
+            "BEGIN ... COMMIT requires at least one statement",
+        ));
+    }
+    Ok(Wql::Transaction(statements))
+}
+
+/// Splits `body` at every `;` outside of strings and chars, skipping empty statements.
+fn split_statements(body: &str) -> Result<Vec<&str>, String> {
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut in_string = false;
+    let mut chars = body.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if in_string => {
+                chars.next();
+            }
+            '"' => in_string = !in_string,
+            '\'' if !in_string => {
+                chars.next();
+                chars.next();
+            }
+            ';' if !in_string => {
+                statements.push(&body[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    if in_string {
+        return Err(String::from("Unterminated string"));
+    }
+    statements.push(&body[start..]);
+
+    Ok(statements
+        .into_iter()
+        .map(str::trim)
+        .filter(|statement| !statement.is_empty())
+        .collect())
+}
@@ -1,6 +1,6 @@
 use std::str::FromStr;
 
-use crate::{logic::parse_value, select::algebra_functions, ToSelect, Types, Wql};
+use crate::{logic::parse_value, select::algebra_functions, Algebra, ToSelect, Types, Wql};
 use serde::{Deserialize, Serialize};
 
 pub fn where_selector(
@@ -55,6 +55,64 @@ pub fn where_selector(
     ))
 }
 
+/// Clauses of `HAVING {...}`, comparing the `count` or an aggregate of each `GROUP BY`
+/// bucket to values, like `(> count 10)` or `(>= sum_amount 1000)`.
+pub fn having_selector(chars: &mut std::str::Chars) -> Result<Vec<Clause>, String> {
+    let mut open = chars.skip_while(|c| c.is_whitespace()).take(1);
+
+    if open.next() != Some('{') {
+        return Err(String::from(
+            "HAVING clauses must be contained inside ` {...}`",
+        ));
+    }
+
+    let mut clauses = Vec::new();
+    let mut clause = String::new();
+    loop {
+        match chars.next() {
+            Some(',') => {
+                clauses.push(clause);
+                clause = String::new();
+            }
+            Some('}') | None => {
+                clauses.push(clause);
+                break;
+            }
+            Some(c) => clause.push(c),
+        }
+    }
+
+    let clauses = clauses
+        .into_iter()
+        .filter(|c| !c.trim().is_empty())
+        .map(|c| {
+            let mut chs = c.trim().chars();
+            set_clause("", &mut chs)
+        })
+        .collect::<Vec<Clause>>();
+    if clauses.is_empty() {
+        return Err(String::from("HAVING clause cannot be empty"));
+    }
+    if !clauses.iter().all(is_having_clause) {
+        return Err(String::from(
+            "HAVING clauses compare `count` or aggregates like `sum_key` with ==, >, >=, <, <=, in, between or or",
+        ));
+    }
+
+    Ok(clauses)
+}
+
+fn is_having_clause(clause: &Clause) -> bool {
+    let is_aggregate = |key: &str| key == "count" || Algebra::from_aggregate_name(key).is_some();
+    match clause {
+        Clause::SimpleComparisonFunction(Function::Like, _, _) => false,
+        Clause::SimpleComparisonFunction(_, key, _)
+        | Clause::ComplexComparisonFunctions(_, key, _) => is_aggregate(key),
+        Clause::Or(_, clauses) => !clauses.is_empty() && clauses.iter().all(is_having_clause),
+        _ => false,
+    }
+}
+
 fn set_clause(entity_name: &str, chs: &mut std::str::Chars) -> Clause {
     let c_str: String = chs
         .skip_while(|c| c.is_whitespace())
@@ -279,6 +337,37 @@ mod test {
         )
     }
 
+    #[test]
+    fn key_paths() {
+        let mut chars = " {
+            ?* my_entity:address.city \"Seoul\",
+            ?* my_entity:tags.0 ?tag,
+        }"
+        .chars();
+        let wql = where_selector("my_entity".to_string(), ToSelect::All, &mut chars);
+
+        assert_eq!(
+            wql.unwrap(),
+            Wql::SelectWhere(
+                "my_entity".to_string(),
+                ToSelect::All,
+                vec![
+                    Clause::ContainsKeyValue(
+                        "my_entity".to_string(),
+                        "address.city".to_string(),
+                        Types::String("Seoul".to_string())
+                    ),
+                    Clause::ValueAttribution(
+                        "my_entity".to_string(),
+                        "tags.0".to_string(),
+                        Value("?tag".to_string())
+                    ),
+                ],
+                HashMap::new()
+            )
+        )
+    }
+
     #[test]
     fn simple_comparison() {
         let mut chars = " {